@@ -0,0 +1,111 @@
+//! Applies random sequences of spawns, despawns, sets, removes and clears to every storage kind and
+//! checks them against `StorageModel`, e.g. with `cargo fuzz run storage_ops`.
+//!
+//! The first byte of the input configures the entities: bit 0 selects `ReusePolicy::Lifo`, and bit 1
+//! leaves the first slots with generations close to `u32::MAX`, so reusing them wraps the generation.
+//! Every following chunk of three bytes is one operation: its kind, its entity and its component.
+
+#![no_main]
+
+use genesis::{
+    Entities, Entity, IndexKey, IndexedStorage, MapStorage, ModelStorage, ReusePolicy,
+    StorageModel, StorageOp, VecStorage,
+};
+use libfuzzer_sys::fuzz_target;
+use std::sync::{Arc, RwLock};
+
+/// The number of slots aged by bit 1 of the configuration.
+const AGED_SLOTS: u32 = 8;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Key(u8);
+
+impl IndexKey for Key {
+    type Key = u8;
+
+    fn index_key(&self) -> u8 {
+        self.0
+    }
+}
+
+fn entities(config: u8) -> Arc<RwLock<Entities>> {
+    let policy = match config & 1 {
+        0 => ReusePolicy::LowestFree,
+        _ => ReusePolicy::Lifo,
+    };
+    let mut entities = Entities::new(2).with_policy(policy);
+    if config & 2 != 0 {
+        let aged = (0..AGED_SLOTS)
+            .map(|index| Entity {
+                index,
+                generation: u32::MAX - index % 4,
+            })
+            .collect::<Vec<_>>();
+        entities.mirror_alive(&aged);
+        entities.mirror_alive(&[]);
+    }
+    Arc::new(RwLock::new(entities))
+}
+
+fn operations(data: &[u8]) -> impl Iterator<Item = StorageOp<Key>> + '_ {
+    data.chunks_exact(3).map(|chunk| {
+        let entity = usize::from(chunk[1]);
+        match chunk[0] % 8 {
+            0 | 1 => StorageOp::Spawn,
+            2 => StorageOp::Despawn(entity),
+            3 | 4 => StorageOp::Set(entity, Key(chunk[2])),
+            5 => StorageOp::Remove(entity),
+            6 => StorageOp::Get(entity),
+            _ => StorageOp::Clear,
+        }
+    })
+}
+
+/// Apply the operations to the storage, panicking on the first deviation from the model.
+fn check(
+    entities: Arc<RwLock<Entities>>,
+    storage: &mut impl ModelStorage<Key>,
+    data: &[u8],
+) -> Vec<Entity> {
+    let mut model = StorageModel::new(entities);
+    if let Err(violation) = model.apply_all(storage, operations(data)) {
+        panic!("{}", violation);
+    }
+    model.spawned().to_vec()
+}
+
+fuzz_target!(|data: &[u8]| {
+    let (config, data) = match data.split_first() {
+        Some((config, data)) => (*config, data),
+        None => return,
+    };
+
+    let vec_entities = entities(config);
+    let mut vec = VecStorage::new(Arc::clone(&vec_entities), 2);
+    check(Arc::clone(&vec_entities), &mut vec, data);
+    vec.check_integrity(&vec_entities, "vec").unwrap();
+
+    let map_entities = entities(config);
+    let mut map = MapStorage::new(Arc::clone(&map_entities));
+    check(Arc::clone(&map_entities), &mut map, data);
+    map.check_integrity(&map_entities, "map").unwrap();
+
+    let indexed_entities = entities(config);
+    let mut indexed = IndexedStorage::new(Arc::clone(&indexed_entities), 2);
+    let spawned = check(Arc::clone(&indexed_entities), &mut indexed, data);
+    indexed
+        .check_integrity(&indexed_entities, "indexed")
+        .unwrap();
+    // The reverse index has to list exactly the entities whose component has the key.
+    for entity in spawned {
+        if let Some(Key(key)) = indexed.get(entity).cloned() {
+            assert!(indexed.find(&key).any(|found| found == entity));
+        }
+    }
+    for key in 0..=u8::MAX {
+        let found = indexed.find(&key).collect::<Vec<_>>();
+        for entity in found {
+            assert_eq!(indexed.get(entity), Some(&Key(key)));
+        }
+    }
+});
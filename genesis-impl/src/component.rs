@@ -1,11 +1,14 @@
 use proc_macro2::TokenStream;
-use quote::{quote, ToTokens};
+use quote::{format_ident, quote, ToTokens};
+use syn::Ident;
 
 use crate::input::*;
 
 pub(crate) fn generate_code(input: &Input) -> TokenStream {
     let enum_definition = generate_enum_definition(input);
     let from_impls = generate_from_impls(input);
+    let try_from_impls = generate_try_from_impls(input);
+    let kind_code = generate_kind_code(input);
 
     let extra_attributes = input.attributes.iter().map(|attr| {
         let tokens = &attr.to_token_stream();
@@ -18,14 +21,162 @@ pub(crate) fn generate_code(input: &Input) -> TokenStream {
         #(#extra_attributes)*
         #enum_definition
         #from_impls
+        #try_from_impls
+        #kind_code
+    }
+}
+
+/// The name of the generated enum of component kinds, e.g. `MyComponentKind` for `MyComponent`.
+pub(crate) fn kind_enum_name(input: &Input) -> Ident {
+    format_ident!("{}Kind", input.component_enum_name)
+}
+
+fn generate_kind_code(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let component_enum = &input.component_enum_name;
+    let kind_enum = kind_enum_name(input);
+    let component_count = input.components.len();
+    let variants = input
+        .components
+        .iter()
+        .map(|c| &c.variant)
+        .collect::<Vec<_>>();
+    let names = input
+        .components
+        .iter()
+        .map(|c| c.component_type.to_token_stream().to_string())
+        .collect::<Vec<_>>();
+    let ids = input.components.iter().map(|c| c.id()).collect::<Vec<_>>();
+
+    let serde_derive = if input.derives("Serialize") && input.derives("Deserialize") {
+        quote! {
+            #[derive(::genesis::serde::Serialize, ::genesis::serde::Deserialize)]
+            #[serde(crate = "::genesis::serde")]
+        }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        /// The kinds of components of the World, e.g. for filtering entities by component at runtime.
+        #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+        #serde_derive
+        #vis enum #kind_enum {
+            #(#variants,)*
+        }
+
+        impl #kind_enum {
+            /// All component kinds, in the order of the World definition.
+            #vis const ALL: [#kind_enum; #component_count] = [#(#kind_enum::#variants,)*];
+
+            /// The name of the component type of this kind.
+            #vis fn name(self) -> &'static str {
+                match self {
+                    #(#kind_enum::#variants => #names,)*
+                }
+            }
+
+            /// The stable numeric id of the component type of this kind, e.g. to tag components in a
+            /// network protocol. Defaults to a hash of the type name and can be set via `#[component(id = n)]`.
+            #vis fn id(self) -> u32 {
+                match self {
+                    #(#kind_enum::#variants => #ids,)*
+                }
+            }
+
+            /// The position of this kind in the World definition, i.e. in `ALL` and in the
+            /// `COMPONENT_NAMES` of the World.
+            #vis const fn index(self) -> usize {
+                self as usize
+            }
+
+            /// The kind at the given position in the World definition, if any; see `index()`.
+            #vis fn from_index(index: usize) -> ::std::option::Option<#kind_enum> {
+                #kind_enum::ALL.get(index).copied()
+            }
+
+            /// The kind with the given id, if any; see `id()`.
+            #vis fn from_id(id: u32) -> ::std::option::Option<#kind_enum> {
+                match id {
+                    #(#ids => ::std::option::Option::Some(#kind_enum::#variants),)*
+                    _ => ::std::option::Option::None,
+                }
+            }
+        }
+
+        impl #component_enum {
+            /// The kind of this component.
+            #vis fn kind(&self) -> #kind_enum {
+                match self {
+                    #(#component_enum::#variants(_) => #kind_enum::#variants,)*
+                }
+            }
+        }
+    }
+}
+
+/// Generate the serde attributes serializing the given component as its `#[component(serialize_with = ...)]`
+/// surrogate, if any; `optional` is set for `Option<T>` fields like those of templates.
+pub(crate) fn generate_surrogate_attributes(
+    input: &Input,
+    component: &WorldComponent,
+    optional: bool,
+) -> TokenStream {
+    let surrogate = match &component.serialize_with {
+        Some(surrogate) => surrogate,
+        None => return quote! {},
+    };
+    let ty = &component.component_type;
+    let (serialize, deserialize) = if optional {
+        ("serialize_option", "deserialize_option")
+    } else {
+        ("serialize", "deserialize")
+    };
+    let function = |name: &str| {
+        format!(
+            "::genesis::surrogate::{}::<{}, {}, _>",
+            name,
+            surrogate.to_token_stream(),
+            ty.to_token_stream()
+        )
+    };
+
+    let serialize_attribute = if input.derives("Serialize") {
+        let path = function(serialize);
+        quote! { #[serde(serialize_with = #path)] }
+    } else {
+        quote! {}
+    };
+    let deserialize_attribute = if input.derives("Deserialize") {
+        let path = function(deserialize);
+        // Unlike plain `Option<T>` fields, fields with `deserialize_with` are required unless they have a default.
+        let default = if optional {
+            quote! { #[serde(default)] }
+        } else {
+            quote! {}
+        };
+        quote! {
+            #[serde(deserialize_with = #path)]
+            #default
+        }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        #serialize_attribute
+        #deserialize_attribute
     }
 }
 
 fn generate_enum_definition(input: &Input) -> TokenStream {
     let component_fields = input.components.iter().map(|c| {
         let ty = &c.component_type;
+        let variant = &c.variant;
+        let surrogate_attributes = generate_surrogate_attributes(input, c, false);
         quote! {
-            #ty(#ty),
+            #surrogate_attributes
+            #variant(#ty),
         }
     });
 
@@ -41,12 +192,13 @@ fn generate_enum_definition(input: &Input) -> TokenStream {
 
 fn generate_from_impls(input: &Input) -> TokenStream {
     let component_enum = &input.component_enum_name;
-    let from_impls = input.components.iter().map(|c| {
+    let from_impls = input.primary_components().map(|c| {
         let ty = &c.component_type;
+        let variant = &c.variant;
         quote! {
             impl From<#ty> for #component_enum {
                 fn from(component: #ty) -> Self {
-                    Self::#ty(component)
+                    Self::#variant(component)
                 }
             }
         }
@@ -56,3 +208,28 @@ fn generate_from_impls(input: &Input) -> TokenStream {
         #(#from_impls)*
     }
 }
+
+fn generate_try_from_impls(input: &Input) -> TokenStream {
+    let component_enum = &input.component_enum_name;
+    let try_from_impls = input.primary_components().map(|c| {
+        let ty = &c.component_type;
+        let variant = &c.variant;
+        quote! {
+            impl ::std::convert::TryFrom<#component_enum> for #ty {
+                type Error = #component_enum;
+
+                fn try_from(component: #component_enum) -> ::std::result::Result<Self, Self::Error> {
+                    match component {
+                        #component_enum::#variant(component) => Ok(component),
+                        #[allow(unreachable_patterns)]
+                        other => Err(other),
+                    }
+                }
+            }
+        }
+    });
+
+    quote! {
+        #(#try_from_impls)*
+    }
+}
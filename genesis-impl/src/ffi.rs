@@ -0,0 +1,246 @@
+use proc_macro2::{Literal, TokenStream};
+use quote::{format_ident, quote, quote_spanned};
+use syn::spanned::Spanned;
+
+use crate::input::*;
+
+pub(crate) fn generate_code(input: &Input) -> TokenStream {
+    if !input.options.ffi {
+        return quote! {};
+    }
+
+    let world = &input.world_name;
+    let vis = &input.vis;
+    let prefix = snake_case(&world.to_string());
+    let table = format_ident!("{}_COMPONENTS", prefix.to_uppercase());
+    let new = format_ident!("{}_new", prefix);
+    let free = format_ident!("{}_free", prefix);
+    let spawn = format_ident!("{}_spawn", prefix);
+    let despawn = format_ident!("{}_despawn", prefix);
+    let exists = format_ident!("{}_exists", prefix);
+    let component_count = format_ident!("{}_component_count", prefix);
+    let components = format_ident!("{}_components", prefix);
+    let get = format_ident!("{}_get", prefix);
+    let set = format_ident!("{}_set", prefix);
+    let remove = format_ident!("{}_remove", prefix);
+
+    let count = input.components.len();
+    let descriptors = input.components.iter().map(|c| {
+        let ty = &c.component_type;
+        let name = c.field_name.to_string();
+        quote! {
+            ::genesis::ffi::ComponentDescriptor {
+                name: ::std::concat!(#name, "\0").as_ptr() as *const ::std::os::raw::c_char,
+                size: ::std::mem::size_of::<#ty>(),
+                align: ::std::mem::align_of::<#ty>(),
+            },
+        }
+    });
+    let ids = (0..count as u32)
+        .map(Literal::u32_unsuffixed)
+        .collect::<Vec<_>>();
+    let fields = input
+        .components
+        .iter()
+        .map(|c| &c.field_name)
+        .collect::<Vec<_>>();
+    let types = input
+        .components
+        .iter()
+        .map(|c| &c.component_type)
+        .collect::<Vec<_>>();
+    let variants = input
+        .components
+        .iter()
+        .map(|c| &c.variant)
+        .collect::<Vec<_>>();
+    let component_enum = &input.component_enum_name;
+
+    let copy_checks = types.iter().map(|ty| {
+        quote_spanned! {ty.span()=>
+            const _: () = ::genesis::ffi::assert_ffi_component::<#ty>();
+        }
+    });
+
+    let table_doc = format!(
+        "The component types of `{}` by component id, as exposed to C code.",
+        world
+    );
+
+    quote! {
+        #(#copy_checks)*
+
+        #[doc = #table_doc]
+        #vis static #table: [::genesis::ffi::ComponentDescriptor; #count] = [
+            #(#descriptors)*
+        ];
+
+        /// Create a new World with the given initial capacity.
+        /// The World must be freed with the matching `_free` function.
+        #[no_mangle]
+        #vis extern "C" fn #new(initial_capacity: u32) -> *mut #world {
+            ::std::boxed::Box::into_raw(::std::boxed::Box::new(#world::new(initial_capacity)))
+        }
+
+        /// Free a World created by the matching `_new` function. Does nothing if `world` is null.
+        ///
+        /// # Safety
+        /// `world` must be null or a World created by the matching `_new` function that wasn't freed yet.
+        #[no_mangle]
+        #vis unsafe extern "C" fn #free(world: *mut #world) {
+            if !world.is_null() {
+                ::std::mem::drop(::std::boxed::Box::from_raw(world));
+            }
+        }
+
+        /// Spawn a new entity, returned packed by `Entity::to_bits()`.
+        /// Returns `Entity::INVALID`, i.e. `UINT64_MAX`, if `world` is null.
+        ///
+        /// # Safety
+        /// `world` must be null or a valid World created by the matching `_new` function.
+        #[no_mangle]
+        #vis unsafe extern "C" fn #spawn(world: *mut #world) -> u64 {
+            match world.as_mut() {
+                None => ::genesis::Entity::INVALID.to_bits(),
+                Some(world) => world.spawn().to_bits(),
+            }
+        }
+
+        /// Despawn the given entity and remove all its components.
+        ///
+        /// # Safety
+        /// `world` must be a valid World created by the matching `_new` function.
+        #[no_mangle]
+        #vis unsafe extern "C" fn #despawn(world: *mut #world, entity: u64) -> ::genesis::ffi::FfiStatus {
+            match world.as_mut() {
+                None => ::genesis::ffi::FfiStatus::NullPointer,
+                Some(world) => match world.despawn(::genesis::Entity::from_bits(entity)) {
+                    Ok(()) => ::genesis::ffi::FfiStatus::Ok,
+                    Err(_) => ::genesis::ffi::FfiStatus::NoSuchEntity,
+                },
+            }
+        }
+
+        /// Check if the given entity exists.
+        ///
+        /// # Safety
+        /// `world` must be a valid World created by the matching `_new` function.
+        #[no_mangle]
+        #vis unsafe extern "C" fn #exists(world: *const #world, entity: u64) -> bool {
+            match world.as_ref() {
+                None => false,
+                Some(world) => world.entities.read().unwrap().exists(::genesis::Entity::from_bits(entity)),
+            }
+        }
+
+        /// The number of component types of the World.
+        #[no_mangle]
+        #vis extern "C" fn #component_count() -> u32 {
+            #count as u32
+        }
+
+        /// The descriptors of all component types of the World, indexed by component id.
+        #[no_mangle]
+        #vis extern "C" fn #components() -> *const ::genesis::ffi::ComponentDescriptor {
+            #table.as_ptr()
+        }
+
+        /// Copy the component with the given id of the given entity to `out`. Component types are `Copy`,
+        /// so the copy owns nothing and needs no cleanup.
+        ///
+        /// # Safety
+        /// `world` must be a valid World created by the matching `_new` function, and `out` must be
+        /// valid for writes of the size of the component type given by its descriptor.
+        #[no_mangle]
+        #[allow(unused_variables)]
+        #vis unsafe extern "C" fn #get(
+            world: *const #world,
+            entity: u64,
+            component: u32,
+            out: *mut ::std::os::raw::c_void,
+        ) -> ::genesis::ffi::FfiStatus {
+            let world = match world.as_ref() {
+                Some(world) if !out.is_null() => world,
+                _ => return ::genesis::ffi::FfiStatus::NullPointer,
+            };
+            let entity = ::genesis::Entity::from_bits(entity);
+            if !world.entities.read().unwrap().exists(entity) {
+                return ::genesis::ffi::FfiStatus::NoSuchEntity;
+            }
+            match component {
+                #(
+                    #ids => match world.#fields.get(entity) {
+                        Some(data) => {
+                            ::genesis::ffi::write_component(data, out);
+                            ::genesis::ffi::FfiStatus::Ok
+                        }
+                        None => ::genesis::ffi::FfiStatus::MissingComponent,
+                    },
+                )*
+                _ => ::genesis::ffi::FfiStatus::UnknownComponent,
+            }
+        }
+
+        /// Set the component with the given id of the given entity to a copy of `data`, checking the
+        /// requirements of the component like registering it does. Component types are `Copy`, so the
+        /// bytes at `data` stay owned by the caller.
+        ///
+        /// # Safety
+        /// `world` must be a valid World created by the matching `_new` function, and `data` must point
+        /// to a valid value of the component type given by its descriptor.
+        #[no_mangle]
+        #[allow(unused_variables)]
+        #vis unsafe extern "C" fn #set(
+            world: *mut #world,
+            entity: u64,
+            component: u32,
+            data: *const ::std::os::raw::c_void,
+        ) -> ::genesis::ffi::FfiStatus {
+            let world = match world.as_mut() {
+                Some(world) if !data.is_null() => world,
+                _ => return ::genesis::ffi::FfiStatus::NullPointer,
+            };
+            let entity = ::genesis::Entity::from_bits(entity);
+            match component {
+                #(
+                    #ids => {
+                        let data = #component_enum::#variants(::genesis::ffi::read_component::<#types>(data));
+                        match ::genesis::Register::register(world, entity, data) {
+                            Ok(_) => ::genesis::ffi::FfiStatus::Ok,
+                            Err(error) => ::genesis::ffi::FfiStatus::from(error),
+                        }
+                    }
+                )*
+                _ => ::genesis::ffi::FfiStatus::UnknownComponent,
+            }
+        }
+
+        /// Remove the component with the given id from the given entity.
+        ///
+        /// # Safety
+        /// `world` must be a valid World created by the matching `_new` function.
+        #[no_mangle]
+        #[allow(unused_variables)]
+        #vis unsafe extern "C" fn #remove(
+            world: *mut #world,
+            entity: u64,
+            component: u32,
+        ) -> ::genesis::ffi::FfiStatus {
+            let world = match world.as_mut() {
+                Some(world) => world,
+                None => return ::genesis::ffi::FfiStatus::NullPointer,
+            };
+            let entity = ::genesis::Entity::from_bits(entity);
+            match component {
+                #(
+                    #ids => match world.#fields.remove(entity) {
+                        Ok(Some(_)) => ::genesis::ffi::FfiStatus::Ok,
+                        Ok(None) => ::genesis::ffi::FfiStatus::MissingComponent,
+                        Err(error) => ::genesis::ffi::FfiStatus::from(error),
+                    },
+                )*
+                _ => ::genesis::ffi::FfiStatus::UnknownComponent,
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::spanned::Spanned;
+use syn::{
+    parse_quote, AngleBracketedGenericArguments, Data, DataStruct, DeriveInput, Field, Fields,
+    GenericArgument, PathArguments, Result, Token, Type, TypePath,
+};
+
+const EXPECTED_NAMED_STRUCT: &str = "FromQuery can only be derived for structs with named fields.";
+const UNKNOWN_QUERY_OPTION: &str =
+    "Unknown query option; expected `entity` or `from = <component>`.";
+
+/// An option passed to a field via `#[query(...)]`.
+enum QueryOption {
+    Entity,
+    From(Box<Type>),
+}
+
+impl Parse for QueryOption {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let option = input.parse::<syn::Ident>()?;
+        if option == "entity" {
+            Ok(QueryOption::Entity)
+        } else if option == "from" {
+            let _assignment = input.parse::<Token![=]>()?;
+            Ok(QueryOption::From(Box::new(input.parse::<Type>()?)))
+        } else {
+            Err(syn::Error::new(option.span(), UNKNOWN_QUERY_OPTION))
+        }
+    }
+}
+
+/// The argument of the given type if it is an `Option<T>`.
+fn option_argument(ty: &Type) -> Option<&Type> {
+    let path = match ty {
+        Type::Path(TypePath { qself: None, path }) => path,
+        _ => return None,
+    };
+    let segment = path.segments.last().filter(|s| s.ident == "Option")?;
+    match &segment.arguments {
+        PathArguments::AngleBracketed(AngleBracketedGenericArguments { args, .. }) => {
+            match args.first() {
+                Some(GenericArgument::Type(ty)) if args.len() == 1 => Some(ty),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn query_option(field: &Field) -> Result<Option<QueryOption>> {
+    let mut option = None;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("query"))
+    {
+        option = Some(attr.parse_args::<QueryOption>()?);
+    }
+    Ok(option)
+}
+
+pub(crate) fn generate_derive(input: &DeriveInput) -> Result<TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(DataStruct {
+            fields: Fields::Named(named),
+            ..
+        }) => &named.named,
+        _ => return Err(syn::Error::new(input.span(), EXPECTED_NAMED_STRUCT)),
+    };
+
+    let mut component_types = vec![];
+    let mut initializers = vec![];
+    for field in fields {
+        let name = field.ident.as_ref().unwrap();
+        let option = query_option(field)?;
+        if let Some(QueryOption::Entity) = option {
+            initializers.push(quote! { #name: entity, });
+            continue;
+        }
+
+        let optional = option_argument(&field.ty);
+        let component_type = match option {
+            Some(QueryOption::From(component_type)) => *component_type,
+            _ => optional.unwrap_or(&field.ty).clone(),
+        };
+        let component = quote! {
+            ::genesis::Storage::<#component_type>::get_unchecked(
+                <W as ::genesis::HasComponent<#component_type>>::storage(world),
+                entity,
+            )
+        };
+        let value = if optional.is_some() {
+            quote! { #component.cloned().map(::std::convert::From::from) }
+        } else {
+            quote! { ::std::convert::From::from(::std::clone::Clone::clone(#component?)) }
+        };
+        initializers.push(quote! { #name: #value, });
+        component_types.push(component_type);
+    }
+
+    let name = &input.ident;
+    let (_, ty_generics, _) = input.generics.split_for_impl();
+    let mut generics = input.generics.clone();
+    generics.params.push(parse_quote!(W));
+    let where_clause = generics.make_where_clause();
+    where_clause
+        .predicates
+        .push(parse_quote!(W: ::genesis::HasEntities));
+    for component_type in &component_types {
+        where_clause
+            .predicates
+            .push(parse_quote!(W: ::genesis::HasComponent<#component_type>));
+    }
+    let (impl_generics, _, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::genesis::FromQuery<W> for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn from_entity(world: &W, entity: ::genesis::Entity) -> ::std::option::Option<Self> {
+                ::std::option::Option::Some(Self {
+                    #(#initializers)*
+                })
+            }
+        }
+    })
+}
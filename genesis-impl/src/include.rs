@@ -0,0 +1,95 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::parse::{Parse, ParseStream};
+use syn::{Data, DataStruct, DeriveInput, Fields, FieldsNamed, Ident, Path, Result, Visibility};
+
+/// A named set of storage fields declared with `component_set!`.
+pub(crate) struct ComponentSet {
+    pub vis: Visibility,
+    pub name: Ident,
+    pub fields: FieldsNamed,
+}
+
+impl Parse for ComponentSet {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        Ok(Self {
+            vis: input.parse()?,
+            name: input.parse()?,
+            fields: input.parse()?,
+        })
+    }
+}
+
+const INCLUDE_COMPONENTS: &str = "include_components";
+const EXPECTED_NAMED_FIELDS: &str = "Only structs with named fields can include components.";
+
+/// Generate the `macro_rules!` macro of a component set, which appends the fields of the set to the
+/// World struct it is invoked with. Public sets are exported so other crates can include them.
+pub(crate) fn generate_component_set(set: &ComponentSet) -> TokenStream {
+    let name = &set.name;
+    let fields = set.fields.named.iter();
+    let export = match set.vis {
+        Visibility::Inherited => quote! {},
+        _ => quote! { #[macro_export] },
+    };
+
+    quote! {
+        #export
+        macro_rules! #name {
+            ($(#[$attr:meta])* $vis:vis struct $name:ident { $($field:tt)* }) => {
+                $(#[$attr])*
+                $vis struct $name {
+                    $($field)*
+                    #(#fields,)*
+                }
+            };
+        }
+    }
+}
+
+/// If the World struct has an `#[include_components(path)]` attribute, hand the struct with that
+/// attribute removed to the macro of the component set at `path`, which appends the fields of the set
+/// and applies `#[world]` again with the given arguments; further includes are expanded the same way.
+pub(crate) fn expand_include(
+    args: &TokenStream,
+    input: &DeriveInput,
+) -> Option<Result<TokenStream>> {
+    let position = input
+        .attrs
+        .iter()
+        .position(|attr| attr.path.is_ident(INCLUDE_COMPONENTS))?;
+    Some(generate_include(args, input, position))
+}
+
+fn generate_include(
+    args: &TokenStream,
+    input: &DeriveInput,
+    position: usize,
+) -> Result<TokenStream> {
+    let set = input.attrs[position].parse_args::<Path>()?;
+    let fields = match &input.data {
+        Data::Struct(DataStruct {
+            fields: Fields::Named(fields),
+            ..
+        }) => fields.named.iter(),
+        _ => return Err(syn::Error::new_spanned(&input.ident, EXPECTED_NAMED_FIELDS)),
+    };
+    let attributes = input
+        .attrs
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != position)
+        .map(|(_, attr)| attr);
+    let vis = &input.vis;
+    let name = &input.ident;
+
+    Ok(quote! {
+        #set! {
+            #[::genesis::world(#args)]
+            #(#attributes)*
+            #vis struct #name {
+                #(#fields,)*
+            }
+        }
+    })
+}
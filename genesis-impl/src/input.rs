@@ -1,10 +1,13 @@
+use quote::quote;
+use std::collections::HashSet;
 use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
 use syn::spanned::Spanned;
 use syn::Attribute;
 use syn::Token;
 use syn::{
-    AngleBracketedGenericArguments, Data, DataStruct, DeriveInput, Field, GenericArgument, Ident,
-    Path, PathArguments, Result, Type, TypePath, Visibility,
+    AngleBracketedGenericArguments, Data, DataStruct, DeriveInput, Expr, Field, GenericArgument,
+    Ident, LitInt, Meta, NestedMeta, Path, PathArguments, Result, Type, TypePath, Visibility,
 };
 
 pub(crate) struct Input {
@@ -14,11 +17,49 @@ pub(crate) struct Input {
     pub components: Vec<WorldComponent>,
     pub vis: Visibility,
     pub attributes: Vec<Attribute>,
+    pub options: WorldOptions,
 }
 
 pub struct InputArgs {
     pub component_name: Ident,
     pub template_name: Ident,
+    pub options: WorldOptions,
+}
+
+/// Optional arguments passed to the `#[world]` macro after the component enum and template names,
+/// either as `name = value` or as a plain flag.
+#[derive(Default)]
+pub struct WorldOptions {
+    pub reuse_policy: Option<Expr>,
+    pub locked_name: Option<Ident>,
+    pub shared_name: Option<Ident>,
+    pub wasm_name: Option<Ident>,
+    pub inspect: bool,
+    pub schema: bool,
+    pub track_leaks: bool,
+    pub despawn_events: bool,
+    pub spawn_order: bool,
+    pub json: bool,
+    pub ron: bool,
+    pub ffi: bool,
+    pub uuids: bool,
+    pub access: bool,
+    pub unknown_data: bool,
+    pub rkyv: bool,
+    /// The size in bytes above which components in dense storages fail to compile, as given in
+    /// `max_component_size = n`.
+    pub max_component_size: Option<usize>,
+    /// The size in bytes above which components in dense storages cause a warning, as given in
+    /// `warn_component_size = n`.
+    pub warn_component_size: Option<usize>,
+    pub partial_templates: Vec<PartialTemplate>,
+}
+
+/// An additional template covering only the given components, passed to the `#[world]` macro
+/// as `Name(a, b)` with the storage field or template names of the components.
+pub struct PartialTemplate {
+    pub name: Ident,
+    pub components: Vec<Ident>,
 }
 
 impl Parse for InputArgs {
@@ -26,25 +67,172 @@ impl Parse for InputArgs {
         let component_name = input.parse::<Ident>()?;
         let _separator = input.parse::<Token![,]>()?;
         let template_name = input.parse::<Ident>()?;
+        let mut options = WorldOptions::default();
+
+        while input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            let option = input.parse::<Ident>()?;
+            if input.peek(syn::token::Paren) {
+                let inner;
+                syn::parenthesized!(inner in input);
+                let components = Punctuated::<Ident, Token![,]>::parse_terminated(&inner)?;
+                options.partial_templates.push(PartialTemplate {
+                    name: option,
+                    components: components.into_iter().collect(),
+                });
+                continue;
+            }
+            if option == "inspect" {
+                options.inspect = true;
+                continue;
+            }
+            if option == "schema" {
+                options.schema = true;
+                continue;
+            }
+            if option == "track_leaks" {
+                options.track_leaks = true;
+                continue;
+            }
+            if option == "despawn_events" {
+                options.despawn_events = true;
+                continue;
+            }
+            if option == "spawn_order" {
+                options.spawn_order = true;
+                continue;
+            }
+            if option == "json" {
+                options.json = true;
+                continue;
+            }
+            if option == "ron" {
+                options.ron = true;
+                continue;
+            }
+            if option == "ffi" {
+                options.ffi = true;
+                continue;
+            }
+            if option == "uuids" {
+                options.uuids = true;
+                continue;
+            }
+            if option == "access" {
+                options.access = true;
+                continue;
+            }
+            if option == "rkyv" {
+                options.rkyv = true;
+                continue;
+            }
+            if option == "unknown_data" {
+                options.unknown_data = true;
+                continue;
+            }
+
+            let _assignment = input.parse::<Token![=]>()?;
+            if option == "reuse_policy" {
+                options.reuse_policy = Some(input.parse::<Expr>()?);
+            } else if option == "locked" {
+                options.locked_name = Some(input.parse::<Ident>()?);
+            } else if option == "shared" {
+                options.shared_name = Some(input.parse::<Ident>()?);
+            } else if option == "wasm" {
+                options.wasm_name = Some(input.parse::<Ident>()?);
+            } else if option == "max_component_size" {
+                options.max_component_size =
+                    Some(input.parse::<LitInt>()?.base10_parse::<usize>()?);
+            } else if option == "warn_component_size" {
+                options.warn_component_size =
+                    Some(input.parse::<LitInt>()?.base10_parse::<usize>()?);
+            } else {
+                return Err(syn::Error::new(option.span(), UNKNOWN_WORLD_OPTION));
+            }
+        }
+
         Ok(Self {
             component_name,
             template_name,
+            options,
         })
     }
 }
 
+const UNKNOWN_WORLD_OPTION: &str =
+    "Unknown world option; expected `reuse_policy`, `locked`, `shared`, `wasm`, `max_component_size`, `warn_component_size`, `inspect`, `schema`, `track_leaks`, `despawn_events`, `spawn_order`, `json`, `ron`, `ffi`, `uuids`, `access`, `rkyv` or `unknown_data`.";
+
 #[derive(Debug)]
 pub(crate) struct WorldComponent {
     pub template_name: Ident,
     pub storage_type: ComponentStorageType,
     pub component_type: Type,
+    /// The name of the variants of the component and kind enums for this component: the name of the
+    /// component type, or the name given in `#[component(variant = Name)]`.
+    pub variant: Ident,
     pub field_name: Ident,
+    pub maps_entities: bool,
+    pub auto_default: bool,
+    /// Names of the components required by this component, as given in `#[component(requires(...))]`.
+    pub requires: Vec<Ident>,
+    /// The function called with removed components of despawned entities, as given in
+    /// `#[component(on_despawn = path)]`.
+    pub on_despawn: Option<Path>,
+    /// The name of the relation component whose target receives the component of a despawned entity,
+    /// as given in `#[component(transfer_to = name)]`.
+    pub transfer_to: Option<Ident>,
+    /// The position of the component when removing the components of a despawned entity, as given in
+    /// `#[component(cleanup_order = n)]`; lower orders are removed first. Defaults to 0.
+    pub cleanup_order: i32,
+    /// The numeric id of the component type, as given in `#[component(id = n)]`; see `id()`.
+    pub id: Option<u32>,
+    /// The `genesis::Surrogate` the component is serialized as, as given in
+    /// `#[component(serialize_with = Surrogate)]`.
+    pub serialize_with: Option<Type>,
+    /// The visibility of the storage field, as given in `#[component(vis = pub(crate))]`; defaults to the
+    /// visibility of the World.
+    pub vis: Option<Visibility>,
+    /// Whether the component is exempt from `max_component_size` and `warn_component_size`, as given in
+    /// `#[component(allow_large)]`.
+    pub allow_large: bool,
+    /// Whether this is the first field storing its component type, which impls keyed by the type like
+    /// `HasComponent<T>` and `Register<T>` refer to. Types are compared by their spelling.
+    pub primary: bool,
+}
+
+impl WorldComponent {
+    /// The stable numeric id of the component type: the id given in `#[component(id = n)]`, or else
+    /// the 32-bit FNV-1a hash of the name of the type, so it doesn't change when fields are reordered.
+    /// Further fields of the same type hash the type name followed by their variant name instead.
+    pub(crate) fn id(&self) -> u32 {
+        let ty = &self.component_type;
+        let variant = &self.variant;
+        let name = if self.primary {
+            quote!(#ty)
+        } else {
+            quote!(#ty #variant)
+        };
+        self.id.unwrap_or_else(|| {
+            name.to_string().bytes().fold(0x811c_9dc5, |hash, byte| {
+                (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+            })
+        })
+    }
+
+    /// The visibility of the storage field of the component in the World and its storage borrows.
+    pub(crate) fn vis<'a>(&'a self, input: &'a Input) -> &'a Visibility {
+        self.vis.as_ref().unwrap_or(&input.vis)
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
 pub(crate) enum ComponentStorageType {
     Vec,
     Map,
+    DoubleBuffered,
+    Indexed,
+    Mmap,
+    Stable,
+    Dynamic,
 }
 
 impl ComponentStorageType {
@@ -52,8 +240,37 @@ impl ComponentStorageType {
         match self {
             ComponentStorageType::Vec => "VecStorage",
             ComponentStorageType::Map => "MapStorage",
+            ComponentStorageType::DoubleBuffered => "DoubleBuffered",
+            ComponentStorageType::Indexed => "IndexedStorage",
+            ComponentStorageType::Mmap => "MmapStorage",
+            ComponentStorageType::Stable => "StableStorage",
+            ComponentStorageType::Dynamic => "DynamicStorage",
         }
     }
+
+    /// The name of the corresponding variant of `genesis::StorageKind`.
+    pub(crate) fn kind(self) -> &'static str {
+        match self {
+            ComponentStorageType::Vec => "Vec",
+            ComponentStorageType::Map => "Map",
+            ComponentStorageType::DoubleBuffered => "DoubleBuffered",
+            ComponentStorageType::Indexed => "Indexed",
+            ComponentStorageType::Mmap => "Mmap",
+            ComponentStorageType::Stable => "Stable",
+            ComponentStorageType::Dynamic => "Dynamic",
+        }
+    }
+
+    /// Whether the storage records the tick each component was last modified at.
+    pub(crate) fn tracks_ticks(self) -> bool {
+        !matches!(
+            self,
+            ComponentStorageType::Map
+                | ComponentStorageType::Mmap
+                | ComponentStorageType::Stable
+                | ComponentStorageType::Dynamic
+        )
+    }
 }
 
 pub(crate) struct TemplateName {
@@ -71,6 +288,107 @@ impl Parse for TemplateName {
     }
 }
 
+/// An option passed to a component via `#[component(...)]`.
+pub(crate) enum ComponentOption {
+    MapsEntities,
+    AutoDefault,
+    AllowLarge,
+    Stable,
+    Requires(Vec<Ident>),
+    OnDespawn(Path),
+    TransferTo(Ident),
+    CleanupOrder(i32),
+    Id(u32),
+    SerializeWith(Box<Type>),
+    Vis(Visibility),
+    Variant(Ident),
+}
+
+const UNKNOWN_COMPONENT_OPTION: &str =
+    "Unknown component option; expected `maps_entities`, `auto_default`, `allow_large`, `stable`, `requires(...)`, `on_despawn = <fn>`, `transfer_to = <component>`, `cleanup_order = <n>`, `id = <n>`, `serialize_with = <surrogate>`, `vis = <visibility>` or `variant = <name>`.";
+const UNKNOWN_REQUIRED_COMPONENT: &str =
+    "Unknown required component; expected the storage field or template name of a component.";
+const UNKNOWN_RELATION_COMPONENT: &str =
+    "Unknown relation component; expected the storage field or template name of a component.";
+const UNKNOWN_TEMPLATE_COMPONENT: &str =
+    "Unknown template component; expected the storage field or template name of a component.";
+const STABLE_REQUIRES_VEC_STORAGE: &str =
+    "`#[component(stable)]` stores the component in a `StableStorage`; declare the field as `VecStorage<T>` or `StableStorage<T>`.";
+const MISSING_VARIANT_NAME: &str =
+    "Component types other than plain type names, e.g. `Box<dyn Behavior>`, need a name for their enum variants; pass `#[component(variant = <name>)]`.";
+const DUPLICATE_COMPONENT_ID: &str =
+    "Component ids must be unique; pass a different `#[component(id = <n>)]` to one of the components.";
+const RKYV_REQUIRES_VEC_OR_MAP_STORAGE: &str =
+    "Worlds archived with `rkyv` can only store components in `VecStorage` or `MapStorage`.";
+const RKYV_UNSUPPORTED_OPTION: &str =
+    "Worlds archived with `rkyv` can't use `uuids` or `access`, which can't be archived yet.";
+const DUPLICATE_VARIANT: &str =
+    "Variant names must be unique; components of a type stored in several fields need a different `#[component(variant = <name>)]` for each field but the first.";
+
+impl Parse for ComponentOption {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let option = input.parse::<Ident>()?;
+        if option == "maps_entities" {
+            Ok(ComponentOption::MapsEntities)
+        } else if option == "auto_default" {
+            Ok(ComponentOption::AutoDefault)
+        } else if option == "allow_large" {
+            Ok(ComponentOption::AllowLarge)
+        } else if option == "stable" {
+            Ok(ComponentOption::Stable)
+        } else if option == "requires" {
+            let inner;
+            syn::parenthesized!(inner in input);
+            let names = Punctuated::<Ident, Token![,]>::parse_terminated(&inner)?;
+            Ok(ComponentOption::Requires(names.into_iter().collect()))
+        } else if option == "on_despawn" {
+            let _assignment = input.parse::<Token![=]>()?;
+            Ok(ComponentOption::OnDespawn(input.parse::<Path>()?))
+        } else if option == "transfer_to" {
+            let _assignment = input.parse::<Token![=]>()?;
+            Ok(ComponentOption::TransferTo(input.parse::<Ident>()?))
+        } else if option == "cleanup_order" {
+            let _assignment = input.parse::<Token![=]>()?;
+            let order = input.parse::<LitInt>()?.base10_parse::<i32>()?;
+            Ok(ComponentOption::CleanupOrder(order))
+        } else if option == "id" {
+            let _assignment = input.parse::<Token![=]>()?;
+            let id = input.parse::<LitInt>()?.base10_parse::<u32>()?;
+            Ok(ComponentOption::Id(id))
+        } else if option == "serialize_with" {
+            let _assignment = input.parse::<Token![=]>()?;
+            Ok(ComponentOption::SerializeWith(Box::new(
+                input.parse::<Type>()?,
+            )))
+        } else if option == "vis" {
+            let _assignment = input.parse::<Token![=]>()?;
+            Ok(ComponentOption::Vis(input.parse::<Visibility>()?))
+        } else if option == "variant" {
+            let _assignment = input.parse::<Token![=]>()?;
+            Ok(ComponentOption::Variant(input.parse::<Ident>()?))
+        } else {
+            Err(syn::Error::new(option.span(), UNKNOWN_COMPONENT_OPTION))
+        }
+    }
+}
+
+pub(crate) struct ComponentOptions {
+    pub options: Vec<ComponentOption>,
+}
+
+impl Parse for ComponentOptions {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let inner;
+        syn::parenthesized!(inner in input);
+
+        let options = Punctuated::<ComponentOption, Token![,]>::parse_terminated(&inner)?;
+
+        Ok(Self {
+            options: options.into_iter().collect(),
+        })
+    }
+}
+
 const EXPECTED_NAMED_STRUCT_FIELDS: &str = "Only structs with named fields are supported.";
 
 impl Input {
@@ -80,11 +398,16 @@ impl Input {
                 fields: syn::Fields::Named(fields_named),
                 ..
             }) => {
-                let fields = fields_named
+                let mut fields = fields_named
                     .named
                     .iter()
-                    .map(|f| world_component(f))
-                    .collect();
+                    .map(world_component)
+                    .collect::<Result<Vec<_>>>()?;
+                mark_primary_components(&mut fields);
+                validate_component_types(&fields)?;
+                validate_requirements(&fields)?;
+                validate_partial_templates(&fields, &args.options.partial_templates)?;
+                validate_rkyv(&fields, &args.options, input)?;
                 Ok(Self {
                     world_name: input.ident.clone(),
                     template_name: args.template_name,
@@ -92,6 +415,7 @@ impl Input {
                     components: fields,
                     vis: input.vis.clone(),
                     attributes: input.attrs.clone(),
+                    options: args.options,
                 })
             }
             Data::Struct(data_struct) => {
@@ -107,10 +431,169 @@ impl Input {
             _ => Err(syn::Error::new(input.span(), EXPECTED_NAMED_STRUCT_FIELDS)),
         }
     }
+
+    /// Find the component with the given storage field name or template name.
+    pub(crate) fn component(&self, name: &Ident) -> Option<&WorldComponent> {
+        find_component(&self.components, name)
+    }
+
+    /// The first component of every component type, for impls keyed by the type; see `WorldComponent::primary`.
+    pub(crate) fn primary_components(&self) -> impl Iterator<Item = &WorldComponent> {
+        self.components.iter().filter(|c| c.primary)
+    }
+
+    /// The components in the order their components are removed from despawned entities: by
+    /// `cleanup_order`, then in the order of the World definition.
+    pub(crate) fn components_in_cleanup_order(&self) -> Vec<&WorldComponent> {
+        let mut components = self.components.iter().collect::<Vec<_>>();
+        components.sort_by_key(|c| c.cleanup_order);
+        components
+    }
+
+    /// The components covered by the given partial template, in the order of the World definition.
+    pub(crate) fn partial_template_components(
+        &self,
+        template: &PartialTemplate,
+    ) -> Vec<&WorldComponent> {
+        self.components
+            .iter()
+            .filter(|c| {
+                template
+                    .components
+                    .iter()
+                    .any(|name| &c.field_name == name || &c.template_name == name)
+            })
+            .collect()
+    }
+
+    /// Check if any component has requirements, in which case registering templates and the
+    /// component enum fails with `RegisterError` instead of `StorageError`.
+    pub(crate) fn has_requirements(&self) -> bool {
+        self.components.iter().any(|c| !c.requires.is_empty())
+    }
+
+    /// Check if the given trait is derived via one of the attributes applied to the world definition.
+    /// Since these attributes are applied to the component enum, deriving a trait implies that
+    /// every component type implements it as well.
+    pub(crate) fn derives(&self, trait_name: &str) -> bool {
+        self.attributes
+            .iter()
+            .filter(|attr| attr.path.is_ident("derive"))
+            .filter_map(|attr| attr.parse_meta().ok())
+            .any(|meta| match meta {
+                Meta::List(list) => list.nested.iter().any(|nested| match nested {
+                    NestedMeta::Meta(Meta::Path(path)) => path
+                        .segments
+                        .last()
+                        .is_some_and(|segment| segment.ident == trait_name),
+                    _ => false,
+                }),
+                _ => false,
+            })
+    }
 }
 
-fn world_component(f: &Field) -> WorldComponent {
+fn find_component<'a>(
+    components: &'a [WorldComponent],
+    name: &Ident,
+) -> Option<&'a WorldComponent> {
+    components
+        .iter()
+        .find(|c| &c.field_name == name)
+        .or_else(|| components.iter().find(|c| &c.template_name == name))
+}
+
+/// Mark the first field of every component type as primary, comparing types by their spelling, so
+/// `Position` and `crate::Position` count as different types.
+fn mark_primary_components(components: &mut [WorldComponent]) {
+    let mut types = HashSet::new();
+    for component in components {
+        let ty = &component.component_type;
+        component.primary = types.insert(quote!(#ty).to_string());
+    }
+}
+
+fn validate_component_types(components: &[WorldComponent]) -> Result<()> {
+    let mut variants = HashSet::new();
+    let mut ids = HashSet::new();
+    for component in components {
+        let ty = &component.component_type;
+        if !variants.insert(&component.variant) {
+            return Err(syn::Error::new(component.variant.span(), DUPLICATE_VARIANT));
+        }
+        if !ids.insert(component.id()) {
+            return Err(syn::Error::new(ty.span(), DUPLICATE_COMPONENT_ID));
+        }
+    }
+    Ok(())
+}
+
+fn validate_requirements(components: &[WorldComponent]) -> Result<()> {
+    for name in components.iter().flat_map(|c| &c.requires) {
+        if find_component(components, name).is_none() {
+            return Err(syn::Error::new(name.span(), UNKNOWN_REQUIRED_COMPONENT));
+        }
+    }
+    for name in components.iter().flat_map(|c| &c.transfer_to) {
+        if find_component(components, name).is_none() {
+            return Err(syn::Error::new(name.span(), UNKNOWN_RELATION_COMPONENT));
+        }
+    }
+    Ok(())
+}
+
+fn validate_rkyv(
+    components: &[WorldComponent],
+    options: &WorldOptions,
+    input: &DeriveInput,
+) -> Result<()> {
+    if !options.rkyv {
+        return Ok(());
+    }
+    if options.uuids || options.access {
+        return Err(syn::Error::new(input.ident.span(), RKYV_UNSUPPORTED_OPTION));
+    }
+    let unsupported = components.iter().find(|c| {
+        !matches!(
+            c.storage_type,
+            ComponentStorageType::Vec | ComponentStorageType::Map
+        )
+    });
+    match unsupported {
+        Some(c) => Err(syn::Error::new(
+            c.field_name.span(),
+            RKYV_REQUIRES_VEC_OR_MAP_STORAGE,
+        )),
+        None => Ok(()),
+    }
+}
+
+fn validate_partial_templates(
+    components: &[WorldComponent],
+    templates: &[PartialTemplate],
+) -> Result<()> {
+    for name in templates.iter().flat_map(|t| &t.components) {
+        if find_component(components, name).is_none() {
+            return Err(syn::Error::new(name.span(), UNKNOWN_TEMPLATE_COMPONENT));
+        }
+    }
+    Ok(())
+}
+
+fn world_component(f: &Field) -> Result<WorldComponent> {
     let mut template_name = f.ident.as_ref().unwrap().clone();
+    let mut maps_entities = false;
+    let mut auto_default = false;
+    let mut allow_large = false;
+    let mut stable = false;
+    let mut requires = vec![];
+    let mut on_despawn = None;
+    let mut transfer_to = None;
+    let mut cleanup_order = 0;
+    let mut id = None;
+    let mut serialize_with = None;
+    let mut vis = None;
+    let mut variant = None;
     for attr in f.attrs.iter() {
         let path_ident = attr.path.get_ident();
         if path_ident.is_some() && path_ident.unwrap() == "template_name" {
@@ -118,22 +601,87 @@ fn world_component(f: &Field) -> WorldComponent {
             if let Ok(name) = syn::parse2::<TemplateName>(tokens) {
                 template_name = name.ident;
             }
+        } else if path_ident.is_some() && path_ident.unwrap() == "component" {
+            let component_options = syn::parse2::<ComponentOptions>(attr.tokens.clone())?;
+            for option in component_options.options {
+                match option {
+                    ComponentOption::MapsEntities => maps_entities = true,
+                    ComponentOption::AutoDefault => auto_default = true,
+                    ComponentOption::AllowLarge => allow_large = true,
+                    ComponentOption::Stable => stable = true,
+                    ComponentOption::Requires(names) => requires.extend(names),
+                    ComponentOption::OnDespawn(path) => on_despawn = Some(path),
+                    ComponentOption::TransferTo(name) => transfer_to = Some(name),
+                    ComponentOption::CleanupOrder(order) => cleanup_order = order,
+                    ComponentOption::Id(value) => id = Some(value),
+                    ComponentOption::SerializeWith(surrogate) => serialize_with = Some(*surrogate),
+                    ComponentOption::Vis(visibility) => vis = Some(visibility),
+                    ComponentOption::Variant(name) => variant = Some(name),
+                }
+            }
         }
     }
     let (component_type, storage_type) = get_inner_type(f, "VecStorage")
         .map(|t| (t.clone(), ComponentStorageType::Vec))
         .or_else(|| get_inner_type(f, "MapStorage").map(|t| (t.clone(), ComponentStorageType::Map)))
-        .expect("World components must be wrapped in VecStorage or MapStorage");
+        .or_else(|| {
+            get_inner_type(f, "DoubleBuffered")
+                .map(|t| (t.clone(), ComponentStorageType::DoubleBuffered))
+        })
+        .or_else(|| {
+            get_inner_type(f, "IndexedStorage").map(|t| (t.clone(), ComponentStorageType::Indexed))
+        })
+        .or_else(|| get_inner_type(f, "MmapStorage").map(|t| (t.clone(), ComponentStorageType::Mmap)))
+        .or_else(|| get_inner_type(f, "StableStorage").map(|t| (t.clone(), ComponentStorageType::Stable)))
+        .or_else(|| get_inner_type(f, "DynamicStorage").map(|t| (t.clone(), ComponentStorageType::Dynamic)))
+        .expect(
+            "World components must be wrapped in VecStorage, MapStorage, DoubleBuffered, IndexedStorage, MmapStorage, StableStorage or DynamicStorage",
+        );
+    let storage_type = match (stable, storage_type) {
+        (false, storage_type) | (true, storage_type @ ComponentStorageType::Stable) => storage_type,
+        (true, ComponentStorageType::Vec) => ComponentStorageType::Stable,
+        (true, _) => return Err(syn::Error::new(f.ty.span(), STABLE_REQUIRES_VEC_STORAGE)),
+    };
+    let variant = match variant.or_else(|| type_name(&component_type)) {
+        Some(variant) => variant,
+        None => return Err(syn::Error::new(component_type.span(), MISSING_VARIANT_NAME)),
+    };
 
-    WorldComponent {
+    Ok(WorldComponent {
         field_name: f.ident.clone().unwrap(),
         storage_type,
         template_name,
         component_type,
+        variant,
+        maps_entities,
+        auto_default,
+        requires,
+        on_despawn,
+        transfer_to,
+        cleanup_order,
+        id,
+        serialize_with,
+        vis,
+        allow_large,
+        primary: true,
+    })
+}
+
+/// The name of a type given as a plain path like `Position` or `physics::Body`, without generic arguments.
+fn type_name(ty: &Type) -> Option<Ident> {
+    match ty {
+        Type::Path(TypePath { qself: None, path }) => {
+            let segment = path.segments.last()?;
+            match segment.arguments {
+                PathArguments::None => Some(segment.ident.clone()),
+                _ => None,
+            }
+        }
+        _ => None,
     }
 }
 
-fn get_inner_type<'a, 'b>(field: &'a Field, name: &'b str) -> Option<&'a Type> {
+fn get_inner_type<'a>(field: &'a Field, name: &str) -> Option<&'a Type> {
     match &field.ty {
         Type::Path(TypePath {
             qself: None,
@@ -156,3 +704,19 @@ fn get_inner_type<'a, 'b>(field: &'a Field, name: &'b str) -> Option<&'a Type> {
         _ => None,
     }
 }
+
+/// Convert a type name like `FfiWorld` to snake case, e.g. `ffi_world`.
+pub(crate) fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.char_indices() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
@@ -0,0 +1,106 @@
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::spanned::Spanned;
+use syn::{Data, DataStruct, DeriveInput, Fields, Index, Result};
+
+use crate::input::*;
+
+const EXPECTED_STRUCT: &str = "Inspect can only be derived for structs.";
+
+pub(crate) fn generate_derive(input: &DeriveInput) -> Result<TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(DataStruct { fields, .. }) => fields,
+        _ => return Err(syn::Error::new(input.span(), EXPECTED_STRUCT)),
+    };
+
+    let (field_names, field_accessors): (Vec<_>, Vec<_>) = match fields {
+        Fields::Named(named) => named
+            .named
+            .iter()
+            .map(|f| {
+                let ident = f.ident.as_ref().unwrap();
+                (ident.to_string(), ident.to_token_stream())
+            })
+            .unzip(),
+        Fields::Unnamed(unnamed) => unnamed
+            .unnamed
+            .iter()
+            .enumerate()
+            .map(|(i, _f)| (i.to_string(), Index::from(i).to_token_stream()))
+            .unzip(),
+        Fields::Unit => (vec![], vec![]),
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::genesis::Inspect for #name #ty_generics #where_clause {
+            fn field_names() -> &'static [&'static str] {
+                &[#(#field_names),*]
+            }
+
+            #[allow(unused_variables)]
+            fn inspect_fields(&self, visitor: &mut dyn FnMut(&'static str, &dyn ::std::fmt::Debug)) {
+                #(visitor(#field_names, &self.#field_accessors);)*
+            }
+        }
+    })
+}
+
+pub(crate) fn generate_code(input: &Input) -> TokenStream {
+    if !input.options.inspect {
+        return quote! {};
+    }
+
+    let world = &input.world_name;
+    let vis = &input.vis;
+
+    let component_infos = input.components.iter().map(|c| {
+        let ty = &c.component_type;
+        let name = ty.to_token_stream().to_string();
+        let storage_name = c.field_name.to_string();
+        quote! {
+            ::genesis::ComponentInfo {
+                name: #name,
+                storage_name: #storage_name,
+                field_names: <#ty as ::genesis::Inspect>::field_names(),
+            }
+        }
+    });
+
+    let visit_calls = input.components.iter().enumerate().map(|(i, c)| {
+        let name = &c.field_name;
+        quote! {
+            if let Some(component) = self.#name.get_unchecked(entity) {
+                visitor.visit(&infos[#i], component);
+            }
+        }
+    });
+
+    quote! {
+        impl #world {
+            /// Metadata about all component types of this World, in declaration order.
+            #vis fn component_infos() -> ::std::vec::Vec<::genesis::ComponentInfo> {
+                vec![#(#component_infos),*]
+            }
+
+            /// Call `visitor` for every component of the given entity, in declaration order.
+            /// Returns Err(NoSuchEntity) if the given entity doesn't exist.
+            #[allow(unused_variables)]
+            #vis fn inspect(
+                &self,
+                entity: ::genesis::Entity,
+                visitor: &mut dyn ::genesis::ComponentVisitor,
+            ) -> ::std::result::Result<(), ::genesis::NoSuchEntity> {
+                let read = self.entities.read().unwrap();
+                if !read.exists(entity) {
+                    return Err(::genesis::NoSuchEntity::new(entity).with_operation("inspect"));
+                }
+                let infos = Self::component_infos();
+                #(#visit_calls)*
+                Ok(())
+            }
+        }
+    }
+}
@@ -2,8 +2,17 @@
 #![deny(clippy::all)]
 
 mod component;
+mod ffi;
+mod from_query;
+mod include;
 mod input;
+mod inspect;
+mod locked;
+mod query;
+mod shared;
+mod split;
 mod template;
+mod wasm;
 mod world;
 
 use input::*;
@@ -22,6 +31,12 @@ use syn::{parse_macro_input, DeriveInput, Result};
 /// the component enum. The component enum is a generated enum with one variant per component type that
 /// can be used to register any of the component types on the generated World as an alternative to
 /// directly calling `.set()` on the corresponding storage field.
+/// Every component type can be converted into the component enum via `From` and back via `TryFrom`.
+/// A fieldless `<ComponentEnum>Kind` enum is generated as well; `entities_with_kind(kind)` and
+/// `count_kind(kind)` on the World filter entities by component kind at runtime, e.g. in a debug console.
+/// `World::COMPONENT_COUNT` and `World::COMPONENT_NAMES` describe the component types in the order of the
+/// World definition, matching `index()` and `from_index(index)` of the kind enum, e.g. for generic tooling
+/// over all storages.
 ///
 /// The generated ECS has a shared set of `Entities` that is also used by each storage to check if
 /// an entity exists; it is available via the `.entities` field. To avoid concurrency hazards,
@@ -29,16 +44,289 @@ use syn::{parse_macro_input, DeriveInput, Result};
 /// spawning new entities; these are handy shortcuts to accessing the underlying `entities` directly.
 /// When spawning entities in a batch, direct access is recommended to avoid re-acquiring the write
 /// lock over and over.
+/// To change the World while iterating over a component type, use `iter_with()`, which records
+/// changes in a `CommandBuffer` and applies them after the iteration.
+/// The policy for reusing the indices of despawned entities can be set by passing
+/// `reuse_policy = <policy>` to the `#[world]` macro, e.g. `#[world(MyComponent, Template, reuse_policy = ReusePolicy::Lifo)]`.
+/// The generated World implements `WorldIntegrity`, which can be used to verify that all storages
+/// share the World's entities.
 ///
 /// In addition to the component enum, this macro generates a "template" for an entity;
 /// this template has one public field of type `Option<T>` for every component and can be used
 /// to set the corresponding components on an entity. The name of these fields defaults to the name of the
 /// field in the World definition and can be customized via `#[template_name(name)]`.
+/// Every component type can be converted into a template with only the corresponding field set.
+///
+/// Additional templates covering only a subset of the components can be passed to the `#[world]` macro
+/// as `Name(a, b)` with the storage field or template names of the components, e.g.
+/// `#[world(MyComponent, FullTemplate, RenderTemplate(position, sprite))]`. They can be registered
+/// like the full template and converted into it.
+///
+/// The generated World has a `COMPONENT_LAYOUTS` constant and a `layout_report()` function describing
+/// the memory layout of all component types, which helps choosing between `VecStorage` and `MapStorage`.
+/// `fragmentation_report()` lists how densely the slots of every `VecStorage` are occupied at runtime,
+/// pointing out storages of rare components that are better stored in a `MapStorage`.
+/// `memory_snapshot()` records the components, slots and bytes of every storage; `diff()` of two snapshots
+/// lists the storages that grew in between, e.g. to find the component whose count creeps up over hours.
+///
+/// `retain(keep)` despawns every entity for which the given predicate returns false while locking the
+/// entities only once, e.g. for cleaning up after a wave.
+/// `despawn_batch(entities)` despawns many entities at once, e.g. the projectiles that hit something this
+/// frame, locking the entities once and removing their components storage by storage in index order.
+///
+/// `spawn_if_absent(key)` spawns an entity for a `u64` key, e.g. the id of a network spawn message, only if
+/// the entity spawned for that key isn't alive anymore, so replayed or duplicated messages return the existing
+/// entity instead; see `Entities::spawn_if_absent()`.
+///
+/// For frames processed in phases, entities can be spawned in the `Lifecycle::Spawning` state via
+/// `Entities::spawn_staged()`, activated once initialized and marked via `Entities::mark_pending_despawn()`;
+/// `despawn_pending()` then despawns all marked entities in the cleanup phase.
+///
+/// `random_entities(rng, n)` picks `n` alive entities uniformly at random without collecting all entities
+/// first, e.g. for AI target selection; it takes any random number generator as a closure returning `u32`s.
+///
+/// `new_group()` creates an `EntityGroup`, a set of entities of the World for e.g. squads or selections
+/// that never yields despawned entities and supports union and intersection.
+///
+/// Every World implements `Default`, creating an empty World with a small capacity, and `empty()` creates
+/// one without any capacity that only allocates once it is used.
+///
+/// `spawn_from_templates(templates)` spawns one entity per template while locking the entities only once,
+/// e.g. when loading a level from deserialized templates.
+/// Templates implement `genesis::Overlay`, so named templates can inherit from base templates in a
+/// `genesis::TemplateLibrary`; if `Clone` is derived, `spawn_from_library(library, name)` spawns an entity
+/// from a resolved template.
+///
+/// `visit_entity(entity, visitor)` calls a method like `visit_position(entity, &Position)` of the
+/// generated `<World>Visitor` trait for every component of the entity, without cloning the components
+/// or collecting them into the component enum like `iter_all()` does.
+///
+/// `profile_scope()` counts the storage accesses of the current thread by component type until the
+/// returned `genesis::ProfileScope` is finished, e.g. to export a flamegraph of a frame; this requires
+/// the `profile` feature of genesis, which also emits `tracing` spans for storage accesses.
+///
+/// `allocation_report()` returns the allocations performed by the storages of the World since its
+/// previous call, e.g. slot growth or hash map rehashes by component type, to localize frame hitches
+/// caused by incidental growth; this requires the `track_allocations` feature of genesis.
+///
+/// Every World implements `genesis::HasComponent<T>` for each of its component types, giving access
+/// to the storage of `T`, so systems can be written once for all Worlds with the components they need.
+///
+/// Every World implements `genesis::RegisterWithReport<T>` for its templates, which registers a template
+/// like `register()` does and returns a generated `<Template>Report` telling for every component whether it
+/// was skipped, newly added or replaced, including the replaced component.
+///
+/// `clear_component::<T>()` removes the component of type `T` from all alive entities at once while
+/// keeping the entities themselves alive.
+///
+/// Components stored in `DoubleBuffered<T>` are read from the previous frame and written to the next one;
+/// the generated `flip()` makes the written values visible. This requires these component types to implement `Clone`.
+///
+/// Every generated World implements `genesis::Shard`, so it can be partitioned into independently
+/// loaded shards with disjoint entity index ranges using `genesis::Shards`, e.g. one per region of a large map.
+/// `Shards::move_entity()` moves an entity with all its components to another shard.
+///
+/// Components stored in `IndexedStorage<T>` implement `IndexKey`; the storage keeps a reverse index from
+/// their keys to entities up to date on every mutation, so `find(key)` looks up entities by e.g. name.
+///
+/// With the `mmap` feature, components implementing `MmapComponent` can be stored in `MmapStorage<T>`,
+/// which keeps them in memory-mapped pages; `World::new()` maps anonymous memory, so replace the storage
+/// with `MmapStorage::open(entities, path)` to back it by a file that is loaded lazily per page.
+///
+/// Components marked with `#[component(stable)]` on a `VecStorage<T>` field, or declared as `StableStorage<T>`,
+/// never move in memory while they are stored, so C libraries can hold pointers to them between frames;
+/// see `StableStorage::get_ptr()`.
+///
+/// Components declared as `DynamicStorage<T>` start out in a `VecStorage<T>` and can be moved into a
+/// `MapStorage<T>` and back at runtime with `migrate_storage::<T>(StorageKind::Map)` on the World, keeping
+/// their data, e.g. when a server finds a component is used by fewer entities than expected.
+///
+/// `VecStorage<T>` and `DoubleBuffered<T>` record the tick each component was last set or mutably accessed at;
+/// `set_tick(tick)` on the World sets the current tick of all of them, and `last_modified(entity)` on a
+/// storage returns it, e.g. to interpolate network state by freshness.
+/// Independent consumers of these changes each keep their own `genesis::ChangeCursor`, created by
+/// `change_cursor()`; `changes_since_cursor::<T>(&mut cursor)` lists the components of type T changed
+/// since the last check of that cursor.
+///
+/// `preallocate_for(n)` grows the entities and all Vec-backed storages once, so spawning `n` entities and
+/// setting their components doesn't allocate mid-frame; see `Entities::reserve()`.
+///
+/// With the `thread_checks` feature of genesis, spawning, despawning or clearing entities and setting,
+/// removing or mutably accessing components panics unless it happens on the thread that created the World,
+/// while reads are allowed from any thread.
+/// `bind_to_thread(Some(id))` moves the World to another thread, and `bind_to_thread(None)` allows all
+/// threads; the locked and shared Worlds allow all threads.
+///
+/// Passing `max_component_size = <bytes>` fails compilation for every component larger than the given size
+/// stored in any storage other than `MapStorage`, whose memory is allocated for every entity index;
+/// `warn_component_size = <bytes>` emits a deprecation warning instead. The message suggests storing the
+/// component in a `MapStorage` or a `Box`; components marked with `#[component(allow_large)]` are exempt.
+///
+/// `spawn_scene(scene)` spawns a `genesis::SceneTemplate`, a group of templates like a house with its furniture,
+/// as one unit: components marked with `#[component(maps_entities)]` refer to other entities of the scene via
+/// local references returned by `SceneTemplate::add()`, which are resolved to the spawned entities.
+///
+/// Every component type has a stable numeric id, returned by `id()` on its kind and looked up by
+/// `from_id(id)`, e.g. to tag components in a network protocol. It defaults to a hash of the type name,
+/// so it doesn't change when fields are reordered, and can be set via `#[component(id = <n>)]`.
+///
+/// The variants of the component and kind enums are named after the component types. Components of other
+/// types than plain type names, like trait objects in `VecStorage<Box<dyn Behavior>>`, need a variant name
+/// given via `#[component(variant = Behavior)]`, which also names their `visit_behavior()` visitor method.
+///
+/// A component type can be stored in several fields, e.g. items in `equipped: VecStorage<Item>` and
+/// `stored: MapStorage<Item>`, if every field but the first has its own variant name. Impls keyed by the
+/// type, like `HasComponent<Item>`, `Register<Item>` and `From<Item>` of the component enum and template,
+/// refer to the first field; the others are reached via their field and kind. The generated
+/// `move_between(entity, from, to)` moves the component of an entity between two such fields, given by
+/// their kinds, failing with `genesis::MoveError` without changing anything if it can't.
+/// Types are compared by their spelling, so `Item` and `crate::Item` or a type alias count as different
+/// types and fail to compile with conflicting impls instead.
+///
+/// Components marked with `#[component(auto_default)]` are set to their default value for every
+/// spawned entity.
+///
+/// Storage fields are as visible as the World unless marked with `#[component(vis = pub(crate))]` or any
+/// other visibility, e.g. `vis = pub(self)` to only allow changing the components through methods of the
+/// World. This applies to the storage fields of the World, its locked variant and `storages_mut()`;
+/// trait impls like `Register` and `HasComponent` still reach every storage.
+///
+/// Components marked with `#[component(requires(a, b))]` can only be registered on entities that
+/// already have the listed components, given by storage field or template name. Registering them
+/// otherwise fails with `RegisterError::MissingRequirement`; a template may provide the required
+/// components itself.
+///
+/// Components are dropped when their entity is despawned via `despawn()`, `despawn_batch()` or `retain()`,
+/// or when the World is cleared via `clear()` or `clear_budget()`. Components marked with
+/// `#[component(transfer_to = <relation>)]` are moved to the target of the given relation component
+/// instead, which implements `Relation`, if that entity is alive and doesn't have such a component yet;
+/// clearing the World never transfers components, since all targets are cleared as well.
+/// Components marked with `#[component(on_despawn = <fn>)]` are passed to the given `fn(Entity, T)`
+/// instead of being dropped, e.g. to release GPU handles, on every one of these paths.
+/// Components are removed in the order of the World definition by default; components marked with
+/// `#[component(cleanup_order = <n>)]` are removed in ascending order of `n`, which defaults to 0,
+/// e.g. to remove a physics body before the transform it references.
+///
+/// Passing `locked = <name>` to the `#[world]` macro additionally generates a variant of the World
+/// with the given name where every storage is wrapped in its own `RwLock`, so that systems touching
+/// different storages can run in parallel. It can be converted from and into the regular World.
+///
+/// Passing `shared = <name>` generates a cloneable handle with the given name that wraps the World
+/// in an `Arc<RwLock<_>>` and locks it per method call, e.g. for sharing a World between async tasks.
+///
+/// Passing `wasm = <name>` generates a wrapper with the given name that exports the World to JavaScript
+/// via `wasm-bindgen`, with `spawn()`, `spawn_from_template(template)`, `despawn(entity)` and `get`, `set`
+/// and `remove` taking the storage field name of a component. Entities are passed as numbers; see
+/// `genesis::wasm::entity_to_js()`. This requires the `wasm` feature of `genesis`, every component type
+/// to implement `Serialize` and `Deserialize`, and deriving `Deserialize` for the template.
+///
+/// Worlds deriving `Debug` implement `genesis::DebugWorld`, so they can be registered under a name in
+/// the global `genesis::registry()` for debug tooling, e.g. `registry().register("server", &world)`
+/// for a `world: Arc<RwLock<World>>`, and driven by text commands like `get 12:0 position` via
+/// `genesis::console::execute()`.
+///
+/// Passing the `inspect` flag to the `#[world]` macro generates `component_infos()` and
+/// `inspect(entity, visitor)` on the World for generic inspection of components, e.g. in editors.
+/// This requires every component type to implement `Inspect`.
+///
+/// Passing the `track_leaks` flag enables leak tracking on the entities of the World: every spawn records
+/// its call site, and `report_leaks(min_age)` lists long-lived entities grouped by where they were spawned.
+///
+/// Passing the `despawn_events` flag records every despawned entity, including those removed by `clear()`,
+/// until it is taken via `despawn_events()`, so external caches like a render scene graph or physics
+/// bodies can be invalidated without wrapping every call site that despawns entities.
+///
+/// Passing the `spawn_order` flag makes the entities of the World iterate in the order they were spawned
+/// instead of by index, so queries of turn-based games visit entities in spawn order even after indices
+/// are reused; see `Entities::with_spawn_order()`.
+///
+/// Passing the `json` flag generates `apply_json_patch(entity, &value)`, which deserializes the template
+/// from a `serde_json::Value` and registers the components it sets, and `entity_to_json(entity)`, which
+/// exports all components of an entity in the same format, e.g. for live-tuning from a web dashboard.
+/// This requires the `json` feature of `genesis` and the World to derive `Clone`, `Serialize` and
+/// `Deserialize`.
+///
+/// Passing the `ron` flag generates `to_ron_string()` and `from_ron_str(source)`, which write and read all
+/// entities and components of the World as [RON](https://github.com/ron-rs/ron), e.g. for test fixtures or
+/// game data, as well as `apply_ron_patch(entity, source)` and `entity_to_ron(entity)` for single entities
+/// in the format of the template. Unlike JSON, RON keeps the variants of the component enum readable.
+/// This requires the `ron` feature of `genesis` and the World to derive `Clone`, `Serialize` and
+/// `Deserialize`.
+///
+/// Passing the `rkyv` flag derives rkyv's `Archive` and `Serialize` for the World and implements
+/// `Deserialize` for its archived form, so a whole World including its entities can be saved with
+/// `genesis::rkyv::to_bytes()` and read back without copying, e.g. for fast save games. The entities are
+/// archived once and shared again by all storages after deserializing. This requires the `rkyv` feature of
+/// `genesis`, every component type to implement the rkyv traits with `check_bytes`, and all components to
+/// be stored in `VecStorage` or `MapStorage`; it can't be combined with `uuids` or `access`.
+///
+/// Passing the `unknown_data` flag adds an `unknown_data: genesis::UnknownData` field to the templates, which
+/// collects the fields of a deserialized template that don't belong to any component instead of dropping
+/// them, e.g. components added by a newer build or removed since. `unknown_data.report()` lists them, and
+/// they are serialized again with the template. Templates are then deserialized through serde's `flatten`,
+/// which requires a self-describing format like JSON.
+///
+/// Passing the `ffi` flag generates a C interface for the World: `extern "C"` functions prefixed with the
+/// snake case name of the World, e.g. `my_world_new()`, `my_world_spawn()` and `my_world_get()`, that pass
+/// the World as an opaque pointer and entities packed into a `u64` by `Entity::to_bits()`. Components are
+/// identified by their position in the World definition and described by `my_world_components()`.
+/// This requires the `ffi` feature of `genesis` and every component type to be `Copy` and `#[repr(C)]`;
+/// components are copied byte by byte in and out of `my_world_get()` and `my_world_set()`, and the macro
+/// fails with an error on the field of any component type that isn't `Copy`. `my_world_spawn()` returns
+/// `Entity::INVALID`, i.e. `UINT64_MAX`, when passed a null World.
+///
+/// Passing the `uuids` flag adds a `uuids: genesis::Uuids` field mapping entities to externally supplied
+/// `u128` UUIDs, e.g. the ids of a save game or server database, and generates `spawn_with_uuid(uuid)` and
+/// `entity_by_uuid(uuid)`. The UUID of an entity is removed when it is despawned, so it never refers to
+/// another entity reusing its index.
+///
+/// Passing the `access` flag adds an `access: genesis::AccessGrants` field and generates
+/// `grant_access::<A>()`, which hands out typed tokens like `(Read<Velocity>, Write<Position>)` that never
+/// conflict with tokens granted earlier in the same frame, and `next_access_frame()`, which revokes them.
+/// `read(&token)` and `write(&token)` borrow the storage a token is for, so systems can only touch the
+/// storages they were granted. Locked and forked Worlds start without grants.
+///
+/// Passing the `schema` flag generates a `schemars::JsonSchema` impl for the template and a
+/// `template_schema()` function on the World returning its JSON schema, e.g. for validating prefab
+/// files in CI. This requires the `schemars` feature of `genesis` and every component type to
+/// implement `JsonSchema`.
+///
+/// Components that contain entities and implement `MapEntities` can be marked with
+/// `#[component(maps_entities)]`; the generated World and template then rewrite them in `map_entities()`.
 ///
 /// Attribute macros like `#[derive(Debug)]` are applied to both the component enum and the
 /// template struct. This can be very useful for debugging and provides a quick and simple way
 /// to define entities in data files and using e.g. serde to deserialize them into the generated
 /// Template struct.
+/// `PartialEq`, `Eq` and `Debug` derived this way are implemented for the World as well, comparing and
+/// showing the components of its alive entities.
+///
+/// If `Clone` is derived this way, the generated World also gets a `fork()` method that
+/// creates a deep copy of the world with its own, independent set of entities, and a
+/// `register_from()` method that registers a borrowed template, cloning only the components it sets.
+/// The World then implements `genesis::Fork`, so `genesis::History` can record snapshots of it, e.g. once
+/// per frame, and rewind it by a number of snapshots to reproduce intermittent gameplay bugs.
+/// It also gets an `iter_all()` method that iterates over all alive entities together with clones of
+/// all their components converted into the component enum, e.g. for generic debug dumps.
+///
+/// Large components shared by many entities, like meshes, can be stored as `genesis::Shared<T>` behind a
+/// type alias (e.g. `type SharedMesh = Shared<Mesh>;`); clones and forks then share a single value,
+/// which is only copied when an entity's component is mutated.
+///
+/// Components that can't implement `Serialize` and `Deserialize`, e.g. because they hold GPU handles, can
+/// be marked with `#[component(serialize_with = Surrogate)]` for a type implementing
+/// `genesis::Surrogate<T>`; the component enum and the templates then serialize them as their surrogate.
+///
+/// If `Clone`, `Serialize` and `Deserialize` are derived, `changes_since(tick)` collects the entities and
+/// components changed after the given tick into a serializable `genesis::ChangeSet`, and `apply_changes()`
+/// applies it to another World, e.g. for autosaves or resyncing a co-op session without serializing the
+/// whole World every time.
+///
+/// If both `PartialEq` and `Debug` are derived, the World implements `genesis::WorldEq`, which compares
+/// two Worlds entity by entity and component by component; `genesis::assert_world_eq!` uses it to
+/// assert that a World matches an expected one, listing all differences on failure.
+/// `genesis::check_determinism()` runs a system on two identically set up Worlds and reports the first
+/// tick after which they diverged, e.g. to hunt desyncs in lockstep multiplayer.
 ///
 /// # Example
 /// ```ignore
@@ -68,6 +356,11 @@ use syn::{parse_macro_input, DeriveInput, Result};
 #[proc_macro_attribute]
 pub fn world(args: TokenStream, input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
+    if let Some(include) = include::expand_include(&args.clone().into(), &input) {
+        return include
+            .map(TokenStream::from)
+            .unwrap_or_else(|e| e.to_compile_error().into());
+    }
     let args = parse_macro_input!(args as InputArgs);
     generate_code(args, input).unwrap_or_else(|e| e.to_compile_error().into())
 }
@@ -77,12 +370,187 @@ fn generate_code(args: InputArgs, input: DeriveInput) -> Result<TokenStream> {
     let template_code = template::generate_code(&input);
     let component_code = component::generate_code(&input);
     let world_code = world::generate_code(&input);
+    let locked_code = locked::generate_code(&input);
+    let inspect_code = inspect::generate_code(&input);
+    let shared_code = shared::generate_code(&input);
+    let ffi_code = ffi::generate_code(&input);
+    let wasm_code = wasm::generate_code(&input);
 
     let output = quote! {
         #template_code
         #component_code
         #world_code
+        #locked_code
+        #inspect_code
+        #shared_code
+        #ffi_code
+        #wasm_code
     };
 
     Ok(TokenStream::from(output))
 }
+
+/// Iterates over all alive entities of a World generated by `#[world]` that have the queried components.
+///
+/// Takes the world and a closure. The first parameter of the closure is the entity;
+/// every other parameter must be annotated with its type, which is one of `&T`, `&mut T`, `Option<&T>`
+/// or `Option<&mut T>` for a component type `T` of the world.
+/// The closure is called for every alive entity that has all non-optional components.
+/// Each component type can only be queried once.
+///
+/// The entities of the world are read-locked once for the whole iteration, and the storages
+/// are accessed without re-checking that each entity exists.
+///
+/// Entities are visited in ascending index order, or in spawn order for Worlds passed the `spawn_order`
+/// flag, regardless of the storage types of the queried components, so the hash order of a `MapStorage`
+/// never leaks into the results. This keeps rendering and replays stable from frame to frame.
+/// `query_into!` and `query_explain!` visit entities in the same order.
+///
+/// Unless entities are iterated in spawn order, a query with a non-optional component stored in a
+/// `MapStorage` collects the indices of the entities having it first, and only visits those; with several
+/// such components, the one with the fewest entities is used. Other queries visit every alive entity.
+///
+/// # Example
+/// ```ignore
+/// query!(world, |entity, position: &mut Position, name: Option<&NameComponent>| {
+///     position.position.0 += 1;
+///     if let Some(name) = name {
+///         println!("moved {:?} ({})", entity, name.name);
+///     }
+/// });
+/// ```
+#[proc_macro]
+pub fn query(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as query::QueryInput);
+    query::generate_code(&input, query::QueryMode::Run)
+        .map(TokenStream::from)
+        .unwrap_or_else(|e| e.to_compile_error().into())
+}
+
+/// Runs a query like `query!`, collecting the values returned by the closure into a caller-provided
+/// `&mut Vec` after clearing it, and evaluates to the number of collected values.
+///
+/// Reusing the same buffer every frame keeps its capacity, so the query doesn't allocate once the buffer
+/// is large enough; neither does `query!` itself. The closure must return owned values, e.g. copies of
+/// the queried components, since the components are only borrowed while the query runs.
+///
+/// # Example
+/// ```ignore
+/// let mut moving = Vec::new();
+/// loop {
+///     let count = query_into!(world, &mut moving, |entity, position: &Position, velocity: &Velocity| {
+///         (entity, *position, *velocity)
+///     });
+/// }
+/// ```
+#[proc_macro]
+pub fn query_into(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as query::QueryIntoInput);
+    query::generate_code(&input.query, query::QueryMode::Into(&input.buffer))
+        .map(TokenStream::from)
+        .unwrap_or_else(|e| e.to_compile_error().into())
+}
+
+/// Runs a query like `query!` and evaluates to `genesis::QueryStats` describing its execution:
+/// what drove the iteration, how many entities were visited and matched and how many locks were acquired.
+///
+/// # Example
+/// ```ignore
+/// let stats = query_explain!(world, |entity, position: &Position| {});
+/// println!("{}", stats.explain());
+/// ```
+#[proc_macro]
+pub fn query_explain(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as query::QueryInput);
+    query::generate_code(&input, query::QueryMode::Explain)
+        .map(TokenStream::from)
+        .unwrap_or_else(|e| e.to_compile_error().into())
+}
+
+/// Declares a named set of storage fields that Worlds can include with
+/// `#[include_components(Set)]`, e.g. engine-level components shared by the Worlds of several games.
+/// The fields take the same attributes as the fields of a World. Sets declared `pub` are exported
+/// from the root of their crate, so other crates include them as `#[include_components(engine::Set)]`;
+/// other sets can only be included after their declaration in the same crate.
+/// Like for the fields of the World itself, the component types of an included set must be in scope
+/// by name, e.g. via `use engine::{Position, Mesh};`.
+///
+/// # Example
+/// ```ignore
+/// component_set! {
+///     pub RenderComponents {
+///         #[template_name(position)]
+///         positions: VecStorage<Position>,
+///         meshes: MapStorage<Mesh>,
+///     }
+/// }
+///
+/// #[world(GameComponent, GameTemplate)]
+/// #[include_components(engine::RenderComponents)]
+/// pub struct GameWorld {
+///     healths: VecStorage<Health>,
+/// }
+/// ```
+#[proc_macro]
+pub fn component_set(input: TokenStream) -> TokenStream {
+    let set = parse_macro_input!(input as include::ComponentSet);
+    include::generate_component_set(&set).into()
+}
+
+/// Borrows several storages of a World at once, each shared or mutably as marked with `mut`, and
+/// evaluates to a tuple of the borrows in the given order, or to the borrow itself for a single storage.
+/// This is the partial borrow of disjoint fields that the borrow checker accepts, without repeating
+/// the World for every field; each storage can only be borrowed once.
+/// The World must be a place like a variable, e.g. a `&mut World` parameter of a system, since it is
+/// evaluated once per storage.
+///
+/// # Example
+/// ```ignore
+/// let (positions, velocities) = split_storages!(world => positions mut, velocities);
+/// for entity in velocities.keys() {
+///     if let (Some(position), Some(velocity)) = (positions.get_mut(entity), velocities.get(entity)) {
+///         position.0 += velocity.0;
+///     }
+/// }
+/// ```
+#[proc_macro]
+pub fn split_storages(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as split::SplitInput);
+    split::generate_code(&input)
+        .map(TokenStream::from)
+        .unwrap_or_else(|e| e.to_compile_error().into())
+}
+
+/// Derives `FromQuery` for a struct with named fields, building it from the components of an entity:
+/// fields of a component type are cloned from the entity, which is skipped if it lacks them, `Option`
+/// fields hold optional components, `#[query(from = T)]` converts a component of type `T` via `From`, and
+/// `#[query(entity)]` marks the field receiving the entity. `RenderItem::collect(&world)` then extracts
+/// one struct per matching entity in a single pass.
+///
+/// # Example
+/// ```ignore
+/// #[derive(FromQuery)]
+/// struct RenderItem {
+///     #[query(entity)]
+///     entity: Entity,
+///     position: Position,
+///     #[query(from = NameComponent)]
+///     name: String,
+/// }
+/// ```
+#[proc_macro_derive(FromQuery, attributes(query))]
+pub fn derive_from_query(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_query::generate_derive(&input)
+        .map(TokenStream::from)
+        .unwrap_or_else(|e| e.to_compile_error().into())
+}
+
+/// Derives `Inspect` for a struct, exposing its field names and `Debug` representations of its fields.
+#[proc_macro_derive(Inspect)]
+pub fn derive_inspect(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    inspect::generate_derive(&input)
+        .map(TokenStream::from)
+        .unwrap_or_else(|e| e.to_compile_error().into())
+}
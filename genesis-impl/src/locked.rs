@@ -0,0 +1,209 @@
+use proc_macro2::Span;
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use syn::Ident;
+
+use crate::input::*;
+use crate::world;
+
+pub(crate) fn generate_code(input: &Input) -> TokenStream {
+    let locked = match &input.options.locked_name {
+        Some(locked) => locked,
+        None => return quote! {},
+    };
+    let world = &input.world_name;
+
+    let struct_definition = generate_struct_definition(input, locked);
+    let new_fn = generate_new(input);
+    let spawn_fn = generate_spawn_fn(input);
+    let despawn_fn = generate_despawn_fn(input);
+    let clear_fn = generate_clear_fn(input);
+    let into_inner_fn = generate_into_inner_fn(input);
+    let from_impl = generate_from_impl(input, locked);
+
+    quote! {
+        #struct_definition
+
+        impl #locked {
+            #new_fn
+
+            #spawn_fn
+
+            #despawn_fn
+
+            #clear_fn
+
+            #into_inner_fn
+        }
+
+        impl ::std::convert::From<#world> for #locked {
+            #from_impl
+        }
+    }
+}
+
+fn generate_struct_definition(input: &Input, locked: &Ident) -> TokenStream {
+    let vis = &input.vis;
+    let world = &input.world_name;
+
+    let locked_fields = input.components.iter().map(|c| {
+        let vis = c.vis(input);
+        let name = &c.field_name;
+        let ty = &c.component_type;
+        let storage_type = Ident::new(c.storage_type.name(), Span::call_site());
+        quote! {
+            #vis #name: ::std::sync::RwLock<::genesis::#storage_type<#ty>>,
+        }
+    });
+
+    let doc = format!(
+        "A variant of `{}` where every storage is wrapped in its own `RwLock`.",
+        world
+    );
+
+    let uuids_field = if input.options.uuids {
+        quote! { #vis uuids: ::std::sync::RwLock<::genesis::Uuids>, }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        #[doc = #doc]
+        ///
+        /// All methods take `&self`, so this can be shared between threads to run systems that access
+        /// different storages in parallel.
+        /// Methods that touch several storages, like `despawn()`, lock the entities first and then
+        /// each storage in declaration order; avoid holding a storage lock while calling them.
+        #vis struct #locked {
+            #vis entities: ::std::sync::Arc<::std::sync::RwLock<::genesis::Entities>>,
+            #(#locked_fields)*
+            #uuids_field
+        }
+    }
+}
+
+fn generate_new(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let world = &input.world_name;
+
+    quote! {
+        #vis fn new(initial_capacity: u32) -> Self {
+            #world::new(initial_capacity).into()
+        }
+    }
+}
+
+fn generate_spawn_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let auto_default_calls =
+        world::generate_auto_default_calls(input, |name| quote! { self.#name.write().unwrap() });
+    quote! {
+        #[track_caller]
+        #vis fn spawn(&self) -> ::genesis::Entity {
+            let entity = self.entities.write().unwrap().spawn();
+            #(#auto_default_calls)*
+            entity
+        }
+    }
+}
+
+fn generate_despawn_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+
+    let remove_for_despawn_calls =
+        world::generate_despawn_removals(input, quote! { write }, |name| {
+            quote! { self.#name.write().unwrap() }
+        });
+
+    quote! {
+        #vis fn despawn(&self, entity: ::genesis::Entity) -> ::std::result::Result<(), ::genesis::NoSuchEntity> {
+            let mut write = self.entities.write().unwrap();
+            write.despawn(entity)?;
+            #remove_for_despawn_calls
+            Ok(())
+        }
+    }
+}
+
+fn generate_clear_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+
+    let clear_calls = world::generate_clear_calls(input, &quote! { write }, |name| {
+        quote! { self.#name.write().unwrap() }
+    });
+
+    let uuids_clear = if input.options.uuids {
+        quote! { self.uuids.write().unwrap().clear(); }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        #vis fn clear(&self) {
+            let mut write = self.entities.write().unwrap();
+            #clear_calls
+            #uuids_clear
+        }
+    }
+}
+
+fn generate_into_inner_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let world = &input.world_name;
+
+    let storage_fields = input.components.iter().map(|c| {
+        let name = &c.field_name;
+        quote! {
+            #name: self.#name.into_inner().unwrap(),
+        }
+    });
+
+    let uuids_field = if input.options.uuids {
+        quote! { uuids: self.uuids.into_inner().unwrap(), }
+    } else {
+        quote! {}
+    };
+    let access_field = world::generate_access_field(input);
+
+    quote! {
+        /// Unwrap the storages, turning self back into a regular World.
+        #vis fn into_inner(self) -> #world {
+            #world {
+                entities: self.entities,
+                #(#storage_fields)*
+                #uuids_field
+                #access_field
+            }
+        }
+    }
+}
+
+fn generate_from_impl(input: &Input, locked: &Ident) -> TokenStream {
+    let world = &input.world_name;
+
+    let storage_fields = input.components.iter().map(|c| {
+        let name = &c.field_name;
+        quote! {
+            #name: ::std::sync::RwLock::new(world.#name),
+        }
+    });
+
+    let uuids_field = if input.options.uuids {
+        quote! { uuids: ::std::sync::RwLock::new(world.uuids), }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        fn from(mut world: #world) -> Self {
+            // The storages are locked individually and meant to be written from any thread.
+            world.bind_to_thread(None);
+            #locked {
+                entities: world.entities,
+                #(#storage_fields)*
+                #uuids_field
+            }
+        }
+    }
+}
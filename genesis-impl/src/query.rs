@@ -0,0 +1,315 @@
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, ToTokens};
+use syn::parse::{Parse, ParseStream};
+use syn::spanned::Spanned;
+use syn::{
+    AngleBracketedGenericArguments, Expr, ExprClosure, GenericArgument, Ident, Pat, PatType,
+    PathArguments, Result, Token, Type, TypePath, TypeReference,
+};
+
+pub(crate) struct QueryInput {
+    pub world: Expr,
+    pub closure: ExprClosure,
+}
+
+impl Parse for QueryInput {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let world = input.parse::<Expr>()?;
+        let _separator = input.parse::<Token![,]>()?;
+        let closure = input.parse::<ExprClosure>()?;
+        let _trailing_separator = input.parse::<Option<Token![,]>>()?;
+        Ok(Self { world, closure })
+    }
+}
+
+/// The input of `query_into!`: a query with the buffer to collect its results into.
+pub(crate) struct QueryIntoInput {
+    pub buffer: Expr,
+    pub query: QueryInput,
+}
+
+impl Parse for QueryIntoInput {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let world = input.parse::<Expr>()?;
+        let _separator = input.parse::<Token![,]>()?;
+        let buffer = input.parse::<Expr>()?;
+        let _separator = input.parse::<Token![,]>()?;
+        let closure = input.parse::<ExprClosure>()?;
+        let _trailing_separator = input.parse::<Option<Token![,]>>()?;
+        Ok(Self {
+            buffer,
+            query: QueryInput { world, closure },
+        })
+    }
+}
+
+/// What the generated code of a query evaluates to.
+pub(crate) enum QueryMode<'a> {
+    /// Nothing; the closure is only called for its side effects.
+    Run,
+    /// The `genesis::QueryStats` of the query.
+    Explain,
+    /// The number of results of the closure pushed into the given buffer after clearing it.
+    Into(&'a Expr),
+}
+
+struct QueryParameter {
+    component_type: Type,
+    mutable: bool,
+    optional: bool,
+}
+
+const EXPECTED_QUERY_PARAMETER: &str =
+    "Query parameters must be of the form `&T`, `&mut T`, `Option<&T>` or `Option<&mut T>`.";
+const EXPECTED_ENTITY_PARAMETER: &str =
+    "The first parameter of a query closure must be the entity.";
+const DUPLICATE_COMPONENT: &str = "Each component type can only be queried once.";
+
+fn query_parameter(pat: &Pat) -> Result<QueryParameter> {
+    let ty = match pat {
+        Pat::Type(PatType { ty, .. }) => ty.as_ref(),
+        _ => return Err(syn::Error::new(pat.span(), EXPECTED_QUERY_PARAMETER)),
+    };
+
+    let (reference, optional) = match ty {
+        Type::Reference(reference) => (reference, false),
+        Type::Path(TypePath { qself: None, path }) => {
+            let option_argument = path
+                .segments
+                .last()
+                .filter(|segment| segment.ident == "Option")
+                .and_then(|segment| match &segment.arguments {
+                    PathArguments::AngleBracketed(AngleBracketedGenericArguments {
+                        args, ..
+                    }) => args.first(),
+                    _ => None,
+                });
+            match option_argument {
+                Some(GenericArgument::Type(Type::Reference(reference))) => (reference, true),
+                _ => return Err(syn::Error::new(ty.span(), EXPECTED_QUERY_PARAMETER)),
+            }
+        }
+        _ => return Err(syn::Error::new(ty.span(), EXPECTED_QUERY_PARAMETER)),
+    };
+
+    let TypeReference {
+        mutability, elem, ..
+    } = reference;
+    Ok(QueryParameter {
+        component_type: elem.as_ref().clone(),
+        mutable: mutability.is_some(),
+        optional,
+    })
+}
+
+/// Generate the code of a query, evaluating to the result described by `mode`.
+pub(crate) fn generate_code(input: &QueryInput, mode: QueryMode<'_>) -> Result<TokenStream> {
+    let mut inputs = input.closure.inputs.iter();
+    if inputs.next().is_none() {
+        return Err(syn::Error::new(
+            input.closure.span(),
+            EXPECTED_ENTITY_PARAMETER,
+        ));
+    }
+
+    let parameters = inputs.map(query_parameter).collect::<Result<Vec<_>>>()?;
+
+    let mut seen_types = vec![];
+    for parameter in &parameters {
+        let type_name = parameter.component_type.to_token_stream().to_string();
+        if seen_types.contains(&type_name) {
+            return Err(syn::Error::new(
+                parameter.component_type.span(),
+                DUPLICATE_COMPONENT,
+            ));
+        }
+        seen_types.push(type_name);
+    }
+
+    let storages = Ident::new("storages", Span::mixed_site());
+    let entities = Ident::new("entities", Span::mixed_site());
+    let entity = Ident::new("entity", Span::mixed_site());
+    let query = Ident::new("query", Span::mixed_site());
+    let storage_names = (0..parameters.len())
+        .map(|i| Ident::new(&format!("storage_{}", i), Span::mixed_site()))
+        .collect::<Vec<_>>();
+    let argument_names = (0..parameters.len())
+        .map(|i| Ident::new(&format!("argument_{}", i), Span::mixed_site()))
+        .collect::<Vec<_>>();
+
+    let take_storages = parameters.iter().zip(&storage_names).map(|(p, storage)| {
+        let ty = &p.component_type;
+        let message = format!("storage for `{}` was already taken", ty.to_token_stream());
+        quote! {
+            let #storage = <_ as ::genesis::TakeStorage<'_, #ty>>::take_storage(&mut #storages)
+                .expect(#message);
+        }
+    });
+
+    let fetch_arguments = parameters
+        .iter()
+        .zip(&storage_names)
+        .zip(&argument_names)
+        .map(|((p, storage), argument)| {
+            let ty = &p.component_type;
+            let fetch = if p.mutable {
+                quote! { ::genesis::Storage::<#ty>::get_mut_unchecked(&mut *#storage, #entity) }
+            } else {
+                quote! { ::genesis::Storage::<#ty>::get_unchecked(&*#storage, #entity) }
+            };
+            if p.optional {
+                quote! {
+                    let #argument = #fetch;
+                }
+            } else {
+                quote! {
+                    let #argument = match #fetch {
+                        ::std::option::Option::Some(component) => component,
+                        ::std::option::Option::None => continue,
+                    };
+                }
+            }
+        });
+
+    let fetch_arguments = fetch_arguments.collect::<Vec<_>>();
+
+    let world = &input.world;
+    let closure = &input.closure;
+    let stats = Ident::new("stats", Span::mixed_site());
+    let driver = Ident::new("driver", Span::mixed_site());
+    let bits = Ident::new("bits", Span::mixed_site());
+    let index = Ident::new("index", Span::mixed_site());
+    let results = Ident::new("results", Span::mixed_site());
+    let explain = matches!(mode, QueryMode::Explain);
+    let span = Ident::new("_span", Span::mixed_site());
+    let component_names = parameters
+        .iter()
+        .map(|p| p.component_type.to_token_stream().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    // Storages of required components that list their components without visiting every alive entity,
+    // e.g. `MapStorage`s, can drive the iteration instead; the one with the fewest components is used.
+    let count_lock = if explain {
+        quote! { #stats.lock_acquisitions += 1; }
+    } else {
+        quote! {}
+    };
+    let find_driver = parameters
+        .iter()
+        .zip(&storage_names)
+        .filter(|(p, _storage)| !p.optional)
+        .map(|(p, storage)| {
+            let ty = &p.component_type;
+            let name = ty.to_token_stream().to_string();
+            quote! {
+                {
+                    let mut #bits = ::genesis::BitSet::new(0);
+                    if ::genesis::Storage::<#ty>::sparse_presence_into(&*#storage, &mut #bits) {
+                        #count_lock
+                        if #driver
+                            .as_ref()
+                            .map_or(true, |(_name, driver_bits)| #bits.len() < driver_bits.len())
+                        {
+                            #driver = ::std::option::Option::Some((#name, #bits));
+                        }
+                    }
+                }
+            }
+        });
+
+    let (visit, record_driver, run_query) = match mode {
+        QueryMode::Run => (
+            quote! {},
+            quote! {},
+            quote! { #query(#entity, #(#argument_names),*); },
+        ),
+        QueryMode::Into(_) => (
+            quote! {},
+            quote! {},
+            quote! { #results.push(#query(#entity, #(#argument_names),*)); },
+        ),
+        QueryMode::Explain => (
+            quote! { #stats.visited += 1; },
+            quote! { #stats.driver = name; },
+            quote! {
+                #stats.matched += 1;
+                #query(#entity, #(#argument_names),*);
+            },
+        ),
+    };
+
+    // Entities iterated in spawn order keep that order instead of being driven by a storage.
+    let iterate = quote! {
+        #[allow(unused_mut)]
+        let mut #driver: ::std::option::Option<(&'static str, ::genesis::BitSet)> =
+            ::std::option::Option::None;
+        #(#find_driver)*
+        let #entities = #storages.entities.read().unwrap();
+        match #driver.filter(|_| !#entities.is_spawn_ordered()) {
+            #[allow(unused_variables)]
+            ::std::option::Option::Some((name, #bits)) => {
+                #record_driver
+                for #index in #bits.iter() {
+                    let #entity = match #entities.current_entity_at(#index) {
+                        ::std::option::Option::Some(entity) => entity,
+                        ::std::option::Option::None => continue,
+                    };
+                    #visit
+                    #(#fetch_arguments)*
+                    #run_query
+                }
+            }
+            ::std::option::Option::None => {
+                for #entity in #entities.iter() {
+                    #visit
+                    #(#fetch_arguments)*
+                    #run_query
+                }
+            }
+        }
+    };
+
+    Ok(match mode {
+        QueryMode::Run => quote! {
+            {
+                let #span = ::genesis::trace_query(#component_names);
+                let mut #storages = (#world).storages_mut();
+                #(#take_storages)*
+                #[allow(unused_mut)]
+                let mut #query = #closure;
+                #iterate
+            }
+        },
+        QueryMode::Into(buffer) => quote! {
+            {
+                let #span = ::genesis::trace_query(#component_names);
+                let #results: &mut ::std::vec::Vec<_> = #buffer;
+                #results.clear();
+                let mut #storages = (#world).storages_mut();
+                #(#take_storages)*
+                #[allow(unused_mut)]
+                let mut #query = #closure;
+                #iterate
+                #results.len()
+            }
+        },
+        QueryMode::Explain => quote! {
+            {
+                let #span = ::genesis::trace_query(#component_names);
+                let mut #storages = (#world).storages_mut();
+                #(#take_storages)*
+                #[allow(unused_mut)]
+                let mut #query = #closure;
+                let mut #stats = ::genesis::QueryStats {
+                    driver: "entities",
+                    visited: 0,
+                    matched: 0,
+                    lock_acquisitions: 1,
+                };
+                #iterate
+                #stats
+            }
+        },
+    })
+}
@@ -0,0 +1,91 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::input::*;
+
+pub(crate) fn generate_code(input: &Input) -> TokenStream {
+    let shared = match &input.options.shared_name {
+        Some(shared) => shared,
+        None => return quote! {},
+    };
+    let world = &input.world_name;
+    let vis = &input.vis;
+
+    let doc = format!(
+        "A cheaply cloneable, thread-safe handle to a `{}` behind an `RwLock`.",
+        world
+    );
+
+    quote! {
+        #[doc = #doc]
+        ///
+        /// Each method locks the World only for its own duration: reads run concurrently,
+        /// while writes like spawning or registering components are serialized.
+        #[derive(Clone)]
+        #vis struct #shared {
+            inner: ::std::sync::Arc<::std::sync::RwLock<#world>>,
+        }
+
+        impl #shared {
+            #vis fn new(initial_capacity: u32) -> Self {
+                #world::new(initial_capacity).into()
+            }
+
+            /// Lock the World for reading.
+            #vis fn read(&self) -> ::std::sync::RwLockReadGuard<'_, #world> {
+                self.inner.read().unwrap()
+            }
+
+            /// Lock the World for writing.
+            #vis fn write(&self) -> ::std::sync::RwLockWriteGuard<'_, #world> {
+                self.inner.write().unwrap()
+            }
+
+            /// Call `f` with the read-locked World.
+            #vis fn with<R>(&self, f: impl FnOnce(&#world) -> R) -> R {
+                f(&self.read())
+            }
+
+            /// Call `f` with the write-locked World.
+            #vis fn with_mut<R>(&self, f: impl FnOnce(&mut #world) -> R) -> R {
+                f(&mut self.write())
+            }
+
+            #[track_caller]
+            #vis fn spawn(&self) -> ::genesis::Entity {
+                self.write().spawn()
+            }
+
+            #vis fn despawn(&self, entity: ::genesis::Entity) -> ::std::result::Result<(), ::genesis::NoSuchEntity> {
+                self.write().despawn(entity)
+            }
+
+            /// Register the given item for the given entity; see `Register`.
+            #vis fn register<T>(&self, entity: ::genesis::Entity, item: T)
+                -> ::std::result::Result<::std::option::Option<T>, <#world as ::genesis::Register<T>>::Error>
+            where
+                #world: ::genesis::Register<T>,
+            {
+                ::genesis::Register::register(&mut *self.write(), entity, item)
+            }
+
+            /// Unwrap the World if this is the only handle to it; otherwise, returns self.
+            #vis fn try_unwrap(self) -> ::std::result::Result<#world, Self> {
+                match ::std::sync::Arc::try_unwrap(self.inner) {
+                    Ok(lock) => Ok(lock.into_inner().unwrap()),
+                    Err(inner) => Err(Self { inner }),
+                }
+            }
+        }
+
+        impl ::std::convert::From<#world> for #shared {
+            fn from(mut world: #world) -> Self {
+                // The World is locked as a whole and meant to be written from any thread.
+                world.bind_to_thread(None);
+                Self {
+                    inner: ::std::sync::Arc::new(::std::sync::RwLock::new(world)),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::collections::HashSet;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Expr, Ident, Result, Token};
+
+pub(crate) struct SplitInput {
+    pub world: Expr,
+    pub storages: Vec<SplitStorage>,
+}
+
+pub(crate) struct SplitStorage {
+    pub name: Ident,
+    pub mutable: bool,
+}
+
+impl Parse for SplitStorage {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let name = input.parse::<Ident>()?;
+        let mutable = input.parse::<Option<Token![mut]>>()?.is_some();
+        Ok(Self { name, mutable })
+    }
+}
+
+impl Parse for SplitInput {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let world = input.parse::<Expr>()?;
+        let _arrow = input.parse::<Token![=>]>()?;
+        let storages = Punctuated::<SplitStorage, Token![,]>::parse_terminated(input)?;
+        Ok(Self {
+            world,
+            storages: storages.into_iter().collect(),
+        })
+    }
+}
+
+const EXPECTED_STORAGE: &str =
+    "Expected at least one storage field, e.g. `world => positions mut`.";
+const DUPLICATE_STORAGE: &str = "Each storage can only be borrowed once.";
+
+pub(crate) fn generate_code(input: &SplitInput) -> Result<TokenStream> {
+    let world = &input.world;
+    if input.storages.is_empty() {
+        return Err(syn::Error::new_spanned(world, EXPECTED_STORAGE));
+    }
+
+    let mut seen = HashSet::new();
+    for storage in &input.storages {
+        if !seen.insert(storage.name.to_string()) {
+            return Err(syn::Error::new(storage.name.span(), DUPLICATE_STORAGE));
+        }
+    }
+
+    let borrows = input.storages.iter().map(|storage| {
+        let name = &storage.name;
+        if storage.mutable {
+            quote! { &mut (#world).#name }
+        } else {
+            quote! { &(#world).#name }
+        }
+    });
+
+    if input.storages.len() == 1 {
+        Ok(quote! { #(#borrows)* })
+    } else {
+        Ok(quote! { (#(#borrows),*) })
+    }
+}
@@ -1,39 +1,255 @@
 use proc_macro2::TokenStream;
-use quote::{quote, ToTokens};
+use quote::{format_ident, quote, ToTokens};
+use syn::Ident;
 
+use crate::component;
 use crate::input::*;
 
 pub(crate) fn generate_code(input: &Input) -> TokenStream {
-    let template_definition = generate_template_definition(input);
-    let extra_attributes = input.attributes.iter().map(|attr| {
-        let tokens = &attr.to_token_stream();
+    let components = input.components.iter().collect::<Vec<_>>();
+    let template_definition =
+        generate_template_definition(input, &input.template_name, &components);
+    let map_entities_impl = generate_map_entities_impl(&input.template_name, &components);
+    let overlay_impl = generate_overlay_impl(input, &input.template_name, &components);
+    let report_definition = generate_report_definition(input, &input.template_name, &components);
+    let from_impls = generate_from_impls(input);
+    let partial_templates = input
+        .options
+        .partial_templates
+        .iter()
+        .map(|template| generate_partial_template(input, template));
+
+    quote! {
+        #template_definition
+
+        #map_entities_impl
+
+        #overlay_impl
+
+        #report_definition
+
+        #from_impls
+
+        #(#partial_templates)*
+    }
+}
+
+fn generate_partial_template(input: &Input, template: &PartialTemplate) -> TokenStream {
+    let components = input.partial_template_components(template);
+    let name = &template.name;
+    let template_definition = generate_template_definition(input, name, &components);
+    let map_entities_impl = generate_map_entities_impl(name, &components);
+    let overlay_impl = generate_overlay_impl(input, name, &components);
+    let report_definition = generate_report_definition(input, name, &components);
+
+    let full_template = &input.template_name;
+    let fields = components.iter().map(|c| {
+        let field = &c.template_name;
         quote! {
-            #tokens
+            #field: template.#field,
         }
     });
+    let unknown_data = if input.options.unknown_data {
+        quote! { unknown_data: template.unknown_data, }
+    } else {
+        quote! {}
+    };
 
     quote! {
-        #(#extra_attributes)*
         #template_definition
+
+        #map_entities_impl
+
+        #overlay_impl
+
+        #report_definition
+
+        impl ::std::convert::From<#name> for #full_template {
+            #[allow(clippy::needless_update)]
+            fn from(template: #name) -> Self {
+                Self {
+                    #(#fields)*
+                    #unknown_data
+                    ..::std::default::Default::default()
+                }
+            }
+        }
     }
 }
 
-fn generate_template_definition(input: &Input) -> TokenStream {
+fn generate_from_impls(input: &Input) -> TokenStream {
+    let template = &input.template_name;
+    let from_impls = input.primary_components().map(|c| {
+        let ty = &c.component_type;
+        let name = &c.template_name;
+        quote! {
+            impl ::std::convert::From<#ty> for #template {
+                #[allow(clippy::needless_update)]
+                fn from(component: #ty) -> Self {
+                    Self {
+                        #name: ::std::option::Option::Some(component),
+                        ..::std::default::Default::default()
+                    }
+                }
+            }
+        }
+    });
+
+    quote! {
+        #(#from_impls)*
+    }
+}
+
+fn generate_map_entities_impl(name: &Ident, components: &[&WorldComponent]) -> TokenStream {
+    let map_entities_calls = components.iter().filter(|c| c.maps_entities).map(|c| {
+        let name = &c.template_name;
+        quote! {
+            ::genesis::MapEntities::map_entities(&mut self.#name, mapper);
+        }
+    });
+
+    quote! {
+        impl ::genesis::MapEntities for #name {
+            #[allow(unused_variables)]
+            fn map_entities(&mut self, mapper: &mut dyn FnMut(::genesis::Entity) -> ::genesis::Entity) {
+                #(#map_entities_calls)*
+            }
+        }
+    }
+}
+
+fn generate_overlay_impl(
+    input: &Input,
+    name: &Ident,
+    components: &[&WorldComponent],
+) -> TokenStream {
+    let fields = components.iter().map(|c| {
+        let name = &c.template_name;
+        quote! {
+            #name: self.#name.or(base.#name),
+        }
+    });
+    let unknown_data = if input.options.unknown_data {
+        quote! { unknown_data: self.unknown_data.overlay(base.unknown_data), }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        impl ::genesis::Overlay for #name {
+            #[allow(unused_variables)]
+            fn overlay(self, base: Self) -> Self {
+                Self {
+                    #(#fields)*
+                    #unknown_data
+                }
+            }
+        }
+    }
+}
+
+/// The name of the generated report of registering the given template, e.g. `MyTemplateReport`.
+pub(crate) fn report_name(template: &Ident) -> Ident {
+    format_ident!("{}Report", template)
+}
+
+fn generate_report_definition(
+    input: &Input,
+    template: &Ident,
+    components: &[&WorldComponent],
+) -> TokenStream {
+    let vis = &input.vis;
+    let name = report_name(template);
+    let report_fields = components.iter().map(|c| {
+        let ty = &c.component_type;
+        let name = &c.template_name;
+        quote! {
+            #vis #name: ::genesis::Registered<#ty>,
+        }
+    });
+
+    let derives = ["Debug", "Clone", "PartialEq", "Eq"]
+        .iter()
+        .filter(|name| input.derives(name))
+        .map(|name| format_ident!("{}", name));
+    let doc = format!(
+        "What registering a `{}` did with each of its components; see `genesis::RegisterWithReport`.",
+        template
+    );
+
+    quote! {
+        #[doc = #doc]
+        #[derive(#(#derives),*)]
+        #vis struct #name {
+            #(#report_fields)*
+        }
+    }
+}
+
+fn generate_template_definition(
+    input: &Input,
+    name: &Ident,
+    components: &[&WorldComponent],
+) -> TokenStream {
     let vis = &input.vis;
-    let template_fields = input.components.iter().map(|c| {
+    let template_fields = components.iter().map(|c| {
         let ty = &c.component_type;
         let name = &c.template_name;
+        let surrogate_attributes = component::generate_surrogate_attributes(input, c, true);
         quote! {
+            #surrogate_attributes
             #vis #name: ::std::option::Option<#ty>,
         }
     });
 
-    let name = &input.template_name;
+    let unknown_data_field = if input.options.unknown_data {
+        let schema_skip = if input.options.schema {
+            quote! { #[schemars(skip)] }
+        } else {
+            quote! {}
+        };
+        let serde_attribute = if input.derives("Serialize") || input.derives("Deserialize") {
+            quote! { #[serde(flatten)] }
+        } else {
+            quote! {}
+        };
+        quote! {
+            /// The fields of the deserialized template that don't belong to any of its components.
+            #serde_attribute
+            #schema_skip
+            #vis unknown_data: ::genesis::UnknownData,
+        }
+    } else {
+        quote! {}
+    };
+
+    let extra_attributes = input.attributes.iter().map(|attr| attr.to_token_stream());
+    let schema_attributes = if input.options.schema {
+        quote! {
+            #[derive(::genesis::schemars::JsonSchema)]
+            #[schemars(crate = "::genesis::schemars")]
+        }
+    } else {
+        quote! {}
+    };
 
     quote! {
+        #(#extra_attributes)*
         #[derive(Default)]
+        #schema_attributes
         #vis struct #name {
             #(#template_fields)*
+            #unknown_data_field
         }
     }
 }
+
+/// The `unknown_data` field of a template without any skipped fields, for constructing templates in
+/// generated code; empty unless the `unknown_data` flag is passed.
+pub(crate) fn empty_unknown_data_field(input: &Input) -> TokenStream {
+    if input.options.unknown_data {
+        quote! { unknown_data: ::std::default::Default::default(), }
+    } else {
+        quote! {}
+    }
+}
@@ -0,0 +1,166 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::input::*;
+
+pub(crate) fn generate_code(input: &Input) -> TokenStream {
+    let wasm = match &input.options.wasm_name {
+        Some(wasm) => wasm,
+        None => return quote! {},
+    };
+    let world = &input.world_name;
+    let vis = &input.vis;
+    let template_name = &input.template_name;
+    let names = input
+        .components
+        .iter()
+        .map(|c| c.field_name.to_string())
+        .collect::<Vec<_>>();
+    let fields = input
+        .components
+        .iter()
+        .map(|c| &c.field_name)
+        .collect::<Vec<_>>();
+    let types = input
+        .components
+        .iter()
+        .map(|c| &c.component_type)
+        .collect::<Vec<_>>();
+    let variants = input
+        .components
+        .iter()
+        .map(|c| &c.variant)
+        .collect::<Vec<_>>();
+    let component_enum = &input.component_enum_name;
+
+    let doc = format!("A `{}` exported to JavaScript via `wasm-bindgen`.", world);
+    let unknown_component = format!("`{}` has no component named `{{}}`", world);
+
+    quote! {
+        #[doc = #doc]
+        ///
+        /// Entities are passed as numbers created by `genesis::wasm::entity_to_js()` and components
+        /// are identified by the name of their storage field and converted with `serde-wasm-bindgen`.
+        #[::genesis::wasm_bindgen::prelude::wasm_bindgen(wasm_bindgen = ::genesis::wasm_bindgen)]
+        #vis struct #wasm {
+            world: #world,
+        }
+
+        #[::genesis::wasm_bindgen::prelude::wasm_bindgen(wasm_bindgen = ::genesis::wasm_bindgen)]
+        impl #wasm {
+            #[wasm_bindgen(constructor)]
+            pub fn new(initial_capacity: u32) -> Self {
+                #world::new(initial_capacity).into()
+            }
+
+            pub fn spawn(&mut self) -> f64 {
+                ::genesis::wasm::entity_to_js(self.world.spawn())
+            }
+
+            /// Spawn a new entity with the components set in the given template object.
+            pub fn spawn_from_template(
+                &mut self,
+                template: ::genesis::wasm_bindgen::JsValue,
+            ) -> ::std::result::Result<f64, ::genesis::wasm_bindgen::JsError> {
+                let template = ::genesis::serde_wasm_bindgen::from_value::<#template_name>(template)?;
+                let entity = self.world.spawn();
+                if let Err(error) = ::genesis::Register::register(&mut self.world, entity, template) {
+                    let _ = self.world.despawn(entity);
+                    return Err(error.into());
+                }
+                Ok(::genesis::wasm::entity_to_js(entity))
+            }
+
+            pub fn despawn(&mut self, entity: f64) -> ::std::result::Result<(), ::genesis::wasm_bindgen::JsError> {
+                let entity = ::genesis::wasm::entity_from_js(entity)?;
+                Ok(self.world.despawn(entity)?)
+            }
+
+            pub fn exists(&self, entity: f64) -> bool {
+                match ::genesis::wasm::entity_from_js(entity) {
+                    Ok(entity) => self.world.entities.read().unwrap().exists(entity),
+                    Err(_) => false,
+                }
+            }
+
+            /// Get the component with the given name of the given entity, or `undefined` if it has none.
+            pub fn get(
+                &self,
+                entity: f64,
+                component: &str,
+            ) -> ::std::result::Result<::genesis::wasm_bindgen::JsValue, ::genesis::wasm_bindgen::JsError> {
+                let entity = ::genesis::wasm::entity_from_js(entity)?;
+                match component {
+                    #(
+                        #names => match self.world.#fields.get(entity) {
+                            Some(data) => Ok(::genesis::serde_wasm_bindgen::to_value(data)?),
+                            None => Ok(::genesis::wasm_bindgen::JsValue::UNDEFINED),
+                        },
+                    )*
+                    _ => Err(::genesis::wasm_bindgen::JsError::new(&::std::format!(#unknown_component, component))),
+                }
+            }
+
+            /// Set the component with the given name of the given entity, checking its requirements
+            /// like registering it does.
+            pub fn set(
+                &mut self,
+                entity: f64,
+                component: &str,
+                value: ::genesis::wasm_bindgen::JsValue,
+            ) -> ::std::result::Result<(), ::genesis::wasm_bindgen::JsError> {
+                let entity = ::genesis::wasm::entity_from_js(entity)?;
+                match component {
+                    #(
+                        #names => {
+                            let data = ::genesis::serde_wasm_bindgen::from_value::<#types>(value)?;
+                            let data = #component_enum::#variants(data);
+                            ::genesis::Register::register(&mut self.world, entity, data)?;
+                            Ok(())
+                        }
+                    )*
+                    _ => Err(::genesis::wasm_bindgen::JsError::new(&::std::format!(#unknown_component, component))),
+                }
+            }
+
+            /// Remove the component with the given name from the given entity.
+            /// Returns true if the entity had the component.
+            pub fn remove(
+                &mut self,
+                entity: f64,
+                component: &str,
+            ) -> ::std::result::Result<bool, ::genesis::wasm_bindgen::JsError> {
+                let entity = ::genesis::wasm::entity_from_js(entity)?;
+                match component {
+                    #(
+                        #names => Ok(self.world.#fields.remove(entity)?.is_some()),
+                    )*
+                    _ => Err(::genesis::wasm_bindgen::JsError::new(&::std::format!(#unknown_component, component))),
+                }
+            }
+        }
+
+        impl #wasm {
+            /// The wrapped World.
+            #vis fn world(&self) -> &#world {
+                &self.world
+            }
+
+            /// The wrapped World.
+            #vis fn world_mut(&mut self) -> &mut #world {
+                &mut self.world
+            }
+
+            /// Unwrap the World.
+            #vis fn into_inner(self) -> #world {
+                self.world
+            }
+        }
+
+        impl ::std::convert::From<#world> for #wasm {
+            fn from(world: #world) -> Self {
+                Self { world }
+            }
+        }
+    }
+}
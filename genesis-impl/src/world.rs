@@ -1,10 +1,13 @@
 use proc_macro2::Span;
 use proc_macro2::TokenStream;
-use quote::quote;
+use quote::{format_ident, quote, quote_spanned, ToTokens};
 
+use syn::spanned::Spanned;
 use syn::Ident;
 
+use crate::component;
 use crate::input::*;
+use crate::template;
 
 pub(crate) fn generate_code(input: &Input) -> TokenStream {
     let world = &input.world_name;
@@ -12,10 +15,58 @@ pub(crate) fn generate_code(input: &Input) -> TokenStream {
     let struct_definition = generate_struct_definition(input);
     let new_fn = generate_new(input);
     let spawn_fn = generate_spawn_fn(input);
+    let spawn_from_templates_fn = generate_spawn_from_templates_fn(input);
+    let spawn_from_library_fn = generate_spawn_from_library_fn(input);
+    let spawn_scene_fn = generate_spawn_scene_fn(input);
     let despawn_fn = generate_despawn_fn(input);
+    let new_group_fn = generate_new_group_fn(input);
+    let random_entities_fn = generate_random_entities_fn(input);
+    let retain_fn = generate_retain_fn(input);
     let clear_fn = generate_clear_fn(input);
+    let incremental_clear_fns = generate_incremental_clear_fns(input);
+    let clear_component_fn = generate_clear_component_fn(input);
+    let flip_fn = generate_flip_fn(input);
+    let set_tick_fn = generate_set_tick_fn(input);
+    let preallocate_fn = generate_preallocate_fn(input);
+    let migrate_storage_fn = generate_migrate_storage_fn(input);
+    let bind_to_thread_fn = generate_bind_to_thread_fn(input);
+    let move_between_fn = generate_move_between_fn(input);
+    let change_cursor_fns = generate_change_cursor_fns(input);
+    let access_fns = generate_access_fns(input);
+    let report_leaks_fn = generate_report_leaks_fn(input);
+    let despawn_events_fn = generate_despawn_events_fn(input);
+    let profile_scope_fn = generate_profile_scope_fn(input);
+    let allocation_report_fn = generate_allocation_report_fn(input);
+    let kind_fns = generate_kind_fns(input);
+    let iter_all_fn = generate_iter_all_fn(input);
+    let visit_entity_fn = generate_visit_entity_fn(input);
+    let changes_fns = generate_changes_fns(input);
+    let fork_fn = generate_fork_fn(input);
+    let register_from_fn = generate_register_from_fn(input);
+    let storages_mut_fn = generate_storages_mut_fn(input);
+    let map_entities_fn = generate_map_entities_fn(input);
+    let iter_with_fn = generate_iter_with_fn(input);
+    let layout_items = generate_layout_items(input);
+    let uuid_fns = generate_uuid_fns(input);
+    let template_schema_fn = generate_template_schema_fn(input);
+    let json_fns = generate_json_fns(input);
+    let ron_fns = generate_ron_fns(input);
 
     let register_impls = generate_register_impls(input);
+    let integrity_impl = generate_integrity_impl(input);
+    let world_eq_impl = generate_world_eq_impl(input);
+    let debug_world_impl = generate_debug_world_impl(input);
+    let derived_impls = generate_derived_impls(input);
+    let storages_definition = generate_storages_definition(input);
+    let visitor_definition = generate_visitor_definition(input);
+    let despawn_impl = generate_despawn_impl(input);
+    let fork_impl = generate_fork_impl(input);
+    let default_impl = generate_default_impl(input);
+    let shard_impl = generate_shard_impl(input);
+    let clear_component_impls = generate_clear_component_impls(input);
+    let has_component_impls = generate_has_component_impls(input);
+    let size_checks = generate_size_checks(input);
+    let rkyv_impl = generate_rkyv_impl(input);
 
     quote! {
 
@@ -26,32 +77,148 @@ pub(crate) fn generate_code(input: &Input) -> TokenStream {
 
             #spawn_fn
 
+            #spawn_from_templates_fn
+            #spawn_from_library_fn
+            #spawn_scene_fn
+
             #despawn_fn
 
+            #uuid_fns
+
+            #new_group_fn
+            #random_entities_fn
+
+            #retain_fn
+
             #clear_fn
+
+            #incremental_clear_fns
+
+            #clear_component_fn
+
+            #flip_fn
+
+            #set_tick_fn
+
+            #preallocate_fn
+            #migrate_storage_fn
+            #bind_to_thread_fn
+            #move_between_fn
+
+            #change_cursor_fns
+
+            #access_fns
+
+            #report_leaks_fn
+
+            #despawn_events_fn
+
+            #profile_scope_fn
+
+            #allocation_report_fn
+
+            #kind_fns
+
+            #iter_all_fn
+            #visit_entity_fn
+            #changes_fns
+
+            #fork_fn
+
+            #register_from_fn
+
+            #storages_mut_fn
+
+            #map_entities_fn
+
+            #iter_with_fn
+
+            #layout_items
+
+            #template_schema_fn
+
+            #json_fns
+
+            #ron_fns
         }
 
         #register_impls
+
+        #integrity_impl
+
+        #world_eq_impl
+
+        #debug_world_impl
+
+        #derived_impls
+
+        #storages_definition
+
+        #visitor_definition
+
+        #despawn_impl
+
+        #fork_impl
+
+        #default_impl
+
+        #shard_impl
+
+        #clear_component_impls
+
+        #has_component_impls
+
+        #size_checks
+
+        #rkyv_impl
     }
 }
 
 fn generate_struct_definition(input: &Input) -> TokenStream {
     let world_fields = input.components.iter().map(|c| {
+        let vis = c.vis(input);
         let name = &c.field_name;
         let ty = &c.component_type;
         let storage_type = Ident::new(c.storage_type.name(), Span::call_site());
         quote! {
-            #name: ::genesis::#storage_type<#ty>,
+            #vis #name: ::genesis::#storage_type<#ty>,
         }
     });
 
     let world = &input.world_name;
     let vis = &input.vis;
+    let uuids_field = if input.options.uuids {
+        quote! { #vis uuids: ::genesis::Uuids, }
+    } else {
+        quote! {}
+    };
+    let access_field = if input.options.access {
+        quote! { #vis access: ::genesis::AccessGrants, }
+    } else {
+        quote! {}
+    };
+
+    // The derived `Deserialize` would leave every storage with entities of its own; see `generate_rkyv_impl`.
+    let (rkyv_attributes, entities_attribute) = if input.options.rkyv {
+        (
+            quote! {
+                #[derive(::genesis::rkyv::Archive, ::genesis::rkyv::Serialize)]
+                #[archive(crate = "::genesis::rkyv", check_bytes)]
+            },
+            quote! { #[with(::genesis::archive::SharedEntities)] },
+        )
+    } else {
+        (quote! {}, quote! {})
+    };
 
     quote! {
+        #rkyv_attributes
         #vis struct #world {
+            #entities_attribute
             #vis entities: ::std::sync::Arc<::std::sync::RwLock<::genesis::Entities>>,
-            #(#vis #world_fields)*
+            #(#world_fields)*
+            #uuids_field
+            #access_field
         }
     }
 }
@@ -64,7 +231,12 @@ fn generate_new(input: &Input) -> TokenStream {
         let name = &c.field_name;
         let storage_type_name = Ident::new(c.storage_type.name(), Span::call_site());
         match c.storage_type {
-            ComponentStorageType::Vec => quote! {
+            ComponentStorageType::Vec
+            | ComponentStorageType::DoubleBuffered
+            | ComponentStorageType::Indexed
+            | ComponentStorageType::Mmap
+            | ComponentStorageType::Stable
+            | ComponentStorageType::Dynamic => quote! {
                 let #name = ::genesis::#storage_type_name::new(::std::sync::Arc::clone(&#entities_arg), #capacity_arg);
             },
             ComponentStorageType::Map => quote! {
@@ -77,15 +249,39 @@ fn generate_new(input: &Input) -> TokenStream {
         let name = &c.field_name;
         quote! { #name, }
     });
+    let uuids_field = if input.options.uuids {
+        quote! { uuids: ::genesis::Uuids::new(::std::sync::Arc::clone(&entities)), }
+    } else {
+        quote! {}
+    };
+    let access_field = generate_access_field(input);
+
+    let new_entities = generate_new_entities(input, &capacity_arg);
 
     let vis = &input.vis;
     quote! {
         #vis fn new(#capacity_arg: u32) -> Self {
-            let entities = ::std::sync::Arc::new(::std::sync::RwLock::new(::genesis::Entities::new(#capacity_arg)));
+            Self::with_entities(#new_entities, #capacity_arg)
+        }
+
+        /// Create a new World without capacity, e.g. as a placeholder in test fixtures or for
+        /// optional subsystems. Only the shared entities are allocated; storages and entity slots
+        /// are allocated on first use.
+        #vis fn empty() -> Self {
+            Self::new(0)
+        }
+
+        /// Create a new World that spawns its entities from the given `entities`, e.g. to start
+        /// its indices at an index base; see `Entities::with_index_base()`.
+        /// The options of the World, like its reuse policy, are not applied to `entities`.
+        #vis fn with_entities(#entities_arg: ::genesis::Entities, #capacity_arg: u32) -> Self {
+            let entities = ::std::sync::Arc::new(::std::sync::RwLock::new(#entities_arg));
 
             #(#storage_locals)*
 
             Self {
+                #uuids_field
+                #access_field
                 entities,
                 #(#storage_names)*
             }
@@ -93,124 +289,2397 @@ fn generate_new(input: &Input) -> TokenStream {
     }
 }
 
+fn generate_default_impl(input: &Input) -> TokenStream {
+    let world = &input.world_name;
+
+    quote! {
+        /// An empty World with a capacity of 16 entities.
+        impl ::std::default::Default for #world {
+            fn default() -> Self {
+                Self::new(16)
+            }
+        }
+    }
+}
+
+/// Generate the expression creating the `Entities` of a new World with the options of the World applied.
+fn generate_new_entities(input: &Input, capacity_arg: &Ident) -> TokenStream {
+    let new_entities = match &input.options.reuse_policy {
+        Some(policy) => quote! {
+            ::genesis::Entities::new(#capacity_arg).with_policy(#policy)
+        },
+        None => quote! {
+            ::genesis::Entities::new(#capacity_arg)
+        },
+    };
+    let new_entities = if input.options.track_leaks {
+        quote! { #new_entities.with_leak_tracking() }
+    } else {
+        new_entities
+    };
+    let new_entities = if input.options.spawn_order {
+        quote! { #new_entities.with_spawn_order() }
+    } else {
+        new_entities
+    };
+    if input.options.despawn_events {
+        quote! { #new_entities.with_despawn_events() }
+    } else {
+        new_entities
+    }
+}
+
 fn generate_spawn_fn(input: &Input) -> TokenStream {
     let vis = &input.vis;
+    let auto_default_calls =
+        generate_auto_default_calls(input, |name| quote! { self.#name }).collect::<TokenStream>();
     quote! {
+        #[track_caller]
         #vis fn spawn(&mut self) -> ::genesis::Entity {
-            self.entities.write().unwrap().spawn()
+            let entity = self.entities.write().unwrap().spawn();
+            #auto_default_calls
+            entity
+        }
+
+        /// Spawn a new entity unless the limit configured on the entities is reached; see `Entities::with_limit()`.
+        #[track_caller]
+        #vis fn try_spawn(&mut self) -> ::std::result::Result<::genesis::Entity, ::genesis::EntityLimitReached> {
+            let entity = self.entities.write().unwrap().try_spawn()?;
+            #auto_default_calls
+            Ok(entity)
+        }
+
+        /// Spawn a new entity for the given key unless the entity spawned for it is still alive, e.g. for
+        /// network spawn messages that may be replayed or duplicated; see `Entities::spawn_if_absent()`.
+        /// Returns the entity of the key and whether it was spawned by this call.
+        #[track_caller]
+        #vis fn spawn_if_absent(&mut self, key: u64) -> (::genesis::Entity, bool) {
+            let (entity, spawned) = self.entities.write().unwrap().spawn_if_absent(key);
+            if spawned {
+                #auto_default_calls
+            }
+            (entity, spawned)
         }
     }
 }
 
-fn generate_despawn_fn(input: &Input) -> TokenStream {
+fn generate_spawn_from_templates_fn(input: &Input) -> TokenStream {
     let vis = &input.vis;
+    let template_name = &input.template_name;
 
-    let remove_unchecked_calls = input.components.iter().map(|c| {
-        let name = &c.field_name;
+    // A freshly spawned entity only has the components of its template and the auto-default ones.
+    let requirement_checks = input
+        .components
+        .iter()
+        .filter(|c| !c.requires.is_empty())
+        .map(|c| {
+            let name = &c.template_name;
+            let component = c.component_type.to_token_stream().to_string();
+            let checks = c.requires.iter().map(|required| {
+                let required = input.component(required).unwrap();
+                let required_name = required.component_type.to_token_stream().to_string();
+                let provided = if required.auto_default {
+                    quote! { true }
+                } else {
+                    let required_field = &required.template_name;
+                    quote! { template.#required_field.is_some() }
+                };
+                quote! {
+                    if !(#provided) {
+                        entities.despawn(entity).ok();
+                        return Err(::genesis::RegisterError::MissingRequirement {
+                            entity,
+                            component: #component,
+                            required: #required_name,
+                        });
+                    }
+                }
+            });
+            quote! {
+                if template.#name.is_some() {
+                    #(#checks)*
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let set_calls = input.components.iter().map(|c| {
+        let name = &c.template_name;
+        let storage = &c.field_name;
+        if c.auto_default {
+            quote! {
+                self.#storage.set_unchecked(
+                    entity,
+                    template.#name.unwrap_or_default(),
+                );
+            }
+        } else {
+            quote! {
+                if let Some(component) = template.#name {
+                    self.#storage.set_unchecked(entity, component);
+                }
+            }
+        }
+    });
+
+    let spawn_all = quote! {
+        let mut entities = self.entities.write().unwrap();
+        let templates = templates.into_iter();
+        let mut spawned = ::std::vec::Vec::with_capacity(templates.size_hint().0);
+        for template in templates {
+            let entity = entities.spawn();
+            #(#requirement_checks)*
+            #(#set_calls)*
+            spawned.push(entity);
+        }
+    };
+
+    if requirement_checks.is_empty() {
+        quote! {
+            /// Spawn one entity per template and set its components, locking the entities only once.
+            /// Returns the spawned entities in the order of the templates.
+            #[track_caller]
+            #vis fn spawn_from_templates<I>(&mut self, templates: I) -> ::std::vec::Vec<::genesis::Entity>
+            where
+                I: ::std::iter::IntoIterator<Item = #template_name>,
+            {
+                #spawn_all
+                spawned
+            }
+        }
+    } else {
         quote! {
-            self.#name.remove_unchecked(entity);
+            /// Spawn one entity per template and set its components, locking the entities only once.
+            /// Returns the spawned entities in the order of the templates, or
+            /// `RegisterError::MissingRequirement` for the first template that doesn't provide a
+            /// required component; its entity is despawned again, while the entities of the templates
+            /// before it stay spawned.
+            #[track_caller]
+            #vis fn spawn_from_templates<I>(&mut self, templates: I)
+                -> ::std::result::Result<::std::vec::Vec<::genesis::Entity>, ::genesis::RegisterError>
+            where
+                I: ::std::iter::IntoIterator<Item = #template_name>,
+            {
+                #spawn_all
+                Ok(spawned)
+            }
+        }
+    }
+}
+
+fn generate_spawn_from_library_fn(input: &Input) -> TokenStream {
+    if !input.derives("Clone") {
+        return quote! {};
+    }
+
+    let vis = &input.vis;
+    let template_name = &input.template_name;
+    let has_requirements = input.components.iter().any(|c| !c.requires.is_empty());
+    let spawn = if has_requirements {
+        quote! { self.spawn_from_templates(::std::iter::once(template))? }
+    } else {
+        quote! { self.spawn_from_templates(::std::iter::once(template)) }
+    };
+
+    quote! {
+        /// Resolve the template with the given name from the library, layering it over its base templates,
+        /// and spawn an entity with its components; see `genesis::TemplateLibrary`.
+        #[track_caller]
+        #vis fn spawn_from_library(
+            &mut self,
+            library: &::genesis::TemplateLibrary<#template_name>,
+            name: &str,
+        ) -> ::std::result::Result<::genesis::Entity, ::genesis::TemplateError> {
+            let template = library.resolve(name)?;
+            Ok(#spawn[0])
+        }
+    }
+}
+
+fn generate_spawn_scene_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    quote! {
+        /// Spawn one entity per template of the scene and register the templates, e.g. a prefab whose
+        /// templates may be partial ones. Local references in components marked with
+        /// `#[component(maps_entities)]` are resolved to the spawned entities first; see
+        /// `genesis::SceneTemplate`. Returns the spawned entities in the order of the templates;
+        /// if registering any template fails, all entities of the scene are despawned again.
+        #[track_caller]
+        #vis fn spawn_scene<T>(
+            &mut self,
+            scene: ::genesis::SceneTemplate<T>,
+        ) -> ::std::result::Result<::std::vec::Vec<::genesis::Entity>, <Self as ::genesis::Register<T>>::Error>
+        where
+            Self: ::genesis::Register<T>,
+            T: ::genesis::MapEntities,
+        {
+            let mut spawned = ::std::vec::Vec::with_capacity(scene.len());
+            for _ in 0..scene.len() {
+                spawned.push(self.spawn());
+            }
+            for (entity, template) in spawned.iter().zip(scene.instantiate(&spawned)) {
+                if let Err(error) = ::genesis::Register::register(self, *entity, template) {
+                    for entity in &spawned {
+                        self.despawn(*entity).ok();
+                    }
+                    return Err(error);
+                }
+            }
+            Ok(spawned)
         }
+    }
+}
+
+/// Generate the code setting the default value of all components marked with
+/// `#[component(auto_default)]` for a newly spawned `entity`.
+/// `storage` generates the expression accessing a storage given its field name.
+pub(crate) fn generate_auto_default_calls<'a>(
+    input: &'a Input,
+    storage: impl Fn(&Ident) -> TokenStream + 'a,
+) -> impl Iterator<Item = TokenStream> + 'a {
+    input
+        .components
+        .iter()
+        .filter(|c| c.auto_default)
+        .map(move |c| {
+            let storage = storage(&c.field_name);
+            quote! {
+                #storage.set(entity, ::std::default::Default::default()).ok();
+            }
+        })
+}
+
+/// Generate the code removing all components of the just despawned `entity`, applying the despawn
+/// policies given by `#[component(transfer_to = ...)]` and `#[component(on_despawn = ...)]`.
+/// `entities` is the expression accessing the locked entities, and `storage` generates the expression
+/// accessing a storage given its field name.
+pub(crate) fn generate_despawn_removals(
+    input: &Input,
+    entities: TokenStream,
+    storage: impl Fn(&Ident) -> TokenStream,
+) -> TokenStream {
+    let transfer_targets = input.components.iter().filter_map(|c| {
+        let relation = input.component(c.transfer_to.as_ref()?)?;
+        let target = format_ident!("{}_target", c.field_name);
+        let relation_storage = storage(&relation.field_name);
+        Some(quote! {
+            let #target = #relation_storage.get_unchecked(entity).map(::genesis::Relation::target);
+        })
     });
 
+    let removals = input
+        .components_in_cleanup_order()
+        .into_iter()
+        .map(|c| generate_component_removal(c, &entities, storage(&c.field_name)));
+
+    let uuids_removal = if input.options.uuids {
+        let uuids = storage(&format_ident!("uuids"));
+        quote! { #uuids.remove_for_despawn(entity); }
+    } else {
+        quote! {}
+    };
+
     quote! {
-        #vis fn despawn(&mut self, entity: ::genesis::Entity) -> ::std::result::Result<(), ::genesis::NoSuchEntity> {
-            let mut write = self.entities.write().unwrap();
-            write.despawn(entity)?;
-            #(#remove_unchecked_calls)*
-            Ok(())
+        #(#transfer_targets)*
+        #(#removals)*
+        #uuids_removal
+    }
+}
+
+/// Generate the code removing the given component of the despawned `entity`, transferring it to the
+/// entity in `<field>_target` or passing it to its `on_despawn` function if the component asks for it.
+fn generate_component_removal(
+    c: &WorldComponent,
+    entities: &TokenStream,
+    storage: TokenStream,
+) -> TokenStream {
+    let name = &c.field_name;
+    if c.transfer_to.is_none() && c.on_despawn.is_none() {
+        return quote! {
+            #storage.remove_for_despawn(entity);
+        };
+    }
+
+    let transfer = c.transfer_to.as_ref().map(|_| {
+        let target = format_ident!("{}_target", name);
+        quote! {
+            let component = match #target {
+                Some(target) if #entities.exists(target) && #storage.get_unchecked(target).is_none() => {
+                    #storage.set_unchecked(target, component);
+                    None
+                }
+                _ => Some(component),
+            };
+        }
+    });
+    let callback = match (&c.on_despawn, &transfer) {
+        (Some(on_despawn), Some(_)) => quote! {
+            if let Some(component) = component {
+                #on_despawn(entity, component);
+            }
+        },
+        (Some(on_despawn), None) => quote! {
+            #on_despawn(entity, component);
+        },
+        (None, _) => quote! {},
+    };
+    // Bind the removed component first so that a lock guard in `storage` is released before it is used again.
+    quote! {
+        let removed = #storage.remove_for_despawn(entity);
+        if let Some(component) = removed {
+            #transfer
+            #callback
         }
     }
 }
 
-fn generate_clear_fn(input: &Input) -> TokenStream {
+/// Generate the code passing the component of `entity` to its `on_despawn` function while the World is
+/// cleared, or None if the component has no such function. Transfers are skipped since every target entity
+/// is cleared as well.
+fn generate_cleared_component_removal(
+    c: &WorldComponent,
+    storage: &TokenStream,
+) -> Option<TokenStream> {
+    let on_despawn = c.on_despawn.as_ref()?;
+    Some(quote! {
+        let removed = #storage.remove_for_despawn(entity);
+        if let Some(component) = removed {
+            #on_despawn(entity, component);
+        }
+    })
+}
+
+/// Generate the code clearing all storages in cleanup order after the entities were cleared, passing the
+/// components of the previously alive entities to their `on_despawn` functions.
+/// `storage` maps a storage field name to an expression of the storage, like in `generate_despawn_removals`.
+pub(crate) fn generate_clear_calls(
+    input: &Input,
+    entities: &TokenStream,
+    storage: impl Fn(&Ident) -> TokenStream,
+) -> TokenStream {
+    let components = input.components_in_cleanup_order();
+    let has_hooks = components.iter().any(|c| c.on_despawn.is_some());
+    let cleared = if has_hooks {
+        quote! { let cleared = #entities.iter().collect::<::std::vec::Vec<_>>(); }
+    } else {
+        quote! {}
+    };
+    let clear_calls = components.into_iter().map(|c| {
+        let storage = storage(&c.field_name);
+        match generate_cleared_component_removal(c, &storage) {
+            Some(removal) => quote! {
+                for entity in cleared.iter().copied() {
+                    #removal
+                }
+                #storage.clear();
+            },
+            None => quote! {
+                #storage.clear();
+            },
+        }
+    });
+
+    quote! {
+        #cleared
+        #entities.clear();
+        #(#clear_calls)*
+    }
+}
+
+fn generate_despawn_fn(input: &Input) -> TokenStream {
     let vis = &input.vis;
+    let remove_for_despawn_calls =
+        generate_despawn_removals(input, quote! { write }, |name| quote! { self.#name });
 
-    let clear_calls = input.components.iter().map(|c| {
+    let transfer_targets = input.components.iter().filter_map(|c| {
+        let relation = input.component(c.transfer_to.as_ref()?)?;
+        let targets = format_ident!("{}_targets", c.field_name);
+        let relation_storage = &relation.field_name;
+        Some(quote! {
+            let #targets = despawned
+                .iter()
+                .map(|entity| self.#relation_storage.get_unchecked(*entity).map(::genesis::Relation::target))
+                .collect::<::std::vec::Vec<_>>();
+        })
+    });
+    let batch_removals = input.components_in_cleanup_order().into_iter().map(|c| {
         let name = &c.field_name;
-        quote! {
-            self.#name.clear();
+        let removal = generate_component_removal(c, &quote! { write }, quote! { self.#name });
+        if c.transfer_to.is_some() {
+            let target = format_ident!("{}_target", name);
+            let targets = format_ident!("{}_targets", name);
+            quote! {
+                for (entity, #target) in despawned.iter().copied().zip(#targets) {
+                    #removal
+                }
+            }
+        } else {
+            quote! {
+                for entity in despawned.iter().copied() {
+                    #removal
+                }
+            }
         }
     });
+    let uuids_removal = if input.options.uuids {
+        quote! {
+            for entity in despawned.iter().copied() {
+                self.uuids.remove_for_despawn(entity);
+            }
+        }
+    } else {
+        quote! {}
+    };
 
     quote! {
-        #vis fn clear(&mut self) {
+        #vis fn despawn(&mut self, entity: ::genesis::Entity) -> ::std::result::Result<(), ::genesis::NoSuchEntity> {
+            let mut write = self.entities.write().unwrap();
+            write.despawn(entity)?;
+            #remove_for_despawn_calls
+            Ok(())
+        }
+
+        /// Despawn all given entities at once, e.g. the projectiles that hit something this frame.
+        /// The entities are sorted by index and deduplicated, despawned while locking the entities once,
+        /// and their components are then removed storage by storage in index order. Entities that don't
+        /// exist are skipped. Components transferred via `#[component(transfer_to = ...)]` are only
+        /// transferred to entities that survive the batch.
+        /// Returns the number of despawned entities.
+        #vis fn despawn_batch(&mut self, entities: impl ::std::iter::IntoIterator<Item = ::genesis::Entity>) -> usize {
+            let mut despawned = entities.into_iter().collect::<::std::vec::Vec<_>>();
+            despawned.sort_unstable_by_key(|entity| (entity.index, entity.generation));
+            despawned.dedup();
+
             let mut write = self.entities.write().unwrap();
-            write.clear();
-            #(#clear_calls)*
+            despawned.retain(|entity| write.despawn(*entity).is_ok());
+            #(#transfer_targets)*
+            #(#batch_removals)*
+            #uuids_removal
+            despawned.len()
         }
     }
 }
 
-fn generate_register_impls(input: &Input) -> TokenStream {
-    let world = &input.world_name;
-    let register_impls = input.components.iter().map(|c| {
-        let ty = &c.component_type;
-        let component_storage_name = &c.field_name;
-        quote! {
-            impl ::genesis::Register<#ty> for #world {
-                fn register(&mut self, entity: ::genesis::Entity, component: #ty)
-                    -> ::std::result::Result<std::option::Option<#ty>, ::genesis::NoSuchEntity> {
-                    self.#component_storage_name.set(entity, component)
-                }
+fn generate_uuid_fns(input: &Input) -> TokenStream {
+    if !input.options.uuids {
+        return quote! {};
+    }
+
+    let vis = &input.vis;
+    quote! {
+        /// Spawn a new entity with the given UUID; see `genesis::Uuids`.
+        /// Returns Err(UuidError::Taken) without spawning if the UUID already belongs to an alive entity.
+        #[track_caller]
+        #vis fn spawn_with_uuid(&mut self, uuid: u128) -> ::std::result::Result<::genesis::Entity, ::genesis::UuidError> {
+            if let Some(entity) = self.uuids.entity(uuid) {
+                return Err(::genesis::UuidError::Taken { uuid, entity });
             }
+            let entity = self.spawn();
+            self.uuids.insert(entity, uuid)?;
+            Ok(entity)
         }
-    });
-    let component_enum_register_impl = {
-        let component_enum = &input.component_enum_name;
-        let component_enum_match_impl_register = input.components.iter().map(|c| {
-            let ty = &c.component_type;
 
-            quote! {
-                #component_enum::#ty(c) => self.register(entity, c)?.map(|c| c.into()),
+        /// The alive entity with the given UUID, if any.
+        #vis fn entity_by_uuid(&self, uuid: u128) -> ::std::option::Option<::genesis::Entity> {
+            self.uuids.entity(uuid)
+        }
+    }
+}
+
+fn generate_shard_impl(input: &Input) -> TokenStream {
+    let world = &input.world_name;
+    let capacity_arg = Ident::new("initial_capacity", Span::call_site());
+    let new_entities = generate_new_entities(input, &capacity_arg);
+
+    // Hygienic names, so fields named e.g. `entity` or `target` don't shadow the arguments.
+    let moved_locals = input
+        .components
+        .iter()
+        .map(|c| Ident::new(&format!("moved_{}", c.field_name), Span::mixed_site()))
+        .collect::<Vec<_>>();
+    // Both frames of `DoubleBuffered` components are moved, so the entity keeps its previous-frame value.
+    let component_locals = input
+        .components
+        .iter()
+        .zip(&moved_locals)
+        .map(|(c, local)| {
+            let name = &c.field_name;
+            if matches!(c.storage_type, ComponentStorageType::DoubleBuffered) {
+                quote! {
+                    let #local = self.#name.remove_frames_for_despawn(entity);
+                }
+            } else {
+                quote! {
+                    let #local = self.#name.remove_for_despawn(entity);
+                }
+            }
+        });
+    let set_calls = input
+        .components
+        .iter()
+        .zip(&moved_locals)
+        .map(|(c, local)| {
+            let name = &c.field_name;
+            if matches!(c.storage_type, ComponentStorageType::DoubleBuffered) {
+                quote! {
+                    let (previous, next) = #local;
+                    target.#name.set_frames_unchecked(moved, previous, next);
+                }
+            } else {
+                quote! {
+                    if let Some(component) = #local {
+                        target.#name.set_unchecked(moved, component);
+                    }
+                }
             }
         });
 
+    let moved_uuid = if input.options.uuids {
         quote! {
-            impl ::genesis::Register<#component_enum> for #world {
-                fn register(&mut self, entity: ::genesis::Entity, component: #component_enum)
-                -> ::std::result::Result<::std::option::Option::<#component_enum>, ::genesis::NoSuchEntity> {
-                Ok(match component {
-                #(#component_enum_match_impl_register)*
-                })
-                }
+            if let Some(uuid) = self.uuids.remove_for_despawn(entity) {
+                target.uuids.insert(moved, uuid).ok();
             }
         }
+    } else {
+        quote! {}
     };
 
-    let template_register_impl = {
-        let template_fields_register = input.components.iter().map(|c| {
-            let name = &c.template_name;
+    quote! {
+        impl ::genesis::Shard for #world {
+            fn new_shard(#capacity_arg: u32, base: u32, size: u32) -> Self {
+                let entities = #new_entities
+                    .with_index_base(base)
+                    .with_max_index(base + (size - 1))
+                    .with_limit(size, size);
+                #world::with_entities(entities, #capacity_arg)
+            }
 
-            quote! {
-                #name: if let Some(#name) = template.#name {
-                    self.register(id, #name)?
-                } else {
-                    None
-                },
+            fn try_spawn(&mut self) -> ::std::result::Result<::genesis::Entity, ::genesis::EntityLimitReached> {
+                #world::try_spawn(self)
             }
-        });
 
-        let template_name = &input.template_name;
+            fn move_entity_to(
+                &mut self,
+                entity: ::genesis::Entity,
+                target: &mut Self,
+            ) -> ::std::result::Result<::genesis::Entity, ::genesis::ShardError> {
+                let mut entities = self.entities.write().unwrap();
+                if !entities.exists(entity) {
+                    return Err(::genesis::NoSuchEntity::new(entity).with_operation("move").into());
+                }
+                let moved = target.entities.write().unwrap().try_spawn()?;
+                entities.despawn(entity)?;
+                #(#component_locals)*
+                #(#set_calls)*
+                drop(entities);
+                #moved_uuid
+                Ok(moved)
+            }
 
-        quote! {
-            impl ::genesis::Register<#template_name> for #world {
-                fn register(&mut self, id: ::genesis::Entity, template: #template_name)
-                    -> ::std::result::Result<::std::option::Option::<#template_name>, ::genesis::NoSuchEntity> {
-                    Ok(Some(
-                        #template_name {
-                            #(#template_fields_register)*
-                        }
-                    ))
+            fn map_entities(&mut self, mapper: &mut dyn FnMut(::genesis::Entity) -> ::genesis::Entity) {
+                #world::map_entities(self, mapper)
+            }
+        }
+    }
+}
+
+fn generate_retain_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let remove_for_despawn_calls =
+        generate_despawn_removals(input, quote! { entities }, |name| quote! { self.#name });
+
+    quote! {
+        /// Despawn every entity for which `keep` returns false, e.g. to stream out far away entities.
+        /// `keep` is called for every alive entity with a view of this World while the entities are not
+        /// locked; afterwards, the entities are locked once to despawn the rejected ones.
+        /// Returns the number of despawned entities.
+        #vis fn retain(&mut self, mut keep: impl FnMut(::genesis::Entity, &Self) -> bool) -> usize {
+            let snapshot = self.entities.read().unwrap().iter_snapshot();
+            let rejected = snapshot
+                .filter(|entity| !keep(*entity, self))
+                .collect::<::std::vec::Vec<_>>();
+
+            let mut entities = self.entities.write().unwrap();
+            for entity in rejected.iter().copied() {
+                if entities.despawn(entity).is_ok() {
+                    #remove_for_despawn_calls
                 }
             }
+            rejected.len()
         }
-    };
 
+        /// Despawn every entity marked via `Entities::mark_pending_despawn()`, e.g. in the cleanup
+        /// phase at the end of a frame. Returns the number of despawned entities.
+        #vis fn despawn_pending(&mut self) -> usize {
+            let mut entities = self.entities.write().unwrap();
+            let pending = entities
+                .iter_in(::genesis::Lifecycle::PendingDespawn)
+                .collect::<::std::vec::Vec<_>>();
+            for entity in pending.iter().copied() {
+                if entities.despawn(entity).is_ok() {
+                    #remove_for_despawn_calls
+                }
+            }
+            pending.len()
+        }
+    }
+}
+
+fn generate_random_entities_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
     quote! {
-        #(#register_impls)*
+        /// Pick `n` distinct alive entities uniformly at random using the given random number generator;
+        /// see `Entities::sample()`.
+        #vis fn random_entities(&self, rng: impl FnMut() -> u32, n: u32) -> ::std::vec::Vec<::genesis::Entity> {
+            self.entities.read().unwrap().sample(rng, n)
+        }
+    }
+}
 
-        #component_enum_register_impl
+fn generate_new_group_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    quote! {
+        /// Create a new, empty group of entities of this World that drops its members when they despawn.
+        #vis fn new_group(&self) -> ::genesis::EntityGroup {
+            ::genesis::EntityGroup::new(::std::sync::Arc::clone(&self.entities))
+        }
+    }
+}
 
-        #template_register_impl
+fn generate_despawn_impl(input: &Input) -> TokenStream {
+    let world = &input.world_name;
+    quote! {
+        impl ::genesis::Despawn for #world {
+            fn despawn(&mut self, entity: ::genesis::Entity) -> ::std::result::Result<(), ::genesis::NoSuchEntity> {
+                #world::despawn(self, entity)
+            }
+        }
+    }
+}
+
+fn generate_fork_impl(input: &Input) -> TokenStream {
+    if !input.derives("Clone") {
+        return quote! {};
+    }
+
+    let world = &input.world_name;
+    quote! {
+        impl ::genesis::Fork for #world {
+            fn fork(&self) -> Self {
+                #world::fork(self)
+            }
+        }
+    }
+}
+
+fn generate_iter_with_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let storages = storages_name(input);
+
+    quote! {
+        /// Call `f` for every alive entity with a component of type `T`.
+        /// Changes to the World that would require locking its entities, like spawning or despawning,
+        /// can be recorded in the given `CommandBuffer` and are applied after the iteration.
+        #vis fn iter_with<T, F>(&mut self, mut f: F)
+        where
+            for<'a> #storages<'a>: ::genesis::TakeStorage<'a, T>,
+            F: FnMut(::genesis::Entity, &mut T, &mut ::genesis::CommandBuffer<Self>),
+        {
+            let mut commands = ::genesis::CommandBuffer::new();
+            {
+                let mut storages = self.storages_mut();
+                let storage = ::genesis::TakeStorage::<T>::take_storage(&mut storages).unwrap();
+                let entities = storages.entities.read().unwrap();
+                for entity in entities.iter() {
+                    if let Some(component) = ::genesis::Storage::<T>::get_mut_unchecked(storage, entity) {
+                        f(entity, component, &mut commands);
+                    }
+                }
+            }
+            commands.apply(self);
+        }
+    }
+}
+
+fn generate_clear_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+
+    let clear_calls = generate_clear_calls(input, &quote! { write }, |name| quote! { self.#name });
+
+    let uuids_clear = if input.options.uuids {
+        quote! { self.uuids.clear(); }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        /// Despawn all entities and remove all components, passing those marked with
+        /// `#[component(on_despawn = <fn>)]` to their function; components aren't transferred.
+        #vis fn clear(&mut self) {
+            let mut write = self.entities.write().unwrap();
+            #clear_calls
+            #uuids_clear
+        }
+    }
+}
+
+fn generate_fork_fn(input: &Input) -> TokenStream {
+    if !input.derives("Clone") {
+        return quote! {};
+    }
+
+    let vis = &input.vis;
+
+    let forked_storages = input.components.iter().map(|c| {
+        let name = &c.field_name;
+        quote! {
+            #name: self.#name.clone_with_entities(::std::sync::Arc::clone(&entities)),
+        }
+    });
+
+    let forked_uuids = if input.options.uuids {
+        quote! { uuids: self.uuids.clone_with_entities(::std::sync::Arc::clone(&entities)), }
+    } else {
+        quote! {}
+    };
+    let access_field = generate_access_field(input);
+
+    quote! {
+        /// Create a deep copy of this world.
+        /// Unlike cloning the `entities` field, the forked world gets its own set of entities,
+        /// so spawning and despawning entities in the fork doesn't affect the original world.
+        #vis fn fork(&self) -> Self {
+            let entities = self.entities.read().unwrap().clone();
+            let entities = ::std::sync::Arc::new(::std::sync::RwLock::new(entities));
+            Self {
+                #(#forked_storages)*
+                #forked_uuids
+                #access_field
+                entities,
+            }
+        }
+    }
+}
+
+/// The initializer of the `access` field of a new World, which starts without any grants.
+pub(crate) fn generate_access_field(input: &Input) -> TokenStream {
+    if input.options.access {
+        quote! { access: ::genesis::AccessGrants::new(), }
+    } else {
+        quote! {}
+    }
+}
+
+fn generate_access_fns(input: &Input) -> TokenStream {
+    if !input.options.access {
+        return quote! {};
+    }
+
+    let vis = &input.vis;
+
+    quote! {
+        /// Grant the requested set of tokens, e.g. `(Read<Velocity>, Write<Position>)` for one system,
+        /// unless they conflict with each other or with tokens granted earlier in the current frame;
+        /// see `genesis::AccessGrants`.
+        #vis fn grant_access<A: ::genesis::AccessSet>(&mut self) -> ::std::result::Result<A, ::genesis::AccessConflict> {
+            self.access.grant()
+        }
+
+        /// Start a new access frame, revoking all tokens granted so far.
+        #vis fn next_access_frame(&mut self) {
+            self.access.next_frame();
+        }
+
+        /// Borrow the storage of components of type T with a read token.
+        ///
+        /// # Panics
+        /// Panics if the token wasn't granted by this World in the current access frame.
+        #vis fn read<T>(&self, token: &::genesis::Read<T>) -> &<Self as ::genesis::HasComponent<T>>::Storage
+        where
+            Self: ::genesis::HasComponent<T>,
+        {
+            assert!(self.access.is_current(token), "stale or foreign access token {:?}", token);
+            ::genesis::HasComponent::<T>::storage(self)
+        }
+
+        /// Borrow the storage of components of type T mutably with a write token.
+        ///
+        /// # Panics
+        /// Panics if the token wasn't granted by this World in the current access frame.
+        #vis fn write<T>(&mut self, token: &::genesis::Write<T>) -> &mut <Self as ::genesis::HasComponent<T>>::Storage
+        where
+            Self: ::genesis::HasComponent<T>,
+        {
+            assert!(self.access.is_current(token), "stale or foreign access token {:?}", token);
+            ::genesis::HasComponent::<T>::storage_mut(self)
+        }
+    }
+}
+
+fn generate_map_entities_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+
+    let map_entities_calls = input
+        .components
+        .iter()
+        .filter(|c| c.maps_entities)
+        .map(|c| {
+            let name = &c.field_name;
+            quote! {
+                self.#name.map_entities(mapper);
+            }
+        });
+
+    quote! {
+        /// Rewrite the entities stored in all components marked with `#[component(maps_entities)]`.
+        /// The entities of the World itself are left unchanged.
+        #[allow(unused_variables)]
+        #vis fn map_entities(&mut self, mapper: &mut dyn FnMut(::genesis::Entity) -> ::genesis::Entity) {
+            #(#map_entities_calls)*
+        }
+    }
+}
+
+pub(crate) fn storages_name(input: &Input) -> Ident {
+    format_ident!("{}Storages", input.world_name)
+}
+
+fn generate_storages_mut_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let storages = storages_name(input);
+
+    let storage_fields = input.components.iter().map(|c| {
+        let name = &c.field_name;
+        quote! {
+            #name: ::std::option::Option::Some(&mut self.#name),
+        }
+    });
+
+    quote! {
+        /// Borrow all storages at once, e.g. to access several of them mutably at the same time.
+        /// This is used by the `query!` macro.
+        #vis fn storages_mut(&mut self) -> #storages<'_> {
+            #storages {
+                entities: &self.entities,
+                #(#storage_fields)*
+            }
+        }
+    }
+}
+
+fn generate_storages_definition(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let world = &input.world_name;
+    let storages = storages_name(input);
+
+    let storage_fields = input.components.iter().map(|c| {
+        let vis = c.vis(input);
+        let name = &c.field_name;
+        let ty = &c.component_type;
+        let storage_type = Ident::new(c.storage_type.name(), Span::call_site());
+        quote! {
+            #vis #name: ::std::option::Option<&'a mut ::genesis::#storage_type<#ty>>,
+        }
+    });
+
+    let take_storage_impls = input.primary_components().map(|c| {
+        let name = &c.field_name;
+        let ty = &c.component_type;
+        let storage_type = Ident::new(c.storage_type.name(), Span::call_site());
+        quote! {
+            impl<'a> ::genesis::TakeStorage<'a, #ty> for #storages<'a> {
+                type Storage = ::genesis::#storage_type<#ty>;
+
+                fn take_storage(&mut self) -> ::std::option::Option<&'a mut Self::Storage> {
+                    self.#name.take()
+                }
+            }
+        }
+    });
+
+    let doc = format!(
+        "Mutable borrows of all storages of `{}`, created via `{}::storages_mut()`.",
+        world, world
+    );
+
+    quote! {
+        #[doc = #doc]
+        /// Each storage can be taken out of its field independently of the others.
+        #vis struct #storages<'a> {
+            #vis entities: &'a ::std::sync::Arc<::std::sync::RwLock<::genesis::Entities>>,
+            #(#storage_fields)*
+        }
+
+        #(#take_storage_impls)*
+    }
+}
+
+fn generate_integrity_impl(input: &Input) -> TokenStream {
+    let world = &input.world_name;
+
+    let storage_checks = input.components.iter().map(|c| {
+        let name = &c.field_name;
+        let storage_name = name.to_string();
+        quote! {
+            self.#name.check_integrity(&self.entities, #storage_name)?;
+        }
+    });
+
+    quote! {
+        impl ::genesis::WorldIntegrity for #world {
+            fn check(&self) -> ::std::result::Result<(), ::genesis::IntegrityError> {
+                #(#storage_checks)*
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Generate the impls of `PartialEq`, `Eq` and `Debug` on the World if they are derived; they can't
+/// be derived on the World itself since its entities are wrapped in a lock.
+fn generate_derived_impls(input: &Input) -> TokenStream {
+    let world = &input.world_name;
+    let names = input
+        .components
+        .iter()
+        .map(|c| &c.field_name)
+        .collect::<Vec<_>>();
+
+    let partial_eq_impl = if input.derives("PartialEq") {
+        quote! {
+            /// Worlds are equal if they have the same alive entities with equal components.
+            impl ::std::cmp::PartialEq for #world {
+                fn eq(&self, other: &Self) -> bool {
+                    let same_entities = ::std::sync::Arc::ptr_eq(&self.entities, &other.entities) || {
+                        let left = self.entities.read().unwrap();
+                        let right = other.entities.read().unwrap();
+                        left.iter().eq(right.iter())
+                    };
+                    same_entities #(&& self.#names == other.#names)*
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let eq_impl = if input.derives("Eq") {
+        quote! {
+            impl ::std::cmp::Eq for #world {}
+        }
+    } else {
+        quote! {}
+    };
+
+    let debug_impl = if input.derives("Debug") {
+        let world_name = world.to_string();
+        let storage_names = names.iter().map(|name| name.to_string());
+        quote! {
+            impl ::std::fmt::Debug for #world {
+                fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                    let entities = match self.entities.try_read() {
+                        Ok(entities) => entities.iter_snapshot().collect::<::std::vec::Vec<_>>(),
+                        Err(_) => return f.write_str(concat!(#world_name, " { <entities locked> }")),
+                    };
+                    f.debug_struct(#world_name)
+                        .field("entities", &entities)
+                        #(.field(#storage_names, &self.#names))*
+                        .finish()
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        #partial_eq_impl
+        #eq_impl
+        #debug_impl
+    }
+}
+
+fn generate_debug_world_impl(input: &Input) -> TokenStream {
+    if !input.derives("Debug") {
+        return quote! {};
+    }
+
+    let world = &input.world_name;
+    let world_name = world.to_string();
+
+    let component_pushes = input.components.iter().map(|c| {
+        let name = &c.field_name;
+        let storage_name = name.to_string();
+        quote! {
+            if let Some(component) = self.#name.get_unchecked(entity) {
+                components.push((#storage_name, ::std::format!("{:?}", component)));
+            }
+        }
+    });
+
+    let component_names = input.components.iter().map(|c| {
+        let storage = c.field_name.to_string();
+        let template = c.template_name.to_string();
+        let ty = &c.component_type;
+        let type_name = ty.to_token_stream().to_string();
+        quote! {
+            ::genesis::ComponentNames {
+                storage: #storage,
+                template: #template,
+                type_name: #type_name,
+            },
+        }
+    });
+
+    quote! {
+        impl ::genesis::DebugWorld for #world {
+            fn type_name(&self) -> &'static str {
+                #world_name
+            }
+
+            fn component_names(&self) -> &'static [::genesis::ComponentNames] {
+                &[#(#component_names)*]
+            }
+
+            fn alive_entities(&self) -> ::std::vec::Vec<::genesis::Entity> {
+                self.entities.read().unwrap().iter().collect()
+            }
+
+            #[allow(unused_mut)]
+            fn debug_components(&self, entity: ::genesis::Entity) -> ::std::vec::Vec<(&'static str, ::std::string::String)> {
+                let mut components = ::std::vec::Vec::new();
+                if !self.entities.read().unwrap().exists(entity) {
+                    return components;
+                }
+                #(#component_pushes)*
+                components
+            }
+
+            fn despawn_entity(&mut self, entity: ::genesis::Entity) -> ::std::result::Result<(), ::genesis::NoSuchEntity> {
+                self.despawn(entity)
+            }
+        }
+    }
+}
+
+fn generate_world_eq_impl(input: &Input) -> TokenStream {
+    if !input.derives("PartialEq") || !input.derives("Debug") {
+        return quote! {};
+    }
+
+    let world = &input.world_name;
+
+    let component_checks = input.components.iter().map(|c| {
+        let name = &c.field_name;
+        let storage_name = name.to_string();
+        quote! {
+            mismatches.extend(::genesis::WorldMismatch::component(
+                entity,
+                #storage_name,
+                self.#name.get_unchecked(entity),
+                other.#name.get_unchecked(entity),
+            ));
+        }
+    });
+
+    quote! {
+        impl ::genesis::WorldEq for #world {
+            #[allow(unused_variables)]
+            fn mismatches(&self, other: &Self) -> ::std::vec::Vec<::genesis::WorldMismatch> {
+                let left = self.entities.read().unwrap();
+                let right = other.entities.read().unwrap();
+                let mut mismatches = ::genesis::WorldMismatch::entities(&left, &right);
+                for entity in left.iter().filter(|entity| right.exists(*entity)) {
+                    #(#component_checks)*
+                }
+                mismatches
+            }
+        }
+    }
+}
+
+fn generate_incremental_clear_fns(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+
+    // Hygienic names, so fields named e.g. `entity` or `write` don't shadow the locals.
+    let borrowed =
+        |c: &WorldComponent| Ident::new(&format!("cleared_{}", c.field_name), Span::mixed_site());
+    let storage_borrows = input.components.iter().map(|c| {
+        let name = &c.field_name;
+        let local = borrowed(c);
+        quote! {
+            let #local = &mut self.#name;
+        }
+    });
+
+    let remove_for_despawn_calls = input.components_in_cleanup_order().into_iter().map(|c| {
+        let local = borrowed(c);
+        generate_cleared_component_removal(c, &quote! { #local }).unwrap_or_else(|| {
+            quote! {
+                #local.remove_for_despawn(entity);
+            }
+        })
+    });
+
+    // UUIDs are dropped as their entities are despawned, so alive entities keep theirs between steps.
+    let (uuids_borrow, uuids_removal) = if input.options.uuids {
+        let uuids = Ident::new("cleared_uuids", Span::mixed_site());
+        (
+            quote! { let #uuids = &mut self.uuids; },
+            quote! { #uuids.remove_for_despawn(entity); },
+        )
+    } else {
+        (quote! {}, quote! {})
+    };
+
+    quote! {
+        /// Start removing all entities and components incrementally; see `clear_budget()`.
+        /// This avoids the long pause of `clear()` for very large Worlds.
+        #vis fn begin_clear(&mut self) {
+            self.entities.write().unwrap().begin_clear();
+        }
+
+        /// Continue a clear started with `begin_clear()`, processing at most `budget` entity slots.
+        /// Like `clear()`, passes components marked with `#[component(on_despawn = <fn>)]` to their function.
+        #[allow(unused_variables)]
+        #vis fn clear_budget(&mut self, budget: u32) -> ::genesis::ClearProgress {
+            let mut write = self.entities.write().unwrap();
+            #(#storage_borrows)*
+            #uuids_borrow
+            write.clear_step(budget, |entity| {
+                #(#remove_for_despawn_calls)*
+                #uuids_removal
+            })
+        }
+    }
+}
+
+fn generate_layout_items(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let component_count = input.components.len();
+
+    let layouts = input.components.iter().map(|c| {
+        let ty = &c.component_type;
+        let name = ty.to_token_stream().to_string();
+        let kind = Ident::new(c.storage_type.kind(), Span::call_site());
+        quote! {
+            ::genesis::ComponentLayout {
+                name: #name,
+                storage: ::genesis::StorageKind::#kind,
+                size: ::std::mem::size_of::<#ty>(),
+                align: ::std::mem::align_of::<#ty>(),
+            },
+        }
+    });
+
+    let occupancies = input
+        .components
+        .iter()
+        .filter(|c| matches!(c.storage_type, ComponentStorageType::Vec))
+        .map(|c| {
+            let field = &c.field_name;
+            let storage = field.to_string();
+            let name = c.component_type.to_token_stream().to_string();
+            quote! {
+                ::genesis::StorageOccupancy {
+                    storage: #storage,
+                    name: #name,
+                    occupied: self.#field.occupied(),
+                    slots: self.#field.slots(),
+                },
+            }
+        });
+
+    let memory = input.components.iter().map(|c| {
+        let field = &c.field_name;
+        let storage = field.to_string();
+        let name = c.component_type.to_token_stream().to_string();
+        quote! {
+            ::genesis::StorageMemory {
+                storage: #storage,
+                name: #name,
+                usage: self.#field.memory_usage(),
+            },
+        }
+    });
+
+    quote! {
+        /// Memory layout information about all component types of this World, in declaration order.
+        #vis const COMPONENT_LAYOUTS: [::genesis::ComponentLayout; #component_count] = [
+            #(#layouts)*
+        ];
+
+        /// A table listing the storage kind, size and alignment of every component type.
+        #vis fn layout_report() -> ::std::string::String {
+            ::genesis::ComponentLayout::report(&Self::COMPONENT_LAYOUTS)
+        }
+
+        /// The occupancy of every `VecStorage` of this World, e.g. to find storages better stored in a `MapStorage`.
+        #vis fn fragmentation_report(&self) -> ::genesis::FragmentationReport {
+            ::genesis::FragmentationReport {
+                storages: ::std::vec![#(#occupancies)*],
+            }
+        }
+
+        /// The memory used by every storage of this World; compare two snapshots with
+        /// `MemorySnapshot::diff()` to find storages that keep growing, e.g. leaked components.
+        #vis fn memory_snapshot(&self) -> ::genesis::MemorySnapshot {
+            ::genesis::MemorySnapshot {
+                storages: ::std::vec![#(#memory)*],
+            }
+        }
+    }
+}
+
+fn generate_register_from_fn(input: &Input) -> TokenStream {
+    if !input.derives("Clone") {
+        return quote! {};
+    }
+
+    let vis = &input.vis;
+    let template_name = &input.template_name;
+    let register_error = register_error_type(input);
+    let components = input.components.iter().collect::<Vec<_>>();
+    let register_body = generate_template_register_body(input, template_name, &components, true);
+
+    quote! {
+        /// Register the components of a borrowed template for the given entity.
+        /// Only the components that are set in the template are cloned, which makes this cheaper than
+        /// cloning the whole template when spawning many entities from the same template.
+        /// Returns the previously associated components like registering an owned template does.
+        #vis fn register_from(&mut self, entity: ::genesis::Entity, template: &#template_name)
+            -> ::std::result::Result<::std::option::Option<#template_name>, #register_error> {
+            #register_body
+        }
+    }
+}
+
+/// The error type of registering templates and the component enum.
+fn register_error_type(input: &Input) -> TokenStream {
+    if input.has_requirements() {
+        quote! { ::genesis::RegisterError }
+    } else {
+        quote! { ::genesis::StorageError }
+    }
+}
+
+/// Generate the check that `entity` exists, for registrations that check requirements.
+fn generate_exists_check() -> TokenStream {
+    quote! {
+        if !self.entities.read().unwrap().exists(entity) {
+            return Err(::genesis::NoSuchEntity::new(entity).with_operation("register").into());
+        }
+    }
+}
+
+/// Generate the checks that `entity` has all components required by the given component.
+/// `provided` generates an expression checking if a required component is about to be registered
+/// together with the given component.
+fn generate_requirement_checks(
+    input: &Input,
+    c: &WorldComponent,
+    provided: impl Fn(&WorldComponent) -> TokenStream,
+) -> TokenStream {
+    let component = c.component_type.to_token_stream().to_string();
+    let checks = c.requires.iter().map(|name| {
+        let required = input.component(name).unwrap();
+        let required_storage = &required.field_name;
+        let required_name = required.component_type.to_token_stream().to_string();
+        let provided = provided(required);
+        quote! {
+            if !(#provided) && self.#required_storage.get(entity).is_none() {
+                return Err(::genesis::RegisterError::MissingRequirement {
+                    entity,
+                    component: #component,
+                    required: #required_name,
+                });
+            }
+        }
+    });
+
+    quote! {
+        #(#checks)*
+    }
+}
+
+/// Generate the body of registering a template with the given name and components, given as
+/// `template` and either owned or borrowed, for `entity`.
+fn generate_template_register_body(
+    input: &Input,
+    template_name: &Ident,
+    components: &[&WorldComponent],
+    borrowed: bool,
+) -> TokenStream {
+    let exists_check = if components.iter().any(|c| !c.requires.is_empty()) {
+        generate_exists_check()
+    } else {
+        quote! {}
+    };
+
+    let requirement_checks = components
+        .iter()
+        .filter(|c| !c.requires.is_empty())
+        .map(|c| {
+            let name = &c.template_name;
+            let checks = generate_requirement_checks(input, c, |required| {
+                let required_name = &required.template_name;
+                if components
+                    .iter()
+                    .any(|c| c.field_name == required.field_name)
+                {
+                    quote! { template.#required_name.is_some() }
+                } else {
+                    quote! { false }
+                }
+            });
+            quote! {
+                if template.#name.is_some() {
+                    #checks
+                }
+            }
+        });
+
+    // Bound with a hygienic name, as a template field named e.g. `entity` would shadow the argument.
+    let value = Ident::new("component", Span::mixed_site());
+    let template_fields_register = components.iter().map(|c| {
+        let name = &c.template_name;
+        let storage = &c.field_name;
+        let storage_name = storage.to_string();
+        let (pattern, component) = if borrowed {
+            (
+                quote! { &template.#name },
+                quote! { ::std::clone::Clone::clone(#value) },
+            )
+        } else {
+            (quote! { template.#name }, quote! { #value })
+        };
+
+        quote! {
+            #name: if let Some(#value) = #pattern {
+                self.#storage
+                    .set(entity, #component)
+                    .map_err(|e| e.in_storage(#storage_name))?
+            } else {
+                None
+            },
+        }
+    });
+
+    let unknown_data = template::empty_unknown_data_field(input);
+
+    quote! {
+        #exists_check
+        #(#requirement_checks)*
+        Ok(Some(
+            #template_name {
+                #(#template_fields_register)*
+                #unknown_data
+            }
+        ))
+    }
+}
+
+fn generate_register_impls(input: &Input) -> TokenStream {
+    let world = &input.world_name;
+    let register_error = register_error_type(input);
+
+    let register_impls = input.primary_components().map(|c| {
+        let ty = &c.component_type;
+        let component_storage_name = &c.field_name;
+        let storage_name = component_storage_name.to_string();
+        if c.requires.is_empty() {
+            quote! {
+                impl ::genesis::Register<#ty> for #world {
+                    type Error = ::genesis::StorageError;
+
+                    fn register(&mut self, entity: ::genesis::Entity, component: #ty)
+                        -> ::std::result::Result<std::option::Option<#ty>, ::genesis::StorageError> {
+                        self.#component_storage_name
+                            .set(entity, component)
+                            .map_err(|e| e.in_storage(#storage_name))
+                    }
+                }
+            }
+        } else {
+            let exists_check = generate_exists_check();
+            let requirement_checks = generate_requirement_checks(input, c, |_| quote! { false });
+            quote! {
+                impl ::genesis::Register<#ty> for #world {
+                    type Error = ::genesis::RegisterError;
+
+                    fn register(&mut self, entity: ::genesis::Entity, component: #ty)
+                        -> ::std::result::Result<std::option::Option<#ty>, ::genesis::RegisterError> {
+                        #exists_check
+                        #requirement_checks
+                        Ok(self.#component_storage_name
+                            .set(entity, component)
+                            .map_err(|e| e.in_storage(#storage_name))?)
+                    }
+                }
+            }
+        }
+    });
+
+    let component_enum_register_impl = {
+        let component_enum = &input.component_enum_name;
+        // Components are set in the field of their variant, since `Register<T>` only reaches the
+        // primary field of a type stored in several fields.
+        let component_enum_match_impl_register = input.components.iter().map(|c| {
+            let variant = &c.variant;
+            let storage = &c.field_name;
+            let storage_name = storage.to_string();
+            let (exists_check, requirement_checks) = if c.requires.is_empty() {
+                (quote! {}, quote! {})
+            } else {
+                (
+                    generate_exists_check(),
+                    generate_requirement_checks(input, c, |_| quote! { false }),
+                )
+            };
+
+            quote! {
+                #component_enum::#variant(c) => {
+                    #exists_check
+                    #requirement_checks
+                    self.#storage
+                        .set(entity, c)
+                        .map_err(|e| e.in_storage(#storage_name))?
+                        .map(#component_enum::#variant)
+                }
+            }
+        });
+
+        quote! {
+            impl ::genesis::Register<#component_enum> for #world {
+                type Error = #register_error;
+
+                fn register(&mut self, entity: ::genesis::Entity, component: #component_enum)
+                -> ::std::result::Result<::std::option::Option::<#component_enum>, #register_error> {
+                Ok(match component {
+                #(#component_enum_match_impl_register)*
+                })
+                }
+            }
+        }
+    };
+
+    let full_template = (
+        &input.template_name,
+        input.components.iter().collect::<Vec<_>>(),
+    );
+    let partial_templates = input
+        .options
+        .partial_templates
+        .iter()
+        .map(|template| (&template.name, input.partial_template_components(template)));
+    let template_register_impls = std::iter::once(full_template)
+        .chain(partial_templates)
+        .map(|(template_name, components)| {
+            let register_body =
+                generate_template_register_body(input, template_name, &components, false);
+            let report = template::report_name(template_name);
+            let names = components
+                .iter()
+                .map(|c| &c.template_name)
+                .collect::<Vec<_>>();
+            // Hygienic names, so template fields named e.g. `entity` or `previous` don't shadow the locals.
+            let given = components
+                .iter()
+                .map(|c| Ident::new(&format!("has_{}", c.template_name), Span::mixed_site()))
+                .collect::<Vec<_>>();
+
+            quote! {
+                impl ::genesis::Register<#template_name> for #world {
+                    type Error = #register_error;
+
+                    fn register(&mut self, entity: ::genesis::Entity, template: #template_name)
+                        -> ::std::result::Result<::std::option::Option::<#template_name>, #register_error> {
+                        #register_body
+                    }
+                }
+
+                impl ::genesis::RegisterWithReport<#template_name> for #world {
+                    type Report = #report;
+                    type Error = #register_error;
+
+                    #[allow(unused_variables)]
+                    fn register_with_report(&mut self, entity: ::genesis::Entity, template: #template_name)
+                        -> ::std::result::Result<#report, #register_error> {
+                        #(let #given = template.#names.is_some();)*
+                        let previous = ::genesis::Register::register(self, entity, template)?
+                            .expect("registering a template returns the previous components");
+                        Ok(#report {
+                            #(
+                                #names: match previous.#names {
+                                    _ if !#given => ::genesis::Registered::Skipped,
+                                    ::std::option::Option::Some(replaced) => ::genesis::Registered::Replaced(replaced),
+                                    ::std::option::Option::None => ::genesis::Registered::Added,
+                                },
+                            )*
+                        })
+                    }
+                }
+            }
+        });
+
+    quote! {
+        #(#register_impls)*
+
+        #component_enum_register_impl
+
+        #(#template_register_impls)*
+    }
+}
+
+fn generate_clear_component_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+
+    quote! {
+        /// Remove the component of type T from all alive entities, e.g. when the system using it shuts down.
+        /// The entities themselves stay alive. Returns the number of removed components.
+        #vis fn clear_component<T>(&mut self) -> usize
+        where
+            Self: ::genesis::ClearComponent<T>,
+        {
+            <Self as ::genesis::ClearComponent<T>>::clear_component(self)
+        }
+    }
+}
+
+fn generate_clear_component_impls(input: &Input) -> TokenStream {
+    let world = &input.world_name;
+
+    let impls = input.primary_components().map(|c| {
+        let ty = &c.component_type;
+        let field_name = &c.field_name;
+
+        quote! {
+            impl ::genesis::ClearComponent<#ty> for #world {
+                fn clear_component(&mut self) -> usize {
+                    self.#field_name.clear_checked()
+                }
+            }
+        }
+    });
+
+    quote! {
+        #(#impls)*
+    }
+}
+
+fn generate_has_component_impls(input: &Input) -> TokenStream {
+    let world = &input.world_name;
+
+    let impls = input.primary_components().map(|c| {
+        let ty = &c.component_type;
+        let field_name = &c.field_name;
+        let storage_type = Ident::new(c.storage_type.name(), Span::call_site());
+
+        quote! {
+            impl ::genesis::HasComponent<#ty> for #world {
+                type Storage = ::genesis::#storage_type<#ty>;
+
+                fn storage(&self) -> &Self::Storage {
+                    &self.#field_name
+                }
+
+                fn storage_mut(&mut self) -> &mut Self::Storage {
+                    &mut self.#field_name
+                }
+            }
+        }
+    });
+
+    quote! {
+        impl ::genesis::HasEntities for #world {
+            fn entities(&self) -> &::std::sync::Arc<::std::sync::RwLock<::genesis::Entities>> {
+                &self.entities
+            }
+        }
+
+        #(#impls)*
+    }
+}
+
+fn generate_template_schema_fn(input: &Input) -> TokenStream {
+    if !input.options.schema {
+        return quote! {};
+    }
+
+    let vis = &input.vis;
+    let template_name = &input.template_name;
+
+    quote! {
+        /// The JSON schema of the template, e.g. for validating prefab files against the World definition.
+        #vis fn template_schema() -> ::genesis::schemars::schema::RootSchema {
+            ::genesis::schemars::schema_for!(#template_name)
+        }
+    }
+}
+
+fn generate_json_fns(input: &Input) -> TokenStream {
+    if !input.options.json {
+        return quote! {};
+    }
+
+    let vis = &input.vis;
+    let template_name = &input.template_name;
+    let unknown_data = template::empty_unknown_data_field(input);
+    let template_fields = input.components.iter().map(|c| {
+        let name = &c.template_name;
+        let storage = &c.field_name;
+        let ty = &c.component_type;
+        quote! {
+            #name: ::genesis::Storage::<#ty>::get(&self.#storage, entity).cloned(),
+        }
+    });
+
+    quote! {
+        /// Deserialize a template from the given JSON value and register its components for the given
+        /// entity, e.g. for live-tuning from a web dashboard. Components missing from the patch or set to
+        /// `null` are left untouched.
+        #vis fn apply_json_patch(
+            &mut self,
+            entity: ::genesis::Entity,
+            patch: &::genesis::serde_json::Value,
+        ) -> ::std::result::Result<(), ::genesis::json::JsonError> {
+            let template = <#template_name as ::genesis::serde::Deserialize>::deserialize(patch)?;
+            ::genesis::Register::register(self, entity, template)
+                .map_err(::genesis::RegisterError::from)?;
+            Ok(())
+        }
+
+        /// Serialize all components of the given entity as a JSON object in the format of the template.
+        #vis fn entity_to_json(
+            &self,
+            entity: ::genesis::Entity,
+        ) -> ::std::result::Result<::genesis::serde_json::Value, ::genesis::json::JsonError> {
+            if !self.entities.read().unwrap().exists(entity) {
+                let error = ::genesis::NoSuchEntity::new(entity).with_operation("entity_to_json");
+                return Err(::genesis::RegisterError::from(error).into());
+            }
+            let template = #template_name {
+                #(#template_fields)*
+                #unknown_data
+            };
+            Ok(::genesis::serde_json::to_value(template)?)
+        }
+    }
+}
+
+fn generate_ron_fns(input: &Input) -> TokenStream {
+    if !input.options.ron {
+        return quote! {};
+    }
+
+    let vis = &input.vis;
+    let template_name = &input.template_name;
+    let component_enum = &input.component_enum_name;
+    let kind_enum = component::kind_enum_name(input);
+    let unknown_data = template::empty_unknown_data_field(input);
+    let template_fields = input.components.iter().map(|c| {
+        let name = &c.template_name;
+        let storage = &c.field_name;
+        let ty = &c.component_type;
+        quote! {
+            #name: ::genesis::Storage::<#ty>::get(&self.#storage, entity).cloned(),
+        }
+    });
+    let collect_calls = input.components.iter().map(|c| {
+        let name = &c.field_name;
+        let variant = &c.variant;
+        quote! {
+            for entity in alive.iter().copied() {
+                if let Some(component) = self.#name.get_unchecked(entity) {
+                    set.push((entity, #component_enum::#variant(::std::clone::Clone::clone(component))));
+                }
+            }
+        }
+    });
+
+    quote! {
+        /// Serialize all alive entities and their components as pretty-printed RON, e.g. for test
+        /// fixtures or game data; see `from_ron_str()`. The World is written as a `genesis::ChangeSet`
+        /// since tick 0 holding every component, so components keep the variants of the component enum.
+        #vis fn to_ron_string(&self) -> ::std::result::Result<::std::string::String, ::genesis::ron_format::RonError> {
+            let alive = self.entities.read().unwrap().iter().collect::<::std::vec::Vec<_>>();
+            let mut set = ::std::vec::Vec::new();
+            #(#collect_calls)*
+            let snapshot: ::genesis::ChangeSet<#component_enum, #kind_enum> = ::genesis::ChangeSet {
+                since: 0,
+                alive,
+                set,
+                removed: ::std::vec::Vec::new(),
+            };
+            ::genesis::ron_format::to_string(&snapshot)
+        }
+
+        /// Create a World from RON written by `to_ron_string()`, with the exact entities and components it
+        /// holds. Components of `DoubleBuffered` storages become visible after the next `flip()`.
+        #vis fn from_ron_str(source: &str) -> ::std::result::Result<Self, ::genesis::ron_format::RonError> {
+            let mut world = Self::empty();
+            world.apply_changes(::genesis::ron_format::from_str(source)?)?;
+            Ok(world)
+        }
+
+        /// Deserialize a template from the given RON source and register its components for the given
+        /// entity. Components missing from the template or set to `None` are left untouched.
+        #vis fn apply_ron_patch(
+            &mut self,
+            entity: ::genesis::Entity,
+            source: &str,
+        ) -> ::std::result::Result<(), ::genesis::ron_format::RonError> {
+            let template: #template_name = ::genesis::ron_format::from_str(source)?;
+            ::genesis::Register::register(self, entity, template)
+                .map_err(::genesis::RegisterError::from)?;
+            Ok(())
+        }
+
+        /// Serialize all components of the given entity as pretty-printed RON in the format of the template.
+        #vis fn entity_to_ron(
+            &self,
+            entity: ::genesis::Entity,
+        ) -> ::std::result::Result<::std::string::String, ::genesis::ron_format::RonError> {
+            if !self.entities.read().unwrap().exists(entity) {
+                let error = ::genesis::NoSuchEntity::new(entity).with_operation("entity_to_ron");
+                return Err(::genesis::RegisterError::from(error).into());
+            }
+            let template = #template_name {
+                #(#template_fields)*
+                #unknown_data
+            };
+            ::genesis::ron_format::to_string(&template)
+        }
+    }
+}
+
+fn generate_flip_fn(input: &Input) -> TokenStream {
+    let double_buffered = input
+        .components
+        .iter()
+        .filter(|c| matches!(c.storage_type, ComponentStorageType::DoubleBuffered))
+        .map(|c| &c.field_name)
+        .collect::<Vec<_>>();
+    if double_buffered.is_empty() {
+        return quote! {};
+    }
+
+    let vis = &input.vis;
+
+    quote! {
+        /// Flip all double-buffered storages, making the components written during this frame visible to reads.
+        #vis fn flip(&mut self) {
+            #(self.#double_buffered.flip();)*
+        }
+    }
+}
+
+fn generate_set_tick_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let ticked = input
+        .components
+        .iter()
+        .filter(|c| c.storage_type.tracks_ticks())
+        .map(|c| &c.field_name);
+
+    quote! {
+        /// Set the current tick of all storages that track when their components were last modified,
+        /// e.g. once per simulation step; see `VecStorage::last_modified()`.
+        #[allow(unused_variables)]
+        #vis fn set_tick(&mut self, tick: u32) {
+            #(self.#ticked.set_tick(tick);)*
+        }
+    }
+}
+
+fn generate_preallocate_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let dense = input
+        .components
+        .iter()
+        .filter(|c| {
+            !matches!(
+                c.storage_type,
+                ComponentStorageType::Map | ComponentStorageType::Mmap
+            )
+        })
+        .map(|c| &c.field_name);
+
+    quote! {
+        /// Grow the entities and all Vec-backed storages once so that `n_new_entities` can be spawned and
+        /// get their components without allocating, e.g. before streaming in a chunk of the level.
+        /// `MapStorage` and `MmapStorage` components still allocate when they are set.
+        #[allow(unused_variables)]
+        #vis fn preallocate_for(&mut self, n_new_entities: u32) {
+            let index_end = {
+                let mut entities = self.entities.write().unwrap();
+                entities.reserve(n_new_entities);
+                entities.index_end()
+            };
+            #(self.#dense.grow_to(index_end);)*
+        }
+    }
+}
+
+fn generate_migrate_storage_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let has_dynamic = input
+        .components
+        .iter()
+        .any(|c| matches!(c.storage_type, ComponentStorageType::Dynamic));
+    if !has_dynamic {
+        return quote! {};
+    }
+
+    quote! {
+        /// Move the components of type `T` into a storage of the given kind, `StorageKind::Vec` or
+        /// `StorageKind::Map`, keeping all their data; see `DynamicStorage::migrate()`.
+        /// Only available for components declared as `DynamicStorage<T>`.
+        #vis fn migrate_storage<T>(
+            &mut self,
+            kind: ::genesis::StorageKind,
+        ) -> ::std::result::Result<(), ::genesis::UnsupportedMigration>
+        where
+            Self: ::genesis::HasComponent<T, Storage = ::genesis::DynamicStorage<T>>,
+        {
+            ::genesis::HasComponent::<T>::storage_mut(self).migrate(kind)
+        }
+    }
+}
+
+fn generate_move_between_fn(input: &Input) -> TokenStream {
+    if input.components.iter().all(|c| c.primary) {
+        return quote! {};
+    }
+
+    let vis = &input.vis;
+    let kind_enum = &component::kind_enum_name(input);
+    let type_name = |c: &WorldComponent| c.component_type.to_token_stream().to_string();
+    let moves = input.components.iter().flat_map(|from| {
+        input
+            .components
+            .iter()
+            .filter(move |to| from.field_name != to.field_name && type_name(from) == type_name(to))
+            .map(move |to| {
+                let (from_variant, to_variant) = (&from.variant, &to.variant);
+                let (from_name, to_name) = (&from.field_name, &to.field_name);
+                let (from_storage, to_storage) = (from_name.to_string(), to_name.to_string());
+                quote! {
+                    (#kind_enum::#from_variant, #kind_enum::#to_variant) => {
+                        if self.#from_name.get_unchecked(entity).is_none() {
+                            return Err(::genesis::MoveError::MissingComponent { entity, storage: #from_storage });
+                        }
+                        if self.#to_name.get_unchecked(entity).is_some() {
+                            return Err(::genesis::MoveError::Occupied { entity, storage: #to_storage });
+                        }
+                        if let Some(component) = self.#from_name.remove_for_despawn(entity) {
+                            self.#to_name.set_unchecked(entity, component);
+                        }
+                        Ok(())
+                    }
+                }
+            })
+    });
+    let storage_names = input.components.iter().map(|c| {
+        let variant = &c.variant;
+        let storage = c.field_name.to_string();
+        quote! { #kind_enum::#variant => #storage, }
+    });
+
+    quote! {
+        /// Move the component of `entity` from the storage of the kind `from` to the storage of the kind
+        /// `to`, which store the same component type, e.g. an item from an `equipped` to a `stored` field.
+        /// Fails without changing anything if the entity doesn't exist, has no component in `from` or
+        /// already has one in `to`, or if the storages don't store the same component type.
+        #vis fn move_between(
+            &mut self,
+            entity: ::genesis::Entity,
+            from: #kind_enum,
+            to: #kind_enum,
+        ) -> ::std::result::Result<(), ::genesis::MoveError> {
+            if !self.entities.read().unwrap().exists(entity) {
+                return Err(::genesis::NoSuchEntity::new(entity).with_operation("move_between").into());
+            }
+            match (from, to) {
+                #(#moves)*
+                _ => {
+                    let storage_name = |kind: #kind_enum| match kind {
+                        #(#storage_names)*
+                    };
+                    Err(::genesis::MoveError::IncompatibleStorages {
+                        from: storage_name(from),
+                        to: storage_name(to),
+                    })
+                }
+            }
+        }
+    }
+}
+
+fn generate_bind_to_thread_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let names = input.components.iter().map(|c| &c.field_name);
+
+    quote! {
+        /// Only allow spawning and despawning entities and mutating the components of self from the given
+        /// thread, or from any thread for None, with the `thread_checks` feature, which panics on mutations from
+        /// other threads; self is bound to the thread that created it. Call this before handing self over to
+        /// another thread.
+        #[allow(unused_variables)]
+        #vis fn bind_to_thread(&mut self, thread: ::std::option::Option<::std::thread::ThreadId>) {
+            self.entities.write().unwrap().bind_to_thread(thread);
+            #(self.#names.bind_to_thread(thread);)*
+        }
+    }
+}
+
+fn generate_rkyv_impl(input: &Input) -> TokenStream {
+    if !input.options.rkyv {
+        return quote! {};
+    }
+
+    let world = &input.world_name;
+    let names = input
+        .components
+        .iter()
+        .map(|c| &c.field_name)
+        .collect::<Vec<_>>();
+    let storage_types = input
+        .components
+        .iter()
+        .map(|c| {
+            let ty = &c.component_type;
+            let storage_type = Ident::new(c.storage_type.name(), Span::call_site());
+            quote! { ::genesis::#storage_type<#ty> }
+        })
+        .collect::<Vec<_>>();
+
+    quote! {
+        /// Deserializes the entities once and attaches them to every storage.
+        impl<__D: ::genesis::rkyv::Fallible + ?::std::marker::Sized>
+            ::genesis::rkyv::Deserialize<#world, __D> for ::genesis::rkyv::Archived<#world>
+        where
+            ::genesis::rkyv::Archived<::genesis::Entities>: ::genesis::rkyv::Deserialize<::genesis::Entities, __D>,
+            #(::genesis::rkyv::Archived<#storage_types>: ::genesis::rkyv::Deserialize<#storage_types, __D>,)*
+        {
+            fn deserialize(&self, deserializer: &mut __D) -> ::std::result::Result<#world, __D::Error> {
+                let entities = ::genesis::rkyv::Deserialize::<::genesis::Entities, __D>::deserialize(
+                    &self.entities,
+                    deserializer,
+                )?;
+                let entities = ::std::sync::Arc::new(::std::sync::RwLock::new(entities));
+                #(
+                    let mut #names = ::genesis::rkyv::Deserialize::<#storage_types, __D>::deserialize(
+                        &self.#names,
+                        deserializer,
+                    )?;
+                    #names.attach_entities(::std::sync::Arc::clone(&entities));
+                )*
+                Ok(#world {
+                    entities,
+                    #(#names,)*
+                })
+            }
+        }
+    }
+}
+
+fn generate_size_checks(input: &Input) -> TokenStream {
+    let options = &input.options;
+    let checks = input
+        .components
+        .iter()
+        .filter(|c| !c.allow_large && !matches!(c.storage_type, ComponentStorageType::Map))
+        .map(|c| {
+            let ty = &c.component_type;
+            let name = ty.to_token_stream().to_string();
+            let error = options.max_component_size.map(|max| {
+                let message = format!(
+                    "component `{}` in `{}` is larger than `max_component_size = {}`; store it in a MapStorage or a Box, or pass `#[component(allow_large)]`",
+                    name,
+                    c.field_name,
+                    max
+                );
+                quote_spanned! {ty.span()=>
+                    const _: () = assert!(::std::mem::size_of::<#ty>() <= #max, #message);
+                }
+            });
+            let warning = options.warn_component_size.map(|max| {
+                quote_spanned! {ty.span()=>
+                    const _: () = ::genesis::SizeCheck::<{ ::std::mem::size_of::<#ty>() > #max }>::check();
+                }
+            });
+            quote! {
+                #error
+                #warning
+            }
+        });
+
+    quote! {
+        #(#checks)*
+    }
+}
+
+fn generate_change_cursor_fns(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let start_calls = input
+        .components
+        .iter()
+        .filter(|c| c.storage_type.tracks_ticks())
+        .map(|c| {
+            let name = &c.field_name;
+            let ty = &c.component_type;
+            quote! {
+                cursor.start_at::<#ty>(::genesis::TrackChanges::<#ty>::tick(&self.#name));
+            }
+        });
+
+    quote! {
+        /// Create a cursor for one consumer of the changes of this World that starts at the current tick of
+        /// every storage tracking ticks; see `genesis::ChangeCursor`.
+        #[allow(unused_mut)]
+        #vis fn change_cursor(&self) -> ::genesis::ChangeCursor {
+            let mut cursor = ::genesis::ChangeCursor::new();
+            #(#start_calls)*
+            cursor
+        }
+
+        /// List the changes of the components of type T since the last check of the given cursor,
+        /// and advance the cursor; see `genesis::ChangeCursor::changes()`.
+        #vis fn changes_since_cursor<T: 'static>(
+            &self,
+            cursor: &mut ::genesis::ChangeCursor,
+        ) -> ::std::vec::Vec<(::genesis::Entity, ::std::option::Option<&T>)>
+        where
+            Self: ::genesis::HasComponent<T>,
+            <Self as ::genesis::HasComponent<T>>::Storage: ::genesis::TrackChanges<T>,
+        {
+            cursor.changes(::genesis::HasComponent::<T>::storage(self))
+        }
+    }
+}
+
+fn generate_report_leaks_fn(input: &Input) -> TokenStream {
+    if !input.options.track_leaks {
+        return quote! {};
+    }
+
+    let vis = &input.vis;
+
+    quote! {
+        /// List the alive entities spawned at least `min_age` frames ago, grouped by where they were spawned.
+        /// See `Entities::report_leaks()`.
+        #vis fn report_leaks(&self, min_age: u32) -> ::std::vec::Vec<::genesis::LeakReport> {
+            self.entities.read().unwrap().report_leaks(min_age)
+        }
+    }
+}
+
+fn generate_despawn_events_fn(input: &Input) -> TokenStream {
+    if !input.options.despawn_events {
+        return quote! {};
+    }
+
+    let vis = &input.vis;
+
+    quote! {
+        /// Take the entities despawned since the last call, in despawn order, e.g. to invalidate
+        /// external caches. See `Entities::drain_despawned()`.
+        #vis fn despawn_events(&self) -> ::std::vec::Vec<::genesis::Entity> {
+            self.entities.write().unwrap().drain_despawned()
+        }
+    }
+}
+
+fn generate_kind_fns(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let kind_enum = component::kind_enum_name(input);
+    let component_count = input.components.len();
+    let names = input
+        .components
+        .iter()
+        .map(|c| c.component_type.to_token_stream().to_string());
+
+    let filters = input.components.iter().map(|c| {
+        let variant = &c.variant;
+        let name = &c.field_name;
+        quote! {
+            #kind_enum::#variant => entities
+                .iter()
+                .filter(|entity| self.#name.get_unchecked(*entity).is_some())
+                .collect(),
+        }
+    });
+
+    quote! {
+        /// The number of component types of the World.
+        #vis const COMPONENT_COUNT: usize = #component_count;
+
+        /// The names of the component types of the World, in the order of the World definition; the name
+        /// of a kind is at its `index()`.
+        #vis const COMPONENT_NAMES: [&'static str; #component_count] = [#(#names),*];
+
+        /// Get all alive entities with a component of the given kind.
+        #[allow(unused_variables)]
+        #vis fn entities_with_kind(&self, kind: #kind_enum) -> ::std::vec::Vec<::genesis::Entity> {
+            let entities = self.entities.read().unwrap();
+            match kind {
+                #(#filters)*
+            }
+        }
+
+        /// Count the alive entities with a component of the given kind.
+        #vis fn count_kind(&self, kind: #kind_enum) -> usize {
+            self.entities_with_kind(kind).len()
+        }
+    }
+}
+
+fn generate_changes_fns(input: &Input) -> TokenStream {
+    if !input.derives("Clone") || !input.derives("Serialize") || !input.derives("Deserialize") {
+        return quote! {};
+    }
+
+    let vis = &input.vis;
+    let component_enum = &input.component_enum_name;
+    let kind_enum = component::kind_enum_name(input);
+    let despawn_removals =
+        generate_despawn_removals(input, quote! { entities }, |name| quote! { self.#name });
+
+    let collect_calls = input.components.iter().map(|c| {
+        let name = &c.field_name;
+        let variant = &c.variant;
+        if !c.storage_type.tracks_ticks() {
+            return quote! {
+                for entity in alive.iter().copied() {
+                    if let Some(component) = self.#name.get_unchecked(entity) {
+                        set.push((entity, #component_enum::#variant(::std::clone::Clone::clone(component))));
+                    }
+                }
+            };
+        }
+        quote! {
+            for (entity, component) in self.#name.changes_since(since) {
+                match component {
+                    Some(component) => {
+                        set.push((entity, #component_enum::#variant(::std::clone::Clone::clone(component))))
+                    }
+                    None => removed.push((entity, #kind_enum::#variant)),
+                }
+            }
+        }
+    });
+    let clear_untracked_calls = input
+        .components
+        .iter()
+        .filter(|c| !c.storage_type.tracks_ticks())
+        .map(|c| {
+            let name = &c.field_name;
+            quote! {
+                self.#name.clear_checked();
+            }
+        });
+    let set_arms = input.components.iter().map(|c| {
+        let name = &c.field_name;
+        let variant = &c.variant;
+        quote! {
+            #component_enum::#variant(component) => {
+                self.#name.set(entity, component)?;
+            }
+        }
+    });
+    let remove_arms = input.components.iter().map(|c| {
+        let name = &c.field_name;
+        let variant = &c.variant;
+        quote! {
+            #kind_enum::#variant => {
+                self.#name.remove(entity)?;
+            }
+        }
+    });
+
+    quote! {
+        /// The changes of this World after the given tick, e.g. for autosaves; see `genesis::ChangeSet`.
+        /// Components are cloned into the change set.
+        #[allow(unused_mut)]
+        #vis fn changes_since(&self, since: u32) -> ::genesis::ChangeSet<#component_enum, #kind_enum> {
+            let alive = self.entities.read().unwrap().iter().collect::<::std::vec::Vec<_>>();
+            let mut set = ::std::vec::Vec::new();
+            let mut removed = ::std::vec::Vec::new();
+            #(#collect_calls)*
+            ::genesis::ChangeSet { since, alive, set, removed }
+        }
+
+        /// Serialize the changes of this World after the given tick with the given serde serializer;
+        /// see `changes_since()`.
+        #vis fn serialize_changes_since<S: ::genesis::serde::Serializer>(&self, since: u32, serializer: S)
+            -> ::std::result::Result<S::Ok, S::Error> {
+            ::genesis::serde::Serialize::serialize(&self.changes_since(since), serializer)
+        }
+
+        /// Apply the changes of another World created by `changes_since()`, e.g. to resync a co-op session.
+        /// The alive entities of this World are made to match the other World exactly, with despawned
+        /// entities losing their components like in `despawn()`; afterwards, the changed components are
+        /// set and removed. Components of `DoubleBuffered` storages become visible after the next `flip()`.
+        #vis fn apply_changes(&mut self, changes: ::genesis::ChangeSet<#component_enum, #kind_enum>)
+            -> ::std::result::Result<(), ::genesis::StorageError> {
+            {
+                let mut entities = self.entities.write().unwrap();
+                for entity in entities.mirror_alive(&changes.alive) {
+                    #despawn_removals
+                }
+            }
+            #(#clear_untracked_calls)*
+            for (entity, component) in changes.set {
+                match component {
+                    #(#set_arms)*
+                }
+            }
+            for (entity, kind) in changes.removed {
+                match kind {
+                    #(#remove_arms)*
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// The name of the generated trait for visiting the components of an entity, e.g. `MyWorldVisitor`.
+fn visitor_name(input: &Input) -> Ident {
+    format_ident!("{}Visitor", input.world_name)
+}
+
+fn visit_fn_name(component: &WorldComponent) -> Ident {
+    format_ident!("visit_{}", snake_case(&component.variant.to_string()))
+}
+
+fn generate_visitor_definition(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let world = &input.world_name;
+    let visitor = visitor_name(input);
+
+    let visit_fns = input.components.iter().map(|c| {
+        let ty = &c.component_type;
+        let visit = visit_fn_name(c);
+        let doc = format!(
+            "Visit the `{}` of the entity, if it has one.",
+            ty.to_token_stream()
+        );
+        quote! {
+            #[doc = #doc]
+            #[allow(unused_variables)]
+            fn #visit(&mut self, entity: ::genesis::Entity, component: &#ty) {}
+        }
+    });
+
+    let doc = format!(
+        "Visitor for the components of an entity of `{}`; see `{}::visit_entity()`.",
+        world, world
+    );
+
+    quote! {
+        #[doc = #doc]
+        /// Every method does nothing by default, so visitors only implement the components they care about.
+        #vis trait #visitor {
+            #(#visit_fns)*
+        }
+    }
+}
+
+fn generate_visit_entity_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let visitor = visitor_name(input);
+
+    let visit_calls = input.components.iter().map(|c| {
+        let name = &c.field_name;
+        let visit = visit_fn_name(c);
+        quote! {
+            if let Some(component) = self.#name.get_unchecked(entity) {
+                visitor.#visit(entity, component);
+            }
+        }
+    });
+
+    quote! {
+        /// Call the method of `visitor` for every component of the given entity, in declaration order,
+        /// without cloning the components or collecting them into the component enum.
+        /// Returns Err(NoSuchEntity) if the given entity doesn't exist.
+        #vis fn visit_entity(
+            &self,
+            entity: ::genesis::Entity,
+            visitor: &mut impl #visitor,
+        ) -> ::std::result::Result<(), ::genesis::NoSuchEntity> {
+            if !self.entities.read().unwrap().exists(entity) {
+                return Err(::genesis::NoSuchEntity::new(entity).with_operation("visit_entity"));
+            }
+            #(#visit_calls)*
+            Ok(())
+        }
+    }
+}
+
+fn generate_profile_scope_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let world_name = input.world_name.to_string();
+
+    quote! {
+        /// Count the storage accesses of the current thread until the returned scope is dropped or
+        /// finished, e.g. during one frame; see `genesis::ProfileScope`.
+        /// Accesses are only counted with the `profile` feature of genesis.
+        #vis fn profile_scope(&self) -> ::genesis::ProfileScope {
+            ::genesis::ProfileScope::new(#world_name)
+        }
+    }
+}
+
+fn generate_allocation_report_fn(input: &Input) -> TokenStream {
+    let vis = &input.vis;
+    let component_types = input.components.iter().map(|c| &c.component_type);
+
+    quote! {
+        /// The allocations performed by the storages of the current thread since the previous call, by
+        /// component type, e.g. slot growth or hash map rehashes; call it once per frame to find the
+        /// operations causing frame hitches. See `genesis::AllocationReport`.
+        /// Allocations are only recorded with the `track_allocations` feature of genesis; storages of other
+        /// Worlds with the same component types on the same thread are counted as well.
+        #vis fn allocation_report(&self) -> ::genesis::AllocationReport {
+            ::genesis::AllocationReport::take(&[#(::std::any::type_name::<#component_types>()),*])
+        }
+    }
+}
+
+fn generate_iter_all_fn(input: &Input) -> TokenStream {
+    if !input.derives("Clone") {
+        return quote! {};
+    }
+
+    let vis = &input.vis;
+    let component_enum = &input.component_enum_name;
+    let component_count = input.components.len();
+
+    let push_calls = input.components.iter().map(|c| {
+        let name = &c.field_name;
+        let variant = &c.variant;
+        quote! {
+            if let Some(component) = self.#name.get_unchecked(entity) {
+                components.push(#component_enum::#variant(::std::clone::Clone::clone(component)));
+            }
+        }
+    });
+
+    quote! {
+        /// Iterate over all alive entities together with clones of all their components, converted
+        /// into the component enum, e.g. for exporters that don't know the concrete component types.
+        /// The alive entities are collected when this is called.
+        #[allow(unused_mut)]
+        #vis fn iter_all(&self) -> impl ::std::iter::Iterator<Item = (::genesis::Entity, ::std::vec::Vec<#component_enum>)> + '_ {
+            let entities = self.entities.read().unwrap().iter_snapshot();
+            entities.map(move |entity| {
+                let mut components = ::std::vec::Vec::with_capacity(#component_count);
+                #(#push_calls)*
+                (entity, components)
+            })
+        }
     }
 }
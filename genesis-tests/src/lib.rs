@@ -1,6 +1,7 @@
 use genesis::*;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq, Inspect)]
 pub struct Position {
     pub position: (u32, u32),
 }
@@ -15,19 +16,557 @@ pub struct RareComponent {
     pub data: u32,
 }
 
-#[world(MyComponent, MyEntityTemplate)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Target {
+    pub entity: Entity,
+}
+
+impl From<NameComponent> for String {
+    fn from(name: NameComponent) -> Self {
+        name.name
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, FromQuery)]
+pub struct RenderItem {
+    #[query(entity)]
+    pub entity: Entity,
+    pub position: Position,
+    #[query(from = NameComponent)]
+    pub name: String,
+    pub data: Option<RareComponent>,
+}
+
+impl MapEntities for Target {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity) {
+        self.entity.map_entities(mapper);
+    }
+}
+
+#[world(MyComponent, MyEntityTemplate, RenderTemplate(position, name))]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct World {
     #[template_name(position)]
     positions: VecStorage<Position>,
     #[template_name(name)]
     names: VecStorage<NameComponent>,
+    #[component(id = 7)]
     rare_data: MapStorage<RareComponent>,
+    #[component(maps_entities)]
+    targets: MapStorage<Target>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Inspect)]
+pub struct Velocity(pub i32, pub i32);
+
+#[world(InspectComponent, InspectTemplate, inspect)]
+pub struct InspectWorld {
+    positions: VecStorage<Position>,
+    velocities: MapStorage<Velocity>,
+}
+
+#[world(LifoComponent, LifoTemplate, reuse_policy = ReusePolicy::Lifo)]
+pub struct LifoWorld {
+    positions: VecStorage<Position>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Health(pub u32);
+
+#[world(
+    LockedComponent,
+    LockedTemplate,
+    locked = LockedWorld,
+    shared = SharedWorld,
+    track_leaks
+)]
+pub struct ParallelWorld {
+    #[component(auto_default)]
+    healths: VecStorage<Health>,
+    positions: VecStorage<Position>,
+    names: VecStorage<NameComponent>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Mesh {
+    pub vertices: Vec<(i32, i32)>,
+}
+
+pub type SharedMesh = Shared<Mesh>;
+
+#[world(MeshComponent, MeshTemplate)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MeshWorld {
+    meshes: VecStorage<SharedMesh>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Lightmap(pub [u8; 1024]);
+
+pub type BoxedLightmap = Box<Lightmap>;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightProbe {
+    pub coefficients: [f32; 27],
+}
+
+#[world(
+    LevelComponent,
+    LevelTemplate,
+    max_component_size = 64,
+    warn_component_size = 32
+)]
+pub struct LevelWorld {
+    positions: VecStorage<Position>,
+    lightmaps: VecStorage<BoxedLightmap>,
+    baked_lightmaps: MapStorage<Lightmap>,
+    #[component(allow_large)]
+    probes: VecStorage<LightProbe>,
+}
+
+component_set! {
+    pub EngineComponents {
+        #[template_name(position)]
+        positions: VecStorage<Position>,
+        #[component(maps_entities)]
+        targets: MapStorage<Target>,
+    }
+}
+
+component_set! {
+    GameplayComponents {
+        #[component(auto_default)]
+        healths: VecStorage<Health>,
+    }
+}
+
+#[world(GameComponent, GameTemplate, GameRenderTemplate(position))]
+#[include_components(EngineComponents)]
+#[include_components(GameplayComponents)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GameWorld {
+    names: VecStorage<NameComponent>,
+}
+
+#[world(DependentComponent, DependentTemplate, MotionTemplate(velocities))]
+pub struct DependentWorld {
+    positions: VecStorage<Position>,
+    #[component(requires(positions))]
+    velocities: MapStorage<Velocity>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cell {
+    pub alive: bool,
+}
+
+#[world(AutomatonComponent, AutomatonTemplate)]
+pub struct AutomatonWorld {
+    cells: DoubleBuffered<Cell>,
+    positions: VecStorage<Position>,
+}
+
+impl IndexKey for NameComponent {
+    type Key = String;
+
+    fn index_key(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[world(DirectoryComponent, DirectoryTemplate)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DirectoryWorld {
+    #[template_name(name)]
+    names: IndexedStorage<NameComponent>,
+    positions: VecStorage<Position>,
+}
+
+#[world(
+    PlayerComponent,
+    PlayerTemplate,
+    uuids,
+    despawn_events,
+    locked = LockedPlayerWorld
+)]
+#[derive(Clone)]
+pub struct PlayerWorld {
+    names: MapStorage<NameComponent>,
+    positions: VecStorage<Position>,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Vertex {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+unsafe impl MmapComponent for Vertex {}
+
+#[world(TerrainComponent, TerrainTemplate)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct TerrainWorld {
+    vertices: MmapStorage<Vertex>,
+    positions: VecStorage<Position>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Owner(pub Entity);
+
+impl Relation for Owner {
+    fn target(&self) -> Entity {
+        self.0
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GpuHandle(pub u32);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Inventory(pub Vec<u32>);
+
+thread_local! {
+    static RELEASED_HANDLES: std::cell::RefCell<Vec<u32>> = const { std::cell::RefCell::new(vec![]) };
+}
+
+fn release_handle(_entity: Entity, handle: GpuHandle) {
+    RELEASED_HANDLES.with(|released| released.borrow_mut().push(handle.0));
+}
+
+#[world(CascadeComponent, CascadeTemplate, locked = LockedCascadeWorld)]
+pub struct CascadeWorld {
+    owners: MapStorage<Owner>,
+    #[component(on_despawn = release_handle)]
+    handles: VecStorage<GpuHandle>,
+    #[component(transfer_to = owners)]
+    inventories: MapStorage<Inventory>,
+}
+
+thread_local! {
+    static DROPPED_COMPONENTS: std::cell::RefCell<Vec<&'static str>> = const { std::cell::RefCell::new(vec![]) };
+}
+
+#[derive(Debug)]
+pub struct Transform;
+
+impl Drop for Transform {
+    fn drop(&mut self) {
+        DROPPED_COMPONENTS.with(|dropped| dropped.borrow_mut().push("transform"));
+    }
+}
+
+#[derive(Debug)]
+pub struct RigidBody;
+
+impl Drop for RigidBody {
+    fn drop(&mut self) {
+        DROPPED_COMPONENTS.with(|dropped| dropped.borrow_mut().push("rigid body"));
+    }
+}
+
+#[world(PhysicsComponent, PhysicsTemplate, access)]
+pub struct PhysicsWorld {
+    transforms: VecStorage<Transform>,
+    #[component(cleanup_order = -1)]
+    bodies: MapStorage<RigidBody>,
+}
+
+/// Body data whose address is handed to a C physics library.
+#[repr(C)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BodyData {
+    pub mass: f32,
+    pub velocity: [f32; 3],
+}
+
+#[world(SimulationComponent, SimulationTemplate)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulationWorld {
+    positions: VecStorage<Position>,
+    #[component(stable)]
+    bodies: VecStorage<BodyData>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Buff(pub u32);
+
+/// Per-entity behavior of a scripting layer, stored as a trait object.
+pub trait Behavior {
+    fn act(&mut self) -> u32;
+}
+
+pub struct Counter(pub u32);
+
+impl Behavior for Counter {
+    fn act(&mut self) -> u32 {
+        self.0 += 1;
+        self.0
+    }
+}
+
+#[world(ScriptComponent, ScriptTemplate)]
+pub struct ScriptWorld {
+    positions: VecStorage<Position>,
+    #[component(variant = Behavior)]
+    behaviors: VecStorage<Box<dyn Behavior>>,
+}
+
+#[world(ServerComponent, ServerTemplate)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerWorld {
+    positions: VecStorage<Position>,
+    buffs: DynamicStorage<Buff>,
+}
+
+#[derive(
+    Clone,
+    Debug,
+    Eq,
+    PartialEq,
+    genesis::rkyv::Archive,
+    genesis::rkyv::Serialize,
+    genesis::rkyv::Deserialize,
+)]
+#[archive(crate = "genesis::rkyv", check_bytes)]
+pub struct Hitpoints(pub u32);
+
+#[derive(
+    Clone,
+    Debug,
+    Eq,
+    PartialEq,
+    genesis::rkyv::Archive,
+    genesis::rkyv::Serialize,
+    genesis::rkyv::Deserialize,
+)]
+#[archive(crate = "genesis::rkyv", check_bytes)]
+pub struct Nameplate(pub String);
+
+#[world(VaultComponent, VaultTemplate, rkyv)]
+#[derive(Debug)]
+pub struct VaultWorld {
+    hitpoints: VecStorage<Hitpoints>,
+    nameplates: MapStorage<Nameplate>,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Item(pub u32);
+
+#[world(LoadoutComponent, LoadoutTemplate, ron, ffi)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LoadoutWorld {
+    #[component(variant = Equipped)]
+    equipped: VecStorage<Item>,
+    #[component(variant = Stored)]
+    stored: MapStorage<Item>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Stat(pub u32);
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Faction {
+    Neutral,
+    Hostile { aggression: u8 },
+}
+
+#[world(FixtureComponent, FixtureTemplate, ron)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FixtureWorld {
+    stats: VecStorage<Stat>,
+    factions: MapStorage<Faction>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Tag(pub String);
+
+/// A runtime resource that can't be serialized; stands in for e.g. a GPU texture handle.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Texture {
+    pub path: String,
+    pub handle: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TexturePath(String);
+
+impl Surrogate<Texture> for TexturePath {
+    fn from_component(texture: &Texture) -> Self {
+        TexturePath(texture.path.clone())
+    }
+
+    fn into_component(self) -> Texture {
+        Texture {
+            handle: self.0.len() as u32,
+            path: self.0,
+        }
+    }
+}
+
+#[world(SaveComponent, SaveTemplate, json, unknown_data)]
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SaveWorld {
+    stats: VecStorage<Stat>,
+    tags: MapStorage<Tag>,
+    #[component(serialize_with = TexturePath)]
+    textures: MapStorage<Texture>,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Point {
+    pub x: i32,
+    pub y: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Speed(pub i32);
+
+#[world(FfiComponent, FfiTemplate, ffi)]
+pub struct FfiWorld {
+    points: VecStorage<Point>,
+    #[component(requires(points))]
+    speeds: MapStorage<Speed>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Initiative(pub u32);
+
+#[world(TurnComponent, TurnTemplate, spawn_order, reuse_policy = ReusePolicy::Lifo)]
+pub struct TurnWorld {
+    initiatives: VecStorage<Initiative>,
+}
+
+// The field names match the argument and local of the generated registration functions.
+#[world(LedgerComponent, LedgerTemplate)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LedgerWorld {
+    stats: VecStorage<Stat>,
+    template: MapStorage<Initiative>,
+    previous: VecStorage<Item>,
+}
+
+pub mod bank {
+    use genesis::*;
+
+    #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+    pub struct Gold(pub u32);
+
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub struct Owner(pub String);
+
+    /// A World whose gold can only be changed through `deposit()` and `withdraw()`.
+    #[world(BankComponent, BankTemplate, locked = LockedBankWorld)]
+    pub struct BankWorld {
+        #[component(vis = pub(self))]
+        gold: VecStorage<Gold>,
+        owners: MapStorage<Owner>,
+    }
+
+    impl BankWorld {
+        pub fn gold(&self, entity: Entity) -> Option<Gold> {
+            self.gold.get(entity).copied()
+        }
+
+        pub fn deposit(&mut self, entity: Entity, amount: u32) -> Result<(), StorageError> {
+            let balance = self.gold(entity).unwrap_or_default();
+            self.gold.set(entity, Gold(balance.0 + amount))?;
+            Ok(())
+        }
+
+        pub fn withdraw(&mut self, entity: Entity, amount: u32) -> Option<u32> {
+            let gold = self.gold.get_mut(entity)?;
+            gold.0 = gold.0.checked_sub(amount)?;
+            Some(gold.0)
+        }
+    }
+}
+
+#[cfg(feature = "schemars")]
+pub mod schema {
+    use genesis::schemars::JsonSchema;
+    use genesis::*;
+
+    #[derive(Clone, Debug, Eq, PartialEq, JsonSchema)]
+    #[schemars(crate = "genesis::schemars")]
+    pub struct Sprite {
+        pub path: String,
+    }
+
+    #[derive(Clone, Debug, Eq, PartialEq, JsonSchema)]
+    #[schemars(crate = "genesis::schemars")]
+    pub struct Owner(pub Entity);
+
+    #[world(SchemaComponent, SchemaTemplate, schema)]
+    pub struct SchemaWorld {
+        #[template_name(sprite)]
+        sprites: VecStorage<Sprite>,
+        owners: MapStorage<Owner>,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_template_schema() {
+            let schema = SchemaWorld::template_schema();
+            let properties = &schema.schema.object.as_ref().unwrap().properties;
+            assert!(properties.contains_key("sprite"));
+            assert!(properties.contains_key("owners"));
+            assert!(schema.definitions.contains_key("Sprite"));
+            assert!(schema.definitions.contains_key("Entity"));
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+pub mod wasm {
+    use genesis::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+    pub struct Label(pub String);
+
+    #[world(BrowserComponent, BrowserTemplate, wasm = JsBrowserWorld)]
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct BrowserWorld {
+        labels: VecStorage<Label>,
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use genesis::wasm::*;
+
+        #[test]
+        fn test_wasm_world() {
+            let mut js_world = JsBrowserWorld::new(3);
+            let entity = js_world.spawn();
+            assert!(js_world.exists(entity));
+            assert!(!js_world.exists(-1.0));
+
+            let native = entity_from_js(entity).unwrap();
+            js_world
+                .world_mut()
+                .labels
+                .set(native, Label("player".into()))
+                .unwrap();
+            assert!(js_world.despawn(entity).is_ok());
+            assert!(!js_world.exists(entity));
+            assert!(js_world.into_inner().labels.get(native).is_none());
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
 
     #[test]
     fn component_bundle() {
@@ -40,7 +579,28 @@ mod tests {
     }
 
     #[test]
-    fn use_world() -> Result<(), NoSuchEntity> {
+    fn component_conversions() {
+        use std::convert::TryFrom;
+
+        let component = MyComponent::from(Position { position: (1, 2) });
+        assert_eq!(
+            Position::try_from(component.clone()),
+            Ok(Position { position: (1, 2) })
+        );
+        assert_eq!(RareComponent::try_from(component.clone()), Err(component));
+
+        let template = MyEntityTemplate::from(RareComponent { data: 3 });
+        assert_eq!(
+            template,
+            MyEntityTemplate {
+                rare_data: Some(RareComponent { data: 3 }),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn use_world() -> Result<(), StorageError> {
         let mut world = World::new(3);
         let entity_a = world.spawn();
         world.register(entity_a, Position { position: (1, 2) })?;
@@ -97,7 +657,7 @@ mod tests {
     }
 
     #[test]
-    fn test_template() -> Result<(), NoSuchEntity> {
+    fn test_template() -> Result<(), StorageError> {
         let mut world = World::new(3);
         let id = world.spawn();
 
@@ -116,6 +676,7 @@ mod tests {
                 position: Some(Position { position: (10, 20) }),
                 name: None,
                 rare_data: Some(RareComponent { data: 42 }),
+                targets: None,
             }
         );
 
@@ -138,4 +699,2934 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_fork() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entity_a = world.spawn();
+        world.register(entity_a, Position { position: (1, 2) })?;
+        world.register(entity_a, RareComponent { data: 17 })?;
+
+        let mut fork = world.fork();
+        assert_eq!(
+            fork.positions.get(entity_a),
+            Some(&Position { position: (1, 2) })
+        );
+        assert_eq!(
+            fork.rare_data.get(entity_a),
+            Some(&RareComponent { data: 17 })
+        );
+
+        fork.register(entity_a, Position { position: (3, 4) })?;
+        fork.despawn(entity_a)?;
+        let entity_b = fork.spawn();
+
+        assert!(world.entities.read().unwrap().exists(entity_a));
+        assert!(!world.entities.read().unwrap().exists(entity_b));
+        assert_eq!(
+            world.positions.get(entity_a),
+            Some(&Position { position: (1, 2) })
+        );
+        assert_eq!(
+            world.rare_data.get(entity_a),
+            Some(&RareComponent { data: 17 })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_integrity() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entity = world.spawn();
+        world.register(entity, RareComponent { data: 1 })?;
+        assert_eq!(world.check(), Ok(()));
+
+        let other_world = World::new(3);
+        world.names = other_world.names;
+        assert_eq!(
+            world.check(),
+            Err(IntegrityError::ForeignEntities { storage: "names" })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_query() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entity_a = world.spawn();
+        world.register(entity_a, Position { position: (1, 2) })?;
+        world.register(
+            entity_a,
+            NameComponent {
+                name: String::from("A"),
+            },
+        )?;
+        let entity_b = world.spawn();
+        world.register(entity_b, Position { position: (3, 4) })?;
+        let entity_c = world.spawn();
+        world.register(entity_c, RareComponent { data: 1 })?;
+
+        let mut visited = vec![];
+        query!(world, |entity,
+                       position: &mut Position,
+                       name: Option<&NameComponent>| {
+            position.position.0 += 10;
+            visited.push((entity, name.map(|n| n.name.clone())));
+        });
+
+        assert_eq!(
+            visited,
+            vec![(entity_a, Some(String::from("A"))), (entity_b, None)]
+        );
+        assert_eq!(
+            world.positions.get(entity_a),
+            Some(&Position { position: (11, 2) })
+        );
+        assert_eq!(
+            world.positions.get(entity_b),
+            Some(&Position { position: (13, 4) })
+        );
+
+        let mut count = 0;
+        query!(world, |_entity, _rare: &RareComponent| count += 1);
+        assert_eq!(count, 1);
+
+        // The MapStorage lists its only component, so it drives the query instead of all 3 entities.
+        let stats = query_explain!(world, |_entity, _rare: &RareComponent| {});
+        assert_eq!(stats.driver, "RareComponent");
+        assert_eq!(stats.visited, 1);
+        assert_eq!(stats.matched, 1);
+        assert_eq!(stats.lock_acquisitions, 2);
+
+        let stats = query_explain!(world, |_entity, _position: &Position| {});
+        assert_eq!(stats.driver, "entities");
+        assert_eq!(stats.visited, 3);
+        assert_eq!(stats.matched, 2);
+        assert_eq!(stats.lock_acquisitions, 1);
+
+        let stats = query_explain!(
+            world,
+            |_entity, _position: &Position, _rare: Option<&RareComponent>| {}
+        );
+        assert_eq!(stats.driver, "entities");
+
+        let stats = query_explain!(
+            world,
+            |_entity, _position: &Position, _rare: &RareComponent| {}
+        );
+        assert_eq!(stats.driver, "RareComponent");
+        assert_eq!(stats.visited, 1);
+        assert_eq!(stats.matched, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_into() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entity_a = world.spawn();
+        let entity_b = world.spawn();
+        world.register(entity_a, Position { position: (1, 2) })?;
+        world.register(entity_b, Position { position: (3, 4) })?;
+
+        let mut buffer = Vec::with_capacity(2);
+        let count = query_into!(world, &mut buffer, |entity, position: &Position| {
+            (entity, position.position)
+        });
+        assert_eq!(count, 2);
+        assert_eq!(buffer, vec![(entity_a, (1, 2)), (entity_b, (3, 4))]);
+
+        world.despawn(entity_a)?;
+        let capacity = buffer.capacity();
+        let count = query_into!(world, &mut buffer, |entity, position: &Position| {
+            (entity, position.position)
+        });
+        assert_eq!(count, 1);
+        assert_eq!(buffer, vec![(entity_b, (3, 4))]);
+        assert_eq!(buffer.capacity(), capacity);
+        Ok(())
+    }
+
+    #[test]
+    fn test_preallocate_for() -> Result<(), StorageError> {
+        let mut world = AutomatonWorld::new(2);
+        world.spawn();
+        world.preallocate_for(100);
+        let index_end = world.entities.read().unwrap().index_end();
+        assert_eq!(index_end, 101);
+        assert_eq!(world.positions.slots(), 101);
+
+        for _ in 0..100 {
+            let entity = world.spawn();
+            world.positions.set(entity, Position { position: (1, 1) })?;
+            world.cells.set(entity, Cell { alive: true })?;
+        }
+        let entities = world.entities.read().unwrap();
+        assert_eq!(entities.index_end(), 101);
+        assert_eq!(entities.high_water_mark(), 101);
+        assert_eq!(entities.free_slots().count(), 0);
+        assert_eq!(world.positions.slots(), 101);
+        Ok(())
+    }
+
+    #[test]
+    fn test_component_size_limits() -> Result<(), StorageError> {
+        let sizes = LevelWorld::COMPONENT_LAYOUTS
+            .iter()
+            .map(|layout| layout.size)
+            .collect::<Vec<_>>();
+        assert!(sizes[1] <= 32 && sizes[2] > 64 && sizes[3] > 64);
+
+        let mut world = LevelWorld::new(2);
+        let entity = world.spawn();
+        world.lightmaps.set(entity, Box::new(Lightmap([7; 1024])))?;
+        world.probes.set(
+            entity,
+            LightProbe {
+                coefficients: [0.5; 27],
+            },
+        )?;
+        assert_eq!(world.lightmaps.get(entity).map(|l| l.0[0]), Some(7));
+        Ok(())
+    }
+
+    #[test]
+    fn test_spawn_scene() -> Result<(), StorageError> {
+        let mut world = World::new(4);
+        let outside = world.spawn();
+        let mut scene = SceneTemplate::new();
+        let house = scene.add(MyEntityTemplate {
+            name: Some(NameComponent {
+                name: "house".to_string(),
+            }),
+            targets: Some(Target { entity: outside }),
+            ..Default::default()
+        });
+        scene.add(MyEntityTemplate {
+            position: Some(Position { position: (1, 2) }),
+            targets: Some(Target { entity: house }),
+            ..Default::default()
+        });
+
+        let spawned = world.spawn_scene(scene)?;
+        assert_eq!(spawned.len(), 2);
+        assert_eq!(
+            world.targets.get(spawned[0]),
+            Some(&Target { entity: outside })
+        );
+        assert_eq!(
+            world.targets.get(spawned[1]),
+            Some(&Target { entity: spawned[0] })
+        );
+
+        let mut dependent = DependentWorld::new(4);
+        let scene = vec![
+            MotionTemplate::default(),
+            MotionTemplate {
+                velocities: Some(Velocity(1, 0)),
+            },
+        ]
+        .into_iter()
+        .collect::<SceneTemplate<_>>();
+        assert!(matches!(
+            dependent.spawn_scene(scene),
+            Err(RegisterError::MissingRequirement { .. })
+        ));
+        assert_eq!(dependent.entities.read().unwrap().len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_stable_storage() -> Result<(), StorageError> {
+        let mut world = SimulationWorld::new(2);
+        let body = |mass| BodyData {
+            mass,
+            velocity: [0.0; 3],
+        };
+        let first = world.spawn();
+        world.bodies.set(first, body(1.0))?;
+        let pointer = world.bodies.get_ptr(first).unwrap();
+
+        let others = (0..600).map(|_| world.spawn()).collect::<Vec<_>>();
+        for entity in &others {
+            world.register(*entity, body(2.0))?;
+        }
+        world.despawn(others[0])?;
+        query!(world, |_entity, body: &mut BodyData| body.velocity[0] +=
+            1.0);
+        assert_eq!(world.bodies.get_ptr(first), Some(pointer));
+        assert_eq!(unsafe { pointer.as_ref() }.velocity, [1.0, 0.0, 0.0]);
+
+        let mut fork = world.fork();
+        assert_eq!(fork, world);
+        assert_ne!(world.bodies.get_ptr(first), fork.bodies.get_ptr(first));
+        assert_eq!(
+            SimulationWorld::COMPONENT_LAYOUTS[1].storage,
+            StorageKind::Stable
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_storage() -> Result<(), StorageError> {
+        let mut world = ServerWorld::new(4);
+        let entities = (0..8).map(|_| world.spawn()).collect::<Vec<_>>();
+        for (i, entity) in entities.iter().enumerate().step_by(2) {
+            world.register(*entity, Buff(i as u32))?;
+        }
+        world.despawn(entities[2])?;
+        let before = world.fork();
+
+        world.migrate_storage::<Buff>(StorageKind::Map).unwrap();
+        assert_eq!(world.buffs.kind(), StorageKind::Map);
+        assert_eq!(world, before);
+        let mut buffs = vec![];
+        query!(world, |entity, buff: &Buff| buffs.push((entity, buff.0)));
+        assert_eq!(
+            buffs,
+            vec![(entities[0], 0), (entities[4], 4), (entities[6], 6)]
+        );
+
+        let spawned = world.spawn();
+        world.register(spawned, Buff(9))?;
+        world.migrate_storage::<Buff>(StorageKind::Vec).unwrap();
+        assert_eq!(world.buffs.get(spawned), Some(&Buff(9)));
+        assert_eq!(world.buffs.get(entities[4]), Some(&Buff(4)));
+        assert!(world.migrate_storage::<Buff>(StorageKind::Mmap).is_err());
+        assert_eq!(
+            ServerWorld::COMPONENT_LAYOUTS[1].storage,
+            StorageKind::Dynamic
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_archive_world() -> Result<(), StorageError> {
+        use genesis::rkyv::Deserialize;
+
+        let mut world = VaultWorld::new(4);
+        let first = world.spawn();
+        let second = world.spawn();
+        let despawned = world.spawn();
+        world.hitpoints.set(first, Hitpoints(10))?;
+        world.hitpoints.set(second, Hitpoints(20))?;
+        world
+            .nameplates
+            .set(second, Nameplate("second".to_string()))?;
+        world.hitpoints.set(despawned, Hitpoints(30))?;
+        world.despawn(despawned)?;
+
+        let bytes = genesis::rkyv::to_bytes::<_, 256>(&world).unwrap();
+        let archived = genesis::rkyv::check_archived_root::<VaultWorld>(&bytes).unwrap();
+        let mut restored: VaultWorld = archived
+            .deserialize(&mut genesis::rkyv::Infallible)
+            .unwrap();
+
+        assert!(restored.entities.read().unwrap().exists(first));
+        assert!(restored.entities.read().unwrap().exists(second));
+        assert!(!restored.entities.read().unwrap().exists(despawned));
+        assert_eq!(restored.hitpoints.get(first), Some(&Hitpoints(10)));
+        assert_eq!(restored.hitpoints.get(second), Some(&Hitpoints(20)));
+        assert_eq!(restored.hitpoints.get(despawned), None);
+        assert_eq!(restored.nameplates.get(first), None);
+        assert_eq!(
+            restored.nameplates.get(second),
+            Some(&Nameplate("second".to_string()))
+        );
+
+        // The storages share the restored entities, so a respawn is seen by all of them.
+        let respawned = restored.spawn();
+        assert_eq!(respawned.index, despawned.index);
+        restored.hitpoints.set(respawned, Hitpoints(40))?;
+        assert_eq!(restored.hitpoints.get(respawned), Some(&Hitpoints(40)));
+        restored.despawn(first)?;
+        assert_eq!(restored.hitpoints.get(first), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_move_between() -> Result<(), StorageError> {
+        let mut world = LoadoutWorld::new(4);
+        let entity = world.spawn();
+        world.register(entity, Item(1))?;
+        assert_eq!(world.equipped.get(entity), Some(&Item(1)));
+
+        world
+            .move_between(
+                entity,
+                LoadoutComponentKind::Equipped,
+                LoadoutComponentKind::Stored,
+            )
+            .unwrap();
+        assert_eq!(world.equipped.get(entity), None);
+        assert_eq!(world.stored.get(entity), Some(&Item(1)));
+        assert_eq!(
+            world.move_between(
+                entity,
+                LoadoutComponentKind::Equipped,
+                LoadoutComponentKind::Stored
+            ),
+            Err(MoveError::MissingComponent {
+                entity,
+                storage: "equipped"
+            })
+        );
+
+        world.equipped.set(entity, Item(2))?;
+        assert_eq!(
+            world.move_between(
+                entity,
+                LoadoutComponentKind::Stored,
+                LoadoutComponentKind::Equipped
+            ),
+            Err(MoveError::Occupied {
+                entity,
+                storage: "equipped"
+            })
+        );
+        assert_eq!(world.stored.get(entity), Some(&Item(1)));
+        assert_eq!(
+            world.move_between(
+                entity,
+                LoadoutComponentKind::Stored,
+                LoadoutComponentKind::Stored
+            ),
+            Err(MoveError::IncompatibleStorages {
+                from: "stored",
+                to: "stored"
+            })
+        );
+        assert_ne!(
+            LoadoutComponentKind::Equipped.id(),
+            LoadoutComponentKind::Stored.id()
+        );
+
+        world.despawn(entity)?;
+        assert!(matches!(
+            world.move_between(
+                entity,
+                LoadoutComponentKind::Stored,
+                LoadoutComponentKind::Equipped
+            ),
+            Err(MoveError::NoSuchEntity(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_non_primary_variant() -> Result<(), Box<dyn std::error::Error>> {
+        let mut world = LoadoutWorld::new(4);
+        let entity = world.spawn();
+        assert_eq!(
+            world.register(entity, LoadoutComponent::Stored(Item(7)))?,
+            None
+        );
+        assert_eq!(world.equipped.get(entity), None);
+        assert_eq!(world.stored.get(entity), Some(&Item(7)));
+        assert_eq!(
+            world.register(entity, LoadoutComponent::Stored(Item(8)))?,
+            Some(LoadoutComponent::Stored(Item(7)))
+        );
+
+        let all = world.iter_all().collect::<Vec<_>>();
+        assert_eq!(all, vec![(entity, vec![LoadoutComponent::Stored(Item(8))])]);
+
+        let changes = world.changes_since(0);
+        assert_eq!(
+            changes.set,
+            vec![(entity, LoadoutComponent::Stored(Item(8)))]
+        );
+        let mut synced = LoadoutWorld::new(4);
+        synced.apply_changes(changes)?;
+        assert_eq!(synced.equipped.get(entity), None);
+        assert_eq!(synced.stored.get(entity), Some(&Item(8)));
+
+        let loaded = LoadoutWorld::from_ron_str(&world.to_ron_string()?)?;
+        assert_eq!(loaded.equipped.get(entity), None);
+        assert_eq!(loaded.stored.get(entity), Some(&Item(8)));
+
+        unsafe {
+            let item = Item(9);
+            let item_ptr = &item as *const Item as *const std::os::raw::c_void;
+            assert_eq!(
+                loadout_world_set(&mut world, entity.to_bits(), 1, item_ptr),
+                genesis::ffi::FfiStatus::Ok
+            );
+        }
+        assert_eq!(world.equipped.get(entity), None);
+        assert_eq!(world.stored.get(entity), Some(&Item(9)));
+        Ok(())
+    }
+
+    /// Records the structural change events and query spans of genesis as `kind name field=value ...`.
+    #[derive(Clone, Default)]
+    struct CapturedTraces(Arc<std::sync::Mutex<Vec<String>>>);
+
+    #[derive(Default)]
+    struct TraceFields(String);
+
+    impl tracing::field::Visit for TraceFields {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.push_str(&format!(" {}={}", field.name(), value));
+        }
+    }
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for CapturedTraces {
+        fn on_new_span(
+            &self,
+            attributes: &tracing::span::Attributes<'_>,
+            _id: &tracing::span::Id,
+            _context: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if attributes.metadata().target() == "genesis::structure" {
+                let mut fields = TraceFields::default();
+                attributes.record(&mut fields);
+                let name = attributes.metadata().name();
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("span {}{}", name, fields.0));
+            }
+        }
+
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            _context: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            if event.metadata().target() == "genesis::structure" {
+                let mut fields = TraceFields::default();
+                event.record(&mut fields);
+                self.0.lock().unwrap().push(format!("event{}", fields.0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_tracing() -> Result<(), StorageError> {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let traces = CapturedTraces::default();
+        let subscriber = tracing_subscriber::registry().with(traces.clone());
+        tracing::subscriber::with_default(subscriber, || -> Result<(), StorageError> {
+            let mut world = World::new(2);
+            let entity = world.spawn();
+            world.register(entity, RareComponent { data: 1 })?;
+            query!(world, |_entity, _rare: &RareComponent| {});
+            world.despawn(entity)?;
+            Ok(())
+        })?;
+
+        assert_eq!(
+            *traces.0.lock().unwrap(),
+            vec![
+                "event entity=0:0 operation=spawn".to_string(),
+                "event entity=0:0 component=genesis_tests::RareComponent operation=set".to_string(),
+                "span query components=RareComponent".to_string(),
+                "event entity=0:0 operation=despawn".to_string(),
+                "event entity=0:0 component=genesis_tests::RareComponent operation=remove"
+                    .to_string(),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_thread_checks() -> Result<(), StorageError> {
+        let mut world = ServerWorld::new(4);
+        let entity = world.spawn();
+        world.register(entity, Buff(1))?;
+        world.migrate_storage::<Buff>(StorageKind::Map).unwrap();
+
+        let (mut world, result) = std::thread::spawn(move || {
+            let mut world = world;
+            assert_eq!(world.buffs.get(entity), Some(&Buff(1)));
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                world.buffs.set(entity, Buff(2)).unwrap();
+            }));
+            (world, result)
+        })
+        .join()
+        .unwrap();
+        assert!(result.is_err());
+        assert_eq!(world.buffs.get(entity), Some(&Buff(1)));
+
+        world.bind_to_thread(None);
+        let world = std::thread::spawn(move || {
+            let mut world = world;
+            world
+                .register(entity, Position { position: (1, 2) })
+                .unwrap();
+            world.buffs.set(entity, Buff(2)).unwrap();
+            world
+        })
+        .join()
+        .unwrap();
+        assert_eq!(world.buffs.get(entity), Some(&Buff(2)));
+        assert_eq!(
+            world.positions.get(entity),
+            Some(&Position { position: (1, 2) })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_thread_checks_entities() -> Result<(), NoSuchEntity> {
+        let world = ServerWorld::new(4);
+        let spawned = std::thread::spawn(move || {
+            let mut world = world;
+            world.spawn()
+        })
+        .join();
+        let message = spawned.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.starts_with("`spawn` on the entities from thread"));
+
+        let mut world = ServerWorld::new(4);
+        let entity = world.spawn();
+        let despawned = std::thread::spawn(move || {
+            let mut world = world;
+            world.despawn(entity)
+        })
+        .join();
+        let message = despawned.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.starts_with("`despawn` on the entities from thread"));
+
+        let mut world = ServerWorld::new(4);
+        world.bind_to_thread(None);
+        let mut world = std::thread::spawn(move || -> Result<_, NoSuchEntity> {
+            let mut world = world;
+            let entity = world.spawn();
+            world.despawn(entity)?;
+            world.spawn();
+            world.clear();
+            Ok(world)
+        })
+        .join()
+        .unwrap()?;
+        assert!(world.entities.read().unwrap().is_empty());
+        world.spawn();
+        Ok(())
+    }
+
+    #[test]
+    fn test_trait_object_components() -> Result<(), RegisterError> {
+        let mut world = ScriptWorld::new(4);
+        let entity = world.spawn();
+        world.register(entity, Box::new(Counter(1)) as Box<dyn Behavior>)?;
+        let other = world.spawn_from_templates(vec![ScriptTemplate {
+            positions: Some(Position { position: (1, 1) }),
+            behaviors: Some(Box::new(Counter(10))),
+        }])[0];
+
+        let mut results = vec![];
+        query!(world, |entity, behavior: &mut Box<dyn Behavior>| {
+            results.push((entity, behavior.act()))
+        });
+        assert_eq!(results, vec![(entity, 2), (other, 11)]);
+
+        let component = ScriptComponent::from(Box::new(Counter(5)) as Box<dyn Behavior>);
+        assert_eq!(component.kind(), ScriptComponentKind::Behavior);
+        assert_eq!(ScriptComponentKind::Behavior.name(), "Box < dyn Behavior >");
+        world.register(entity, component)?;
+        assert_eq!(world.behaviors.get_mut(entity).unwrap().act(), 6);
+        assert_eq!(
+            world
+                .entities_with_kind(ScriptComponentKind::Behavior)
+                .len(),
+            2
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_order_with_map_storage() -> Result<(), StorageError> {
+        let mut world = World::new(32);
+        let entities = (0..32).map(|_| world.spawn()).collect::<Vec<_>>();
+        for (i, entity) in entities.iter().enumerate().rev() {
+            world
+                .positions
+                .set(*entity, Position { position: (0, 0) })?;
+            world
+                .rare_data
+                .set(*entity, RareComponent { data: i as u32 })?;
+        }
+
+        let mut visited = vec![];
+        query_into!(
+            world,
+            &mut visited,
+            |entity, _position: &Position, rare: &RareComponent| { (entity, rare.data) }
+        );
+        let expected = (0..32).map(|i| (entities[i], i as u32)).collect::<Vec<_>>();
+        assert_eq!(visited, expected);
+        let ordered = world
+            .rare_data
+            .ordered()
+            .map(|(entity, rare)| (entity, rare.data))
+            .collect::<Vec<_>>();
+        assert_eq!(ordered, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_component_visibility() -> Result<(), StorageError> {
+        use crate::bank::*;
+
+        let mut world = BankWorld::new(2);
+        let entity = world.spawn();
+        world.owners.set(entity, Owner(String::from("Alice")))?;
+        world.deposit(entity, 10)?;
+        assert_eq!(world.withdraw(entity, 4), Some(6));
+        assert_eq!(world.withdraw(entity, 7), None);
+        assert_eq!(world.gold(entity), Some(Gold(6)));
+        let storages = world.storages_mut();
+        assert!(storages.owners.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_spawn_order() -> Result<(), StorageError> {
+        let mut world = TurnWorld::new(4);
+        let entities = (0..3).map(|_| world.spawn()).collect::<Vec<_>>();
+        for (i, entity) in entities.iter().enumerate() {
+            world.initiatives.set(*entity, Initiative(i as u32))?;
+        }
+        world.despawn(entities[0])?;
+        let late = world.spawn();
+        assert_eq!(late.index, entities[0].index);
+        world.initiatives.set(late, Initiative(3))?;
+
+        let mut turns = vec![];
+        query!(world, |_entity, initiative: &Initiative| turns
+            .push(initiative.0));
+        assert_eq!(turns, vec![1, 2, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_component_ref() -> Result<(), StorageError> {
+        struct Blackboard {
+            target_position: ComponentRef<Position>,
+        }
+
+        let mut world = World::new(2);
+        let target = world.spawn();
+        world.register(target, Position { position: (4, 2) })?;
+        let blackboard = Blackboard {
+            target_position: ComponentRef::new(target),
+        };
+        assert_eq!(
+            blackboard.target_position.resolve(&world),
+            Some(&Position { position: (4, 2) })
+        );
+        blackboard
+            .target_position
+            .resolve_mut(&mut world)
+            .unwrap()
+            .position
+            .0 += 1;
+        assert_eq!(
+            world.positions.get(target),
+            Some(&Position { position: (5, 2) })
+        );
+
+        let json = serde_json::to_string(&blackboard.target_position).unwrap();
+        assert_eq!(json, serde_json::to_string(&target).unwrap());
+        let deserialized: ComponentRef<Position> = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, blackboard.target_position);
+
+        world.despawn(target)?;
+        let reused = world.spawn();
+        world.register(reused, Position { position: (0, 0) })?;
+        assert_eq!(reused.index, target.index);
+        assert_eq!(blackboard.target_position.resolve(&world), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_library() -> Result<(), TemplateError> {
+        let mut library = TemplateLibrary::new();
+        library.insert(
+            "goblin",
+            MyEntityTemplate {
+                position: Some(Position { position: (1, 1) }),
+                name: Some(NameComponent {
+                    name: String::from("Goblin"),
+                }),
+                ..Default::default()
+            },
+        );
+        library.insert_derived(
+            "elite_goblin",
+            "goblin",
+            MyEntityTemplate {
+                name: Some(NameComponent {
+                    name: String::from("Elite Goblin"),
+                }),
+                rare_data: Some(RareComponent { data: 3 }),
+                ..Default::default()
+            },
+        );
+
+        let mut world = World::new(2);
+        let elite = world.spawn_from_library(&library, "elite_goblin")?;
+        assert_eq!(
+            world.positions.get(elite),
+            Some(&Position { position: (1, 1) })
+        );
+        assert_eq!(world.names.get(elite).unwrap().name, "Elite Goblin");
+        assert_eq!(world.rare_data.get(elite), Some(&RareComponent { data: 3 }));
+
+        assert_eq!(
+            world.spawn_from_library(&library, "troll"),
+            Err(TemplateError::UnknownTemplate(String::from("troll")))
+        );
+        assert_eq!(world.entities.read().unwrap().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_presence_bits() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entities = (0..4).map(|_| world.spawn()).collect::<Vec<_>>();
+        for entity in &entities[..3] {
+            world.register(*entity, Position { position: (0, 0) })?;
+        }
+        world.register(entities[1], RareComponent { data: 1 })?;
+        world.register(entities[3], RareComponent { data: 2 })?;
+        world.despawn(entities[0])?;
+
+        let mut positions = BitSet::default();
+        world.positions.presence_into(&mut positions);
+        assert_eq!(positions.iter().collect::<Vec<_>>(), vec![1, 2]);
+
+        let mut rare = BitSet::default();
+        world.rare_data.presence_into(&mut rare);
+        positions.intersect_with(rare.view());
+        assert_eq!(positions.iter().collect::<Vec<_>>(), vec![1]);
+
+        let mut without_position = world.entities.read().unwrap().alive_bits().to_bit_set();
+        world.positions.presence_into(&mut positions);
+        without_position.difference_with(positions.view());
+        assert_eq!(without_position.iter().collect::<Vec<_>>(), vec![3]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reuse_policy() -> Result<(), StorageError> {
+        let mut world = LifoWorld::new(3);
+        assert_eq!(world.entities.read().unwrap().policy(), ReusePolicy::Lifo);
+        let first = world.spawn();
+        let second = world.spawn();
+        world.despawn(first)?;
+        world.despawn(second)?;
+        assert_eq!(world.spawn().index, second.index);
+        Ok(())
+    }
+
+    #[test]
+    fn test_map_entities() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entity_a = world.spawn();
+        let entity_b = world.spawn();
+        world.register(entity_a, Target { entity: entity_b })?;
+
+        world.map_entities(&mut |entity| Entity {
+            index: entity.index + 1,
+            ..entity
+        });
+        assert_eq!(
+            world.targets.get(entity_a),
+            Some(&Target {
+                entity: Entity {
+                    index: entity_b.index + 1,
+                    generation: entity_b.generation
+                }
+            })
+        );
+
+        let mut template = MyEntityTemplate {
+            targets: Some(Target { entity: entity_a }),
+            ..Default::default()
+        };
+        template.map_entities(&mut |_| entity_b);
+        assert_eq!(template.targets, Some(Target { entity: entity_b }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_locked_world() -> Result<(), StorageError> {
+        let world = LockedWorld::new(3);
+        let entity = world.spawn();
+        world
+            .positions
+            .write()
+            .unwrap()
+            .set(entity, Position { position: (1, 2) })?;
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let positions = world.positions.read().unwrap();
+                assert_eq!(positions.get(entity), Some(&Position { position: (1, 2) }));
+            });
+            scope.spawn(|| {
+                let mut names = world.names.write().unwrap();
+                names
+                    .set(
+                        entity,
+                        NameComponent {
+                            name: String::from("A"),
+                        },
+                    )
+                    .unwrap();
+            });
+        });
+
+        let mut world = world.into_inner();
+        assert_eq!(
+            world.names.get(entity),
+            Some(&NameComponent {
+                name: String::from("A")
+            })
+        );
+
+        world.despawn(entity)?;
+        let world = LockedWorld::from(world);
+        assert_eq!(world.positions.read().unwrap().get(entity), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_iter_with() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entity_a = world.spawn();
+        world.register(entity_a, Position { position: (1, 2) })?;
+        let entity_b = world.spawn();
+        world.register(entity_b, Position { position: (3, 4) })?;
+
+        world.iter_with(|entity, position: &mut Position, commands| {
+            position.position.0 += 1;
+            if position.position.0 > 3 {
+                commands.despawn(entity);
+            } else {
+                commands.register(
+                    entity,
+                    NameComponent {
+                        name: String::from("survivor"),
+                    },
+                );
+            }
+        });
+
+        assert!(!world.entities.read().unwrap().exists(entity_b));
+        assert_eq!(
+            world.positions.get(entity_a),
+            Some(&Position { position: (2, 2) })
+        );
+        assert_eq!(
+            world.names.get(entity_a),
+            Some(&NameComponent {
+                name: String::from("survivor")
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_inspect() -> Result<(), StorageError> {
+        let infos = InspectWorld::component_infos();
+        assert_eq!(infos[0].name, "Position");
+        assert_eq!(infos[0].storage_name, "positions");
+        assert_eq!(infos[0].field_names, &["position"]);
+        assert_eq!(infos[1].field_names, &["0", "1"]);
+
+        let mut world = InspectWorld::new(3);
+        let entity = world.spawn();
+        world.register(entity, Velocity(1, -1))?;
+
+        let mut fields = vec![];
+        world.inspect(
+            entity,
+            &mut |info: &ComponentInfo, component: &dyn Inspect| {
+                component.inspect_fields(&mut |field, value| {
+                    fields.push(format!("{}.{} = {:?}", info.name, field, value));
+                });
+            },
+        )?;
+        assert_eq!(fields, vec!["Velocity.0 = 1", "Velocity.1 = -1"]);
+
+        world.despawn(entity)?;
+        assert!(world
+            .inspect(entity, &mut |_: &ComponentInfo, _: &dyn Inspect| {})
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_default() {
+        let mut world = ParallelWorld::new(3);
+        let entity = world.spawn();
+        assert_eq!(world.healths.get(entity), Some(&Health(0)));
+        assert_eq!(world.positions.get(entity), None);
+
+        let world = LockedWorld::from(world);
+        let entity = world.spawn();
+        assert_eq!(world.healths.read().unwrap().get(entity), Some(&Health(0)));
+    }
+
+    #[test]
+    fn test_spawn_if_absent() -> Result<(), StorageError> {
+        let mut world = ParallelWorld::new(3);
+        let (entity, spawned) = world.spawn_if_absent(42);
+        assert!(spawned);
+        world.healths.set(entity, Health(5))?;
+
+        // A replayed spawn message keeps the entity and its components.
+        assert_eq!(world.spawn_if_absent(42), (entity, false));
+        assert_eq!(world.healths.get(entity), Some(&Health(5)));
+        assert_eq!(world.entities.read().unwrap().len(), 1);
+
+        world.despawn(entity)?;
+        let (respawned, spawned) = world.spawn_if_absent(42);
+        assert!(spawned);
+        assert_eq!(world.healths.get(respawned), Some(&Health(0)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_incremental_clear() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entities = (0..4)
+            .map(|i| {
+                let entity = world.spawn();
+                world.register(entity, RareComponent { data: i })?;
+                Ok(entity)
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+
+        world.begin_clear();
+        let progress = world.clear_budget(2);
+        assert!(!progress.is_done());
+        assert_eq!(world.rare_data.get(entities[0]), None);
+        assert_eq!(
+            world.rare_data.get(entities[3]),
+            Some(&RareComponent { data: 3 })
+        );
+
+        assert!(world.clear_budget(2).is_done());
+        assert_eq!(world.entities.read().unwrap().iter().count(), 0);
+        assert!(world.rare_data.values().next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_incremental_clear_keeps_uuids_of_alive_entities() -> Result<(), UuidError> {
+        const ALICE: u128 = 1;
+        const BOB: u128 = 2;
+
+        let mut world = PlayerWorld::new(2);
+        let alice = world.spawn_with_uuid(ALICE)?;
+        let bob = world.spawn_with_uuid(BOB)?;
+
+        world.begin_clear();
+        assert_eq!(world.entity_by_uuid(BOB), Some(bob));
+        assert!(!world.clear_budget(1).is_done());
+        assert_eq!(world.entity_by_uuid(ALICE), None);
+        assert_eq!(world.entity_by_uuid(BOB), Some(bob));
+        assert!(world.entities.read().unwrap().exists(bob));
+        assert!(!world.entities.read().unwrap().exists(alice));
+
+        assert!(world.clear_budget(1).is_done());
+        assert_eq!(world.entity_by_uuid(BOB), None);
+        assert_eq!(world.uuids.len(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_layout() {
+        let layouts = World::COMPONENT_LAYOUTS;
+        assert_eq!(layouts.len(), 4);
+        assert_eq!(layouts[0].name, "Position");
+        assert_eq!(layouts[0].storage, StorageKind::Vec);
+        assert_eq!(layouts[0].size, std::mem::size_of::<Position>());
+        assert_eq!(layouts[2].storage, StorageKind::Map);
+
+        let report = World::layout_report();
+        assert!(report.lines().any(|line| line.starts_with("RareComponent")));
+        assert_eq!(report.lines().count(), 5);
+    }
+
+    #[test]
+    fn test_fragmentation_report() -> Result<(), StorageError> {
+        let mut world = World::new(4);
+        let entities = (0..8).map(|_| world.spawn()).collect::<Vec<_>>();
+        for &entity in &entities {
+            world.register(entity, Position { position: (0, 0) })?;
+        }
+        world.register(
+            entities[7],
+            NameComponent {
+                name: String::from("Boss"),
+            },
+        )?;
+
+        let report = world.fragmentation_report();
+        assert_eq!(report.storages.len(), 2);
+        assert_eq!(report.storages[0].occupancy(), 1.0);
+        assert_eq!(world.names.occupied(), 1);
+        assert_eq!(world.names.occupancy(), 1.0 / world.names.slots() as f64);
+        let poor = report.poorly_occupied(0.25).collect::<Vec<_>>();
+        assert_eq!(poor.len(), 1);
+        assert_eq!(poor[0].storage, "names");
+        assert!(report
+            .report(0.25)
+            .contains("consider MapStorage<NameComponent>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_snapshot() -> Result<(), StorageError> {
+        let mut world = World::new(4);
+        let entities = (0..4).map(|_| world.spawn()).collect::<Vec<_>>();
+        for &entity in &entities {
+            world.register(entity, Position { position: (0, 0) })?;
+        }
+        let earlier = world.memory_snapshot();
+        assert_eq!(earlier.storages.len(), World::COMPONENT_COUNT);
+        assert_eq!(earlier.storages[0].usage.components, 4);
+
+        for i in 0..32 {
+            let entity = world.spawn();
+            world.register(entity, RareComponent { data: i })?;
+        }
+        world.despawn(entities[0])?;
+        let later = world.memory_snapshot();
+        assert!(later.bytes() > earlier.bytes());
+
+        let diff = earlier.diff(&later);
+        let grown = diff.grown();
+        assert_eq!(grown[0].storage, "rare_data");
+        assert_eq!(grown[0].components, 32);
+        assert!(grown.iter().all(|storage| storage.storage != "names"));
+        assert!(diff
+            .report()
+            .starts_with("rare_data (RareComponent): +32 components"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_from() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let prefab = MyEntityTemplate {
+            name: Some(NameComponent {
+                name: String::from("goblin"),
+            }),
+            ..Default::default()
+        };
+
+        let entities = (0..3).map(|_| world.spawn()).collect::<Vec<_>>();
+        for entity in &entities {
+            let old = world.register_from(*entity, &prefab)?;
+            assert_eq!(old, Some(MyEntityTemplate::default()));
+        }
+        for entity in &entities {
+            assert_eq!(world.names.get(*entity), prefab.name.as_ref());
+        }
+
+        world.despawn(entities[0])?;
+        assert!(world.register_from(entities[0], &prefab).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_context() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entity = world.spawn();
+        world.despawn(entity)?;
+
+        let error = world
+            .register(
+                entity,
+                MyEntityTemplate {
+                    rare_data: Some(RareComponent { data: 1 }),
+                    ..Default::default()
+                },
+            )
+            .unwrap_err();
+        let error = match error {
+            StorageError::NoSuchEntity(error) => error,
+            other => panic!("expected NoSuchEntity, got {}", other),
+        };
+        assert_eq!(error.entity, entity);
+        assert_eq!(error.operation, Some("set"));
+        assert_eq!(error.storage, Some("rare_data"));
+
+        let error = world.despawn(entity).unwrap_err();
+        assert_eq!(error.operation, Some("despawn"));
+        assert_eq!(error.storage, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_world() -> Result<(), StorageError> {
+        let world = SharedWorld::new(3);
+        let entity = world.spawn();
+
+        let handles = (0..4)
+            .map(|i| {
+                let world = world.clone();
+                std::thread::spawn(move || {
+                    world
+                        .register(entity, Position { position: (i, i) })
+                        .unwrap();
+                    world.with(|w| w.positions.get(entity).is_some())
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            assert!(handle.join().unwrap());
+        }
+
+        world.despawn(entity)?;
+        let world = world.try_unwrap().ok().unwrap();
+        assert!(!world.entities.read().unwrap().exists(entity));
+        Ok(())
+    }
+
+    #[test]
+    fn test_component_requirements() -> Result<(), RegisterError> {
+        let mut world = DependentWorld::new(3);
+        let entity = world.spawn();
+
+        let error = world.register(entity, Velocity(1, 1)).unwrap_err();
+        assert_eq!(
+            error,
+            RegisterError::MissingRequirement {
+                entity,
+                component: "Velocity",
+                required: "Position",
+            }
+        );
+        assert_eq!(world.velocities.get(entity), None);
+
+        let template = DependentTemplate {
+            velocities: Some(Velocity(1, 1)),
+            ..Default::default()
+        };
+        assert!(world.register(entity, template).is_err());
+        assert_eq!(world.velocities.get(entity), None);
+
+        world.register(entity, Position { position: (0, 0) })?;
+        world.register(entity, Velocity(1, 1))?;
+        assert_eq!(world.velocities.get(entity), Some(&Velocity(1, 1)));
+
+        let other = world.spawn();
+        world.register(
+            other,
+            DependentTemplate {
+                positions: Some(Position { position: (1, 1) }),
+                velocities: Some(Velocity(2, 2)),
+            },
+        )?;
+        assert_eq!(world.velocities.get(other), Some(&Velocity(2, 2)));
+
+        world.despawn(other)?;
+        assert!(matches!(
+            world.register(other, Velocity(3, 3)),
+            Err(RegisterError::NoSuchEntity(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_clear_component() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entity_a = world.spawn();
+        let entity_b = world.spawn();
+        world.register(entity_a, Position { position: (0, 0) })?;
+        world.register(entity_b, Position { position: (1, 1) })?;
+        world.register(entity_a, RareComponent { data: 1 })?;
+
+        assert_eq!(world.clear_component::<Position>(), 2);
+        assert_eq!(world.positions.get(entity_a), None);
+        assert_eq!(world.positions.get(entity_b), None);
+        assert_eq!(
+            world.rare_data.get(entity_a),
+            Some(&RareComponent { data: 1 })
+        );
+        assert!(world.entities.read().unwrap().exists(entity_b));
+
+        assert_eq!(world.clear_component::<RareComponent>(), 1);
+        assert_eq!(world.clear_component::<RareComponent>(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_templates() -> Result<(), RegisterError> {
+        let mut world = World::new(3);
+        let entity = world.spawn();
+        let template = RenderTemplate {
+            position: Some(Position { position: (1, 2) }),
+            name: None,
+        };
+        let old = world.register(entity, template.clone())?;
+        assert_eq!(old, Some(RenderTemplate::default()));
+        assert_eq!(
+            world.positions.get(entity),
+            Some(&Position { position: (1, 2) })
+        );
+
+        let full = MyEntityTemplate::from(template);
+        assert_eq!(full.position, Some(Position { position: (1, 2) }));
+        assert_eq!(full.rare_data, None);
+
+        let mut world = DependentWorld::new(3);
+        let entity = world.spawn();
+        let motion = || MotionTemplate {
+            velocities: Some(Velocity(1, 1)),
+        };
+        assert!(matches!(
+            world.register(entity, motion()),
+            Err(RegisterError::MissingRequirement { .. })
+        ));
+        world.register(entity, Position { position: (0, 0) })?;
+        world.register(entity, motion())?;
+        assert_eq!(world.velocities.get(entity), Some(&Velocity(1, 1)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_double_buffered() -> Result<(), StorageError> {
+        let mut world = AutomatonWorld::new(3);
+        let entities = (0..3)
+            .map(|i| {
+                let entity = world.spawn();
+                world.register(entity, Cell { alive: i == 1 })?;
+                Ok(entity)
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        assert_eq!(world.cells.get(entities[0]), None);
+        world.flip();
+
+        for _generation in 0..2 {
+            let alive = entities
+                .iter()
+                .map(|&e| world.cells.get(e).unwrap().alive)
+                .collect::<Vec<_>>();
+            for (i, &entity) in entities.iter().enumerate() {
+                let left = i.checked_sub(1).is_some_and(|j| alive[j]);
+                let right = alive.get(i + 1).copied().unwrap_or(false);
+                world.cells.get_mut(entity).unwrap().alive = left != right;
+            }
+            assert_eq!(
+                world.cells.get(entities[1]),
+                Some(&Cell { alive: alive[1] })
+            );
+            world.flip();
+        }
+
+        let alive = entities
+            .iter()
+            .map(|&e| world.cells.get(e).unwrap().alive)
+            .collect::<Vec<_>>();
+        assert_eq!(alive, vec![false, false, false]);
+
+        world.despawn(entities[0])?;
+        assert_eq!(world.cells.get(entities[0]), None);
+        assert_eq!(
+            AutomatonWorld::COMPONENT_LAYOUTS[0].storage,
+            StorageKind::DoubleBuffered
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_spawn_from_templates() -> Result<(), RegisterError> {
+        let mut world = World::new(3);
+        let entities = world.spawn_from_templates(vec![
+            MyEntityTemplate::from(Position { position: (1, 2) }),
+            MyEntityTemplate::default(),
+            MyEntityTemplate::from(RareComponent { data: 3 }),
+        ]);
+        assert_eq!(entities.len(), 3);
+        assert_eq!(
+            world.positions.get(entities[0]),
+            Some(&Position { position: (1, 2) })
+        );
+        assert!(world.entities.read().unwrap().exists(entities[1]));
+        assert_eq!(
+            world.rare_data.get(entities[2]),
+            Some(&RareComponent { data: 3 })
+        );
+
+        let mut world = ParallelWorld::new(3);
+        let entities = world.spawn_from_templates(vec![
+            LockedTemplate::default(),
+            LockedTemplate::from(Health(5)),
+        ]);
+        assert_eq!(world.healths.get(entities[0]), Some(&Health(0)));
+        assert_eq!(world.healths.get(entities[1]), Some(&Health(5)));
+
+        let mut world = DependentWorld::new(3);
+        let spawned = world.spawn_from_templates(vec![DependentTemplate {
+            positions: Some(Position { position: (0, 0) }),
+            velocities: Some(Velocity(1, 1)),
+        }])?;
+        assert_eq!(world.velocities.get(spawned[0]), Some(&Velocity(1, 1)));
+
+        let error = world
+            .spawn_from_templates(vec![DependentTemplate::from(Velocity(1, 1))])
+            .unwrap_err();
+        match error {
+            RegisterError::MissingRequirement { entity, .. } => {
+                assert!(!world.entities.read().unwrap().exists(entity))
+            }
+            _ => panic!("expected a missing requirement"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_report_leaks() -> Result<(), StorageError> {
+        let world = SharedWorld::new(3);
+        let leaked = world.spawn();
+        let line = line!() - 1;
+        let despawned = world.spawn();
+        world.despawn(despawned)?;
+
+        let world = world.try_unwrap().ok().unwrap();
+        let reports = world.report_leaks(0);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].count, 1);
+        match reports[0].origin {
+            SpawnOrigin::Caller(location) => {
+                assert_eq!(location.file(), file!());
+                assert_eq!(location.line(), line);
+            }
+            SpawnOrigin::Label(_) => panic!("expected the spawn call site"),
+        }
+        assert!(world.entities.read().unwrap().exists(leaked));
+        Ok(())
+    }
+
+    #[test]
+    fn test_component_kinds() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entity_a = world.spawn();
+        let entity_b = world.spawn();
+        world.register(entity_a, Position { position: (0, 0) })?;
+        world.register(entity_b, Position { position: (1, 1) })?;
+        world.register(entity_b, RareComponent { data: 1 })?;
+
+        assert_eq!(
+            world.entities_with_kind(MyComponentKind::Position),
+            vec![entity_a, entity_b]
+        );
+        assert_eq!(world.count_kind(MyComponentKind::RareComponent), 1);
+        assert_eq!(world.count_kind(MyComponentKind::Target), 0);
+
+        let component = MyComponent::from(RareComponent { data: 2 });
+        assert_eq!(component.kind(), MyComponentKind::RareComponent);
+        assert_eq!(MyComponentKind::ALL.len(), 4);
+        assert_eq!(MyComponentKind::ALL[1].name(), "NameComponent");
+        Ok(())
+    }
+
+    #[test]
+    fn test_component_count_and_names() {
+        const COUNTS: [usize; World::COMPONENT_COUNT] = [0; World::COMPONENT_COUNT];
+        assert_eq!(COUNTS.len(), 4);
+        assert_eq!(
+            World::COMPONENT_NAMES,
+            ["Position", "NameComponent", "RareComponent", "Target"]
+        );
+        for (index, kind) in MyComponentKind::ALL.iter().enumerate() {
+            assert_eq!(kind.index(), index);
+            assert_eq!(MyComponentKind::from_index(index), Some(*kind));
+            assert_eq!(World::COMPONENT_NAMES[kind.index()], kind.name());
+        }
+        assert_eq!(MyComponentKind::from_index(World::COMPONENT_COUNT), None);
+    }
+
+    #[test]
+    fn test_component_ids() {
+        let fnv1a = |name: &str| {
+            name.bytes().fold(0x811c_9dc5_u32, |hash, byte| {
+                (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+            })
+        };
+        assert_eq!(MyComponentKind::Position.id(), fnv1a("Position"));
+        assert_eq!(MyComponentKind::RareComponent.id(), 7);
+        for kind in MyComponentKind::ALL {
+            assert_eq!(MyComponentKind::from_id(kind.id()), Some(kind));
+        }
+        assert_eq!(MyComponentKind::from_id(8), None);
+    }
+
+    #[test]
+    fn test_iter_all() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entity_a = world.spawn();
+        let entity_b = world.spawn();
+        world.register(entity_b, Position { position: (1, 1) })?;
+        world.register(entity_b, RareComponent { data: 1 })?;
+
+        let all = world.iter_all().collect::<Vec<_>>();
+        assert_eq!(
+            all,
+            vec![
+                (entity_a, vec![]),
+                (
+                    entity_b,
+                    vec![
+                        MyComponent::Position(Position { position: (1, 1) }),
+                        MyComponent::RareComponent(RareComponent { data: 1 }),
+                    ]
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_visit_entity() -> Result<(), StorageError> {
+        #[derive(Default)]
+        struct Summary {
+            position: Option<(u32, u32)>,
+            name_length: usize,
+        }
+
+        impl WorldVisitor for Summary {
+            fn visit_position(&mut self, _entity: Entity, component: &Position) {
+                self.position = Some(component.position);
+            }
+
+            fn visit_name_component(&mut self, _entity: Entity, component: &NameComponent) {
+                self.name_length = component.name.len();
+            }
+        }
+
+        let mut world = World::new(3);
+        let entity = world.spawn();
+        world.register(entity, Position { position: (1, 2) })?;
+        world.register(
+            entity,
+            NameComponent {
+                name: String::from("Bob"),
+            },
+        )?;
+        world.register(entity, RareComponent { data: 1 })?;
+
+        let mut summary = Summary::default();
+        world.visit_entity(entity, &mut summary)?;
+        assert_eq!(summary.position, Some((1, 2)));
+        assert_eq!(summary.name_length, 3);
+
+        world.despawn(entity)?;
+        assert!(world.visit_entity(entity, &mut summary).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_world_registry() -> Result<(), StorageError> {
+        let world = Arc::new(std::sync::RwLock::new(World::new(3)));
+        let entity = {
+            let mut world = world.write().unwrap();
+            let entity = world.spawn();
+            world.register(entity, Position { position: (1, 2) })?;
+            world.register(entity, RareComponent { data: 3 })?;
+            entity
+        };
+        registry().register("registry test", &world);
+
+        let registered = registry()
+            .worlds()
+            .into_iter()
+            .find(|registered| registered.name == "registry test")
+            .unwrap();
+        let debug_world = registered.world.read().unwrap();
+        assert_eq!(debug_world.type_name(), "World");
+        assert_eq!(debug_world.alive_entities(), vec![entity]);
+        assert_eq!(
+            debug_world.debug_components(entity),
+            vec![
+                ("positions", String::from("Position { position: (1, 2) }")),
+                ("rare_data", String::from("RareComponent { data: 3 }")),
+            ]
+        );
+        drop(debug_world);
+        drop(registered);
+
+        drop(world);
+        assert!(registry().get("registry test").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_profile_scope() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entity = world.spawn();
+        let scope = world.profile_scope();
+        world.register(entity, Position { position: (1, 1) })?;
+        query!(world, |_entity, position: &mut Position| position
+            .position
+            .0 += 1);
+        assert_eq!(
+            world.positions.get(entity),
+            Some(&Position { position: (2, 1) })
+        );
+        world.despawn(entity)?;
+
+        let profile = scope.finish();
+        let position = std::any::type_name::<Position>();
+        assert_eq!(profile.scope, "World");
+        assert_eq!(profile.counts[&(position, Access::Set)], 1);
+        assert_eq!(profile.counts[&(position, Access::GetMut)], 1);
+        assert_eq!(profile.counts[&(position, Access::Get)], 1);
+        assert_eq!(profile.total(position), 4);
+        assert!(profile
+            .to_folded()
+            .contains(&format!("World;{};get_mut 1\n", position)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_query() -> Result<(), StorageError> {
+        let mut world = World::new(4);
+        let entities = (0..4).map(|_| world.spawn()).collect::<Vec<_>>();
+        for (i, &entity) in entities.iter().enumerate() {
+            world.register(
+                entity,
+                Position {
+                    position: (i as u32, 0),
+                },
+            )?;
+        }
+        for &entity in &entities[1..] {
+            world.register(
+                entity,
+                NameComponent {
+                    name: format!("unit {}", entity.index),
+                },
+            )?;
+        }
+        world.register(entities[2], RareComponent { data: 7 })?;
+        world.despawn(entities[3])?;
+
+        let items = RenderItem::collect(&world);
+        assert_eq!(
+            items,
+            vec![
+                RenderItem {
+                    entity: entities[1],
+                    position: Position { position: (1, 0) },
+                    name: "unit 1".into(),
+                    data: None,
+                },
+                RenderItem {
+                    entity: entities[2],
+                    position: Position { position: (2, 0) },
+                    name: "unit 2".into(),
+                    data: Some(RareComponent { data: 7 }),
+                },
+            ]
+        );
+
+        let mut buffer = Vec::with_capacity(4);
+        buffer.push(RenderItem::from_entity(&world, entities[1]).unwrap());
+        assert_eq!(RenderItem::collect_into(&world, &mut buffer), 2);
+        assert_eq!(buffer, items);
+        assert_eq!(RenderItem::from_entity(&world, entities[0]), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_combinators() -> Result<(), StorageError> {
+        let mut world = World::new(4);
+        let entities = (0..4).map(|_| world.spawn()).collect::<Vec<_>>();
+        for (i, &entity) in entities.iter().enumerate() {
+            world.register(
+                entity,
+                Position {
+                    position: (i as u32, 0),
+                },
+            )?;
+            world.register(
+                entity,
+                NameComponent {
+                    name: format!("unit {}", i),
+                },
+            )?;
+        }
+        world.register(entities[1], RareComponent { data: 1 })?;
+        world.register(entities[3], RareComponent { data: 3 })?;
+        world.despawn(entities[2])?;
+
+        let rare = world
+            .rare_data
+            .ordered()
+            .map_values(|_, rare| rare.data)
+            .collect::<Vec<_>>();
+        let labels = RenderItem::iter(&world)
+            .zip_entities(rare.clone())
+            .filter_values(|_, (_, data)| *data > 1)
+            .map_values(|_, (item, data)| format!("{} ({})", item.name, data))
+            .chain_entities(RenderItem::iter(&world).map_values(|_, item| item.name))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels,
+            vec![
+                (entities[3], "unit 3 (3)".to_string()),
+                (entities[0], "unit 0".to_string()),
+                (entities[1], "unit 1".to_string()),
+            ]
+        );
+
+        world.despawn(entities[3])?;
+        let entities_lock = world.entities.read().unwrap();
+        assert_eq!(
+            rare.into_iter()
+                .alive(&entities_lock)
+                .entities()
+                .collect::<Vec<_>>(),
+            vec![entities[1]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_allocation_report() -> Result<(), StorageError> {
+        let mut world = World::new(4);
+        let entities = (0..4).map(|_| world.spawn()).collect::<Vec<_>>();
+        for &entity in &entities {
+            world.register(entity, Position { position: (1, 1) })?;
+        }
+        let position = std::any::type_name::<Position>();
+        assert_eq!(world.allocation_report().total(position), 0);
+
+        let entity = world.spawn();
+        world.register(entity, Position { position: (2, 2) })?;
+        for &entity in &entities {
+            world.register(entity, RareComponent { data: 1 })?;
+        }
+        let report = world.allocation_report();
+        assert_eq!(report.counts[&(position, AllocationKind::Growth)].count, 1);
+        assert!(report.bytes(position) > 0);
+        assert!(report.total(std::any::type_name::<RareComponent>()) > 0);
+        assert!(report
+            .to_string()
+            .contains(&format!("{} growth: 1", position)));
+
+        world.register(entity, Position { position: (3, 3) })?;
+        assert!(world.allocation_report().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_and_default_worlds() -> Result<(), StorageError> {
+        let mut world = World::empty();
+        assert_eq!(world, World::default());
+        let entities = (0..3).map(|_| world.spawn()).collect::<Vec<_>>();
+        for (i, &entity) in entities.iter().enumerate() {
+            world.register(entity, RareComponent { data: i as u32 })?;
+            world.register(entity, Position { position: (1, 1) })?;
+        }
+        assert_eq!(
+            world.rare_data.get(entities[2]),
+            Some(&RareComponent { data: 2 })
+        );
+        assert_ne!(world, World::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_console() -> Result<(), StorageError> {
+        use genesis::console::{execute, ConsoleError};
+
+        let mut world = World::new(3);
+        let bob = world.spawn();
+        let alice = world.spawn();
+        world.register(bob, Position { position: (1, 2) })?;
+        world.register(
+            bob,
+            NameComponent {
+                name: String::from("Bob"),
+            },
+        )?;
+        world.register(alice, Position { position: (3, 4) })?;
+
+        assert_eq!(execute(&mut world, "list").unwrap(), "0:0\n1:0\n");
+        assert_eq!(
+            execute(&mut world, "list Position").unwrap(),
+            "0:0 Position { position: (1, 2) }\n1:0 Position { position: (3, 4) }\n"
+        );
+        assert_eq!(
+            execute(&mut world, "get 0:0 name").unwrap(),
+            "NameComponent { name: \"Bob\" }\n"
+        );
+        assert_eq!(
+            execute(&mut world, "get 1:0").unwrap(),
+            "positions: Position { position: (3, 4) }\n"
+        );
+        assert_eq!(
+            execute(&mut world, "get 1:0 names"),
+            Err(ConsoleError::MissingComponent {
+                entity: alice,
+                component: "names"
+            })
+        );
+        assert_eq!(
+            execute(&mut world, "get 1:0 Velocity"),
+            Err(ConsoleError::UnknownComponent(String::from("Velocity")))
+        );
+        assert!(matches!(
+            execute(&mut world, "get bob"),
+            Err(ConsoleError::InvalidEntity(_))
+        ));
+        assert_eq!(
+            execute(&mut world, "spawn"),
+            Err(ConsoleError::UnknownCommand(String::from("spawn")))
+        );
+
+        assert_eq!(
+            execute(&mut world, "despawn 0:0").unwrap(),
+            "despawned 0:0\n"
+        );
+        assert!(!world.entities.read().unwrap().exists(bob));
+        assert!(matches!(
+            execute(&mut world, "despawn 0:0"),
+            Err(ConsoleError::NoSuchEntity(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_generic_systems() -> Result<(), StorageError> {
+        /// Move every named entity one step right; returns the number of moved entities.
+        fn move_named<W: HasComponent<Position> + HasComponent<NameComponent>>(
+            world: &mut W,
+        ) -> usize {
+            let entities = world
+                .entities()
+                .read()
+                .unwrap()
+                .iter_snapshot()
+                .collect::<Vec<_>>();
+            let mut moved = 0;
+            for entity in entities {
+                let named = HasComponent::<NameComponent>::storage(world)
+                    .get(entity)
+                    .is_some();
+                let positions = HasComponent::<Position>::storage_mut(world);
+                if let (true, Some(position)) = (named, positions.get_mut(entity)) {
+                    position.position.0 += 1;
+                    moved += 1;
+                }
+            }
+            moved
+        }
+
+        let name = NameComponent {
+            name: String::from("Bob"),
+        };
+        let mut world = World::new(2);
+        let bob = world.spawn();
+        world.register(bob, name.clone())?;
+        world.register(bob, Position { position: (0, 0) })?;
+        let nameless = world.spawn();
+        world.register(nameless, Position { position: (0, 0) })?;
+        assert_eq!(move_named(&mut world), 1);
+        assert_eq!(
+            world.positions.get(bob),
+            Some(&Position { position: (1, 0) })
+        );
+
+        let mut directory = DirectoryWorld::new(2);
+        let bob = directory.spawn();
+        directory.register(bob, name)?;
+        directory.register(bob, Position { position: (5, 0) })?;
+        assert_eq!(move_named(&mut directory), 1);
+        assert_eq!(
+            directory.positions.get(bob),
+            Some(&Position { position: (6, 0) })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_with_report() -> Result<(), RegisterError> {
+        let mut world = World::new(2);
+        let entity = world.spawn();
+        world.register(entity, Position { position: (1, 1) })?;
+
+        let report = world.register_with_report(
+            entity,
+            RenderTemplate {
+                position: Some(Position { position: (2, 2) }),
+                name: Some(NameComponent {
+                    name: String::from("Bob"),
+                }),
+            },
+        )?;
+        assert_eq!(
+            report,
+            RenderTemplateReport {
+                position: Registered::Replaced(Position { position: (1, 1) }),
+                name: Registered::Added,
+            }
+        );
+        assert!(report.name.is_added());
+
+        let report = world.register_with_report(
+            entity,
+            MyEntityTemplate {
+                rare_data: Some(RareComponent { data: 3 }),
+                ..Default::default()
+            },
+        )?;
+        assert!(report.rare_data.is_added());
+        assert_eq!(report.position, Registered::Skipped);
+        assert_eq!(report.name.replaced(), None);
+
+        let despawned = world.spawn();
+        world.despawn(despawned)?;
+        assert!(world
+            .register_with_report(
+                despawned,
+                RenderTemplate {
+                    position: Some(Position { position: (0, 0) }),
+                    name: None,
+                },
+            )
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_register_with_report_hygiene() -> Result<(), StorageError> {
+        let mut world = LedgerWorld::new(2);
+        let entity = world.spawn();
+        world.register(entity, Initiative(1))?;
+        // Despawn without removing the components, so the next entity reuses an index holding a stale one.
+        world.entities.write().unwrap().despawn(entity)?;
+        let respawned = world.spawn();
+        assert_eq!(respawned.index, entity.index);
+
+        let template = LedgerTemplate {
+            stats: Some(Stat(2)),
+            template: Some(Initiative(3)),
+            previous: None,
+        };
+        let report = world.register_with_report(respawned, template.clone())?;
+        assert_eq!(report.stats, Registered::Added);
+        assert_eq!(report.template, Registered::Added);
+        assert_eq!(report.previous, Registered::Skipped);
+        assert_eq!(world.register_from(respawned, &template)?, Some(template));
+        Ok(())
+    }
+
+    #[test]
+    fn test_uuids() -> Result<(), UuidError> {
+        const ALICE: u128 = 0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8;
+        const BOB: u128 = 0x9a0f_0e2d_5c3e_4b4e_8d8e_1d1f_7a3c_2b10;
+
+        let mut world = PlayerWorld::new(2);
+        let alice = world.spawn_with_uuid(ALICE)?;
+        let bob = world.spawn_with_uuid(BOB)?;
+        assert_eq!(
+            world.spawn_with_uuid(ALICE),
+            Err(UuidError::Taken {
+                uuid: ALICE,
+                entity: alice
+            })
+        );
+        assert_eq!(world.entity_by_uuid(ALICE), Some(alice));
+        assert_eq!(world.uuids.uuid(bob), Some(BOB));
+
+        let fork = world.fork();
+        world.despawn(alice)?;
+        assert_eq!(world.entity_by_uuid(ALICE), None);
+        assert_eq!(world.uuids.len(), 1);
+        assert_eq!(fork.entity_by_uuid(ALICE), Some(alice));
+
+        // Index reuse doesn't resurrect the UUID of the despawned entity.
+        let reused = world.spawn();
+        assert_eq!(reused.index, alice.index);
+        assert_eq!(world.uuids.uuid(reused), None);
+
+        let locked = LockedPlayerWorld::from(world);
+        locked.despawn(bob)?;
+        let mut world = locked.into_inner();
+        assert!(world.uuids.is_empty());
+        let bob = world.spawn_with_uuid(BOB)?;
+        world.clear();
+        assert_eq!(world.entity_by_uuid(BOB), None);
+        assert_eq!(world.uuids.uuid(bob), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_storages() -> Result<(), StorageError> {
+        let mut world = World::new(2);
+        let entity = world.spawn();
+        world.positions.set(entity, Position { position: (0, 0) })?;
+        world.rare_data.set(entity, RareComponent { data: 3 })?;
+
+        let (positions, rare_data, names) =
+            split_storages!(world => positions mut, rare_data, names);
+        for entity in rare_data.keys() {
+            let data = rare_data.get(entity).unwrap().data;
+            if let (Some(position), None) = (positions.get_mut(entity), names.get(entity)) {
+                position.position.0 += data;
+            }
+        }
+        assert_eq!(
+            world.positions.get(entity),
+            Some(&Position { position: (3, 0) })
+        );
+
+        let world_ref = &mut world;
+        let names = split_storages!(world_ref => names mut);
+        names.set(
+            entity,
+            NameComponent {
+                name: String::from("Bob"),
+            },
+        )?;
+        let (positions, names) = split_storages!(world => positions, names);
+        assert_eq!(positions.get(entity).unwrap().position, (3, 0));
+        assert_eq!(names.get(entity).unwrap().name, "Bob");
+        Ok(())
+    }
+
+    #[test]
+    fn test_history() -> Result<(), StorageError> {
+        let mut world = World::new(2);
+        let mut history = History::new(4);
+        let entity = world.spawn();
+        world.positions.set(entity, Position { position: (0, 0) })?;
+        for frame in 1..=5 {
+            history.record(&world);
+            world.positions.get_mut(entity).unwrap().position.0 = frame;
+        }
+        let spawned = world.spawn();
+
+        assert!(history.rewind(&mut world, 3));
+        assert_eq!(
+            world.positions.get(entity),
+            Some(&Position { position: (2, 0) })
+        );
+        assert!(!world.entities.read().unwrap().exists(spawned));
+        assert_eq!(history.len(), 2);
+
+        // The rewound World doesn't share its entities with the snapshots.
+        world.despawn(entity)?;
+        assert!(history.get(1).unwrap().positions.get(entity).is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_world_builder() {
+        use genesis::testing::*;
+
+        let (world, entities) = TestWorldBuilder::new(World::new(2))
+            .entities(2)
+            .entity(5, 3)
+            .with(|i, _| {
+                Some(Position {
+                    position: (i as u32, 0),
+                })
+            })
+            .with(|i, _| {
+                (i == 0).then(|| NameComponent {
+                    name: String::from("first"),
+                })
+            })
+            .build();
+        assert_eq!(
+            entities,
+            vec![
+                Entity {
+                    index: 0,
+                    generation: 0
+                },
+                Entity {
+                    index: 1,
+                    generation: 0
+                },
+                Entity {
+                    index: 5,
+                    generation: 3
+                },
+            ]
+        );
+        assert_eq!(world.entities.read().unwrap().len(), 3);
+        assert_component(&world, entities[2], &Position { position: (2, 0) });
+        assert_has::<NameComponent, _>(&world, entities[0]);
+        assert_lacks::<NameComponent, _>(&world, entities[1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected entity 1:0 to have no genesis_tests::Position")]
+    fn test_assert_lacks_panics() {
+        let (world, entities) = genesis::testing::TestWorldBuilder::new(World::new(2))
+            .entities(2)
+            .with(|_, _| Some(Position { position: (1, 1) }))
+            .build();
+        genesis::testing::assert_lacks::<Position, _>(&world, entities[1]);
+    }
+
+    #[test]
+    fn test_despawn_events() -> Result<(), NoSuchEntity> {
+        let mut world = PlayerWorld::new(4);
+        let first = world.spawn();
+        let second = world.spawn();
+        let third = world.spawn();
+        world.despawn(second)?;
+        assert_eq!(world.despawn_events(), vec![second]);
+        assert!(world.despawn_events().is_empty());
+
+        let kept = world.spawn();
+        world.retain(|entity, _| entity == kept);
+        let mut despawned = world.despawn_events();
+        despawned.sort_by_key(|entity| entity.index);
+        assert_eq!(despawned, vec![first, third]);
+
+        let locked = LockedPlayerWorld::from(world);
+        locked.despawn(kept)?;
+        let world = locked.into_inner();
+        assert_eq!(world.despawn_events(), vec![kept]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lifecycle_phases() -> Result<(), LifecycleError> {
+        let mut world = PlayerWorld::new(4);
+        let player = world.spawn();
+        let staged = world.entities.write().unwrap().spawn_staged();
+        world
+            .positions
+            .set(staged, Position { position: (1, 2) })
+            .unwrap();
+        assert_eq!(
+            world
+                .entities
+                .read()
+                .unwrap()
+                .iter_in(Lifecycle::Alive)
+                .collect::<Vec<_>>(),
+            vec![player]
+        );
+
+        let mut entities = world.entities.write().unwrap();
+        entities.activate(staged)?;
+        entities.mark_pending_despawn(player)?;
+        drop(entities);
+        assert_eq!(world.despawn_pending(), 1);
+        assert!(!world.entities.read().unwrap().exists(player));
+        assert_eq!(world.despawn_events(), vec![player]);
+        assert_eq!(
+            world.positions.get(staged),
+            Some(&Position { position: (1, 2) })
+        );
+        assert_eq!(world.despawn_pending(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_include_components() -> Result<(), RegisterError> {
+        let mut world = GameWorld::new(2);
+        let target = world.spawn();
+        let entity = world.spawn();
+        assert_eq!(world.healths.get(entity), Some(&Health(0)));
+        world.register(
+            entity,
+            GameTemplate {
+                position: Some(Position { position: (1, 2) }),
+                targets: Some(Target { entity: target }),
+                names: Some(NameComponent {
+                    name: String::from("Bob"),
+                }),
+                ..Default::default()
+            },
+        )?;
+        world.register(entity, GameRenderTemplate { position: None })?;
+        assert_eq!(
+            world.positions.get(entity),
+            Some(&Position { position: (1, 2) })
+        );
+        assert_eq!(world.targets.get(entity).unwrap().entity, target);
+        assert_eq!(
+            GameComponentKind::ALL,
+            [
+                GameComponentKind::NameComponent,
+                GameComponentKind::Position,
+                GameComponentKind::Target,
+                GameComponentKind::Health
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_components() -> Result<(), StorageError> {
+        let mesh = Shared::new(Mesh {
+            vertices: vec![(0, 0), (1, 0), (0, 1)],
+        });
+        let mut world = MeshWorld::new(8);
+        let entities = (0..8).map(|_| world.spawn()).collect::<Vec<_>>();
+        for &entity in &entities {
+            world.meshes.set(entity, mesh.clone())?;
+        }
+        assert_eq!(mesh.share_count(), 9);
+
+        let fork = world.fork();
+        assert_eq!(mesh.share_count(), 17);
+
+        world
+            .meshes
+            .get_mut(entities[0])
+            .unwrap()
+            .vertices
+            .push((1, 1));
+        assert_eq!(world.meshes.get(entities[0]).unwrap().vertices.len(), 4);
+        assert!(world.meshes.get(entities[1]).unwrap().ptr_eq(&mesh));
+        assert_eq!(mesh.vertices.len(), 3);
+        assert_eq!(fork.meshes.get(entities[0]), Some(&mesh));
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_tick() -> Result<(), StorageError> {
+        let mut world = AutomatonWorld::new(3);
+        let entity = world.spawn();
+        world.set_tick(4);
+        world.register(entity, Cell { alive: true })?;
+        world.flip();
+        world.set_tick(5);
+        assert_eq!(world.cells.last_modified(entity), Some(4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_change_cursors() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entity = world.spawn();
+        let mut renderer = world.change_cursor();
+        let mut physics = world.change_cursor();
+        world.register(entity, Position { position: (1, 2) })?;
+        world.set_tick(1);
+
+        let moved = world.changes_since_cursor::<Position>(&mut renderer);
+        assert_eq!(moved, vec![(entity, Some(&Position { position: (1, 2) }))]);
+        assert!(world
+            .changes_since_cursor::<Position>(&mut renderer)
+            .is_empty());
+
+        world.positions.get_mut(entity).unwrap().position.0 += 1;
+        world.set_tick(2);
+        assert_eq!(
+            world.changes_since_cursor::<Position>(&mut physics).len(),
+            1
+        );
+        assert_eq!(physics.position::<Position>(), Some(2));
+        assert_eq!(
+            world.changes_since_cursor::<Position>(&mut renderer),
+            vec![(entity, Some(&Position { position: (2, 2) }))]
+        );
+        assert!(world
+            .changes_since_cursor::<NameComponent>(&mut renderer)
+            .is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_world_eq() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entity = world.spawn();
+        world.register(entity, Position { position: (1, 2) })?;
+        let mut expected = world.fork();
+        assert_world_eq!(world, expected);
+
+        expected.register(entity, Position { position: (2, 2) })?;
+        let spawned = expected.spawn();
+        assert_eq!(
+            world.mismatches(&expected),
+            vec![
+                WorldMismatch::OnlyRight(spawned),
+                WorldMismatch::Component {
+                    entity,
+                    storage: "positions",
+                    left: Some(String::from("Position { position: (1, 2) }")),
+                    right: Some(String::from("Position { position: (2, 2) }")),
+                },
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_derived_impls() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entity = world.spawn();
+        world.register(entity, Position { position: (1, 2) })?;
+        let mut other = world.fork();
+        assert_eq!(world, other);
+
+        other.spawn();
+        assert_ne!(world, other);
+        let mut other = world.fork();
+        other.register(entity, RareComponent { data: 1 })?;
+        assert_ne!(world, other);
+
+        let debug = format!("{:?}", world);
+        assert!(debug.starts_with("World { entities: [Entity"));
+        assert!(debug.contains("positions: {Entity { index: 0, generation: 0 }: Position"));
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "entity 0:0 differs in `rare_data`: left none, right RareComponent")]
+    fn test_assert_world_eq_reports_mismatches() {
+        let mut world = World::new(3);
+        let entity = world.spawn();
+        let mut expected = world.fork();
+        expected
+            .register(entity, RareComponent { data: 1 })
+            .unwrap();
+        assert_world_eq!(world, expected);
+    }
+
+    #[test]
+    fn test_mmap_storage() -> Result<(), Box<dyn std::error::Error>> {
+        let path = std::env::temp_dir().join(format!("genesis-terrain-{}", std::process::id()));
+        let vertex = |x: f32| Vertex { x, y: 0.0, z: 1.0 };
+        let mut world = TerrainWorld::new(2);
+        world.vertices = MmapStorage::open(Arc::clone(&world.entities), &path)?;
+        let entities = (0..5)
+            .map(|i| {
+                let entity = world.spawn();
+                world.register(entity, vertex(i as f32))?;
+                Ok(entity)
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        world.register(entities[1], Position { position: (1, 1) })?;
+        world.vertices.get_mut(entities[1]).unwrap().y = 2.0;
+        world.despawn(entities[3])?;
+
+        let mut visited = vec![];
+        query!(world, |entity,
+                       vertex: &Vertex,
+                       position: Option<&Position>| {
+            visited.push((entity, *vertex, position.is_some()));
+        });
+        assert_eq!(visited.len(), 4);
+        assert_eq!(
+            visited[1],
+            (
+                entities[1],
+                Vertex {
+                    x: 1.0,
+                    y: 2.0,
+                    z: 1.0
+                },
+                true
+            )
+        );
+        world.vertices.flush()?;
+
+        let mut reloaded = TerrainWorld::new(5);
+        let respawned = (0..5).map(|_| reloaded.spawn()).collect::<Vec<_>>();
+        reloaded.despawn(respawned[3])?;
+        // The file is locked while it is open.
+        let locked = MmapStorage::<Vertex>::open(Arc::clone(&reloaded.entities), &path);
+        assert_eq!(
+            locked.err().map(|error| error.kind()),
+            Some(std::io::ErrorKind::WouldBlock)
+        );
+        let written = world
+            .vertices
+            .clone_with_entities(Arc::clone(&reloaded.entities));
+        drop(world);
+
+        reloaded.vertices = MmapStorage::open(Arc::clone(&reloaded.entities), &path)?;
+        assert_eq!(reloaded.vertices.get(respawned[4]), Some(&vertex(4.0)));
+        assert_eq!(reloaded.vertices.get(respawned[3]), None);
+        assert_eq!(reloaded.vertices, written);
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_indexed_storage() -> Result<(), StorageError> {
+        let mut world = DirectoryWorld::new(3);
+        let bob = world.spawn();
+        let alice = world.spawn();
+        let name = |name: &str| NameComponent {
+            name: String::from(name),
+        };
+        world.register(bob, name("Bob"))?;
+        world.register(alice, name("Alice"))?;
+        assert_eq!(world.names.find("Bob").collect::<Vec<_>>(), vec![bob]);
+
+        world.names.get_mut(alice).unwrap().name = String::from("Bob");
+        let mut bobs = world.names.find("Bob").collect::<Vec<_>>();
+        bobs.sort_by_key(|entity| entity.index);
+        assert_eq!(bobs, vec![bob, alice]);
+
+        world.despawn(bob)?;
+        assert_eq!(world.names.find("Bob").collect::<Vec<_>>(), vec![alice]);
+        world.clear();
+        assert_eq!(world.names.find("Bob").count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_entity_groups() -> Result<(), NoSuchEntity> {
+        let mut world = World::new(3);
+        let entities = (0..3).map(|_| world.spawn()).collect::<Vec<_>>();
+        let mut squad = world.new_group();
+        for entity in &entities {
+            squad.insert(*entity)?;
+        }
+        world.despawn(entities[1])?;
+        assert_eq!(
+            squad.iter().collect::<Vec<_>>(),
+            vec![entities[0], entities[2]]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_retain() -> Result<(), StorageError> {
+        let mut world = World::new(3);
+        let entities = (0..4)
+            .map(|i| {
+                let entity = world.spawn();
+                world.register(entity, Position { position: (i, 0) })?;
+                Ok(entity)
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+
+        let despawned =
+            world.retain(|entity, world| world.positions.get(entity).unwrap().position.0 % 2 == 0);
+        assert_eq!(despawned, 2);
+        assert_eq!(
+            world.entities.read().unwrap().iter().collect::<Vec<_>>(),
+            vec![entities[0], entities[2]]
+        );
+        assert_eq!(world.positions.get_unchecked(entities[1]), None);
+        assert_eq!(world.check(), Ok(()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_despawn_batch() -> Result<(), StorageError> {
+        let mut world = World::new(4);
+        let entities = (0..6)
+            .map(|i| {
+                let entity = world.spawn();
+                world.register(entity, Position { position: (i, 0) })?;
+                Ok(entity)
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+        world.register(entities[4], RareComponent { data: 4 })?;
+        world.despawn(entities[5])?;
+
+        let batch = vec![
+            entities[4],
+            entities[1],
+            entities[4],
+            entities[5],
+            entities[2],
+        ];
+        assert_eq!(world.despawn_batch(batch), 3);
+        assert_eq!(
+            world.entities.read().unwrap().iter().collect::<Vec<_>>(),
+            vec![entities[0], entities[3]]
+        );
+        assert_eq!(world.positions.get_unchecked(entities[1]), None);
+        assert_eq!(world.rare_data.get_unchecked(entities[4]), None);
+        assert_eq!(world.despawn_batch(std::iter::empty()), 0);
+        assert_eq!(world.check(), Ok(()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_try_spawn() {
+        let mut world = ParallelWorld::new(3);
+        world
+            .entities
+            .write()
+            .unwrap()
+            .set_limit(Some(EntityLimit { warn_at: 1, max: 1 }));
+        let entity = world.try_spawn().unwrap();
+        assert_eq!(world.healths.get(entity), Some(&Health(0)));
+        assert_eq!(world.try_spawn(), Err(EntityLimitReached { limit: 1 }));
+        assert_eq!(world.entities.read().unwrap().watermark(), Watermark::Full);
+    }
+
+    #[test]
+    fn test_shards() -> Result<(), ShardError> {
+        let mut shards = Shards::<World>::new(10, 4);
+        shards.load(0);
+        shards.load(3);
+        let entity = shards.spawn(0)?;
+        let world = shards.world_of_mut(entity).unwrap();
+        world
+            .positions
+            .set(entity, Position { position: (1, 2) })
+            .unwrap();
+        world
+            .rare_data
+            .set(entity, RareComponent { data: 3 })
+            .unwrap();
+
+        let follower = shards.spawn(0)?;
+        let world = shards.get_mut(0).unwrap();
+        world.targets.set(follower, Target { entity }).unwrap();
+
+        let moved = shards.move_entity(entity, 3)?;
+        assert_eq!(moved.index, 30);
+        assert_eq!(
+            shards.get(0).unwrap().targets.get(follower),
+            Some(&Target { entity: moved })
+        );
+        assert_eq!(shards.shard_of(moved), 3);
+        assert!(!shards
+            .get(0)
+            .unwrap()
+            .entities
+            .read()
+            .unwrap()
+            .exists(entity));
+        let world = shards.world_of(moved).unwrap();
+        assert_eq!(
+            world.positions.get(moved),
+            Some(&Position { position: (1, 2) })
+        );
+        assert_eq!(world.rare_data.get(moved), Some(&RareComponent { data: 3 }));
+        assert_eq!(world.check(), Ok(()));
+
+        assert_eq!(shards.move_entity(moved, 1), Err(ShardError::NotLoaded(1)));
+        assert!(shards.world_of(moved).is_some());
+        let unloaded = shards.unload(3).unwrap();
+        assert_eq!(shards.loaded().collect::<Vec<_>>(), vec![0]);
+        assert_eq!(shards.spawn(3), Err(ShardError::NotLoaded(3)));
+        shards.insert(3, unloaded);
+        assert!(shards.is_loaded(3));
+
+        for _ in 0..9 {
+            shards.spawn(3)?;
+        }
+        assert_eq!(
+            shards.spawn(3),
+            Err(ShardError::Full(EntityLimitReached { limit: 10 }))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_shards_move_both_frames() -> Result<(), ShardError> {
+        let mut shards = Shards::<AutomatonWorld>::new(10, 2);
+        shards.load(0);
+        shards.load(1);
+        let entity = shards.spawn(0)?;
+        let world = shards.get_mut(0).unwrap();
+        world.cells.set(entity, Cell { alive: true }).unwrap();
+        world.flip();
+        world.cells.set(entity, Cell { alive: false }).unwrap();
+
+        let moved = shards.move_entity(entity, 1)?;
+        let world = shards.world_of(moved).unwrap();
+        assert_eq!(world.cells.get(moved), Some(&Cell { alive: true }));
+        assert_eq!(world.cells.get_next(moved), Some(&Cell { alive: false }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ffi() {
+        use genesis::ffi::*;
+        use std::ffi::CStr;
+        use std::os::raw::c_void;
+
+        assert_eq!(ffi_world_component_count(), 2);
+        let descriptors = unsafe { std::slice::from_raw_parts(ffi_world_components(), 2) };
+        let name = unsafe { CStr::from_ptr(descriptors[1].name) };
+        assert_eq!(name.to_str(), Ok("speeds"));
+        assert_eq!(descriptors[0].size, std::mem::size_of::<Point>());
+
+        let world = ffi_world_new(3);
+        unsafe {
+            let entity = ffi_world_spawn(world);
+            assert!(ffi_world_exists(world, entity));
+
+            let speed = Speed(3);
+            let speed_ptr = &speed as *const Speed as *const c_void;
+            assert_eq!(
+                ffi_world_set(world, entity, 1, speed_ptr),
+                FfiStatus::MissingRequirement
+            );
+            let point = Point { x: 1, y: 2 };
+            let point_ptr = &point as *const Point as *const c_void;
+            assert_eq!(ffi_world_set(world, entity, 0, point_ptr), FfiStatus::Ok);
+            assert_eq!(ffi_world_set(world, entity, 1, speed_ptr), FfiStatus::Ok);
+            assert_eq!(
+                ffi_world_set(world, entity, 2, point_ptr),
+                FfiStatus::UnknownComponent
+            );
+
+            let mut out = Point { x: 0, y: 0 };
+            let out_ptr = &mut out as *mut Point as *mut c_void;
+            assert_eq!(ffi_world_get(world, entity, 0, out_ptr), FfiStatus::Ok);
+            assert_eq!(out, point);
+            assert_eq!((*world).speeds.get(Entity::from_bits(entity)), Some(&speed));
+
+            assert_eq!(ffi_world_remove(world, entity, 0), FfiStatus::Ok);
+            assert_eq!(
+                ffi_world_get(world, entity, 0, out_ptr),
+                FfiStatus::MissingComponent
+            );
+            assert_eq!(ffi_world_despawn(world, entity), FfiStatus::Ok);
+            assert_eq!(
+                ffi_world_get(world, entity, 0, out_ptr),
+                FfiStatus::NoSuchEntity
+            );
+            assert_eq!(ffi_world_despawn(world, entity), FfiStatus::NoSuchEntity);
+            assert_eq!(
+                ffi_world_despawn(std::ptr::null_mut(), entity),
+                FfiStatus::NullPointer
+            );
+            assert_eq!(
+                ffi_world_spawn(std::ptr::null_mut()),
+                genesis::Entity::INVALID.to_bits()
+            );
+            ffi_world_free(world);
+        }
+    }
+
+    #[test]
+    fn test_despawn_policies() -> Result<(), StorageError> {
+        let mut world = CascadeWorld::new(4);
+        let owner = world.spawn();
+        let item = world.spawn();
+        let orphan = world.spawn();
+        world.handles.set(item, GpuHandle(7))?;
+        world.inventories.set(item, Inventory(vec![1, 2]))?;
+        world.owners.set(item, Owner(owner))?;
+        world.inventories.set(orphan, Inventory(vec![3]))?;
+
+        world.despawn(item)?;
+        assert_eq!(world.inventories.get(owner), Some(&Inventory(vec![1, 2])));
+        RELEASED_HANDLES.with(|released| assert_eq!(*released.borrow(), vec![7]));
+
+        world.handles.set(orphan, GpuHandle(8))?;
+        world.owners.set(orphan, Owner(owner))?;
+        world.retain(|entity, _| entity != orphan);
+        assert_eq!(world.inventories.get(owner), Some(&Inventory(vec![1, 2])));
+        RELEASED_HANDLES.with(|released| assert_eq!(*released.borrow(), vec![7, 8]));
+
+        let world = LockedCascadeWorld::from(world);
+        let item = world.spawn();
+        world.handles.write().unwrap().set(item, GpuHandle(9))?;
+        world.owners.write().unwrap().set(item, Owner(owner))?;
+        world.despawn(item)?;
+        RELEASED_HANDLES.with(|released| assert_eq!(*released.borrow(), vec![7, 8, 9]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_despawn_batch_cascades() -> Result<(), StorageError> {
+        let mut world = CascadeWorld::new(4);
+        let entities = (0..4).map(|_| world.spawn()).collect::<Vec<_>>();
+        let (owner, item, doomed_owner, doomed_item) =
+            (entities[0], entities[1], entities[2], entities[3]);
+        for (i, &entity) in entities.iter().enumerate() {
+            world.handles.set(entity, GpuHandle(i as u32))?;
+        }
+        world.inventories.set(item, Inventory(vec![1]))?;
+        world.owners.set(item, Owner(owner))?;
+        world.inventories.set(doomed_item, Inventory(vec![2]))?;
+        world.owners.set(doomed_item, Owner(doomed_owner))?;
+
+        assert_eq!(
+            world.despawn_batch(vec![doomed_item, item, doomed_owner]),
+            3
+        );
+        assert_eq!(world.inventories.get(owner), Some(&Inventory(vec![1])));
+        assert_eq!(world.inventories.get_unchecked(doomed_owner), None);
+        RELEASED_HANDLES.with(|released| assert_eq!(*released.borrow(), vec![1, 2, 3]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_clear_runs_despawn_hooks() -> Result<(), StorageError> {
+        let mut world = CascadeWorld::new(4);
+        let owner = world.spawn();
+        let item = world.spawn();
+        world.handles.set(owner, GpuHandle(1))?;
+        world.handles.set(item, GpuHandle(2))?;
+        world.owners.set(item, Owner(owner))?;
+        world.inventories.set(item, Inventory(vec![1]))?;
+        world.clear();
+        RELEASED_HANDLES.with(|released| assert_eq!(*released.borrow(), vec![1, 2]));
+        assert_eq!(world.inventories.get_unchecked(owner), None);
+
+        let entities = (0..3).map(|_| world.spawn()).collect::<Vec<_>>();
+        for (i, &entity) in entities.iter().enumerate() {
+            world.handles.set(entity, GpuHandle(3 + i as u32))?;
+        }
+        world.begin_clear();
+        while !world.clear_budget(2).is_done() {}
+        RELEASED_HANDLES.with(|released| assert_eq!(*released.borrow(), vec![1, 2, 3, 4, 5]));
+
+        let world = LockedCascadeWorld::from(world);
+        let entity = world.spawn();
+        world.handles.write().unwrap().set(entity, GpuHandle(6))?;
+        world.clear();
+        RELEASED_HANDLES.with(|released| assert_eq!(*released.borrow(), vec![1, 2, 3, 4, 5, 6]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cleanup_order() -> Result<(), StorageError> {
+        let mut world = PhysicsWorld::new(2);
+        let entity = world.spawn();
+        world.transforms.set(entity, Transform)?;
+        world.bodies.set(entity, RigidBody)?;
+        world.despawn(entity)?;
+        DROPPED_COMPONENTS.with(|dropped| {
+            assert_eq!(*dropped.borrow(), vec!["rigid body", "transform"]);
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn test_access_tokens() -> Result<(), StorageError> {
+        let mut world = PhysicsWorld::new(2);
+        let entity = world.spawn();
+        let (bodies, transforms) = world
+            .grant_access::<(Read<RigidBody>, Write<Transform>)>()
+            .unwrap();
+        assert!(world.grant_access::<Read<Transform>>().is_err());
+        let more_bodies = world.grant_access::<Read<RigidBody>>().unwrap();
+
+        world.write(&transforms).set(entity, Transform)?;
+        assert!(world.read(&bodies).get(entity).is_none());
+        assert!(world.read(&more_bodies).get(entity).is_none());
+
+        world.next_access_frame();
+        let transforms = world.grant_access::<Write<Transform>>().unwrap();
+        assert!(world.write(&transforms).get(entity).is_some());
+        let stale = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            world.read(&bodies).get(entity).is_none()
+        }));
+        assert!(stale.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_random_entities() {
+        let mut world = World::new(10);
+        let entities = (0..10).map(|_| world.spawn()).collect::<Vec<_>>();
+        let mut state = 1u32;
+        let mut rng = || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            state
+        };
+        let targets = world.random_entities(&mut rng, 4);
+        assert_eq!(targets.len(), 4);
+        assert!(targets.iter().all(|target| entities.contains(target)));
+        assert_eq!(world.random_entities(&mut rng, 20).len(), 10);
+    }
+
+    #[test]
+    fn test_unknown_data() {
+        let json = serde_json::json!({"stats": 2, "armor": {"value": 3}, "mana": 7});
+        let template = serde_json::from_value::<SaveTemplate>(json.clone()).unwrap();
+        assert_eq!(template.stats, Some(Stat(2)));
+        assert_eq!(
+            template.unknown_data.fields().collect::<Vec<_>>(),
+            vec!["armor", "mana"]
+        );
+        assert_eq!(
+            template.unknown_data.get("mana"),
+            Some(&UnknownValue::U64(7))
+        );
+        assert_eq!(
+            template.unknown_data.report(),
+            "skipped unknown component `armor`\nskipped unknown component `mana`\n"
+        );
+
+        let mut saved = serde_json::to_value(&template).unwrap();
+        saved
+            .as_object_mut()
+            .unwrap()
+            .retain(|_, value| !value.is_null());
+        assert_eq!(saved, json);
+
+        let base = SaveTemplate {
+            tags: Some(Tag("boss".into())),
+            ..Default::default()
+        };
+        let layered = template.overlay(base);
+        assert_eq!(layered.tags, Some(Tag("boss".into())));
+        assert_eq!(layered.unknown_data.len(), 2);
+    }
+
+    #[test]
+    fn test_json_patch() -> Result<(), genesis::json::JsonError> {
+        let mut world = SaveWorld::new(2);
+        let entity = world.spawn();
+        world.apply_json_patch(entity, &serde_json::json!({"stats": 3, "tags": "boss"}))?;
+        assert_eq!(world.stats.get(entity), Some(&Stat(3)));
+        world.apply_json_patch(entity, &serde_json::json!({"stats": 5, "tags": null}))?;
+        assert_eq!(
+            world.entity_to_json(entity)?,
+            serde_json::json!({"stats": 5, "tags": "boss", "textures": null})
+        );
+
+        assert!(world
+            .apply_json_patch(entity, &serde_json::json!({"stats": "high"}))
+            .is_err());
+        world.despawn(entity).map_err(RegisterError::from)?;
+        assert!(matches!(
+            world.entity_to_json(entity),
+            Err(genesis::json::JsonError::Register(
+                RegisterError::NoSuchEntity(_)
+            ))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ron_fixtures() -> Result<(), genesis::ron_format::RonError> {
+        let mut world = FixtureWorld::new(4);
+        let entities = (0..3).map(|_| world.spawn()).collect::<Vec<_>>();
+        world.apply_ron_patch(
+            entities[0],
+            "(stats: Some(Stat(3)), factions: Some(Neutral))",
+        )?;
+        world.apply_ron_patch(entities[2], "(factions: Some(Hostile(aggression: 9)))")?;
+        world.despawn(entities[1]).map_err(RegisterError::from)?;
+
+        let source = world.to_ron_string()?;
+        assert!(source.contains("Faction(Hostile(") && source.contains("Faction(Neutral)"));
+        let loaded = FixtureWorld::from_ron_str(&source)?;
+        let alive =
+            |world: &FixtureWorld| world.entities.read().unwrap().iter().collect::<Vec<_>>();
+        assert_eq!(alive(&loaded), alive(&world));
+        assert_eq!(loaded.stats.get(entities[0]), Some(&Stat(3)));
+        assert_eq!(
+            loaded.factions.get(entities[2]),
+            Some(&Faction::Hostile { aggression: 9 })
+        );
+        assert_eq!(loaded.to_ron_string()?, source);
+
+        assert_eq!(
+            world.entity_to_ron(entities[2])?,
+            genesis::ron_format::to_string(&FixtureTemplate {
+                stats: None,
+                factions: Some(Faction::Hostile { aggression: 9 }),
+            })?
+        );
+        assert!(matches!(
+            world.apply_ron_patch(entities[0], "(stats: Some(\"high\"))"),
+            Err(genesis::ron_format::RonError::Parse(_))
+        ));
+        assert!(matches!(
+            world.entity_to_ron(entities[1]),
+            Err(genesis::ron_format::RonError::Register(
+                RegisterError::NoSuchEntity(_)
+            ))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_changes_since() -> Result<(), StorageError> {
+        let mut world = SaveWorld::new(4);
+        let first = world.spawn();
+        let second = world.spawn();
+        world.stats.set(first, Stat(1))?;
+        world.stats.set(second, Stat(2))?;
+        world.tags.set(second, Tag("boss".into()))?;
+        let mut replica = world.fork();
+        assert_eq!(replica, world);
+
+        world.set_tick(1);
+        world.stats.get_mut(first).unwrap().0 += 10;
+        world.stats.remove(second)?;
+        world.tags.remove(second)?;
+        world.tags.set(first, Tag("hero".into()))?;
+        world.despawn(second)?;
+        let third = world.spawn();
+        world.stats.set(third, Stat(3))?;
+
+        let changes = world.changes_since(0);
+        assert_eq!(changes.alive, vec![first, third]);
+        assert_eq!(changes.set.len(), 3);
+        assert!(changes.removed.is_empty());
+        assert!(world
+            .changes_since(1)
+            .set
+            .iter()
+            .all(|(_, component)| matches!(component, SaveComponent::Tag(_))));
+
+        let json = world
+            .serialize_changes_since(0, serde_json::value::Serializer)
+            .unwrap();
+        replica.apply_changes(serde_json::from_value(json).unwrap())?;
+        assert_eq!(replica, world);
+
+        world.set_tick(2);
+        world.stats.remove(first)?;
+        let changes = world.changes_since(1);
+        assert_eq!(changes.removed, vec![(first, SaveComponentKind::Stat)]);
+        replica.apply_changes(changes)?;
+        assert_eq!(replica, world);
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialize_with_surrogate() -> Result<(), StorageError> {
+        let texture = Texture {
+            path: String::from("grass.png"),
+            handle: 9,
+        };
+        let template = SaveTemplate {
+            stats: Some(Stat(3)),
+            textures: Some(texture.clone()),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&template).unwrap();
+        assert_eq!(json["textures"], "grass.png");
+        let loaded = serde_json::from_value::<SaveTemplate>(json).unwrap();
+        assert_eq!(loaded.textures.unwrap().handle, 9);
+        let partial = serde_json::from_str::<SaveTemplate>(r#"{"stats": 1}"#).unwrap();
+        assert_eq!(partial.textures, None);
+
+        let mut world = SaveWorld::new(2);
+        let entity = world.spawn();
+        world.set_tick(1);
+        world.stats.set(entity, Stat(1))?;
+        world.textures.set(entity, texture)?;
+        let json = world
+            .serialize_changes_since(0, serde_json::value::Serializer)
+            .unwrap();
+        let mut replica = SaveWorld::new(2);
+        replica.spawn();
+        replica.apply_changes(serde_json::from_value(json).unwrap())?;
+        assert_eq!(replica.stats.get(entity), Some(&Stat(1)));
+        assert_eq!(
+            replica.textures.get(entity).map(|texture| texture.handle),
+            Some(9)
+        );
+        Ok(())
+    }
 }
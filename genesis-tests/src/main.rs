@@ -25,7 +25,7 @@ pub struct World {
     pub rare_data: VecStorage<RareComponent>,
 }
 
-fn main() -> Result<(), NoSuchEntity> {
+fn main() -> Result<(), StorageError> {
     let initial_capacity = 1024;
     let mut world = World::new(initial_capacity);
 
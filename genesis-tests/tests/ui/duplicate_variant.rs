@@ -0,0 +1,14 @@
+use genesis::*;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Item;
+
+// Types are compared by their spelling, so `Item` and `self::Item` would count as different types and
+// fail with conflicting impls instead.
+#[world(LoadoutComponent, LoadoutTemplate)]
+pub struct LoadoutWorld {
+    equipped: VecStorage<Item>,
+    stored: MapStorage<Item>,
+}
+
+fn main() {}
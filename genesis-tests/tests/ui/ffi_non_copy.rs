@@ -0,0 +1,13 @@
+use genesis::*;
+
+#[repr(C)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Label(pub u32);
+
+// Components are copied byte by byte across the C interface, so they have to be `Copy`.
+#[world(LabelComponent, LabelTemplate, ffi)]
+pub struct LabelWorld {
+    labels: VecStorage<Label>,
+}
+
+fn main() {}
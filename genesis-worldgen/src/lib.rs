@@ -0,0 +1,411 @@
+//! Generates the Rust module defining a `genesis` World from a description in data, so the list of
+//! components can be maintained e.g. by designers while the World is still generated by the `#[world]`
+//! macro. Descriptions are written in TOML, RON or JSON:
+//!
+//! ```toml
+//! uses = ["crate::components::*"]
+//!
+//! [world]
+//! name = "GameWorld"
+//! component = "GameComponent"
+//! template = "GameTemplate"
+//! derives = ["Clone", "Debug", "PartialEq"]
+//! options = ["spawn_order", "reuse_policy = ReusePolicy::Lifo"]
+//!
+//! [[components]]
+//! field = "positions"
+//! type = "Position"
+//! template_name = "position"
+//!
+//! [[components]]
+//! field = "targets"
+//! type = "Target"
+//! storage = "map"
+//! options = ["maps_entities"]
+//! ```
+//!
+//! Storages are given by the names of `genesis::StorageKind`, i.e. `vec` (the default), `map`, `double`,
+//! `indexed`, `mmap`, `stable` or `dynamic`. World and component options are passed to the macros as written, so
+//! the `#[world]` macro reports unknown options when the generated module is compiled.
+//!
+//! In RON, the same description reads:
+//!
+//! ```ron
+//! (
+//!     uses: ["crate::components::*"],
+//!     world: (name: "GameWorld", component: "GameComponent", template: "GameTemplate"),
+//!     components: [
+//!         (field: "positions", type: "Position", template_name: Some("position")),
+//!         (field: "targets", type: "Target", storage: "map", options: ["maps_entities"]),
+//!     ],
+//! )
+//! ```
+//!
+//! Generate the module from a build script with `generate_file()` and include it in the crate:
+//!
+//! ```ignore
+//! // build.rs
+//! let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("world.rs");
+//! genesis_worldgen::generate_file("world.toml", &out).unwrap();
+//!
+//! // src/lib.rs
+//! include!(concat!(env!("OUT_DIR"), "/world.rs"));
+//! ```
+//!
+//! or once with the `genesis-worldgen` binary: `genesis-worldgen world.toml src/world.rs`.
+
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Error returned when a description can't be read or doesn't describe a valid World.
+#[derive(Error, Debug)]
+pub enum WorldgenError {
+    /// The description or the generated module couldn't be read or written.
+    #[error("{}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// The description isn't valid TOML or doesn't match the format of world descriptions.
+    #[error(transparent)]
+    Toml(#[from] toml::de::Error),
+    /// The description isn't valid RON or doesn't match the format of world descriptions.
+    #[error(transparent)]
+    Ron(#[from] ron::error::SpannedError),
+    /// The description isn't valid JSON or doesn't match the format of world descriptions.
+    #[error(transparent)]
+    Format(#[from] serde_json::Error),
+    /// The file extension of the description is neither `toml`, `ron` nor `json`.
+    #[error("unsupported description format `{0}`, expected `toml`, `ron` or `json`")]
+    UnsupportedFormat(String),
+    /// A name that is used as a Rust identifier isn't one.
+    #[error("`{0}` is not a valid identifier")]
+    InvalidIdentifier(String),
+    /// A component uses an unknown storage kind.
+    #[error("unknown storage `{storage}` of component `{field}`, expected `vec`, `map`, `double`, `indexed`, `mmap`, `stable` or `dynamic`")]
+    UnknownStorage { field: String, storage: String },
+    /// Two components have the same storage field or template name.
+    #[error("duplicate component name `{0}`")]
+    DuplicateComponent(String),
+}
+
+/// The description of a World and its components.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorldDescription {
+    /// Paths imported into the generated module, e.g. the module defining the component types.
+    /// `genesis::*` is always imported.
+    #[serde(default)]
+    pub uses: Vec<String>,
+    pub world: WorldHeader,
+    #[serde(default)]
+    pub components: Vec<ComponentDescription>,
+}
+
+/// The names, derives and options of a World.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorldHeader {
+    /// The name of the World struct.
+    pub name: String,
+    /// The name of the generated component enum.
+    pub component: String,
+    /// The name of the generated template.
+    pub template: String,
+    /// The visibility of the World, e.g. `pub(crate)`; defaults to `pub`.
+    #[serde(default = "default_vis")]
+    pub vis: String,
+    /// The traits derived for the World, its component enum and its templates.
+    #[serde(default)]
+    pub derives: Vec<String>,
+    /// Options passed to the `#[world]` macro after the names, e.g. `json` or `locked = LockedWorld`.
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+/// A component of a World.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ComponentDescription {
+    /// The name of the storage field.
+    pub field: String,
+    /// The component type.
+    #[serde(rename = "type")]
+    pub ty: String,
+    /// The name of the storage kind; see `genesis::StorageKind::name()`. Defaults to `vec`.
+    #[serde(default = "default_storage")]
+    pub storage: String,
+    /// The name of the component in templates; defaults to the field name.
+    #[serde(default)]
+    pub template_name: Option<String>,
+    /// Options passed to `#[component(...)]`, e.g. `maps_entities` or `id = 7`.
+    #[serde(default)]
+    pub options: Vec<String>,
+}
+
+fn default_vis() -> String {
+    "pub".to_owned()
+}
+
+fn default_storage() -> String {
+    "vec".to_owned()
+}
+
+/// The storage type of the given storage kind name.
+fn storage_type(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "vec" => "VecStorage",
+        "map" => "MapStorage",
+        "double" => "DoubleBuffered",
+        "indexed" => "IndexedStorage",
+        "mmap" => "MmapStorage",
+        "stable" => "StableStorage",
+        "dynamic" => "DynamicStorage",
+        _ => return None,
+    })
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+        && name != "_"
+}
+
+fn check_identifier(name: &str) -> Result<(), WorldgenError> {
+    if is_identifier(name) {
+        Ok(())
+    } else {
+        Err(WorldgenError::InvalidIdentifier(name.to_owned()))
+    }
+}
+
+impl WorldDescription {
+    /// Parse a description in TOML.
+    pub fn from_toml(source: &str) -> Result<Self, WorldgenError> {
+        Ok(toml::from_str(source)?)
+    }
+
+    /// Parse a description in RON.
+    pub fn from_ron(source: &str) -> Result<Self, WorldgenError> {
+        Ok(ron::from_str(source)?)
+    }
+
+    /// Parse a description in JSON.
+    pub fn from_json(source: &str) -> Result<Self, WorldgenError> {
+        Ok(serde_json::from_str(source)?)
+    }
+
+    /// Read the description at the given path, in TOML, RON or JSON depending on its extension.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, WorldgenError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|source| WorldgenError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml(&source),
+            Some("ron") => Self::from_ron(&source),
+            Some("json") => Self::from_json(&source),
+            extension => Err(WorldgenError::UnsupportedFormat(
+                extension.unwrap_or_default().to_owned(),
+            )),
+        }
+    }
+
+    /// Generate the Rust module defining the described World.
+    pub fn generate(&self) -> Result<String, WorldgenError> {
+        let world = &self.world;
+        for name in [&world.name, &world.component, &world.template] {
+            check_identifier(name)?;
+        }
+        let mut names = HashSet::new();
+        for component in &self.components {
+            check_identifier(&component.field)?;
+            let template_name = component.template_name.as_ref().unwrap_or(&component.field);
+            check_identifier(template_name)?;
+            // The field and template name of a component may coincide, but not those of different components.
+            let own_names = [&component.field, template_name]
+                .iter()
+                .copied()
+                .collect::<HashSet<_>>();
+            for name in own_names {
+                if !names.insert(name) {
+                    return Err(WorldgenError::DuplicateComponent(name.clone()));
+                }
+            }
+        }
+
+        let mut code = String::new();
+        writeln!(code, "// Generated by genesis-worldgen; do not edit.").unwrap();
+        writeln!(code, "use genesis::*;").unwrap();
+        for path in &self.uses {
+            writeln!(code, "use {};", path).unwrap();
+        }
+        writeln!(code).unwrap();
+
+        let mut arguments = vec![world.component.as_str(), world.template.as_str()];
+        arguments.extend(world.options.iter().map(String::as_str));
+        writeln!(code, "#[world({})]", arguments.join(", ")).unwrap();
+        if !world.derives.is_empty() {
+            writeln!(code, "#[derive({})]", world.derives.join(", ")).unwrap();
+        }
+        writeln!(code, "{} struct {} {{", world.vis, world.name).unwrap();
+        for component in &self.components {
+            let storage =
+                storage_type(&component.storage).ok_or_else(|| WorldgenError::UnknownStorage {
+                    field: component.field.clone(),
+                    storage: component.storage.clone(),
+                })?;
+            if let Some(template_name) = &component.template_name {
+                writeln!(code, "    #[template_name({})]", template_name).unwrap();
+            }
+            if !component.options.is_empty() {
+                writeln!(code, "    #[component({})]", component.options.join(", ")).unwrap();
+            }
+            writeln!(
+                code,
+                "    {}: {}<{}>,",
+                component.field, storage, component.ty
+            )
+            .unwrap();
+        }
+        writeln!(code, "}}").unwrap();
+        Ok(code)
+    }
+}
+
+/// Generate the module defining the World described in the file at `description`, in TOML, RON or JSON, and
+/// write it to `output` unless it is unchanged, e.g. from a build script; see the crate documentation.
+/// Tells cargo to rerun the build script when the description changes.
+pub fn generate_file(
+    description: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+) -> Result<(), WorldgenError> {
+    let (description, output) = (description.as_ref(), output.as_ref());
+    println!("cargo:rerun-if-changed={}", description.display());
+    let code = WorldDescription::read(description)?.generate()?;
+    if fs::read_to_string(output).ok().as_deref() == Some(code.as_str()) {
+        return Ok(());
+    }
+    fs::write(output, code).map_err(|source| WorldgenError::Io {
+        path: output.to_owned(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESCRIPTION: &str = r#"
+        uses = ["crate::components::*"]
+
+        [world]
+        name = "GameWorld"
+        component = "GameComponent"
+        template = "GameTemplate"
+        derives = ["Clone", "Debug"]
+        options = ["spawn_order", "reuse_policy = ReusePolicy::Lifo"]
+
+        [[components]]
+        field = "positions"
+        type = "Position"
+        template_name = "position"
+
+        [[components]]
+        field = "targets"
+        type = "Target"
+        storage = "map"
+        options = ["maps_entities", "id = 7"]
+    "#;
+
+    #[test]
+    fn generates_world_definition() {
+        let description = WorldDescription::from_toml(DESCRIPTION).unwrap();
+        assert_eq!(
+            description.generate().unwrap(),
+            "// Generated by genesis-worldgen; do not edit.\n\
+             use genesis::*;\n\
+             use crate::components::*;\n\
+             \n\
+             #[world(GameComponent, GameTemplate, spawn_order, reuse_policy = ReusePolicy::Lifo)]\n\
+             #[derive(Clone, Debug)]\n\
+             pub struct GameWorld {\n    \
+                 #[template_name(position)]\n    \
+                 positions: VecStorage<Position>,\n    \
+                 #[component(maps_entities, id = 7)]\n    \
+                 targets: MapStorage<Target>,\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_descriptions() {
+        let mut description = WorldDescription::from_toml(DESCRIPTION).unwrap();
+        description.components[1].storage = "sparse".to_owned();
+        assert!(matches!(
+            description.generate(),
+            Err(WorldgenError::UnknownStorage { .. })
+        ));
+
+        description.components[1].storage = "map".to_owned();
+        description.components[1].template_name = Some("position".to_owned());
+        assert!(matches!(
+            description.generate(),
+            Err(WorldgenError::DuplicateComponent(name)) if name == "position"
+        ));
+
+        description.world.name = "Game World".to_owned();
+        assert!(matches!(
+            description.generate(),
+            Err(WorldgenError::InvalidIdentifier(_))
+        ));
+
+        let json = r#"{"world": {"name": "W", "component": "C", "template": "T", "extra": 1}}"#;
+        assert!(WorldDescription::from_json(json).is_err());
+    }
+
+    #[test]
+    fn parses_ron_like_toml() {
+        let ron = r#"(
+            uses: ["crate::components::*"],
+            world: (
+                name: "GameWorld",
+                component: "GameComponent",
+                template: "GameTemplate",
+                derives: ["Clone", "Debug"],
+                options: ["spawn_order", "reuse_policy = ReusePolicy::Lifo"],
+            ),
+            components: [
+                (field: "positions", type: "Position", template_name: Some("position")),
+                (field: "targets", type: "Target", storage: "map", options: ["maps_entities", "id = 7"]),
+            ],
+        )"#;
+        assert_eq!(
+            WorldDescription::from_ron(ron).unwrap().generate().unwrap(),
+            WorldDescription::from_toml(DESCRIPTION)
+                .unwrap()
+                .generate()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn reports_toml_errors_with_location() {
+        let error = WorldDescription::from_toml("[world]\nname = 1.5\n").unwrap_err();
+        assert!(matches!(error, WorldgenError::Toml(_)));
+        assert!(error.to_string().contains("line 2"));
+        assert!(WorldDescription::from_toml("[world]\nname = \"W\"\nname = \"V\"").is_err());
+        assert!(matches!(
+            WorldDescription::from_ron("(world: (name: \"W\"))"),
+            Err(WorldgenError::Ron(_))
+        ));
+    }
+}
@@ -0,0 +1,24 @@
+//! Generates the module defining a World from a description; see the `genesis_worldgen` crate.
+//!
+//! Usage: `genesis-worldgen <description.toml|ron|json> [output.rs]`, printing the module if no output is given.
+
+use genesis_worldgen::{generate_file, WorldDescription};
+use std::process::exit;
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let result = match args.as_slice() {
+        [description] => WorldDescription::read(description)
+            .and_then(|description| description.generate())
+            .map(|code| print!("{}", code)),
+        [description, output] => generate_file(description, output),
+        _ => {
+            eprintln!("usage: genesis-worldgen <description.toml|ron|json> [output.rs]");
+            exit(2);
+        }
+    };
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        exit(1);
+    }
+}
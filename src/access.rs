@@ -0,0 +1,304 @@
+use std::any::{type_name, TypeId};
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use thiserror::Error;
+
+/// The source of unique ids for `AccessGrants`, so tokens of one World are rejected by another.
+static NEXT_LEDGER: AtomicU64 = AtomicU64::new(0);
+
+mod sealed {
+    use std::any::TypeId;
+
+    /// Restricts `AccessSet` and `AccessToken` to the token types of this module.
+    pub trait Sealed {}
+
+    /// The ledger and frame a token was granted in; can't be constructed outside of `genesis`,
+    /// so tokens can't be forged.
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    pub struct Stamp {
+        pub(crate) ledger: u64,
+        pub(crate) frame: u64,
+    }
+
+    /// A single component access requested by an `AccessSet`.
+    #[derive(Debug, Clone, Copy)]
+    pub struct ComponentAccess {
+        pub(crate) type_id: TypeId,
+        pub(crate) name: &'static str,
+        pub(crate) write: bool,
+    }
+}
+
+use sealed::{ComponentAccess, Sealed, Stamp};
+
+/// Token allowing its holder to read the storage of components of type T, e.g. for a system that
+/// renders positions. Tokens are handed out by `grant_access()` of Worlds passed the `access` flag
+/// and can only be used until the next `next_access_frame()`.
+/// Read tokens can be cloned, since any number of readers may share a component type.
+pub struct Read<T> {
+    stamp: Stamp,
+    component: PhantomData<fn() -> T>,
+}
+
+/// Token allowing its holder to read and modify the storage of components of type T, e.g. for a
+/// system that integrates velocities into positions. No other token for T is granted in the same frame.
+pub struct Write<T> {
+    stamp: Stamp,
+    component: PhantomData<fn() -> T>,
+}
+
+impl<T> Read<T> {
+    /// The frame of the `AccessGrants` this token was granted in.
+    pub fn frame(&self) -> u64 {
+        self.stamp.frame
+    }
+}
+
+impl<T> Write<T> {
+    /// The frame of the `AccessGrants` this token was granted in.
+    pub fn frame(&self) -> u64 {
+        self.stamp.frame
+    }
+}
+
+impl<T> Clone for Read<T> {
+    fn clone(&self) -> Self {
+        Self {
+            stamp: self.stamp,
+            component: PhantomData,
+        }
+    }
+}
+
+impl<T> Debug for Read<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Read<{}>(frame {})", type_name::<T>(), self.stamp.frame)
+    }
+}
+
+impl<T> Debug for Write<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Write<{}>(frame {})", type_name::<T>(), self.stamp.frame)
+    }
+}
+
+/// A single `Read<T>` or `Write<T>` token, as checked by `AccessGrants::is_current()`.
+pub trait AccessToken: Sealed {
+    #[doc(hidden)]
+    fn stamp(&self) -> Stamp;
+}
+
+impl<T> Sealed for Read<T> {}
+impl<T> Sealed for Write<T> {}
+
+impl<T> AccessToken for Read<T> {
+    fn stamp(&self) -> Stamp {
+        self.stamp
+    }
+}
+
+impl<T> AccessToken for Write<T> {
+    fn stamp(&self) -> Stamp {
+        self.stamp
+    }
+}
+
+/// A set of tokens requested at once, e.g. all tokens of one system: a single `Read<T>` or `Write<T>`,
+/// or a tuple of up to eight of them like `(Read<Velocity>, Write<Position>)`.
+pub trait AccessSet: Sealed + Sized {
+    #[doc(hidden)]
+    fn accesses(accesses: &mut Vec<ComponentAccess>);
+
+    #[doc(hidden)]
+    fn grant(stamp: Stamp) -> Self;
+}
+
+impl<T: 'static> AccessSet for Read<T> {
+    fn accesses(accesses: &mut Vec<ComponentAccess>) {
+        accesses.push(ComponentAccess {
+            type_id: TypeId::of::<T>(),
+            name: type_name::<T>(),
+            write: false,
+        });
+    }
+
+    fn grant(stamp: Stamp) -> Self {
+        Self {
+            stamp,
+            component: PhantomData,
+        }
+    }
+}
+
+impl<T: 'static> AccessSet for Write<T> {
+    fn accesses(accesses: &mut Vec<ComponentAccess>) {
+        accesses.push(ComponentAccess {
+            type_id: TypeId::of::<T>(),
+            name: type_name::<T>(),
+            write: true,
+        });
+    }
+
+    fn grant(stamp: Stamp) -> Self {
+        Self {
+            stamp,
+            component: PhantomData,
+        }
+    }
+}
+
+macro_rules! impl_access_set {
+    ($($name:ident),*) => {
+        impl<$($name: AccessSet),*> Sealed for ($($name,)*) {}
+
+        impl<$($name: AccessSet),*> AccessSet for ($($name,)*) {
+            fn accesses(accesses: &mut Vec<ComponentAccess>) {
+                $($name::accesses(accesses);)*
+            }
+
+            fn grant(stamp: Stamp) -> Self {
+                ($($name::grant(stamp),)*)
+            }
+        }
+    };
+}
+
+impl_access_set!(A);
+impl_access_set!(A, B);
+impl_access_set!(A, B, C);
+impl_access_set!(A, B, C, D);
+impl_access_set!(A, B, C, D, E);
+impl_access_set!(A, B, C, D, E, F);
+impl_access_set!(A, B, C, D, E, F, G);
+impl_access_set!(A, B, C, D, E, F, G, H);
+
+/// Error returned when requested tokens conflict with each other or with tokens granted earlier
+/// in the same frame, i.e. when a `Write<T>` would coexist with any other token for T.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("conflicting access to component `{component}`")]
+pub struct AccessConflict {
+    /// The name of the component type.
+    pub component: &'static str,
+}
+
+/// The tokens granted in the current frame, stored in the `access` field of Worlds passed the
+/// `access` flag. This is groundwork for running systems in parallel: every system holds the tokens of
+/// the storages it touches, and conflicting tokens are never handed out in the same frame.
+///
+/// Cloning the grants creates a fresh ledger without any grants, so tokens of the original aren't
+/// accepted by the clone.
+pub struct AccessGrants {
+    ledger: u64,
+    frame: u64,
+    /// Whether a `Write` token was granted, by component type.
+    granted: HashMap<TypeId, bool>,
+}
+
+impl AccessGrants {
+    /// Create a ledger without any grants.
+    pub fn new() -> Self {
+        Self {
+            ledger: NEXT_LEDGER.fetch_add(1, Ordering::Relaxed),
+            frame: 0,
+            granted: HashMap::new(),
+        }
+    }
+
+    /// The current frame; tokens of earlier frames are stale.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Start a new frame, revoking all tokens granted so far.
+    pub fn next_frame(&mut self) {
+        self.frame += 1;
+        self.granted.clear();
+    }
+
+    /// Grant the requested set of tokens if they conflict neither with each other nor with tokens
+    /// granted earlier in the current frame. Nothing is granted if there is a conflict.
+    pub fn grant<A: AccessSet>(&mut self) -> Result<A, AccessConflict> {
+        let mut accesses = vec![];
+        A::accesses(&mut accesses);
+        let mut requested = HashMap::new();
+        for access in &accesses {
+            let conflict = match self.granted.get(&access.type_id) {
+                Some(write) => *write || access.write,
+                None => false,
+            };
+            let previous = requested.insert(access.type_id, access.write);
+            if conflict || previous.is_some_and(|write| write || access.write) {
+                return Err(AccessConflict {
+                    component: access.name,
+                });
+            }
+        }
+        for (type_id, write) in requested {
+            let granted = self.granted.entry(type_id).or_insert(false);
+            *granted |= write;
+        }
+        Ok(A::grant(Stamp {
+            ledger: self.ledger,
+            frame: self.frame,
+        }))
+    }
+
+    /// Check if the given token was granted by self in the current frame.
+    pub fn is_current(&self, token: &impl AccessToken) -> bool {
+        let stamp = token.stamp();
+        stamp.ledger == self.ledger && stamp.frame == self.frame
+    }
+}
+
+impl Default for AccessGrants {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for AccessGrants {
+    fn clone(&self) -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for AccessGrants {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccessGrants")
+            .field("frame", &self.frame)
+            .field("granted", &self.granted.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Position;
+    struct Velocity;
+
+    #[test]
+    fn grants_non_conflicting_tokens() {
+        let mut grants = AccessGrants::new();
+        let (velocities, positions) = grants.grant::<(Read<Velocity>, Write<Position>)>().unwrap();
+        assert!(grants.is_current(&velocities) && grants.is_current(&positions));
+        assert!(grants.grant::<Read<Velocity>>().is_ok());
+        assert_eq!(
+            grants.grant::<Read<Position>>().unwrap_err().component,
+            type_name::<Position>()
+        );
+        assert!(grants.grant::<(Write<Velocity>,)>().is_err());
+        assert!(grants.grant::<(Read<u8>, Write<u8>)>().is_err());
+        assert!(grants.grant::<Write<u8>>().is_ok());
+
+        grants.next_frame();
+        assert!(!grants.is_current(&positions));
+        assert!(grants.grant::<Write<Position>>().is_ok());
+        assert!(!grants
+            .clone()
+            .is_current(&grants.grant::<Read<u16>>().unwrap()));
+    }
+}
@@ -0,0 +1,151 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// The kinds of allocations counted for storages.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum AllocationKind {
+    /// The slots of a storage grew, e.g. because a component was set for an index beyond its capacity.
+    Growth,
+    /// A hash map of a storage grew and rehashed its entries, e.g. in a `MapStorage<T>`.
+    Rehash,
+}
+
+impl AllocationKind {
+    /// A short name for the allocation kind.
+    pub fn name(self) -> &'static str {
+        match self {
+            AllocationKind::Growth => "growth",
+            AllocationKind::Rehash => "rehash",
+        }
+    }
+}
+
+/// The number of allocations of one kind and the bytes they allocated.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct AllocationCount {
+    pub count: u64,
+    /// The size of the allocated buffers, estimated from their capacity and element size.
+    pub bytes: u64,
+}
+
+/// The allocations performed by storage operations, by component type and allocation kind;
+/// see the generated `allocation_report()` of Worlds.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct AllocationReport {
+    /// The allocations by component type name and allocation kind.
+    pub counts: BTreeMap<(&'static str, AllocationKind), AllocationCount>,
+}
+
+thread_local! {
+    static ALLOCATIONS: RefCell<BTreeMap<(&'static str, AllocationKind), AllocationCount>> =
+        const { RefCell::new(BTreeMap::new()) };
+}
+
+impl AllocationReport {
+    /// Take the allocations recorded on the current thread for the given component types since they
+    /// were last taken. Used by the generated `allocation_report()` of Worlds.
+    pub fn take(components: &[&'static str]) -> Self {
+        ALLOCATIONS.with(|allocations| {
+            let mut allocations = allocations.borrow_mut();
+            let mut counts = BTreeMap::new();
+            allocations.retain(|key, count| {
+                let taken = components.contains(&key.0);
+                if taken {
+                    counts.insert(*key, *count);
+                }
+                !taken
+            });
+            Self { counts }
+        })
+    }
+
+    /// Check if no allocations were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// The number of allocations of all kinds by the storage of the given component type.
+    pub fn total(&self, component: &str) -> u64 {
+        self.filter(component).map(|count| count.count).sum()
+    }
+
+    /// The bytes allocated by the storage of the given component type.
+    pub fn bytes(&self, component: &str) -> u64 {
+        self.filter(component).map(|count| count.bytes).sum()
+    }
+
+    fn filter<'a>(&'a self, component: &'a str) -> impl Iterator<Item = &'a AllocationCount> + 'a {
+        self.counts
+            .iter()
+            .filter(move |((name, _), _)| *name == component)
+            .map(|(_, count)| count)
+    }
+}
+
+/// One `component kind: count (bytes bytes)` line per entry.
+impl Display for AllocationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for ((component, kind), count) in &self.counts {
+            writeln!(
+                f,
+                "{} {}: {} ({} bytes)",
+                component,
+                kind.name(),
+                count.count,
+                count.bytes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Record an allocation of `bytes` by the storage of components of type `T` on this thread.
+#[cfg(feature = "track_allocations")]
+pub(crate) fn record<T>(kind: AllocationKind, bytes: usize) {
+    let component = std::any::type_name::<T>();
+    ALLOCATIONS.with(|allocations| {
+        let mut allocations = allocations.borrow_mut();
+        let count = allocations.entry((component, kind)).or_default();
+        count.count += 1;
+        count.bytes += bytes as u64;
+    });
+}
+
+/// Record an allocation by the storage of components of type `T`; does nothing without the
+/// `track_allocations` feature.
+#[cfg(not(feature = "track_allocations"))]
+#[inline(always)]
+#[allow(clippy::extra_unused_type_parameters)]
+pub(crate) fn record<T>(_kind: AllocationKind, _bytes: usize) {}
+
+/// Record a growth of a buffer of `E` for components of type `T` if its capacity changed.
+#[inline(always)]
+pub(crate) fn record_growth<T, E>(kind: AllocationKind, old_capacity: usize, new_capacity: usize) {
+    if new_capacity > old_capacity {
+        record::<T>(kind, new_capacity * std::mem::size_of::<E>());
+    }
+}
+
+#[cfg(all(test, feature = "track_allocations"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_allocations_of_given_components() {
+        record_growth::<u32, u32>(AllocationKind::Growth, 4, 8);
+        record_growth::<u32, u32>(AllocationKind::Growth, 8, 8);
+        record::<u32>(AllocationKind::Rehash, 10);
+        record::<u64>(AllocationKind::Growth, 16);
+
+        let report = AllocationReport::take(&["u32"]);
+        assert_eq!(report.total("u32"), 2);
+        assert_eq!(report.bytes("u32"), 42);
+        assert_eq!(
+            report.to_string(),
+            "u32 growth: 1 (32 bytes)\nu32 rehash: 1 (10 bytes)\n"
+        );
+        assert!(AllocationReport::take(&["u32"]).is_empty());
+        assert_eq!(AllocationReport::take(&["u64"]).total("u64"), 1);
+    }
+}
@@ -0,0 +1,196 @@
+//! Wrappers for archiving storages and Worlds with [rkyv], for fields rkyv can't archive by itself.
+//! Worlds passing the `rkyv` flag to the `#[world]` macro use them for their storages and entities.
+//!
+//! [rkyv]: https://github.com/rkyv/rkyv
+
+use crate::Entities;
+use allocator_api2::alloc::Allocator;
+use allocator_api2::vec::Vec;
+use hashbrown::HashMap;
+use rkyv::collections::hash_map::{ArchivedHashMap, HashMapResolver};
+use rkyv::ser::{ScratchSpace, Serializer};
+use rkyv::vec::{ArchivedVec, VecResolver};
+use rkyv::with::{ArchiveWith, DeserializeWith, SerializeWith};
+use rkyv::{Archive, Archived, Deserialize, Fallible, Serialize, SerializeUnsized};
+use std::hash::{BuildHasher, Hash};
+use std::sync::{Arc, RwLock};
+
+/// Archives a `Vec` of `allocator_api2` like a `Vec` of std; it is deserialized into a default allocator.
+pub struct AsVec;
+
+impl<T: Archive, A: Allocator> ArchiveWith<Vec<T, A>> for AsVec {
+    type Archived = ArchivedVec<T::Archived>;
+    type Resolver = VecResolver;
+
+    unsafe fn resolve_with(
+        field: &Vec<T, A>,
+        pos: usize,
+        resolver: VecResolver,
+        out: *mut Self::Archived,
+    ) {
+        ArchivedVec::resolve_from_slice(field.as_slice(), pos, resolver, out);
+    }
+}
+
+impl<T, A, S> SerializeWith<Vec<T, A>, S> for AsVec
+where
+    T: Serialize<S>,
+    [T]: SerializeUnsized<S>,
+    A: Allocator,
+    S: Serializer + ?Sized,
+{
+    fn serialize_with(field: &Vec<T, A>, serializer: &mut S) -> Result<VecResolver, S::Error> {
+        ArchivedVec::serialize_from_slice(field.as_slice(), serializer)
+    }
+}
+
+impl<T, A, D> DeserializeWith<ArchivedVec<T::Archived>, Vec<T, A>, D> for AsVec
+where
+    T: Archive,
+    T::Archived: Deserialize<T, D>,
+    A: Allocator + Default,
+    D: Fallible + ?Sized,
+{
+    fn deserialize_with(
+        field: &ArchivedVec<T::Archived>,
+        deserializer: &mut D,
+    ) -> Result<Vec<T, A>, D::Error> {
+        let mut vec = Vec::with_capacity_in(field.len(), A::default());
+        for item in field.iter() {
+            vec.push(item.deserialize(deserializer)?);
+        }
+        Ok(vec)
+    }
+}
+
+/// Archives a `hashbrown::HashMap` with any allocator like a `HashMap` of std; it is deserialized into a
+/// default hasher and allocator.
+pub struct AsHashMap;
+
+impl<K, V, H, A> ArchiveWith<HashMap<K, V, H, A>> for AsHashMap
+where
+    K: Archive + Hash + Eq,
+    K::Archived: Hash + Eq,
+    V: Archive,
+    A: Allocator,
+{
+    type Archived = ArchivedHashMap<K::Archived, V::Archived>;
+    type Resolver = HashMapResolver;
+
+    unsafe fn resolve_with(
+        field: &HashMap<K, V, H, A>,
+        pos: usize,
+        resolver: HashMapResolver,
+        out: *mut Self::Archived,
+    ) {
+        ArchivedHashMap::resolve_from_len(field.len(), pos, resolver, out);
+    }
+}
+
+impl<K, V, H, A, S> SerializeWith<HashMap<K, V, H, A>, S> for AsHashMap
+where
+    K: Serialize<S> + Hash + Eq,
+    K::Archived: Hash + Eq,
+    V: Serialize<S>,
+    A: Allocator,
+    S: Serializer + ScratchSpace + ?Sized,
+{
+    fn serialize_with(
+        field: &HashMap<K, V, H, A>,
+        serializer: &mut S,
+    ) -> Result<HashMapResolver, S::Error> {
+        // Safety: the keys of a HashMap are unique.
+        unsafe { ArchivedHashMap::serialize_from_iter(field.iter(), serializer) }
+    }
+}
+
+impl<K, V, H, A, D>
+    DeserializeWith<ArchivedHashMap<K::Archived, V::Archived>, HashMap<K, V, H, A>, D> for AsHashMap
+where
+    K: Archive + Hash + Eq,
+    K::Archived: Deserialize<K, D> + Hash + Eq,
+    V: Archive,
+    V::Archived: Deserialize<V, D>,
+    H: BuildHasher + Default,
+    A: Allocator + Default,
+    D: Fallible + ?Sized,
+{
+    fn deserialize_with(
+        field: &ArchivedHashMap<K::Archived, V::Archived>,
+        deserializer: &mut D,
+    ) -> Result<HashMap<K, V, H, A>, D::Error> {
+        let mut map = HashMap::with_capacity_and_hasher_in(field.len(), H::default(), A::default());
+        for (key, value) in field.iter() {
+            map.insert(
+                key.deserialize(deserializer)?,
+                value.deserialize(deserializer)?,
+            );
+        }
+        Ok(map)
+    }
+}
+
+/// Skips the entities of a storage, which are archived once by its World. They are deserialized as new,
+/// empty entities until the World attaches its own; see `VecStorage::attach_entities()`.
+pub struct Detached;
+
+impl ArchiveWith<Arc<RwLock<Entities>>> for Detached {
+    type Archived = ();
+    type Resolver = ();
+
+    unsafe fn resolve_with(_: &Arc<RwLock<Entities>>, _: usize, _: (), _: *mut ()) {}
+}
+
+impl<S: Fallible + ?Sized> SerializeWith<Arc<RwLock<Entities>>, S> for Detached {
+    fn serialize_with(_: &Arc<RwLock<Entities>>, _: &mut S) -> Result<(), S::Error> {
+        Ok(())
+    }
+}
+
+impl<D: Fallible + ?Sized> DeserializeWith<(), Arc<RwLock<Entities>>, D> for Detached {
+    fn deserialize_with(_: &(), _: &mut D) -> Result<Arc<RwLock<Entities>>, D::Error> {
+        Ok(Arc::new(RwLock::new(Entities::new(0))))
+    }
+}
+
+/// Archives the entities shared by the storages of a World, read-locking them while serializing.
+pub struct SharedEntities;
+
+impl ArchiveWith<Arc<RwLock<Entities>>> for SharedEntities {
+    type Archived = Archived<Entities>;
+    type Resolver = <Entities as Archive>::Resolver;
+
+    unsafe fn resolve_with(
+        field: &Arc<RwLock<Entities>>,
+        pos: usize,
+        resolver: Self::Resolver,
+        out: *mut Self::Archived,
+    ) {
+        field.read().unwrap().resolve(pos, resolver, out);
+    }
+}
+
+impl<S: Fallible + ?Sized> SerializeWith<Arc<RwLock<Entities>>, S> for SharedEntities
+where
+    Entities: Serialize<S>,
+{
+    fn serialize_with(
+        field: &Arc<RwLock<Entities>>,
+        serializer: &mut S,
+    ) -> Result<Self::Resolver, S::Error> {
+        field.read().unwrap().serialize(serializer)
+    }
+}
+
+impl<D: Fallible + ?Sized> DeserializeWith<Archived<Entities>, Arc<RwLock<Entities>>, D>
+    for SharedEntities
+where
+    Archived<Entities>: Deserialize<Entities, D>,
+{
+    fn deserialize_with(
+        field: &Archived<Entities>,
+        deserializer: &mut D,
+    ) -> Result<Arc<RwLock<Entities>>, D::Error> {
+        Ok(Arc::new(RwLock::new(field.deserialize(deserializer)?)))
+    }
+}
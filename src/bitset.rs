@@ -0,0 +1,240 @@
+/// A set of entity indices stored as bits in `u64` words, e.g. the alive entities of a World or the
+/// entities with a component; see `Entities::alive_bits()` and `VecStorage::presence_into()`.
+///
+/// Bit `i` of word `w` stands for the entity index `base + 64 * w + i`, where `base` is the index base
+/// of the entities. Sets of the same World share their base, so their words can be combined directly,
+/// e.g. by pathfinding or visibility algorithms doing their own set algebra.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
+pub struct BitSet {
+    words: Vec<u64>,
+    base: u32,
+}
+
+impl BitSet {
+    /// Create an empty set of the entity indices starting at `base`.
+    pub fn new(base: u32) -> Self {
+        Self {
+            words: vec![],
+            base,
+        }
+    }
+
+    /// Remove all indices, keeping the allocated words, and start the set at `base`.
+    pub fn reset(&mut self, base: u32) {
+        self.words.clear();
+        self.base = base;
+    }
+
+    /// The position of the given index in the words, or None if it is below the base.
+    fn position(&self, index: u32) -> Option<(usize, u64)> {
+        let bit = index.checked_sub(self.base)?;
+        Some(((bit / 64) as usize, 1 << (bit % 64)))
+    }
+
+    /// Allocate the words for all entity indices below `index_end` at once, so inserting them doesn't
+    /// allocate.
+    pub fn reserve(&mut self, index_end: u32) {
+        let words = index_end.saturating_sub(self.base).div_ceil(64) as usize;
+        if words > self.words.len() {
+            self.words.resize(words, 0);
+        }
+    }
+
+    /// Add the given entity index to the set.
+    ///
+    /// # Panics
+    /// Panics if the index is below the base of the set.
+    pub fn insert(&mut self, index: u32) {
+        let (word, mask) = self
+            .position(index)
+            .expect("entity index below the base of the bit set");
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= mask;
+    }
+
+    /// Remove the given entity index from the set.
+    pub fn remove(&mut self, index: u32) {
+        if let Some((word, mask)) = self.position(index) {
+            if let Some(word) = self.words.get_mut(word) {
+                *word &= !mask;
+            }
+        }
+    }
+
+    /// Keep only the indices that are also in `other`.
+    pub fn intersect_with(&mut self, other: BitSetView<'_>) {
+        debug_assert_eq!(self.base, other.base);
+        self.words.truncate(other.words.len());
+        for (word, other) in self.words.iter_mut().zip(other.words) {
+            *word &= other;
+        }
+    }
+
+    /// Add all indices of `other`.
+    pub fn union_with(&mut self, other: BitSetView<'_>) {
+        debug_assert_eq!(self.base, other.base);
+        if self.words.len() < other.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (word, other) in self.words.iter_mut().zip(other.words) {
+            *word |= other;
+        }
+    }
+
+    /// Remove all indices of `other`.
+    pub fn difference_with(&mut self, other: BitSetView<'_>) {
+        debug_assert_eq!(self.base, other.base);
+        for (word, other) in self.words.iter_mut().zip(other.words) {
+            *word &= !other;
+        }
+    }
+
+    /// A read-only view of the set.
+    pub fn view(&self) -> BitSetView<'_> {
+        BitSetView {
+            words: &self.words,
+            base: self.base,
+        }
+    }
+
+    /// The words of the set; see `BitSet` for how indices map to bits.
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    /// The entity index of the first bit.
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    /// Check if the given entity index is in the set.
+    pub fn contains(&self, index: u32) -> bool {
+        self.view().contains(index)
+    }
+
+    /// The number of indices in the set.
+    pub fn len(&self) -> usize {
+        self.view().len()
+    }
+
+    /// Check if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.view().is_empty()
+    }
+
+    /// Iterate over the entity indices in the set in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.view().iter()
+    }
+}
+
+/// A read-only view of a `BitSet`, e.g. the alive entities borrowed from `Entities::alive_bits()`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BitSetView<'a> {
+    words: &'a [u64],
+    base: u32,
+}
+
+impl<'a> BitSetView<'a> {
+    /// The words of the set; see `BitSet` for how indices map to bits.
+    pub fn words(&self) -> &'a [u64] {
+        self.words
+    }
+
+    /// The entity index of the first bit.
+    pub fn base(&self) -> u32 {
+        self.base
+    }
+
+    /// Check if the given entity index is in the set.
+    pub fn contains(&self, index: u32) -> bool {
+        let bit = match index.checked_sub(self.base) {
+            Some(bit) => bit,
+            None => return false,
+        };
+        self.words
+            .get((bit / 64) as usize)
+            .is_some_and(|word| word & (1 << (bit % 64)) != 0)
+    }
+
+    /// The number of indices in the set.
+    pub fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// Check if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+
+    /// Iterate over the entity indices in the set in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = u32> + 'a {
+        let base = self.base;
+        self.words.iter().enumerate().flat_map(move |(i, word)| {
+            let mut word = *word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros();
+                word &= word - 1;
+                Some(base + i as u32 * 64 + bit)
+            })
+        })
+    }
+
+    /// Copy the view into an owned set, e.g. as the start of set operations.
+    pub fn to_bit_set(&self) -> BitSet {
+        BitSet {
+            words: self.words.to_vec(),
+            base: self.base,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn combines_sets() {
+        let mut a = BitSet::new(10);
+        for index in [10, 12, 80, 140] {
+            a.insert(index);
+        }
+        let mut b = BitSet::new(10);
+        for index in [12, 80, 200] {
+            b.insert(index);
+        }
+        assert_eq!(a.len(), 4);
+        assert!(a.contains(140) && !a.contains(141) && !a.contains(3));
+
+        let mut both = a.clone();
+        both.intersect_with(b.view());
+        assert_eq!(both.iter().collect::<Vec<_>>(), vec![12, 80]);
+
+        let mut either = a.clone();
+        either.union_with(b.view());
+        assert_eq!(
+            either.iter().collect::<Vec<_>>(),
+            vec![10, 12, 80, 140, 200]
+        );
+
+        a.difference_with(b.view());
+        a.remove(140);
+        assert_eq!(a.iter().collect::<Vec<_>>(), vec![10]);
+
+        a.reset(0);
+        assert!(a.is_empty());
+    }
+}
@@ -0,0 +1,99 @@
+use crate::entity::Entity;
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Storage that records the tick each component was last modified at; implemented for `VecStorage`,
+/// `DoubleBuffered` and `IndexedStorage`. See `VecStorage::set_tick()`.
+pub trait TrackChanges<T> {
+    /// The current tick of the storage.
+    fn tick(&self) -> u32;
+
+    /// List the alive entities whose component was set, mutably accessed or removed at one of the given
+    /// ticks, by index, with their current component or None if it was removed.
+    fn changes_in(&self, ticks: Range<u32>) -> Vec<(Entity, Option<&T>)>;
+}
+
+/// The position of one consumer in the changes of a World, e.g. one system that polls for moved entities.
+/// Every consumer keeps its own cursor, so independent systems track changes at their own pace without
+/// clearing a shared dirty flag out from under each other.
+///
+/// A cursor remembers the first tick it hasn't reported yet per component type. Changes are reported
+/// once their tick is over, i.e. after `set_tick()` advanced past it, so components modified in the same
+/// tick after a check aren't missed. A component type the cursor hasn't seen yet reports all changes
+/// before the current tick; Worlds generated by `genesis` create cursors starting at the current tick of
+/// every storage with `change_cursor()`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ChangeCursor {
+    next: HashMap<TypeId, u32>,
+}
+
+impl ChangeCursor {
+    /// Create a cursor that hasn't reported any changes yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Report changes of components of type T from the given tick on, skipping all earlier changes.
+    pub fn start_at<T: 'static>(&mut self, tick: u32) {
+        self.next.insert(TypeId::of::<T>(), tick);
+    }
+
+    /// The first tick whose changes of components of type T haven't been reported yet, if the cursor
+    /// has seen the type.
+    pub fn position<T: 'static>(&self) -> Option<u32> {
+        self.next.get(&TypeId::of::<T>()).copied()
+    }
+
+    /// List the changes of the components in the given storage since the last call for type T,
+    /// and advance the cursor to the current tick of the storage; see `TrackChanges::changes_in()`.
+    pub fn changes<'s, T: 'static, S: TrackChanges<T>>(
+        &mut self,
+        storage: &'s S,
+    ) -> Vec<(Entity, Option<&'s T>)> {
+        let current = storage.tick();
+        let next = self.next.entry(TypeId::of::<T>()).or_insert(0);
+        let ticks = *next..current;
+        *next = current;
+        if ticks.is_empty() {
+            return vec![];
+        }
+        storage.changes_in(ticks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Entities, VecStorage};
+    use std::sync::{Arc, RwLock};
+
+    #[test]
+    fn cursors_advance_independently() {
+        let entities = Arc::new(RwLock::new(Entities::new(3)));
+        let mut vec = VecStorage::<u32>::new(Arc::clone(&entities), 3);
+        let (first, second) = {
+            let mut lock = entities.write().unwrap();
+            (lock.spawn(), lock.spawn())
+        };
+        let mut fast = ChangeCursor::new();
+        fast.start_at::<u32>(vec.tick());
+        let mut slow = fast.clone();
+
+        vec.set(first, 1).unwrap();
+        assert!(fast.changes(&vec).is_empty());
+        vec.set(second, 2).unwrap();
+        vec.set_tick(1);
+        assert_eq!(
+            fast.changes(&vec),
+            vec![(first, Some(&1)), (second, Some(&2))]
+        );
+
+        vec.remove(first).unwrap();
+        vec.set_tick(2);
+        assert_eq!(fast.changes(&vec), vec![(first, None)]);
+        assert!(fast.changes(&vec).is_empty());
+        assert_eq!(slow.changes(&vec), vec![(first, None), (second, Some(&2))]);
+        assert_eq!(slow.position::<u32>(), Some(2));
+    }
+}
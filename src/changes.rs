@@ -0,0 +1,28 @@
+use crate::entity::Entity;
+use serde::{Deserialize, Serialize};
+
+/// The changes of a World after a tick, created by `changes_since()` and applied by `apply_changes()` on
+/// Worlds generated by `genesis` that derive `Clone`, `Serialize` and `Deserialize`.
+/// Serialize it with any serde format, e.g. for autosaves or resyncing a co-op session, instead of
+/// serializing the whole World every time.
+///
+/// Changes are based on the ticks recorded by the storages; see `VecStorage::set_tick()`.
+/// `MapStorage`, `MmapStorage` and `StableStorage` don't record ticks, so all of their components are always included.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ChangeSet<C, K> {
+    /// The tick the changes were recorded after.
+    pub since: u32,
+    /// All alive entities of the World.
+    pub alive: Vec<Entity>,
+    /// The components that were set or mutably accessed after the tick, as the component enum.
+    pub set: Vec<(Entity, C)>,
+    /// The kinds of components that were removed from alive entities after the tick.
+    pub removed: Vec<(Entity, K)>,
+}
+
+impl<C, K> ChangeSet<C, K> {
+    /// Check if no component was set or removed; entities may still have been spawned or despawned.
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty() && self.removed.is_empty()
+    }
+}
@@ -0,0 +1,7 @@
+/// Type that all components of type T can be removed from at once; implemented for all Worlds
+/// generated by `genesis` for each of their component types.
+pub trait ClearComponent<T> {
+    /// Remove the component of type T from all alive entities.
+    /// Returns the number of removed components.
+    fn clear_component(&mut self) -> usize;
+}
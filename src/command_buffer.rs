@@ -0,0 +1,87 @@
+use crate::entity::Entity;
+use crate::Despawn;
+use crate::Register;
+
+type Command<W> = Box<dyn FnOnce(&mut W)>;
+
+/// A list of deferred operations on a World, applied in the order they were recorded.
+/// Used to change a World while iterating over it, e.g. in `iter_with()` of generated Worlds,
+/// without having to lock its entities for writing during the iteration.
+pub struct CommandBuffer<W> {
+    commands: Vec<Command<W>>,
+}
+
+impl<W> Default for CommandBuffer<W> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W> CommandBuffer<W> {
+    /// Create an empty CommandBuffer.
+    pub fn new() -> Self {
+        Self { commands: vec![] }
+    }
+
+    /// Record an arbitrary operation on the World.
+    pub fn push(&mut self, command: impl FnOnce(&mut W) + 'static) {
+        self.commands.push(Box::new(command));
+    }
+
+    /// Record registering the given item for the given entity.
+    /// If the entity doesn't exist by the time the command is applied, the item is dropped.
+    pub fn register<T: 'static>(&mut self, entity: Entity, item: T)
+    where
+        W: Register<T>,
+    {
+        self.push(move |world| {
+            let _ = world.register(entity, item);
+        });
+    }
+
+    /// Record despawning the given entity.
+    /// If the entity doesn't exist by the time the command is applied, this does nothing.
+    pub fn despawn(&mut self, entity: Entity)
+    where
+        W: Despawn,
+    {
+        self.push(move |world| {
+            let _ = world.despawn(entity);
+        });
+    }
+
+    /// The number of recorded commands.
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Check if no commands were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Apply all recorded commands to the given World, leaving self empty.
+    pub fn apply(&mut self, world: &mut W) {
+        for command in self.commands.drain(..) {
+            command(world);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_are_applied_in_order() {
+        let mut commands = CommandBuffer::<Vec<i32>>::new();
+        commands.push(|v| v.push(1));
+        commands.push(|v| v.push(2));
+        assert_eq!(commands.len(), 2);
+
+        let mut v = vec![];
+        commands.apply(&mut v);
+        assert_eq!(v, vec![1, 2]);
+        assert!(commands.is_empty());
+    }
+}
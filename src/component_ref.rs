@@ -0,0 +1,95 @@
+use crate::entity::Entity;
+use crate::has_component::HasComponent;
+use crate::map_entities::MapEntities;
+use crate::storage::Storage;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+/// A weak handle to the component of type T of an entity, e.g. for an AI blackboard caching
+/// "the position of my target" across frames.
+///
+/// The handle only stores the entity; the component type selects the storage of any World implementing
+/// `HasComponent<T>`. Resolving it validates the generation of the entity, so a handle to a despawned
+/// entity resolves to `None` even after its index has been reused.
+/// Handles are `Copy` and serialize as their entity, so they can be stored in components; they implement
+/// `MapEntities` for components marked with `#[component(maps_entities)]`.
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ComponentRef<T> {
+    entity: Entity,
+    #[serde(skip)]
+    component: PhantomData<fn() -> T>,
+}
+
+impl<T> ComponentRef<T> {
+    /// Create a handle to the component of type T of the given entity.
+    pub fn new(entity: Entity) -> Self {
+        Self {
+            entity,
+            component: PhantomData,
+        }
+    }
+
+    /// The entity whose component is referenced.
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+
+    /// Get the referenced component, or `None` if the entity was despawned or has no component of type T.
+    pub fn resolve<'w, W: HasComponent<T>>(&self, world: &'w W) -> Option<&'w T> {
+        world.storage().get(self.entity)
+    }
+
+    /// Get the referenced component mutably, or `None` if the entity was despawned or has no component
+    /// of type T.
+    pub fn resolve_mut<'w, W: HasComponent<T>>(&self, world: &'w mut W) -> Option<&'w mut T> {
+        world.storage_mut().get_mut(self.entity)
+    }
+}
+
+impl<T> Clone for ComponentRef<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ComponentRef<T> {}
+
+impl<T> PartialEq for ComponentRef<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.entity == other.entity
+    }
+}
+
+impl<T> Eq for ComponentRef<T> {}
+
+impl<T> Hash for ComponentRef<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.entity.hash(state);
+    }
+}
+
+impl<T> Debug for ComponentRef<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "ComponentRef<{}>({})",
+            std::any::type_name::<T>(),
+            self.entity
+        )
+    }
+}
+
+impl<T> From<Entity> for ComponentRef<T> {
+    fn from(entity: Entity) -> Self {
+        Self::new(entity)
+    }
+}
+
+impl<T> MapEntities for ComponentRef<T> {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity) {
+        self.entity = mapper(self.entity);
+    }
+}
@@ -0,0 +1,129 @@
+//! Text commands for debug consoles, executed against any World implementing `DebugWorld`.
+//!
+//! Supported commands:
+//! - `list`: list all alive entities.
+//! - `list <component>`: list all alive entities with the given component and its value.
+//! - `get <entity>`: show all components of the given entity.
+//! - `get <entity> <component>`: show the given component of the given entity.
+//! - `despawn <entity>`: despawn the given entity.
+//!
+//! Entities are given as `index:generation`, e.g. `12:0`, and components by their storage field name,
+//! template name or type name.
+
+use crate::entity::{Entity, ParseEntityError};
+use crate::no_such_entity::NoSuchEntity;
+use crate::registry::DebugWorld;
+use std::fmt::Write;
+use thiserror::Error;
+
+/// Error returned by `execute()` for commands that can't be executed.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum ConsoleError {
+    /// The command is empty.
+    #[error("empty command; expected `list`, `get` or `despawn`")]
+    EmptyCommand,
+    /// The command isn't supported.
+    #[error("unknown command `{0}`; expected `list`, `get` or `despawn`")]
+    UnknownCommand(String),
+    /// A required argument is missing, e.g. the entity of `get`.
+    #[error("missing argument `{0}`")]
+    MissingArgument(&'static str),
+    /// The command got more arguments than it takes.
+    #[error("unexpected argument `{0}`")]
+    UnexpectedArgument(String),
+    /// An argument is not a valid entity.
+    #[error(transparent)]
+    InvalidEntity(#[from] ParseEntityError),
+    /// The World has no component with the given name.
+    #[error("unknown component `{0}`")]
+    UnknownComponent(String),
+    /// The entity doesn't exist.
+    #[error(transparent)]
+    NoSuchEntity(#[from] NoSuchEntity),
+    /// The entity doesn't have the given component.
+    #[error("entity {entity} has no component `{component}`")]
+    MissingComponent {
+        entity: Entity,
+        component: &'static str,
+    },
+}
+
+/// Execute the given command against the given World and return its output, one line per entity
+/// or component. See the module documentation for the supported commands.
+pub fn execute(world: &mut dyn DebugWorld, command: &str) -> Result<String, ConsoleError> {
+    let mut words = command.split_whitespace();
+    let name = words.next().ok_or(ConsoleError::EmptyCommand)?;
+    let first = words.next();
+    let second = words.next();
+    if let Some(argument) = words.next() {
+        return Err(ConsoleError::UnexpectedArgument(argument.to_owned()));
+    }
+
+    let mut output = String::new();
+    match (name, first, second) {
+        ("list", None, _) => {
+            for entity in world.alive_entities() {
+                let _ = writeln!(output, "{}", entity);
+            }
+        }
+        ("list", Some(component), None) => {
+            let storage = storage_name(world, component)?;
+            for entity in world.alive_entities() {
+                for (_, value) in world
+                    .debug_components(entity)
+                    .into_iter()
+                    .filter(|(name, _)| *name == storage)
+                {
+                    let _ = writeln!(output, "{} {}", entity, value);
+                }
+            }
+        }
+        ("get", None, _) => return Err(ConsoleError::MissingArgument("entity")),
+        ("get", Some(entity), component) => {
+            let entity = entity.parse::<Entity>()?;
+            if !world.alive_entities().contains(&entity) {
+                return Err(NoSuchEntity::new(entity).with_operation("get").into());
+            }
+            let components = world.debug_components(entity);
+            match component {
+                None => {
+                    for (storage, value) in components {
+                        let _ = writeln!(output, "{}: {}", storage, value);
+                    }
+                }
+                Some(component) => {
+                    let storage = storage_name(world, component)?;
+                    let (_, value) = components
+                        .into_iter()
+                        .find(|(name, _)| *name == storage)
+                        .ok_or(ConsoleError::MissingComponent {
+                            entity,
+                            component: storage,
+                        })?;
+                    let _ = writeln!(output, "{}", value);
+                }
+            }
+        }
+        ("despawn", None, _) => return Err(ConsoleError::MissingArgument("entity")),
+        ("despawn", Some(entity), None) => {
+            let entity = entity.parse::<Entity>()?;
+            world.despawn_entity(entity)?;
+            let _ = writeln!(output, "despawned {}", entity);
+        }
+        ("list", Some(_), Some(argument)) | ("despawn", Some(_), Some(argument)) => {
+            return Err(ConsoleError::UnexpectedArgument(argument.to_owned()))
+        }
+        (name, _, _) => return Err(ConsoleError::UnknownCommand(name.to_owned())),
+    }
+    Ok(output)
+}
+
+/// The storage field name of the component with the given name.
+fn storage_name(world: &dyn DebugWorld, component: &str) -> Result<&'static str, ConsoleError> {
+    world
+        .component_names()
+        .iter()
+        .find(|names| names.matches(component))
+        .map(|names| names.storage)
+        .ok_or_else(|| ConsoleError::UnknownComponent(component.to_owned()))
+}
@@ -0,0 +1,17 @@
+use crate::entity::Entity;
+use crate::NoSuchEntity;
+
+/// Type that entities can be despawned from; implemented for all Worlds generated by `genesis`.
+pub trait Despawn {
+    /// Despawn the given entity and remove all of its components.
+    /// Returns Err(NoSuchEntity) if the given entity doesn't exist.
+    fn despawn(&mut self, entity: Entity) -> Result<(), NoSuchEntity>;
+}
+
+/// A component that points to another entity, e.g. the owner of an item.
+/// Components declared with `#[component(transfer_to = <relation>)]` are moved to the target of the
+/// given relation component when their entity is despawned.
+pub trait Relation {
+    /// The entity this component points to.
+    fn target(&self) -> Entity;
+}
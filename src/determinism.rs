@@ -0,0 +1,107 @@
+use crate::world_eq::{WorldEq, WorldMismatch};
+use std::fmt::{Display, Formatter};
+use thiserror::Error;
+
+/// The first step after which two Worlds run by `check_determinism()` differed.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub struct Divergence {
+    /// The tick passed to the system in the step that caused the divergence.
+    pub tick: u32,
+    /// All differences between the Worlds after that step; never empty.
+    pub mismatches: Vec<WorldMismatch>,
+}
+
+impl Divergence {
+    /// The first difference between the Worlds, e.g. the first entity or component that diverged.
+    pub fn first(&self) -> &WorldMismatch {
+        &self.mismatches[0]
+    }
+}
+
+impl Display for Divergence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "worlds diverged at tick {}:", self.tick)?;
+        for mismatch in &self.mismatches {
+            write!(f, "\n  {}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check that a system is deterministic, e.g. to hunt desyncs in lockstep multiplayer.
+/// Creates two Worlds with `setup`, which must create identical Worlds, e.g. from the same seed,
+/// and runs `system` on both of them for the given number of steps, passing the tick of each step.
+/// Returns the first divergence, comparing the Worlds entity by entity using `WorldEq` after every step.
+pub fn check_determinism<W: WorldEq>(
+    mut setup: impl FnMut() -> W,
+    steps: u32,
+    mut system: impl FnMut(&mut W, u32),
+) -> Result<(), Divergence> {
+    let mut left = setup();
+    let mut right = setup();
+    for tick in 0..steps {
+        system(&mut left, tick);
+        system(&mut right, tick);
+        let mismatches = left.mismatches(&right);
+        if !mismatches.is_empty() {
+            return Err(Divergence { tick, mismatches });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Entities;
+
+    struct Counter {
+        entities: Entities,
+        count: u32,
+    }
+
+    impl WorldEq for Counter {
+        fn mismatches(&self, other: &Self) -> Vec<WorldMismatch> {
+            let entity = self.entities.iter().next().unwrap();
+            WorldMismatch::component(entity, "count", Some(&self.count), Some(&other.count))
+                .into_iter()
+                .collect()
+        }
+    }
+
+    fn setup() -> Counter {
+        let mut entities = Entities::new(1);
+        entities.spawn();
+        Counter { entities, count: 0 }
+    }
+
+    #[test]
+    fn reports_first_divergent_tick() {
+        assert_eq!(
+            check_determinism(setup, 10, |world, tick| world.count += tick),
+            Ok(())
+        );
+
+        let mut calls = 0;
+        let divergence = check_determinism(setup, 10, |world, tick| {
+            calls += 1;
+            if calls == 8 {
+                world.count += 1;
+            }
+            world.count += tick;
+        })
+        .unwrap_err();
+        assert_eq!(divergence.tick, 3);
+        assert!(matches!(
+            divergence.first(),
+            WorldMismatch::Component {
+                storage: "count",
+                ..
+            }
+        ));
+        assert_eq!(
+            divergence.to_string(),
+            "worlds diverged at tick 3:\n  entity 0:0 differs in `count`: left 6, right 7"
+        );
+    }
+}
@@ -0,0 +1,269 @@
+use crate::change_cursor::TrackChanges;
+use crate::entity::Entity;
+use crate::integrity::IntegrityError;
+use crate::layout::MemoryUsage;
+use crate::map_entities::MapEntities;
+use crate::storage_error::StorageError;
+use crate::Entities;
+use crate::Storage;
+use crate::VecStorage;
+use std::sync::{Arc, RwLock};
+use std::thread::ThreadId;
+
+/// A storage type that keeps two buffers of components: reads see the values of the previous frame,
+/// while writes go to the buffer of the next frame. `flip()` makes the written values visible and
+/// starts the next frame with a copy of them, so systems can read consistent previous-frame state.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DoubleBuffered<T> {
+    front: VecStorage<T>,
+    back: VecStorage<T>,
+}
+
+impl<T> DoubleBuffered<T> {
+    /// Create a new DoubleBuffered<T> with the specified initial capacity.
+    pub fn new(entities: Arc<RwLock<Entities>>, capacity: u32) -> Self {
+        Self {
+            front: VecStorage::new(Arc::clone(&entities), capacity),
+            back: VecStorage::new(entities, capacity),
+        }
+    }
+
+    /// Get a reference to the component of the previous frame associated with the given entity, if any.
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.front.get(entity)
+    }
+
+    /// Get a mutable reference to the component of the next frame associated with the given entity, if any.
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.back.get_mut(entity)
+    }
+
+    /// Get a reference to the component of the next frame associated with the given entity, if any.
+    pub fn get_next(&self, entity: Entity) -> Option<&T> {
+        self.back.get(entity)
+    }
+
+    /// Get a reference to the component of the previous frame associated with the given entity, if any.
+    /// Does not check if the entity exists; only use this if you know it exists.
+    pub fn get_unchecked(&self, entity: Entity) -> Option<&T> {
+        self.front.get_unchecked(entity)
+    }
+
+    /// Get a mutable reference to the component of the next frame associated with the given entity, if any.
+    /// Does not check if the entity exists; only use this if you know it exists.
+    pub fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T> {
+        self.back.get_mut_unchecked(entity)
+    }
+
+    /// Set the component of the next frame for the given entity.
+    /// Returns Err(StorageError::NoSuchEntity) if the given entity doesn't exist.
+    /// Otherwise, returns Ok(data), where data is previous data of the next frame evicted by this operation (if any).
+    pub fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        self.back.set(entity, data)
+    }
+
+    /// Set the component of the next frame for the given entity.
+    /// Returns the previous data of the next frame stored in self for the given entity.
+    /// Does not check if the entity exists and doesn't lock the entities; only use this if you know it exists.
+    pub fn set_unchecked(&mut self, entity: Entity, data: T) -> Option<T> {
+        self.back.set_unchecked(entity, data)
+    }
+
+    /// Remove the component of the next frame for the given entity.
+    /// Returns the previous data of the next frame associated with the given entity in self.
+    pub fn remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        self.back.remove(entity)
+    }
+
+    /// Remove the components of both frames of the given entity after it was despawned.
+    /// Returns the data of the next frame associated with the given entity in self, if any.
+    /// Components set for another entity at the same index are left untouched.
+    pub fn remove_for_despawn(&mut self, entity: Entity) -> Option<T> {
+        self.front.remove_for_despawn(entity);
+        self.back.remove_for_despawn(entity)
+    }
+
+    /// Remove the components of both frames of the given entity after it was despawned, e.g. to move them to
+    /// another World; see `set_frames_unchecked()`. Returns the data of the previous and of the next frame.
+    pub fn remove_frames_for_despawn(&mut self, entity: Entity) -> (Option<T>, Option<T>) {
+        (
+            self.front.remove_for_despawn(entity),
+            self.back.remove_for_despawn(entity),
+        )
+    }
+
+    /// Set the components of both frames for the given entity, e.g. after moving it from another World.
+    /// Does not check if the entity exists and doesn't lock the entities; only use this if you know it exists.
+    pub fn set_frames_unchecked(&mut self, entity: Entity, previous: Option<T>, next: Option<T>) {
+        if let Some(previous) = previous {
+            self.front.set_unchecked(entity, previous);
+        }
+        if let Some(next) = next {
+            self.back.set_unchecked(entity, next);
+        }
+    }
+
+    /// Remove the components of both frames for the given entity.
+    /// Returns the previous data of the next frame associated with the given entity in self.
+    #[deprecated(
+        note = "use `remove_for_despawn`, which leaves components of newer entities untouched"
+    )]
+    pub fn remove_unchecked(&mut self, entity: Entity) -> Option<T> {
+        self.remove_for_despawn(entity)
+    }
+
+    /// Only allow mutating the components of both frames from the given thread, or from any thread for None;
+    /// see `VecStorage::bind_to_thread()`.
+    pub fn bind_to_thread(&mut self, thread: Option<ThreadId>) {
+        self.front.bind_to_thread(thread);
+        self.back.bind_to_thread(thread);
+    }
+
+    /// The memory used by self; components are those of the next frame, slots and bytes those of both.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let (front, back) = (self.front.memory_usage(), self.back.memory_usage());
+        MemoryUsage {
+            components: back.components,
+            slots: front.slots + back.slots,
+            bytes: front.bytes + back.bytes,
+        }
+    }
+
+    /// Remove the data of both frames stored in self for all entities.
+    pub fn clear(&mut self) {
+        self.front.clear();
+        self.back.clear();
+    }
+
+    /// Remove the components of both frames of all alive entities from self, leaving the entities themselves untouched.
+    /// Returns the number of removed components of the next frame.
+    pub fn clear_checked(&mut self) -> usize {
+        self.front.clear_checked();
+        self.back.clear_checked()
+    }
+
+    /// Grow both frames to hold components for all entity indices below `index_end`;
+    /// see `VecStorage::grow_to()`.
+    pub fn grow_to(&mut self, index_end: u32) {
+        self.front.grow_to(index_end);
+        self.back.grow_to(index_end);
+    }
+
+    /// Set the current tick of both frames; see `VecStorage::set_tick()`.
+    pub fn set_tick(&mut self, tick: u32) {
+        self.front.set_tick(tick);
+        self.back.set_tick(tick);
+    }
+
+    /// The tick the component of the previous frame of the given entity was last modified at, if any.
+    pub fn last_modified(&self, entity: Entity) -> Option<u32> {
+        self.front.last_modified(entity)
+    }
+
+    /// List the alive entities whose component of the previous frame was modified after the given tick;
+    /// see `VecStorage::changes_since()`.
+    pub fn changes_since(&self, since: u32) -> Vec<(Entity, Option<&T>)> {
+        self.front.changes_since(since)
+    }
+
+    /// Make the components written for the next frame visible to reads and start the following frame
+    /// with a copy of them.
+    pub fn flip(&mut self)
+    where
+        T: Clone,
+    {
+        std::mem::swap(&mut self.front, &mut self.back);
+        self.back.clone_from(&self.front);
+    }
+
+    /// Rewrite the entities stored in the components of both frames; see `MapEntities`.
+    pub fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity)
+    where
+        T: MapEntities,
+    {
+        self.front.map_entities(mapper);
+        self.back.map_entities(mapper);
+    }
+
+    /// Check that both frames use the given entities and don't hold components for unallocated indices.
+    /// `storage` is the name of this storage used for error reporting.
+    pub fn check_integrity(
+        &self,
+        entities: &Arc<RwLock<Entities>>,
+        storage: &'static str,
+    ) -> Result<(), IntegrityError> {
+        self.front.check_integrity(entities, storage)?;
+        self.back.check_integrity(entities, storage)
+    }
+
+    /// Clone the data of both frames stored in self into a new DoubleBuffered<T> that uses the given entities.
+    pub fn clone_with_entities(&self, entities: Arc<RwLock<Entities>>) -> Self
+    where
+        T: Clone,
+    {
+        Self {
+            front: self.front.clone_with_entities(Arc::clone(&entities)),
+            back: self.back.clone_with_entities(entities),
+        }
+    }
+}
+
+impl<T> Storage<T> for DoubleBuffered<T> {
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.get(entity)
+    }
+
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_mut(entity)
+    }
+
+    fn get_unchecked(&self, entity: Entity) -> Option<&T> {
+        self.get_unchecked(entity)
+    }
+
+    fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_mut_unchecked(entity)
+    }
+}
+
+impl<T> TrackChanges<T> for DoubleBuffered<T> {
+    fn tick(&self) -> u32 {
+        self.front.tick()
+    }
+
+    /// Lists the changes of the previous frame; see `changes_since()`.
+    fn changes_in(&self, ticks: std::ops::Range<u32>) -> Vec<(Entity, Option<&T>)> {
+        self.front.changes_in(ticks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+    struct Cell(u32);
+
+    #[test]
+    fn writes_are_visible_after_flip() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(3)));
+        let mut cells = DoubleBuffered::<Cell>::new(Arc::clone(&entities), 3);
+        let entity = entities.write().unwrap().spawn();
+
+        cells.set(entity, Cell(1))?;
+        assert_eq!(cells.get(entity), None);
+        assert_eq!(cells.get_next(entity), Some(&Cell(1)));
+
+        cells.flip();
+        assert_eq!(cells.get(entity), Some(&Cell(1)));
+
+        cells.get_mut(entity).unwrap().0 += 1;
+        assert_eq!(cells.get(entity), Some(&Cell(1)));
+        cells.flip();
+        assert_eq!(cells.get(entity), Some(&Cell(2)));
+
+        assert_eq!(cells.remove_for_despawn(entity), Some(Cell(2)));
+        assert_eq!(cells.get(entity), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,314 @@
+use crate::bitset::BitSet;
+use crate::entity::Entity;
+use crate::integrity::IntegrityError;
+use crate::layout::MemoryUsage;
+use crate::map_entities::MapEntities;
+use crate::storage_error::StorageError;
+use crate::Entities;
+use crate::MapStorage;
+use crate::ModelStorage;
+use crate::Storage;
+use crate::StorageKind;
+use crate::VecStorage;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, RwLock};
+use std::thread::ThreadId;
+use thiserror::Error;
+
+/// Error returned by `DynamicStorage::migrate()` for storage kinds it can't switch to.
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq)]
+#[error("cannot migrate to a `{}` storage, expected `vec` or `map`", kind.name())]
+pub struct UnsupportedMigration {
+    pub kind: StorageKind,
+}
+
+enum Backend<T> {
+    Vec(VecStorage<T>),
+    Map(MapStorage<T>),
+}
+
+/// A storage type whose components are kept in a `VecStorage<T>` or a `MapStorage<T>`, which can be
+/// switched at runtime without losing data, e.g. when a long-running server finds that a component it
+/// expected to be rare is set for most entities.
+///
+/// Starts out as a `VecStorage<T>`; see `migrate()` and the generated `migrate_storage::<T>()` of Worlds.
+/// Unlike a plain `VecStorage<T>`, it doesn't record modification ticks, so it can't be used with
+/// `changes_since()` or change cursors.
+pub struct DynamicStorage<T> {
+    backend: Backend<T>,
+    entities: Arc<RwLock<Entities>>,
+}
+
+macro_rules! delegate {
+    ($self:expr, $storage:ident => $body:expr) => {
+        match $self {
+            Backend::Vec($storage) => $body,
+            Backend::Map($storage) => $body,
+        }
+    };
+}
+
+impl<T> DynamicStorage<T> {
+    /// Create a new DynamicStorage<T> backed by a `VecStorage<T>` with the specified initial capacity.
+    pub fn new(entities: Arc<RwLock<Entities>>, capacity: u32) -> Self {
+        Self {
+            backend: Backend::Vec(VecStorage::new(Arc::clone(&entities), capacity)),
+            entities,
+        }
+    }
+
+    /// Iterate over the alive entities of the given entities that have a component in self, by index.
+    fn alive_components<'a>(
+        &'a self,
+        entities: &'a Entities,
+    ) -> impl Iterator<Item = (Entity, &'a T)> + 'a {
+        entities
+            .iter()
+            .filter_map(move |entity| Some((entity, self.get_unchecked(entity)?)))
+    }
+
+    /// The kind of storage currently holding the components, `StorageKind::Vec` or `StorageKind::Map`.
+    pub fn kind(&self) -> StorageKind {
+        match self.backend {
+            Backend::Vec(_) => StorageKind::Vec,
+            Backend::Map(_) => StorageKind::Map,
+        }
+    }
+
+    /// Move all components into a storage of the given kind, keeping the entities they belong to.
+    /// Does nothing if self already uses that kind of storage.
+    /// Returns Err(UnsupportedMigration) for kinds other than `StorageKind::Vec` and `StorageKind::Map`.
+    pub fn migrate(&mut self, kind: StorageKind) -> Result<(), UnsupportedMigration> {
+        if !matches!(kind, StorageKind::Vec | StorageKind::Map) {
+            return Err(UnsupportedMigration { kind });
+        }
+        if kind == self.kind() {
+            return Ok(());
+        }
+        let placeholder = Backend::Map(MapStorage::new(Arc::clone(&self.entities)));
+        self.backend = match std::mem::replace(&mut self.backend, placeholder) {
+            Backend::Vec(storage) => Backend::Map(storage.into()),
+            Backend::Map(storage) => Backend::Vec(storage.into()),
+        };
+        Ok(())
+    }
+
+    /// Get a reference to the component associated with the given entity in self, if any.
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        delegate!(&self.backend, storage => storage.get(entity))
+    }
+
+    /// Get a mutable reference to the component associated with the given entity in self, if any.
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        delegate!(&mut self.backend, storage => storage.get_mut(entity))
+    }
+
+    /// Get a reference to the component associated with the given entity in self, if any.
+    /// Does not check if the entity exists; only use this if you know it exists.
+    pub fn get_unchecked(&self, entity: Entity) -> Option<&T> {
+        delegate!(&self.backend, storage => storage.get_unchecked(entity))
+    }
+
+    /// Get a mutable reference to the component associated with the given entity in self, if any.
+    /// Does not check if the entity exists; only use this if you know it exists.
+    pub fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T> {
+        delegate!(&mut self.backend, storage => storage.get_mut_unchecked(entity))
+    }
+
+    /// Set the component for the given entity.
+    /// Returns Err(StorageError::NoSuchEntity) if the given entity doesn't exist.
+    /// Otherwise, returns Ok(data), where data is previous data evicted by this operation (if any).
+    pub fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        delegate!(&mut self.backend, storage => storage.set(entity, data))
+    }
+
+    /// Set the component for the given entity.
+    /// Returns the previous data stored in self for the given entity.
+    /// Does not check if the entity exists and doesn't lock the entities; only use this if you know it exists.
+    pub fn set_unchecked(&mut self, entity: Entity, data: T) -> Option<T> {
+        delegate!(&mut self.backend, storage => storage.set_unchecked(entity, data))
+    }
+
+    /// Remove the component of the given entity after it was despawned.
+    /// Returns the data associated with the given entity in self, if any.
+    pub fn remove_for_despawn(&mut self, entity: Entity) -> Option<T> {
+        delegate!(&mut self.backend, storage => storage.remove_for_despawn(entity))
+    }
+
+    /// Remove the component for the given entity.
+    /// Returns the previous data associated with the given entity in self.
+    pub fn remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        delegate!(&mut self.backend, storage => storage.remove(entity))
+    }
+
+    /// Grow a Vec-backed storage to hold components for all entity indices below `index_end`; see
+    /// `VecStorage::grow_to()`. Does nothing while self is backed by a `MapStorage<T>`.
+    pub fn grow_to(&mut self, index_end: u32) {
+        if let Backend::Vec(storage) = &mut self.backend {
+            storage.grow_to(index_end);
+        }
+    }
+
+    /// Only allow mutating the components of self from the given thread, or from any thread for None;
+    /// see `VecStorage::bind_to_thread()`. The binding is kept when migrating.
+    pub fn bind_to_thread(&mut self, thread: Option<ThreadId>) {
+        delegate!(&mut self.backend, storage => storage.bind_to_thread(thread))
+    }
+
+    /// The memory used by self; see `MemoryUsage`.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        delegate!(&self.backend, storage => storage.memory_usage())
+    }
+
+    /// Remove the data stored in self for all entities.
+    pub fn clear(&mut self) {
+        delegate!(&mut self.backend, storage => storage.clear())
+    }
+
+    /// Remove the components of all alive entities from self, leaving the entities themselves untouched.
+    /// Returns the number of removed components.
+    pub fn clear_checked(&mut self) -> usize {
+        delegate!(&mut self.backend, storage => storage.clear_checked())
+    }
+
+    /// Rewrite the entities stored in all components of self; see `MapEntities`.
+    pub fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity)
+    where
+        T: MapEntities,
+    {
+        delegate!(&mut self.backend, storage => storage.map_entities(mapper))
+    }
+
+    /// Check that self uses the given entities and doesn't hold components for unallocated indices.
+    /// `storage` is the name of this storage used for error reporting.
+    pub fn check_integrity(
+        &self,
+        entities: &Arc<RwLock<Entities>>,
+        storage: &'static str,
+    ) -> Result<(), IntegrityError> {
+        delegate!(&self.backend, backend => backend.check_integrity(entities, storage))
+    }
+
+    /// Copy the data stored in self into a new DynamicStorage<T> of the same kind that uses the given entities.
+    pub fn clone_with_entities(&self, entities: Arc<RwLock<Entities>>) -> Self
+    where
+        T: Clone,
+    {
+        let backend = match &self.backend {
+            Backend::Vec(storage) => {
+                Backend::Vec(storage.clone_with_entities(Arc::clone(&entities)))
+            }
+            Backend::Map(storage) => {
+                Backend::Map(storage.clone_with_entities(Arc::clone(&entities)))
+            }
+        };
+        Self { backend, entities }
+    }
+}
+
+/// Clones share the same `Entities` as the original and use the same kind of storage.
+impl<T: Clone> Clone for DynamicStorage<T> {
+    fn clone(&self) -> Self {
+        self.clone_with_entities(Arc::clone(&self.entities))
+    }
+}
+
+/// Storages are equal if the same alive entities have equal components in them, regardless of the kind
+/// of storage holding them.
+impl<T: PartialEq> PartialEq for DynamicStorage<T> {
+    fn eq(&self, other: &Self) -> bool {
+        let left = self.entities.read().unwrap();
+        if Arc::ptr_eq(&self.entities, &other.entities) {
+            return self
+                .alive_components(&left)
+                .eq(other.alive_components(&left));
+        }
+        let right = other.entities.read().unwrap();
+        self.alive_components(&left)
+            .eq(other.alive_components(&right))
+    }
+}
+
+impl<T: Eq> Eq for DynamicStorage<T> {}
+
+impl<T: Debug> Debug for DynamicStorage<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        delegate!(&self.backend, storage => storage.fmt(f))
+    }
+}
+
+impl<T> Storage<T> for DynamicStorage<T> {
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.get(entity)
+    }
+
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_mut(entity)
+    }
+
+    fn get_unchecked(&self, entity: Entity) -> Option<&T> {
+        self.get_unchecked(entity)
+    }
+
+    fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_mut_unchecked(entity)
+    }
+
+    fn sparse_presence_into(&self, bits: &mut BitSet) -> bool {
+        match &self.backend {
+            Backend::Vec(_) => false,
+            Backend::Map(storage) => Storage::sparse_presence_into(storage, bits),
+        }
+    }
+}
+
+impl<T> ModelStorage<T> for DynamicStorage<T> {
+    fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        self.set(entity, data)
+    }
+
+    fn remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        self.remove(entity)
+    }
+
+    fn remove_for_despawn(&mut self, entity: Entity) -> Option<T> {
+        self.remove_for_despawn(entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_without_losing_components() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(4)));
+        let mut storage = DynamicStorage::<u32>::new(Arc::clone(&entities), 4);
+        let spawned = (0..4)
+            .map(|_| entities.write().unwrap().spawn())
+            .collect::<Vec<_>>();
+        storage.set(spawned[1], 1)?;
+        storage.set(spawned[3], 3)?;
+        let before = storage.clone();
+
+        storage.migrate(StorageKind::Map).unwrap();
+        assert_eq!(storage.kind(), StorageKind::Map);
+        assert_eq!(storage.get(spawned[1]), Some(&1));
+        assert_eq!(storage.get(spawned[0]), None);
+        assert_eq!(storage, before);
+
+        entities.write().unwrap().despawn(spawned[3])?;
+        let reused = entities.write().unwrap().spawn();
+        storage.migrate(StorageKind::Vec).unwrap();
+        assert_eq!(storage.kind(), StorageKind::Vec);
+        assert_eq!(storage.get(reused), None);
+        assert_eq!(storage.get(spawned[1]), Some(&1));
+        assert_eq!(
+            storage.migrate(StorageKind::Stable),
+            Err(UnsupportedMigration {
+                kind: StorageKind::Stable
+            })
+        );
+        Ok(())
+    }
+}
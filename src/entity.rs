@@ -1,14 +1,85 @@
+use crate::bitset::{BitSet, BitSetView};
+use crate::leaks::{LeakReport, LeakTracker, SpawnOrigin};
+use crate::lifecycle::{Lifecycle, LifecycleError};
+use crate::limit::{EntityLimit, EntityLimitReached, Watermark};
 use crate::no_such_entity::NoSuchEntity;
+use crate::thread_check::OwnerThread;
+use crate::trace;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::panic::Location;
+use std::str::FromStr;
+use std::thread::ThreadId;
+use thiserror::Error;
 
 /// An entity.
 #[derive(Eq, PartialEq, Debug, Clone, Copy, Hash, Serialize, Deserialize)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
 pub struct Entity {
     pub index: u32,
     pub generation: u32,
 }
 
+impl Entity {
+    /// An entity that is never spawned, e.g. returned by the C interface of a World when spawning fails.
+    /// It packs to `u64::MAX` by `to_bits()`.
+    pub const INVALID: Entity = Entity {
+        index: u32::MAX,
+        generation: u32::MAX,
+    };
+
+    /// Pack self into a single integer with the generation in the upper and the index in the lower
+    /// 32 bits, e.g. to pass it across an FFI boundary.
+    pub fn to_bits(self) -> u64 {
+        (u64::from(self.generation) << 32) | u64::from(self.index)
+    }
+
+    /// Unpack an entity packed by `to_bits()`.
+    pub fn from_bits(bits: u64) -> Self {
+        Self {
+            index: bits as u32,
+            generation: (bits >> 32) as u32,
+        }
+    }
+}
+
+/// Entities are displayed as `index:generation`, e.g. `12:0`; this format can be parsed back via `FromStr`.
+impl Display for Entity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.index, self.generation)
+    }
+}
+
+/// Error indicating that a string is not a valid entity in the `index:generation` format.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+#[error("invalid entity `{0}`, expected `index:generation`")]
+pub struct ParseEntityError(String);
+
+impl FromStr for Entity {
+    type Err = ParseEntityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseEntityError(s.to_owned());
+        let (index, generation) = s.trim().split_once(':').ok_or_else(error)?;
+        Ok(Entity {
+            index: index.parse().map_err(|_| error())?,
+            generation: generation.parse().map_err(|_| error())?,
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
 enum EntityIDEntry {
     Used(u32),
     Unused(u32),
@@ -22,17 +93,93 @@ impl Default for EntityIDEntry {
 
 impl EntityIDEntry {
     pub fn is_unused(&self) -> bool {
-        match self {
-            EntityIDEntry::Unused(_) => true,
-            _ => false,
-        }
+        matches!(self, EntityIDEntry::Unused(_))
+    }
+}
+
+/// Policy deciding which index is used when spawning a new entity.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
+pub enum ReusePolicy {
+    /// Always reuse the lowest free index. This is the default.
+    #[default]
+    LowestFree,
+    /// Reuse the most recently freed index first.
+    Lifo,
+    /// Only reuse an index after the given number of frames have passed since it was freed;
+    /// see `Entities::next_frame()`. This makes use-after-despawn bugs easier to catch,
+    /// since stale entities don't immediately alias new ones.
+    Delayed { frames: u32 },
+}
+
+/// Progress of an incremental clear; see `Entities::begin_clear()`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ClearProgress {
+    /// The number of entity slots processed so far.
+    pub processed: u32,
+    /// The total number of entity slots to process.
+    pub total: u32,
+}
+
+impl ClearProgress {
+    /// Check if the clear is complete.
+    pub fn is_done(&self) -> bool {
+        self.processed >= self.total
     }
 }
 
 /// A collection of entities.
-#[derive(Debug)]
+/// With the `rkyv` feature enabled, entities can be archived for zero-copy access.
+#[derive(Debug, Clone)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
 pub struct Entities {
     ids: Vec<EntityIDEntry>,
+    /// The index of the first slot in `ids`; see `with_index_base()`.
+    base: u32,
+    policy: ReusePolicy,
+    /// Free indices ready for reuse; not used for `ReusePolicy::LowestFree`.
+    free: Vec<u32>,
+    /// Freed indices waiting to become free again, with the frame they were freed in.
+    pending: VecDeque<(u32, u32)>,
+    frame: u32,
+    /// The next index and the end of the index range of an incremental clear in progress.
+    clearing: Option<(u32, u32)>,
+    /// The number of alive entities.
+    len: u32,
+    limit: Option<EntityLimit>,
+    /// The highest index an entity may be spawned at; see `with_max_index()`.
+    max_index: Option<u32>,
+    /// The origins of alive entities, if leak tracking is enabled.
+    #[cfg_attr(feature = "rkyv", with(rkyv::with::Skip))]
+    tracker: Option<LeakTracker>,
+    /// The entities despawned since the last drain, if despawn events are enabled.
+    #[cfg_attr(feature = "rkyv", with(rkyv::with::Skip))]
+    despawned: Option<Vec<Entity>>,
+    /// The lifecycle states of alive entities by slot, for those that aren't `Lifecycle::Alive`.
+    states: HashMap<u32, Lifecycle>,
+    /// The indices of alive entities; see `alive_bits()`.
+    alive: BitSet,
+    /// The entities in spawn order, if enabled; may contain despawned entities until it is compacted.
+    spawn_order: Option<Vec<Entity>>,
+    /// The number of despawned entities in `spawn_order`.
+    stale_order: u32,
+    /// One past the highest slot an entity was ever spawned in; see `high_water_mark()`.
+    high_water: u32,
+    /// The alive entities spawned via `spawn_if_absent()` by their key.
+    keys: HashMap<u64, Entity>,
+    /// The keys of alive entities spawned via `spawn_if_absent()` by slot, to forget them on despawn.
+    keyed: HashMap<u32, u64>,
+    /// The thread allowed to spawn and despawn entities with the `thread_checks` feature.
+    #[cfg_attr(feature = "rkyv", with(rkyv::with::Skip))]
+    owner: OwnerThread,
 }
 
 impl Entities {
@@ -40,52 +187,455 @@ impl Entities {
     pub fn new(capacity: u32) -> Self {
         let mut vec = vec![];
         vec.resize(capacity as usize, EntityIDEntry::Unused(0));
-        Self { ids: vec }
+        Self {
+            ids: vec,
+            base: 0,
+            policy: ReusePolicy::default(),
+            free: vec![],
+            pending: VecDeque::new(),
+            frame: 0,
+            clearing: None,
+            len: 0,
+            limit: None,
+            max_index: None,
+            tracker: None,
+            despawned: None,
+            states: HashMap::new(),
+            alive: BitSet::new(0),
+            spawn_order: None,
+            stale_order: 0,
+            high_water: 0,
+            keys: HashMap::new(),
+            keyed: HashMap::new(),
+            owner: OwnerThread::current(),
+        }
+    }
+
+    /// Start the indices of spawned entities at `base` instead of 0, e.g. to give each shard of a
+    /// partitioned World its own index range; see `Shards`. Storages must be created after this is set.
+    pub fn with_index_base(mut self, base: u32) -> Self {
+        self.base = base;
+        self.alive.reset(base);
+        self
+    }
+
+    /// The index of the first entity slot.
+    pub fn index_base(&self) -> u32 {
+        self.base
+    }
+
+    /// The slot in `ids` of the given entity index, if the index isn't below the base.
+    fn slot(&self, index: u32) -> Option<usize> {
+        index.checked_sub(self.base).map(|slot| slot as usize)
+    }
+
+    /// Cap the number of alive entities at `max`, reporting `Watermark::Warning` from `watermark()`
+    /// once `warn_at` entities are alive. Only `try_spawn()` respects the cap; `spawn()` ignores it.
+    pub fn with_limit(mut self, warn_at: u32, max: u32) -> Self {
+        self.limit = Some(EntityLimit { warn_at, max });
+        self
+    }
+
+    /// Set or remove the cap on the number of alive entities, e.g. for the entities of an existing World.
+    pub fn set_limit(&mut self, limit: Option<EntityLimit>) {
+        self.limit = limit;
+    }
+
+    /// Only allow spawning and despawning entities from the given thread, or from any thread for None, with the
+    /// `thread_checks` feature; entities are bound to the thread that created them. Does nothing without it.
+    pub fn bind_to_thread(&mut self, thread: Option<ThreadId>) {
+        self.owner = OwnerThread::new(thread);
+    }
+
+    /// The configured cap on the number of alive entities, if any.
+    pub fn limit(&self) -> Option<EntityLimit> {
+        self.limit
+    }
+
+    /// Never spawn entities at an index above `max`, e.g. the last index of the range of a shard; see `Shards`.
+    /// Once all indices up to `max` are in use, `try_spawn()` fails and `spawn()` panics, even if fewer
+    /// entities are alive than the indices allow because freed indices aren't reusable yet.
+    pub fn with_max_index(mut self, max: u32) -> Self {
+        self.max_index = Some(max);
+        self
+    }
+
+    /// The highest index an entity may be spawned at, if any; see `with_max_index()`.
+    pub fn max_index(&self) -> Option<u32> {
+        self.max_index
+    }
+
+    /// Check if the next spawned entity would get an index up to the configured max index, if any.
+    fn next_index_in_range(&self) -> bool {
+        let max = match self.max_index {
+            Some(max) => max,
+            None => return true,
+        };
+        let slot = match self.policy {
+            ReusePolicy::LowestFree => self.ids.iter().position(|id| id.is_unused()),
+            _ => self.free.last().map(|index| *index as usize),
+        }
+        .unwrap_or(self.ids.len());
+        u64::from(self.base) + slot as u64 <= u64::from(max)
+    }
+
+    /// The number of alive entities.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    /// Check if there are no alive entities.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// How close the number of alive entities is to the configured limit.
+    pub fn watermark(&self) -> Watermark {
+        match self.limit {
+            Some(limit) if self.len >= limit.max => Watermark::Full,
+            Some(limit) if self.len >= limit.warn_at => Watermark::Warning,
+            _ => Watermark::Normal,
+        }
+    }
+
+    /// Record where every entity is spawned so that long-lived entities can be listed by origin
+    /// via `report_leaks()`. This is meant for development builds since it costs a lookup per spawn.
+    pub fn with_leak_tracking(mut self) -> Self {
+        self.tracker = Some(LeakTracker::default());
+        self
+    }
+
+    /// Record every despawned entity until it is drained via `drain_despawned()`, e.g. to invalidate
+    /// external caches like a render scene graph or physics bodies without wrapping every despawn call.
+    /// Entities removed by `clear()` and incremental clears are recorded as well.
+    pub fn with_despawn_events(mut self) -> Self {
+        self.despawned = Some(vec![]);
+        self
+    }
+
+    /// Iterate over entities in the order they were spawned instead of by index, e.g. to resolve the
+    /// actions of a turn-based game in spawn order. This applies to `iter()` and everything built on it,
+    /// like queries. Entities that are already alive keep their index order.
+    pub fn with_spawn_order(mut self) -> Self {
+        self.spawn_order = Some(self.iter().collect());
+        self.stale_order = 0;
+        self
+    }
+
+    /// Check if entities are iterated in spawn order; see `with_spawn_order()`.
+    pub fn is_spawn_ordered(&self) -> bool {
+        self.spawn_order.is_some()
+    }
+
+    /// Take the entities despawned since the last drain, in despawn order.
+    /// Returns an empty list if despawn events aren't enabled; see `with_despawn_events()`.
+    pub fn drain_despawned(&mut self) -> Vec<Entity> {
+        self.despawned
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Use the given policy for reusing the indices of despawned entities.
+    /// Indices that are currently waiting to be reused become free immediately.
+    pub fn with_policy(mut self, policy: ReusePolicy) -> Self {
+        self.policy = policy;
+        self.pending.clear();
+        self.free = match policy {
+            ReusePolicy::LowestFree => vec![],
+            _ => (0..self.ids.len() as u32)
+                .rev()
+                .filter(|index| self.ids[*index as usize].is_unused())
+                .collect(),
+        };
+        self
+    }
+
+    /// The policy used for reusing the indices of despawned entities.
+    pub fn policy(&self) -> ReusePolicy {
+        self.policy
+    }
+
+    /// Advance the frame counter used by `ReusePolicy::Delayed`, making indices that were freed
+    /// long enough ago available for reuse.
+    pub fn next_frame(&mut self) {
+        self.frame = self.frame.wrapping_add(1);
+        if let ReusePolicy::Delayed { frames } = self.policy {
+            while let Some((freed_in, index)) = self.pending.front().copied() {
+                if self.frame.wrapping_sub(freed_in) < frames {
+                    break;
+                }
+                self.pending.pop_front();
+                self.free.push(index);
+            }
+        }
+    }
+
+    /// Find the index to use for the next spawned entity, if there is a free one.
+    fn next_free_index(&mut self) -> Option<usize> {
+        match self.policy {
+            ReusePolicy::LowestFree => self.ids.iter().position(|id| id.is_unused()),
+            _ => self.free.pop().map(|index| index as usize),
+        }
+    }
+
+    /// Mark the given index as unused and hand it to the reuse policy.
+    #[track_caller]
+    fn release(&mut self, index: usize, generation: u32) {
+        self.owner.check_entities("despawn");
+        self.ids[index] = EntityIDEntry::Unused(generation.wrapping_add(1));
+        self.len -= 1;
+        trace::entity(
+            "despawn",
+            Entity {
+                index: self.base + index as u32,
+                generation,
+            },
+        );
+        self.states.remove(&(index as u32));
+        if let Some(key) = self.keyed.remove(&(index as u32)) {
+            self.keys.remove(&key);
+        }
+        self.alive.remove(self.base + index as u32);
+        if let Some(tracker) = &mut self.tracker {
+            tracker.forget(self.base + index as u32);
+        }
+        if let Some(order) = &mut self.spawn_order {
+            self.stale_order += 1;
+            if self.stale_order > self.len {
+                let ids = &self.ids;
+                let base = self.base;
+                order.retain(|entity| {
+                    ids[(entity.index - base) as usize] == EntityIDEntry::Used(entity.generation)
+                });
+                self.stale_order = 0;
+            }
+        }
+        if let Some(despawned) = &mut self.despawned {
+            despawned.push(Entity {
+                index: self.base + index as u32,
+                generation,
+            });
+        }
+        match self.policy {
+            ReusePolicy::LowestFree => {}
+            ReusePolicy::Lifo => self.free.push(index as u32),
+            ReusePolicy::Delayed { frames: 0 } => self.free.push(index as u32),
+            ReusePolicy::Delayed { .. } => self.pending.push_back((self.frame, index as u32)),
+        }
     }
 
     /// Spawn a new entity. This will grow the collection if necessary.
+    /// With leak tracking enabled, the caller is recorded as the origin of the entity.
+    /// Panics if all indices up to the configured max index are in use; see `with_max_index()`.
+    #[track_caller]
     pub fn spawn(&mut self) -> Entity {
-        if let Some(index) = self.ids.iter().position(|id| id.is_unused()) {
+        let entity = self.allocate();
+        if let Some(tracker) = &mut self.tracker {
+            tracker.record(
+                entity.index,
+                SpawnOrigin::Caller(Location::caller()),
+                self.frame,
+            );
+        }
+        entity
+    }
+
+    /// Spawn a new entity like `spawn()` in the `Lifecycle::Spawning` state, e.g. to initialize its
+    /// components in a separate phase before update systems see it. See `activate()`.
+    #[track_caller]
+    pub fn spawn_staged(&mut self) -> Entity {
+        let entity = self.spawn();
+        self.states
+            .insert(entity.index - self.base, Lifecycle::Spawning);
+        entity
+    }
+
+    /// Spawn a new entity like `spawn()` unless the configured limit is reached or all indices up to the
+    /// configured max index are in use. In the latter case, the limit of the error is the number of indices.
+    #[track_caller]
+    pub fn try_spawn(&mut self) -> Result<Entity, EntityLimitReached> {
+        match (self.limit, self.max_index) {
+            (Some(limit), _) if self.len >= limit.max => {
+                Err(EntityLimitReached { limit: limit.max })
+            }
+            (_, Some(max)) if !self.next_index_in_range() => Err(EntityLimitReached {
+                limit: max.saturating_sub(self.base).saturating_add(1),
+            }),
+            _ => Ok(self.spawn()),
+        }
+    }
+
+    /// Spawn a new entity like `spawn()`, recording the given label as its origin if leak tracking is enabled.
+    pub fn spawn_labeled(&mut self, label: &'static str) -> Entity {
+        let entity = self.allocate();
+        if let Some(tracker) = &mut self.tracker {
+            tracker.record(entity.index, SpawnOrigin::Label(label), self.frame);
+        }
+        entity
+    }
+
+    /// Spawn a new entity like `spawn()` for the given key unless the entity spawned for it is still alive,
+    /// e.g. to handle network spawn messages that may be replayed or duplicated idempotently.
+    /// Returns the entity of the key and whether it was spawned by this call.
+    /// The key is forgotten when its entity is despawned, so it spawns a new entity afterwards.
+    #[track_caller]
+    pub fn spawn_if_absent(&mut self, key: u64) -> (Entity, bool) {
+        if let Some(entity) = self.keys.get(&key) {
+            return (*entity, false);
+        }
+        let entity = self.spawn();
+        self.keys.insert(key, entity);
+        self.keyed.insert(entity.index - self.base, key);
+        (entity, true)
+    }
+
+    /// The alive entity spawned via `spawn_if_absent()` for the given key, if any.
+    pub fn entity_for_key(&self, key: u64) -> Option<Entity> {
+        self.keys.get(&key).copied()
+    }
+
+    /// List the alive entities spawned at least `min_age` frames ago, grouped by origin with the most
+    /// frequent origins first. Returns an empty list if leak tracking isn't enabled; see `with_leak_tracking()`.
+    pub fn report_leaks(&self, min_age: u32) -> Vec<LeakReport> {
+        self.tracker
+            .as_ref()
+            .map(|tracker| tracker.report(self.frame, min_age))
+            .unwrap_or_default()
+    }
+
+    #[track_caller]
+    fn allocate(&mut self) -> Entity {
+        self.owner.check_entities("spawn");
+        assert!(
+            self.next_index_in_range(),
+            "all entity indices up to the max index {:?} are in use",
+            self.max_index
+        );
+        self.len += 1;
+        if let Some(index) = self.next_free_index() {
             match self.ids[index] {
                 EntityIDEntry::Unused(gen) => {
                     let entity_id = Entity {
                         generation: gen,
-                        index: index as u32,
+                        index: self.base + index as u32,
                     };
                     self.ids[index] = EntityIDEntry::Used(gen);
+                    self.high_water = self.high_water.max(index as u32 + 1);
+                    self.alive.insert(entity_id.index);
+                    trace::entity("spawn", entity_id);
+                    if let Some(order) = &mut self.spawn_order {
+                        order.push(entity_id);
+                    }
                     entity_id
                 }
                 _ => unreachable!(),
             }
         } else {
-            let next_idx = self.ids.len() as u32;
+            let next_idx = self.base + self.ids.len() as u32;
             let gen = 0;
             let entity_id = Entity {
                 index: next_idx,
                 generation: gen,
             };
             self.ids.push(EntityIDEntry::Used(gen));
+            self.high_water = self.ids.len() as u32;
+            self.alive.insert(next_idx);
+            trace::entity("spawn", entity_id);
+            if let Some(order) = &mut self.spawn_order {
+                order.push(entity_id);
+            }
             entity_id
         }
     }
 
-    /// Iterate over all existing entities.
+    /// Iterate over all existing entities, by index or in spawn order; see `with_spawn_order()`.
     pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
-        self.ids
-            .iter()
-            .enumerate()
-            .filter_map(|(i, entry)| match entry {
+        let base = self.base;
+        let by_index = match self.spawn_order {
+            Some(_) => None,
+            None => Some(self.ids.iter().enumerate()),
+        };
+        let by_spawn = self.spawn_order.iter().flatten().copied();
+        by_index
+            .into_iter()
+            .flatten()
+            .filter_map(move |(i, entry)| match entry {
                 EntityIDEntry::Used(gen) => Some(Entity {
-                    index: i as u32,
+                    index: base + i as u32,
                     generation: *gen,
                 }),
                 _ => None,
             })
+            .chain(by_spawn.filter(move |entity| self.exists(*entity)))
+    }
+
+    /// Copy all existing entities into an owned iterator that doesn't borrow self, e.g. to return it from a
+    /// function after releasing the lock of the entities.
+    pub fn iter_snapshot(&self) -> std::vec::IntoIter<Entity> {
+        let mut snapshot = Vec::with_capacity(self.len as usize);
+        self.snapshot_into(&mut snapshot);
+        snapshot.into_iter()
+    }
+
+    /// Replace the contents of the given buffer with all existing entities.
+    /// Reusing the same buffer avoids allocating a new snapshot every time.
+    pub fn snapshot_into(&self, buffer: &mut Vec<Entity>) {
+        buffer.clear();
+        buffer.extend(self.iter());
+    }
+
+    /// The indices of all alive entities as a bit set, e.g. for set algebra with the presence bit sets
+    /// of storages; see `VecStorage::presence_into()`. Kept up to date on every spawn and despawn.
+    pub fn alive_bits(&self) -> BitSetView<'_> {
+        self.alive.view()
+    }
+
+    /// Pick `n` distinct alive entities uniformly at random, or all of them if at most `n` are alive,
+    /// e.g. for AI target selection. `rng` must return uniformly distributed random numbers, e.g.
+    /// `|| rng.next_u32()` with any random number generator.
+    /// Draws random indices and rejects free ones instead of collecting all entities first, unless
+    /// most entities are picked or most indices are free. The entities are in no particular order.
+    pub fn sample(&self, mut rng: impl FnMut() -> u32, n: u32) -> Vec<Entity> {
+        if n >= self.len {
+            return self.iter().collect();
+        }
+
+        let slots = self.slot_count();
+        let mut sample = Vec::with_capacity(n as usize);
+        if n > self.len / 2 || self.len < slots / 4 {
+            // Selection sampling: pick each entity with probability needed / remaining.
+            let mut remaining = self.len;
+            for entity in self.iter() {
+                if sample.len() as u32 == n {
+                    break;
+                }
+                if uniform(&mut rng, remaining) < n - sample.len() as u32 {
+                    sample.push(entity);
+                }
+                remaining -= 1;
+            }
+            return sample;
+        }
+
+        let mut picked = HashSet::with_capacity(n as usize);
+        while sample.len() < n as usize {
+            let slot = uniform(&mut rng, slots);
+            if let EntityIDEntry::Used(generation) = self.ids[slot as usize] {
+                if picked.insert(slot) {
+                    sample.push(Entity {
+                        index: self.base + slot,
+                        generation,
+                    });
+                }
+            }
+        }
+        sample
     }
 
     /// Check if an entity exists.
     pub fn exists(&self, id: Entity) -> bool {
-        if let Some(entry) = self.ids.get(id.index as usize) {
+        if let Some(entry) = self.slot(id.index).and_then(|slot| self.ids.get(slot)) {
             match entry {
                 EntityIDEntry::Unused(_) => false,
                 EntityIDEntry::Used(generation) => *generation == id.generation,
@@ -95,31 +645,404 @@ impl Entities {
         }
     }
 
+    /// The lifecycle state of the given entity, or `None` if it doesn't exist.
+    pub fn lifecycle(&self, entity: Entity) -> Option<Lifecycle> {
+        if !self.exists(entity) {
+            return None;
+        }
+        let slot = entity.index - self.base;
+        Some(self.states.get(&slot).copied().unwrap_or_default())
+    }
+
+    /// Move an entity spawned via `spawn_staged()` from `Lifecycle::Spawning` to `Lifecycle::Alive`.
+    /// Fails for entities in any other state, so entities marked for despawning can't be revived.
+    pub fn activate(&mut self, entity: Entity) -> Result<(), LifecycleError> {
+        match self.lifecycle(entity) {
+            None => Err(NoSuchEntity::new(entity).with_operation("activate").into()),
+            Some(Lifecycle::Spawning) => {
+                self.states.remove(&(entity.index - self.base));
+                Ok(())
+            }
+            Some(from) => Err(LifecycleError::InvalidTransition {
+                entity,
+                from,
+                to: Lifecycle::Alive,
+            }),
+        }
+    }
+
+    /// Move all entities in the `Lifecycle::Spawning` state to `Lifecycle::Alive`, e.g. at the end of
+    /// the spawn initialization phase. Returns the number of activated entities.
+    pub fn activate_all(&mut self) -> usize {
+        let before = self.states.len();
+        self.states.retain(|_, state| *state != Lifecycle::Spawning);
+        before - self.states.len()
+    }
+
+    /// Mark an entity for despawning at the end of the frame, moving it to `Lifecycle::PendingDespawn`.
+    /// The entity stays alive until it is despawned, e.g. by the generated `despawn_pending()` of a World.
+    /// Marking an entity that is already pending does nothing.
+    pub fn mark_pending_despawn(&mut self, entity: Entity) -> Result<(), NoSuchEntity> {
+        if !self.exists(entity) {
+            return Err(NoSuchEntity::new(entity).with_operation("mark_pending_despawn"));
+        }
+        self.states
+            .insert(entity.index - self.base, Lifecycle::PendingDespawn);
+        Ok(())
+    }
+
+    /// Iterate over the alive entities in the given lifecycle state, in index order.
+    pub fn iter_in(&self, state: Lifecycle) -> impl Iterator<Item = Entity> + '_ {
+        let base = self.base;
+        self.iter().filter(move |entity| {
+            self.states
+                .get(&(entity.index - base))
+                .copied()
+                .unwrap_or_default()
+                == state
+        })
+    }
+
+    /// The number of alive entities in the given lifecycle state.
+    pub fn count_in(&self, state: Lifecycle) -> u32 {
+        match state {
+            Lifecycle::Alive => self.len - self.states.len() as u32,
+            _ => self.states.values().filter(|s| **s == state).count() as u32,
+        }
+    }
+
+    /// Get the entity currently alive at the given index, if any.
+    /// Code holding a stale entity can use this to check if its slot has been reused, e.g. to restore
+    /// an editor selection after undo recreated the selected entity at the same index.
+    pub fn current_entity_at(&self, index: u32) -> Option<Entity> {
+        match self.slot(index).and_then(|slot| self.ids.get(slot)) {
+            Some(EntityIDEntry::Used(generation)) => Some(Entity {
+                index,
+                generation: *generation,
+            }),
+            _ => None,
+        }
+    }
+
     #[doc(hidden)]
     pub fn despawn(&mut self, id: Entity) -> Result<(), NoSuchEntity> {
-        if let Some(EntityIDEntry::Used(generation)) = self.ids.get(id.index as usize) {
+        let slot = self.slot(id.index);
+        if let Some(EntityIDEntry::Used(generation)) = slot.and_then(|slot| self.ids.get(slot)) {
             if id.generation == *generation {
-                self.ids[id.index as usize] = EntityIDEntry::Unused(generation.wrapping_add(1));
+                self.release(slot.unwrap(), *generation);
                 return Ok(());
             }
         }
-        Err(NoSuchEntity)
+        Err(NoSuchEntity::new(id).with_operation("despawn"))
+    }
+
+    /// Make exactly the given entities alive, e.g. to mirror the entities of another World when applying
+    /// its changes; see `ChangeSet`. Despawns every alive entity that isn't given and spawns the given
+    /// entities that aren't alive with their exact index and generation.
+    /// Returns the despawned entities, whose components still have to be removed from the storages.
+    pub fn mirror_alive(&mut self, alive: &[Entity]) -> Vec<Entity> {
+        self.owner.check_entities("mirror_alive");
+        let keep = alive.iter().copied().collect::<HashSet<_>>();
+        let despawned = self
+            .iter()
+            .filter(|entity| !keep.contains(entity))
+            .collect::<Vec<_>>();
+        for entity in &despawned {
+            let slot = self.slot(entity.index).unwrap();
+            self.release(slot, entity.generation);
+        }
+
+        for entity in alive {
+            let slot = match self.slot(entity.index) {
+                Some(slot) => slot,
+                None => continue,
+            };
+            if slot >= self.ids.len() {
+                let end = self.ids.len();
+                self.ids.resize(slot + 1, EntityIDEntry::Unused(0));
+                if self.policy != ReusePolicy::LowestFree {
+                    self.free
+                        .extend((end..slot).rev().map(|index| index as u32));
+                }
+            }
+            if let EntityIDEntry::Unused(_) = self.ids[slot] {
+                self.ids[slot] = EntityIDEntry::Used(entity.generation);
+                self.high_water = self.high_water.max(slot as u32 + 1);
+                self.alive.insert(entity.index);
+                trace::entity("spawn", *entity);
+                if let Some(order) = &mut self.spawn_order {
+                    // A stale entry of the same entity may still be waiting for compaction.
+                    order.retain(|ordered| ordered != entity);
+                    order.push(*entity);
+                }
+                self.len += 1;
+                self.free.retain(|index| *index as usize != slot);
+                self.pending.retain(|(_, index)| *index as usize != slot);
+                if let Some(tracker) = &mut self.tracker {
+                    tracker.record(entity.index, SpawnOrigin::Label("mirror_alive"), self.frame);
+                }
+            }
+        }
+        despawned
+    }
+
+    /// The number of slots allocated so far, i.e. one past the highest slot ever used.
+    pub(crate) fn slot_count(&self) -> u32 {
+        self.ids.len() as u32
+    }
+
+    /// One past the highest entity index allocated so far, including slots that were never used,
+    /// e.g. those of the initial capacity or reserved via `reserve()`.
+    pub fn index_end(&self) -> u32 {
+        self.base + self.slot_count()
+    }
+
+    /// One past the highest entity index ever spawned, or the index base if no entity was spawned yet.
+    /// Unlike `index_end()`, this doesn't count slots that were allocated but never used, so it shows
+    /// how much of the capacity a World actually needs.
+    pub fn high_water_mark(&self) -> u32 {
+        self.base + self.high_water
+    }
+
+    /// Iterate over the entity indices that can be spawned without growing, in the order the next
+    /// spawns use them. Indices waiting to become free under `ReusePolicy::Delayed` aren't included.
+    pub fn free_slots(&self) -> impl Iterator<Item = u32> + '_ {
+        let base = self.base;
+        let (lowest_free, reusable) = match self.policy {
+            ReusePolicy::LowestFree => (Some(self.ids.iter().enumerate()), None),
+            _ => (None, Some(self.free.iter().rev())),
+        };
+        let lowest_free = lowest_free
+            .into_iter()
+            .flatten()
+            .filter(|(_, id)| id.is_unused())
+            .map(|(slot, _)| slot as u32);
+        let reusable = reusable.into_iter().flatten().copied();
+        lowest_free.chain(reusable).map(move |slot| base + slot)
+    }
+
+    /// Grow the entity slots once so that at least `additional` entities can be spawned without
+    /// allocating, e.g. before streaming in a chunk of entities. New slots are used after the
+    /// currently free ones. Storages have to be grown separately, e.g. to `index_end()`.
+    pub fn reserve(&mut self, additional: u32) {
+        let free = self.free_slots().count() as u32;
+        let missing = additional.saturating_sub(free) as usize;
+        let end = self.ids.len();
+        self.ids.reserve_exact(missing);
+        self.ids.resize(end + missing, EntityIDEntry::Unused(0));
+        if self.policy != ReusePolicy::LowestFree {
+            let mut free = (end..end + missing)
+                .rev()
+                .map(|slot| slot as u32)
+                .collect::<Vec<_>>();
+            free.append(&mut self.free);
+            self.free = free;
+        }
+        self.alive.reserve(self.index_end());
+        if let Some(order) = &mut self.spawn_order {
+            order.reserve(additional as usize);
+        }
+    }
+
+    /// Start removing all entities incrementally, spreading the work over several calls to
+    /// `clear_step()`. Entities spawned before the clear is done may be removed by it as well.
+    pub fn begin_clear(&mut self) {
+        self.clearing = Some((0, self.slot_count()));
+    }
+
+    /// Continue an incremental clear started with `begin_clear()`, processing at most `budget`
+    /// entity slots. `on_despawn` is called for every removed entity.
+    /// If no clear is in progress, this does nothing and reports the clear as done.
+    pub fn clear_step(&mut self, budget: u32, mut on_despawn: impl FnMut(Entity)) -> ClearProgress {
+        let (start, total) = match self.clearing {
+            Some(clearing) => clearing,
+            None => {
+                return ClearProgress {
+                    processed: 0,
+                    total: 0,
+                }
+            }
+        };
+
+        let end = total.min(start.saturating_add(budget));
+        for index in start..end {
+            if let EntityIDEntry::Used(generation) = self.ids[index as usize] {
+                self.release(index as usize, generation);
+                on_despawn(Entity {
+                    index: self.base + index,
+                    generation,
+                });
+            }
+        }
+
+        self.clearing = if end < total {
+            Some((end, total))
+        } else {
+            None
+        };
+        ClearProgress {
+            processed: end,
+            total,
+        }
     }
 
     /// Remove all entities.
     pub fn clear(&mut self) {
-        for id in &mut self.ids {
-            if let EntityIDEntry::Used(generation) = id {
-                *id = EntityIDEntry::Unused(generation.wrapping_add(1));
+        self.owner.check_entities("clear");
+        self.clearing = None;
+        for index in 0..self.ids.len() {
+            if let EntityIDEntry::Used(generation) = self.ids[index] {
+                self.release(index, generation);
             }
         }
     }
 }
 
+/// A uniformly distributed random number in `0..bound`, rejecting the values of `rng` that would bias it.
+fn uniform(rng: &mut impl FnMut() -> u32, bound: u32) -> u32 {
+    let threshold = bound.wrapping_neg() % bound;
+    loop {
+        let value = rng();
+        if value >= threshold {
+            return value % bound;
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn spawns_once_per_key() {
+        let mut entities = Entities::new(4);
+        let (first, spawned) = entities.spawn_if_absent(7);
+        assert!(spawned);
+        assert_eq!(entities.spawn_if_absent(7), (first, false));
+        assert_eq!(entities.len(), 1);
+        assert_eq!(entities.entity_for_key(7), Some(first));
+
+        entities.despawn(first).unwrap();
+        assert_eq!(entities.entity_for_key(7), None);
+        let (second, spawned) = entities.spawn_if_absent(7);
+        assert!(spawned);
+        assert_ne!(second, first);
+
+        entities.clear();
+        assert_eq!(entities.entity_for_key(7), None);
+    }
+
+    #[test]
+    fn records_despawn_events() {
+        let mut entities = Entities::new(4).with_despawn_events();
+        let first = entities.spawn();
+        let second = entities.spawn();
+        let third = entities.spawn();
+        entities.despawn(second).unwrap();
+        assert!(entities.despawn(second).is_err());
+        assert_eq!(entities.drain_despawned(), vec![second]);
+        assert!(entities.drain_despawned().is_empty());
+
+        entities.clear();
+        assert_eq!(entities.drain_despawned(), vec![first, third]);
+
+        let mut silent = Entities::new(1);
+        let entity = silent.spawn();
+        silent.despawn(entity).unwrap();
+        assert!(silent.drain_despawned().is_empty());
+    }
+
+    #[test]
+    fn lifecycle_transitions() {
+        let mut entities = Entities::new(4);
+        let alive = entities.spawn();
+        let staged = entities.spawn_staged();
+        let other = entities.spawn_staged();
+        assert_eq!(entities.lifecycle(alive), Some(Lifecycle::Alive));
+        assert_eq!(entities.lifecycle(staged), Some(Lifecycle::Spawning));
+        assert_eq!(entities.count_in(Lifecycle::Spawning), 2);
+        assert_eq!(entities.count_in(Lifecycle::Alive), 1);
+
+        entities.activate(staged).unwrap();
+        assert_eq!(
+            entities.iter_in(Lifecycle::Alive).collect::<Vec<_>>(),
+            vec![alive, staged]
+        );
+        assert!(matches!(
+            entities.activate(staged),
+            Err(LifecycleError::InvalidTransition { .. })
+        ));
+
+        entities.mark_pending_despawn(alive).unwrap();
+        entities.mark_pending_despawn(other).unwrap();
+        assert_eq!(entities.activate_all(), 0);
+        assert_eq!(
+            entities
+                .iter_in(Lifecycle::PendingDespawn)
+                .collect::<Vec<_>>(),
+            vec![alive, other]
+        );
+        assert!(entities.activate(other).is_err());
+
+        entities.despawn(alive).unwrap();
+        assert_eq!(entities.lifecycle(alive), None);
+        let reused = entities.spawn();
+        assert_eq!(entities.lifecycle(reused), Some(Lifecycle::Alive));
+        assert_eq!(entities.count_in(Lifecycle::PendingDespawn), 1);
+        assert!(matches!(
+            entities.activate(alive),
+            Err(LifecycleError::NoSuchEntity(_))
+        ));
+    }
+
+    #[test]
+    fn tracks_alive_bits() {
+        let mut entities = Entities::new(2).with_index_base(64);
+        let first = entities.spawn();
+        let second = entities.spawn();
+        let third = entities.spawn();
+        entities.despawn(second).unwrap();
+        assert_eq!(
+            entities.alive_bits().iter().collect::<Vec<_>>(),
+            vec![first.index, third.index]
+        );
+        assert_eq!(entities.alive_bits().words(), &[0b101]);
+
+        entities.mirror_alive(&[second]);
+        assert_eq!(
+            entities.alive_bits().iter().collect::<Vec<_>>(),
+            vec![second.index]
+        );
+        entities.clear();
+        assert!(entities.alive_bits().is_empty());
+    }
+
+    #[test]
+    fn iterates_in_spawn_order() -> Result<(), NoSuchEntity> {
+        let mut entities = Entities::new(4)
+            .with_policy(ReusePolicy::Lifo)
+            .with_spawn_order();
+        let first = entities.spawn();
+        let second = entities.spawn();
+        let third = entities.spawn();
+        entities.despawn(first)?;
+        let reused = entities.spawn();
+        assert_eq!(reused.index, first.index);
+        assert_eq!(
+            entities.iter().collect::<Vec<_>>(),
+            vec![second, third, reused]
+        );
+
+        for _ in 0..3 {
+            let entity = entities.spawn();
+            entities.despawn(entity)?;
+        }
+        assert_eq!(entities.spawn_order.as_ref().unwrap().len(), 3);
+        entities.mirror_alive(&[third, second]);
+        assert_eq!(entities.iter().collect::<Vec<_>>(), vec![second, third]);
+        Ok(())
+    }
+
     #[test]
     fn next_id() {
         let mut id_allocator = Entities::new(3);
@@ -170,4 +1093,330 @@ mod tests {
         assert_eq!(next_id, second_id);
         Ok(())
     }
+
+    #[test]
+    fn lifo_reuses_last_freed_index() -> Result<(), NoSuchEntity> {
+        let mut id_allocator = Entities::new(3).with_policy(ReusePolicy::Lifo);
+        let first = id_allocator.spawn();
+        let second = id_allocator.spawn();
+        assert_eq!((first.index, second.index), (0, 1));
+
+        id_allocator.despawn(first)?;
+        id_allocator.despawn(second)?;
+        assert_eq!(id_allocator.spawn().index, 1);
+        assert_eq!(id_allocator.spawn().index, 0);
+        assert_eq!(id_allocator.spawn().index, 2);
+        assert_eq!(id_allocator.spawn().index, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn delayed_reuse_waits_for_frames() -> Result<(), NoSuchEntity> {
+        let mut id_allocator = Entities::new(1).with_policy(ReusePolicy::Delayed { frames: 2 });
+        let first = id_allocator.spawn();
+        id_allocator.despawn(first)?;
+
+        assert_eq!(id_allocator.spawn().index, 1);
+        id_allocator.next_frame();
+        assert_eq!(id_allocator.spawn().index, 2);
+        id_allocator.next_frame();
+
+        let reused = id_allocator.spawn();
+        assert_eq!(
+            reused,
+            Entity {
+                index: 0,
+                generation: 1
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot() {
+        fn alive(entities: &std::sync::RwLock<Entities>) -> impl Iterator<Item = Entity> {
+            entities.read().unwrap().iter_snapshot()
+        }
+
+        let entities = std::sync::RwLock::new(Entities::new(3));
+        let first = entities.write().unwrap().spawn();
+        let second = entities.write().unwrap().spawn();
+        for entity in alive(&entities) {
+            entities.write().unwrap().despawn(entity).unwrap();
+        }
+        assert!(entities.read().unwrap().is_empty());
+
+        let mut buffer = vec![first, second];
+        entities.read().unwrap().snapshot_into(&mut buffer);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn max_index_bounds_reused_indices() -> Result<(), NoSuchEntity> {
+        let mut entities = Entities::new(4)
+            .with_index_base(10)
+            .with_max_index(11)
+            .with_policy(ReusePolicy::Delayed { frames: 1 });
+        let first = entities.try_spawn().unwrap();
+        let second = entities.try_spawn().unwrap();
+        assert_eq!((first.index, second.index), (10, 11));
+        entities.despawn(first)?;
+        // Only one entity is alive, but the freed index can't be reused before the next frame.
+        assert_eq!(entities.try_spawn(), Err(EntityLimitReached { limit: 2 }));
+
+        entities.next_frame();
+        assert_eq!(entities.try_spawn().unwrap().index, 10);
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic(expected = "all entity indices up to the max index")]
+    fn spawn_above_max_index_panics() {
+        let mut entities = Entities::new(0).with_max_index(0);
+        entities.spawn();
+        entities.spawn();
+    }
+
+    #[test]
+    fn entity_limit() -> Result<(), NoSuchEntity> {
+        let mut entities = Entities::new(3).with_limit(2, 3);
+        assert_eq!(entities.watermark(), Watermark::Normal);
+        let first = entities.try_spawn().unwrap();
+        entities.try_spawn().unwrap();
+        assert_eq!(entities.watermark(), Watermark::Warning);
+        entities.try_spawn().unwrap();
+        assert_eq!(entities.watermark(), Watermark::Full);
+        assert_eq!(entities.try_spawn(), Err(EntityLimitReached { limit: 3 }));
+        assert_eq!(entities.len(), 3);
+
+        entities.despawn(first)?;
+        assert_eq!(entities.watermark(), Watermark::Warning);
+        entities.clear();
+        assert!(entities.is_empty());
+        assert_eq!(entities.watermark(), Watermark::Normal);
+        Ok(())
+    }
+
+    #[test]
+    fn index_base() -> Result<(), NoSuchEntity> {
+        let mut entities = Entities::new(2).with_index_base(100);
+        let first = entities.spawn();
+        let second = entities.spawn();
+        assert_eq!((first.index, second.index), (100, 101));
+        assert!(!entities.exists(Entity {
+            index: 0,
+            generation: 0
+        }));
+        assert_eq!(entities.iter().collect::<Vec<_>>(), vec![first, second]);
+        assert_eq!(entities.index_end(), 102);
+
+        entities.despawn(first)?;
+        assert!(!entities.exists(first));
+        assert_eq!(entities.spawn().index, 100);
+        Ok(())
+    }
+
+    #[test]
+    fn reserves_slots() -> Result<(), NoSuchEntity> {
+        let mut entities = Entities::new(4).with_policy(ReusePolicy::Lifo);
+        assert_eq!(entities.high_water_mark(), 0);
+        let spawned = (0..3).map(|_| entities.spawn()).collect::<Vec<_>>();
+        entities.despawn(spawned[1])?;
+        assert_eq!(entities.free_slots().collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(entities.high_water_mark(), 3);
+
+        entities.reserve(5);
+        assert_eq!(entities.index_end(), 7);
+        assert_eq!(
+            entities.free_slots().collect::<Vec<_>>(),
+            vec![1, 3, 4, 5, 6]
+        );
+        let indices = (0..5).map(|_| entities.spawn().index).collect::<Vec<_>>();
+        assert_eq!(indices, vec![1, 3, 4, 5, 6]);
+        assert_eq!(entities.index_end(), 7);
+        assert_eq!(entities.high_water_mark(), 7);
+
+        let mut lowest_free = Entities::new(2).with_index_base(10);
+        lowest_free.reserve(3);
+        assert_eq!(
+            lowest_free.free_slots().collect::<Vec<_>>(),
+            vec![10, 11, 12]
+        );
+        assert_eq!(lowest_free.high_water_mark(), 10);
+        Ok(())
+    }
+
+    #[test]
+    fn leak_tracking() -> Result<(), NoSuchEntity> {
+        let mut entities = Entities::new(3).with_leak_tracking();
+        let spawned = (0..3).map(|_| entities.spawn()).collect::<Vec<_>>();
+        let line = line!() - 1;
+        entities.despawn(spawned[0])?;
+        entities.next_frame();
+        entities.spawn_labeled("projectile");
+
+        let reports = entities.report_leaks(1);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].count, 2);
+        assert_eq!(reports[0].oldest_frame, 0);
+        match reports[0].origin {
+            SpawnOrigin::Caller(location) => {
+                assert_eq!(location.file(), file!());
+                assert_eq!(location.line(), line);
+            }
+            SpawnOrigin::Label(_) => panic!("expected the spawn call site"),
+        }
+
+        let reports = entities.report_leaks(0);
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[1].origin, SpawnOrigin::Label("projectile"));
+        assert!(Entities::new(3).report_leaks(0).is_empty());
+        Ok(())
+    }
+
+    #[cfg(feature = "rkyv")]
+    #[test]
+    fn archive_entities() -> Result<(), NoSuchEntity> {
+        use rkyv::Deserialize;
+
+        let mut id_allocator = Entities::new(2).with_policy(ReusePolicy::Lifo);
+        let first = id_allocator.spawn();
+        let second = id_allocator.spawn();
+        id_allocator.despawn(first)?;
+
+        let bytes = rkyv::to_bytes::<_, 256>(&id_allocator).unwrap();
+        let archived = rkyv::check_archived_root::<Entities>(&bytes).unwrap();
+        let mut restored: Entities = archived.deserialize(&mut rkyv::Infallible).unwrap();
+
+        assert!(!restored.exists(first));
+        assert!(restored.exists(second));
+        assert_eq!(restored.policy(), ReusePolicy::Lifo);
+        assert_eq!(restored.spawn().index, first.index);
+        Ok(())
+    }
+
+    #[test]
+    fn incremental_clear() {
+        let mut id_allocator = Entities::new(0);
+        let spawned = (0..5).map(|_| id_allocator.spawn()).collect::<Vec<_>>();
+        id_allocator.begin_clear();
+
+        let mut despawned = vec![];
+        let progress = id_allocator.clear_step(3, |entity| despawned.push(entity));
+        assert_eq!(
+            progress,
+            ClearProgress {
+                processed: 3,
+                total: 5
+            }
+        );
+        assert!(!progress.is_done());
+        assert!(id_allocator.exists(spawned[3]));
+
+        let progress = id_allocator.clear_step(3, |entity| despawned.push(entity));
+        assert!(progress.is_done());
+        assert_eq!(despawned, spawned);
+        assert_eq!(id_allocator.iter().count(), 0);
+        assert!(id_allocator.clear_step(3, |_| {}).is_done());
+    }
+
+    #[test]
+    fn display_and_parse() {
+        let entity = Entity {
+            index: 12,
+            generation: 3,
+        };
+        assert_eq!(entity.to_string(), "12:3");
+        assert_eq!("12:3".parse::<Entity>(), Ok(entity));
+        assert_eq!(" 12:3 ".parse::<Entity>(), Ok(entity));
+        assert!("12".parse::<Entity>().is_err());
+        assert!("12:x".parse::<Entity>().is_err());
+        assert!("-1:0".parse::<Entity>().is_err());
+    }
+
+    fn xorshift(mut state: u32) -> impl FnMut() -> u32 {
+        move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        }
+    }
+
+    #[test]
+    fn samples_distinct_alive_entities() {
+        let mut entities = Entities::new(100);
+        let spawned = (0..100).map(|_| entities.spawn()).collect::<Vec<_>>();
+        for entity in spawned.iter().step_by(3) {
+            entities.despawn(*entity).unwrap();
+        }
+
+        let mut rng = xorshift(7);
+        for n in [0, 5, 40, 66, 100].iter().copied() {
+            let sample = entities.sample(&mut rng, n);
+            assert_eq!(sample.len() as u32, n.min(entities.len()));
+            assert!(sample.iter().all(|entity| entities.exists(*entity)));
+            let distinct = sample
+                .iter()
+                .map(|entity| entity.index)
+                .collect::<HashSet<_>>();
+            assert_eq!(distinct.len(), sample.len());
+        }
+
+        let mut counts = [0u32; 100];
+        for _ in 0..2000 {
+            for entity in entities.sample(&mut rng, 3) {
+                counts[entity.index as usize] += 1;
+            }
+        }
+        let mut alive = counts.iter().filter(|count| **count > 0);
+        assert_eq!(alive.clone().count(), 66);
+        assert!(alive.all(|count| (40..=150).contains(count)));
+    }
+
+    #[test]
+    fn mirrors_alive_entities() {
+        let mut source = Entities::new(4).with_policy(ReusePolicy::Lifo);
+        let spawned = (0..4).map(|_| source.spawn()).collect::<Vec<_>>();
+        let mut mirror = source.clone();
+        source.despawn(spawned[1]).unwrap();
+        let respawned = source.spawn();
+        let far = Entity {
+            index: 6,
+            generation: 2,
+        };
+
+        let alive = vec![spawned[0], respawned, spawned[3], far];
+        assert_eq!(mirror.mirror_alive(&alive), vec![spawned[1], spawned[2]]);
+        assert_eq!(mirror.iter().collect::<Vec<_>>(), alive);
+        assert_eq!(mirror.len(), 4);
+        let mut spawned_next = (0..2).map(|_| mirror.spawn().index).collect::<Vec<_>>();
+        spawned_next.sort_unstable();
+        assert_eq!(spawned_next, vec![4, 5]);
+    }
+
+    #[test]
+    fn finds_current_entity_at_index() -> Result<(), NoSuchEntity> {
+        let mut entities = Entities::new(2);
+        let stale = entities.spawn();
+        assert_eq!(entities.current_entity_at(stale.index), Some(stale));
+        entities.despawn(stale)?;
+        assert_eq!(entities.current_entity_at(stale.index), None);
+
+        let recreated = entities.spawn();
+        assert_eq!(recreated.index, stale.index);
+        assert_eq!(entities.current_entity_at(stale.index), Some(recreated));
+        assert_eq!(entities.current_entity_at(5), None);
+        Ok(())
+    }
+
+    #[test]
+    fn bits_roundtrip() {
+        let entity = Entity {
+            index: 12,
+            generation: 3,
+        };
+        assert_eq!(entity.to_bits(), (3 << 32) | 12);
+        assert_eq!(Entity::from_bits(entity.to_bits()), entity);
+    }
 }
@@ -0,0 +1,167 @@
+use crate::entity::Entity;
+use crate::no_such_entity::NoSuchEntity;
+use crate::Entities;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+/// A set of entities of a World, e.g. a squad or a selection, that never yields despawned entities.
+/// Members are checked against the entities of the World when the group is used, so despawned members
+/// drop out automatically, even if their index is reused by a new entity.
+/// Create one via `new_group()` on a World generated by `#[world]`.
+#[derive(Debug, Clone)]
+pub struct EntityGroup {
+    /// The generation of every member by index.
+    members: BTreeMap<u32, u32>,
+    entities: Arc<RwLock<Entities>>,
+}
+
+impl EntityGroup {
+    /// Create a new, empty EntityGroup for the given entities.
+    pub fn new(entities: Arc<RwLock<Entities>>) -> Self {
+        Self {
+            members: BTreeMap::new(),
+            entities,
+        }
+    }
+
+    /// Add the given entity to self.
+    /// Returns Err(NoSuchEntity) if the given entity doesn't exist.
+    /// Otherwise, returns Ok(true) if the entity wasn't a member yet.
+    pub fn insert(&mut self, entity: Entity) -> Result<bool, NoSuchEntity> {
+        if !self.entities.read().unwrap().exists(entity) {
+            return Err(NoSuchEntity::new(entity).with_operation("insert"));
+        }
+        let previous = self.members.insert(entity.index, entity.generation);
+        Ok(previous != Some(entity.generation))
+    }
+
+    /// Remove the given entity from self.
+    /// Returns true if the entity was a member.
+    pub fn remove(&mut self, entity: Entity) -> bool {
+        if self.members.get(&entity.index) == Some(&entity.generation) {
+            self.members.remove(&entity.index);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Check if the given entity is an alive member of self.
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.members.get(&entity.index) == Some(&entity.generation)
+            && self.entities.read().unwrap().exists(entity)
+    }
+
+    /// Iterate over all alive members of self by index.
+    /// The entities are read-locked until the returned iterator is dropped.
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        let lock = self.entities.read().unwrap();
+        self.members
+            .iter()
+            .map(|(index, generation)| Entity {
+                index: *index,
+                generation: *generation,
+            })
+            .filter(move |entity| lock.exists(*entity))
+    }
+
+    /// Iterate over all alive entities that are members of self or `other`, which must belong to the
+    /// same World; members of self come first.
+    /// The entities are read-locked until the returned iterator is dropped.
+    pub fn union<'a>(&'a self, other: &'a EntityGroup) -> impl Iterator<Item = Entity> + 'a {
+        let lock = self.entities.read().unwrap();
+        let others = other.members.iter().filter(move |(index, generation)| {
+            self.members.get(index).copied() != Some(**generation)
+        });
+        self.members
+            .iter()
+            .chain(others)
+            .map(|(index, generation)| Entity {
+                index: *index,
+                generation: *generation,
+            })
+            .filter(move |entity| lock.exists(*entity))
+    }
+
+    /// Iterate over all alive entities that are members of both self and `other` by index.
+    /// The entities are read-locked until the returned iterator is dropped.
+    pub fn intersection<'a>(&'a self, other: &'a EntityGroup) -> impl Iterator<Item = Entity> + 'a {
+        self.iter()
+            .filter(move |entity| other.members.get(&entity.index) == Some(&entity.generation))
+    }
+
+    /// The number of alive members of self.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Check if self has no alive members.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Forget all members that were despawned, freeing their memory.
+    /// Returns the number of forgotten members.
+    pub fn prune(&mut self) -> usize {
+        let lock = self.entities.read().unwrap();
+        let before = self.members.len();
+        self.members.retain(|index, generation| {
+            lock.exists(Entity {
+                index: *index,
+                generation: *generation,
+            })
+        });
+        before - self.members.len()
+    }
+
+    /// Remove all members from self.
+    pub fn clear(&mut self) {
+        self.members.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_despawned_members() -> Result<(), NoSuchEntity> {
+        let entities = Arc::new(RwLock::new(Entities::new(3)));
+        let (first, second, third) = {
+            let mut lock = entities.write().unwrap();
+            (lock.spawn(), lock.spawn(), lock.spawn())
+        };
+        let mut squad = EntityGroup::new(Arc::clone(&entities));
+        let mut selection = EntityGroup::new(Arc::clone(&entities));
+        assert!(squad.insert(first)?);
+        assert!(!squad.insert(first)?);
+        squad.insert(second)?;
+        selection.insert(second)?;
+        selection.insert(third)?;
+
+        assert_eq!(
+            squad.union(&selection).collect::<Vec<_>>(),
+            vec![first, second, third]
+        );
+        assert_eq!(
+            squad.intersection(&selection).collect::<Vec<_>>(),
+            vec![second]
+        );
+
+        let reused = {
+            let mut lock = entities.write().unwrap();
+            lock.despawn(second)?;
+            lock.spawn()
+        };
+        assert_eq!(reused.index, second.index);
+        assert!(!squad.contains(second));
+        assert!(!squad.contains(reused));
+        assert_eq!(squad.len(), 1);
+        assert_eq!(squad.intersection(&selection).count(), 0);
+
+        assert_eq!(squad.prune(), 1);
+        assert!(squad.remove(first));
+        assert!(squad.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use crate::entity::Entity;
+use crate::Entities;
+use std::collections::{HashMap, HashSet};
+
+/// Adapters for iterators over entities and their values, like `FromQuery::iter()` or `MapStorage::ordered()`,
+/// that keep the entity of every value, so queries compose without falling back to nested loops:
+///
+/// ```ignore
+/// let targets = RenderItem::iter(&world)
+///     .filter_values(|_, item| item.velocity.is_some())
+///     .zip_entities(Target::iter(&world))
+///     .map_values(|_, (item, target)| (item.position, target.health));
+/// ```
+///
+/// Entities are compared with their generation, so values of a despawned entity never pair up with those of
+/// a newer entity at the same index.
+pub trait EntityIterator<T>: Iterator<Item = (Entity, T)> + Sized {
+    /// Map every value, keeping its entity.
+    fn map_values<U>(self, mut f: impl FnMut(Entity, T) -> U) -> impl Iterator<Item = (Entity, U)> {
+        self.map(move |(entity, value)| (entity, f(entity, value)))
+    }
+
+    /// Keep the values for which `f` returns true, with their entities.
+    fn filter_values(
+        self,
+        mut f: impl FnMut(Entity, &T) -> bool,
+    ) -> impl Iterator<Item = (Entity, T)> {
+        self.filter(move |(entity, value)| f(*entity, value))
+    }
+
+    /// Pair the values of self with the values of `other` for the same entity, skipping entities missing
+    /// from either, in the order of self. Collects `other` when called.
+    fn zip_entities<U>(
+        self,
+        other: impl IntoIterator<Item = (Entity, U)>,
+    ) -> impl Iterator<Item = (Entity, (T, U))> {
+        let mut other = other.into_iter().collect::<HashMap<_, _>>();
+        self.filter_map(move |(entity, value)| Some((entity, (value, other.remove(&entity)?))))
+    }
+
+    /// Continue with the values of `other` after self, skipping entities that were already visited, so an
+    /// entity matching both queries is only visited once.
+    fn chain_entities(
+        self,
+        other: impl IntoIterator<Item = (Entity, T)>,
+    ) -> impl Iterator<Item = (Entity, T)> {
+        let mut visited = HashSet::new();
+        self.chain(other)
+            .filter(move |(entity, _)| visited.insert(*entity))
+    }
+
+    /// Keep the values of entities that are alive in the given entities, e.g. when values were collected
+    /// before despawning some of them.
+    fn alive(self, entities: &Entities) -> impl Iterator<Item = (Entity, T)> {
+        self.filter(move |(entity, _)| entities.exists(*entity))
+    }
+
+    /// Drop the values, keeping only the entities.
+    fn entities(self) -> impl Iterator<Item = Entity> {
+        self.map(|(entity, _)| entity)
+    }
+}
+
+impl<T, I: Iterator<Item = (Entity, T)>> EntityIterator<T> for I {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_entities_through_adapters() {
+        let mut entities = Entities::new(4);
+        let spawned = (0..4).map(|_| entities.spawn()).collect::<Vec<_>>();
+        let names = vec![(spawned[0], "a"), (spawned[1], "b"), (spawned[3], "d")];
+        let values = vec![(spawned[3], 3), (spawned[1], 1), (spawned[2], 2)];
+
+        let zipped = names
+            .clone()
+            .into_iter()
+            .zip_entities(values.clone())
+            .map_values(|_, (name, value)| format!("{}{}", name, value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            zipped,
+            vec![
+                (spawned[1], "b1".to_string()),
+                (spawned[3], "d3".to_string())
+            ]
+        );
+
+        let chained = values
+            .into_iter()
+            .filter_values(|_, value| *value > 1)
+            .chain_entities(vec![(spawned[0], 0), (spawned[3], 30)])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chained,
+            vec![(spawned[3], 3), (spawned[2], 2), (spawned[0], 0)]
+        );
+
+        entities.despawn(spawned[1]).unwrap();
+        let reused = entities.spawn();
+        let stale = vec![(spawned[1], "b")];
+        assert_eq!(stale.into_iter().zip_entities(vec![(reused, 1)]).count(), 0);
+        assert_eq!(
+            names
+                .into_iter()
+                .alive(&entities)
+                .entities()
+                .collect::<Vec<_>>(),
+            vec![spawned[0], spawned[3]]
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! Building blocks of the C interface generated for Worlds passed the `ffi` option of `#[world]`.
+//!
+//! Components cross the interface as plain bytes copied in and out of the storages, so every component
+//! type of such a World has to be `Copy` and should be `#[repr(C)]`; the macro rejects other types.
+
+use crate::register::RegisterError;
+use crate::storage_error::StorageError;
+use std::os::raw::{c_char, c_void};
+
+/// Describes a component type of a World to C code.
+/// The position of a descriptor in the component table of a World is the id of the component type.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ComponentDescriptor {
+    /// The NUL-terminated name of the component type.
+    pub name: *const c_char,
+    /// The size of the component type in bytes.
+    pub size: usize,
+    /// The alignment of the component type in bytes.
+    pub align: usize,
+}
+
+// The names of descriptors point to static string literals, so sharing them between threads is fine.
+unsafe impl Sync for ComponentDescriptor {}
+
+/// The result of a call to the C interface of a World.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FfiStatus {
+    /// The call succeeded.
+    Ok = 0,
+    /// A pointer passed to the call was null.
+    NullPointer = 1,
+    /// The entity doesn't exist.
+    NoSuchEntity = 2,
+    /// The component id is not in the component table of the World.
+    UnknownComponent = 3,
+    /// The entity doesn't have a component of the given type.
+    MissingComponent = 4,
+    /// The storage failed for another reason; see `StorageError`.
+    StorageFailed = 5,
+    /// The entity doesn't have a component required by the given component; see `RegisterError`.
+    MissingRequirement = 6,
+}
+
+impl From<StorageError> for FfiStatus {
+    fn from(error: StorageError) -> Self {
+        match error {
+            StorageError::NoSuchEntity(_) => FfiStatus::NoSuchEntity,
+            _ => FfiStatus::StorageFailed,
+        }
+    }
+}
+
+impl From<RegisterError> for FfiStatus {
+    fn from(error: RegisterError) -> Self {
+        match error {
+            RegisterError::NoSuchEntity(_) => FfiStatus::NoSuchEntity,
+            RegisterError::MissingRequirement { .. } => FfiStatus::MissingRequirement,
+            RegisterError::Storage(error) => error.into(),
+        }
+    }
+}
+
+/// Implemented for all types that can be passed through the C interface of a World, i.e. `Copy` types.
+#[doc(hidden)]
+#[diagnostic::on_unimplemented(
+    message = "the component type `{Self}` of a World with the `ffi` option must be `Copy`",
+    label = "not `Copy`",
+    note = "components are copied byte by byte across the C interface; derive `Clone` and `Copy` and use `#[repr(C)]`"
+)]
+pub trait FfiCopy: Copy {}
+
+impl<T: Copy> FfiCopy for T {}
+
+/// Fails to compile unless `T` can be passed through the C interface of a World.
+#[doc(hidden)]
+pub const fn assert_ffi_component<T: FfiCopy>() {}
+
+/// Copy the given component to `out`.
+///
+/// # Safety
+/// `out` must be valid for writes of `size_of::<T>()` bytes; it doesn't need to be aligned.
+pub unsafe fn write_component<T: FfiCopy>(component: &T, out: *mut c_void) {
+    std::ptr::write_unaligned(out as *mut T, *component);
+}
+
+/// Read a component from `data`.
+///
+/// # Safety
+/// `data` must be valid for reads of `size_of::<T>()` bytes holding a valid `T`; it doesn't need
+/// to be aligned.
+pub unsafe fn read_component<T: FfiCopy>(data: *const c_void) -> T {
+    std::ptr::read_unaligned(data as *const T)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Entity;
+    use crate::NoSuchEntity;
+
+    #[test]
+    fn copies_components_and_maps_errors() {
+        let mut buffer = [0u8; 9];
+        let out = buffer[1..].as_mut_ptr() as *mut c_void;
+        unsafe {
+            write_component(&0x0102_0304_0506_0708u64, out);
+            assert_eq!(read_component::<u64>(out), 0x0102_0304_0506_0708);
+        }
+
+        let entity = Entity {
+            index: 0,
+            generation: 0,
+        };
+        assert_eq!(
+            FfiStatus::from(StorageError::from(NoSuchEntity::new(entity))),
+            FfiStatus::NoSuchEntity
+        );
+        assert_eq!(
+            FfiStatus::from(StorageError::Disabled),
+            FfiStatus::StorageFailed
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use crate::entity::Entity;
+use crate::has_component::HasEntities;
+
+/// A plain struct built from the components of an entity, e.g. the items a render extraction phase
+/// copies out of a World. Can be derived via `#[derive(FromQuery)]` for structs whose fields are
+/// component types, which are cloned, or `Option`s of them for optional components:
+///
+/// ```ignore
+/// #[derive(FromQuery)]
+/// struct RenderItem {
+///     #[query(entity)]
+///     entity: Entity,
+///     position: Position,
+///     #[query(from = NameComponent)]
+///     name: String,
+///     velocity: Option<Velocity>,
+/// }
+///
+/// let items: Vec<RenderItem> = RenderItem::collect(&world);
+/// ```
+///
+/// `#[query(from = T)]` reads the component of type `T` and converts it via `From<T>`. The derived impl
+/// works for every World with the queried component types; see `HasComponent`.
+pub trait FromQuery<W: HasEntities>: Sized {
+    /// Build a value from the components of the given entity, or None if it lacks a required component.
+    /// Does not check if the entity exists and doesn't lock the entities.
+    fn from_entity(world: &W, entity: Entity) -> Option<Self>;
+
+    /// Build a value for every alive entity with the required components, in the order `query!` visits
+    /// entities.
+    fn collect(world: &W) -> Vec<Self> {
+        let mut values = vec![];
+        Self::collect_into(world, &mut values);
+        values
+    }
+
+    /// Iterate over the values built for every alive entity with the required components together with
+    /// their entities, in the order `query!` visits entities; see `EntityIterator` for combining them with
+    /// other queries. The alive entities are collected when this is called.
+    fn iter(world: &W) -> impl Iterator<Item = (Entity, Self)> + '_ {
+        let entities = world.entities().read().unwrap().iter_snapshot();
+        entities.filter_map(move |entity| Some((entity, Self::from_entity(world, entity)?)))
+    }
+
+    /// Build values like `collect()` into the given buffer after clearing it, so it doesn't allocate once
+    /// the buffer is large enough, and return their count.
+    fn collect_into(world: &W, buffer: &mut Vec<Self>) -> usize {
+        buffer.clear();
+        let entities = world.entities().read().unwrap();
+        buffer.extend(
+            entities
+                .iter()
+                .filter_map(|entity| Self::from_entity(world, entity)),
+        );
+        buffer.len()
+    }
+}
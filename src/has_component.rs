@@ -0,0 +1,25 @@
+use crate::Entities;
+use crate::Storage;
+use std::sync::{Arc, RwLock};
+
+/// Type that owns entities; implemented for all Worlds generated by `genesis`.
+pub trait HasEntities {
+    /// The entities of the World.
+    fn entities(&self) -> &Arc<RwLock<Entities>>;
+}
+
+/// World that stores components of type T; implemented for all Worlds generated by `genesis` for each
+/// of their component types. This allows writing systems generic over every World with the components
+/// they need, e.g. `fn integrate<W: HasComponent<Position> + HasComponent<Velocity>>(world: &mut W)`,
+/// and reusing them across Worlds.
+/// Named traits like `trait HasPosition: HasComponent<Position> {}` can be added with a blanket impl.
+pub trait HasComponent<T>: HasEntities {
+    /// The type of the storage holding components of type T.
+    type Storage: Storage<T>;
+
+    /// The storage of components of type T.
+    fn storage(&self) -> &Self::Storage;
+
+    /// The storage of components of type T.
+    fn storage_mut(&mut self) -> &mut Self::Storage;
+}
@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+
+/// Type that can be deep-copied with its own, independent set of entities; implemented for all Worlds
+/// generated by `genesis` that derive `Clone`, see their `fork()`.
+pub trait Fork {
+    /// Create a deep copy of self with its own set of entities.
+    fn fork(&self) -> Self;
+}
+
+/// A ring buffer of the last snapshots of a World, e.g. one per frame, to step the World back in time
+/// when reproducing intermittent gameplay bugs.
+///
+/// Every snapshot is a full `fork()` of the World, so keep the capacity small for large Worlds.
+/// Rewinding replaces the World with a snapshot, including its entities; handles to the entities of
+/// the replaced World, e.g. from `WorldRegistry`, no longer refer to the rewound World.
+#[derive(Debug, Clone)]
+pub struct History<W> {
+    snapshots: VecDeque<W>,
+    capacity: usize,
+}
+
+impl<W: Fork> History<W> {
+    /// Create an empty history keeping at most `capacity` snapshots.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Record a snapshot of the given World, dropping the oldest snapshot if the history is full.
+    pub fn record(&mut self, world: &W) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(world.fork());
+    }
+
+    /// Replace the given World with the snapshot recorded `steps` records ago, where 1 is the latest
+    /// snapshot, and drop all snapshots recorded after it. The rewound-to snapshot stays in the history,
+    /// so rewinding by 1 again restores it once more.
+    /// Returns false and leaves the World untouched if fewer than `steps` snapshots were recorded.
+    pub fn rewind(&mut self, world: &mut W, steps: usize) -> bool {
+        if steps == 0 || steps > self.snapshots.len() {
+            return false;
+        }
+        self.snapshots.truncate(self.snapshots.len() - steps + 1);
+        *world = self.snapshots.back().unwrap().fork();
+        true
+    }
+
+    /// The snapshot recorded `steps` records ago, where 1 is the latest snapshot, e.g. to inspect it
+    /// without rewinding.
+    pub fn get(&self, steps: usize) -> Option<&W> {
+        if steps == 0 {
+            return None;
+        }
+        self.snapshots.get(self.snapshots.len().checked_sub(steps)?)
+    }
+
+    /// The number of recorded snapshots.
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    /// Check if no snapshots are recorded.
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// The maximum number of snapshots kept.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Drop all snapshots.
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl Fork for Vec<u32> {
+        fn fork(&self) -> Self {
+            self.clone()
+        }
+    }
+
+    #[test]
+    fn rewinds_to_recorded_snapshots() {
+        let mut history = History::new(3);
+        let mut world = vec![];
+        for frame in 0..5 {
+            world.push(frame);
+            history.record(&world);
+        }
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.get(3), Some(&vec![0, 1, 2]));
+        assert_eq!(history.get(0), None);
+        assert!(!history.rewind(&mut world, 4));
+        assert_eq!(world, vec![0, 1, 2, 3, 4]);
+
+        world.push(5);
+        assert!(history.rewind(&mut world, 2));
+        assert_eq!(world, vec![0, 1, 2, 3]);
+        assert_eq!(history.len(), 2);
+        world.push(9);
+        assert!(history.rewind(&mut world, 1));
+        assert_eq!(world, vec![0, 1, 2, 3]);
+
+        let mut disabled = History::new(0);
+        disabled.record(&world);
+        assert!(disabled.is_empty());
+    }
+}
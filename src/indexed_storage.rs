@@ -0,0 +1,367 @@
+use crate::allocations::{self, AllocationKind};
+use crate::change_cursor::TrackChanges;
+use crate::entity::Entity;
+use crate::integrity::IntegrityError;
+use crate::layout::MemoryUsage;
+use crate::map_entities::MapEntities;
+use crate::storage_error::StorageError;
+use crate::Entities;
+use crate::ModelStorage;
+use crate::Storage;
+use crate::VecStorage;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::hash::Hash;
+use std::sync::{Arc, RwLock};
+use std::thread::ThreadId;
+
+/// A component type that can be stored in an `IndexedStorage<T>`, which finds entities by the key
+/// of their component, e.g. a name or a grid cell.
+pub trait IndexKey {
+    /// The type of the key.
+    type Key: Hash + Eq + Clone;
+
+    /// The key to index this component by.
+    fn index_key(&self) -> Self::Key;
+}
+
+/// A storage type that stores components like a `VecStorage<T>` and additionally maintains a reverse
+/// index from the key of each component to the entities having it; see `find()`.
+/// The index is updated by every operation that sets or removes components. Components accessed via
+/// `get_mut()` are re-indexed lazily the next time the index is used.
+#[derive(Clone)]
+pub struct IndexedStorage<T: IndexKey> {
+    storage: VecStorage<T>,
+    /// The entities with a component by key.
+    index: HashMap<T::Key, Vec<Entity>>,
+    /// The key each entity is indexed under, by entity index.
+    keys: HashMap<u32, T::Key>,
+    /// Entities whose components were mutably accessed since they were last indexed.
+    stale: Vec<Entity>,
+}
+
+impl<T: IndexKey> IndexedStorage<T> {
+    /// Create a new IndexedStorage<T> with the specified initial capacity.
+    pub fn new(entities: Arc<RwLock<Entities>>, capacity: u32) -> Self {
+        Self {
+            storage: VecStorage::new(entities, capacity),
+            index: HashMap::new(),
+            keys: HashMap::new(),
+            stale: vec![],
+        }
+    }
+
+    /// Iterate over all entities whose component has the given key, in no particular order.
+    /// Takes `&mut self` to re-index components that were mutably accessed first.
+    pub fn find<Q>(&mut self, key: &Q) -> impl Iterator<Item = Entity> + '_
+    where
+        T::Key: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.refresh_index();
+        self.index.get(key).into_iter().flatten().copied()
+    }
+
+    /// Re-index the components that were mutably accessed since they were last indexed.
+    /// This is done automatically by `find()`.
+    pub fn refresh_index(&mut self) {
+        for entity in std::mem::take(&mut self.stale) {
+            if let Some(key) = self.storage.get(entity).map(IndexKey::index_key) {
+                self.unindex(entity.index);
+                self.insert_key(entity, key);
+            }
+        }
+    }
+
+    fn insert_key(&mut self, entity: Entity, key: T::Key) {
+        let capacities = (self.keys.capacity(), self.index.capacity());
+        self.keys.insert(entity.index, key.clone());
+        self.index.entry(key).or_default().push(entity);
+        allocations::record_growth::<T, (u32, T::Key)>(
+            AllocationKind::Rehash,
+            capacities.0,
+            self.keys.capacity(),
+        );
+        allocations::record_growth::<T, (T::Key, Vec<Entity>)>(
+            AllocationKind::Rehash,
+            capacities.1,
+            self.index.capacity(),
+        );
+    }
+
+    fn unindex(&mut self, index: u32) {
+        if let Some(key) = self.keys.remove(&index) {
+            if let Some(entities) = self.index.get_mut(&key) {
+                entities.retain(|entity| entity.index != index);
+                if entities.is_empty() {
+                    self.index.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Get a reference to the component associated with the given entity in self, if any.
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        self.storage.get(entity)
+    }
+
+    /// Get a mutable reference to the component associated with the given entity in self, if any.
+    /// The component is re-indexed the next time the index is used.
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        let component = self.storage.get_mut(entity)?;
+        self.stale.push(entity);
+        Some(component)
+    }
+
+    /// Get a reference to the component associated with the given entity in self, if any.
+    /// Does not check if the entity exists; only use this if you know it exists.
+    pub fn get_unchecked(&self, entity: Entity) -> Option<&T> {
+        self.storage.get_unchecked(entity)
+    }
+
+    /// Get a mutable reference to the component associated with the given entity in self, if any.
+    /// Does not check if the entity exists; only use this if you know it exists.
+    /// The component is re-indexed the next time the index is used.
+    pub fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T> {
+        let component = self.storage.get_mut_unchecked(entity)?;
+        self.stale.push(entity);
+        Some(component)
+    }
+
+    /// Set the component for the given entity and index it by its key.
+    /// Returns Err(StorageError::NoSuchEntity) if the given entity doesn't exist.
+    /// Otherwise, returns Ok(data), where data is previous data evicted by this operation (if any).
+    pub fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        let key = data.index_key();
+        let previous = self.storage.set(entity, data)?;
+        self.unindex(entity.index);
+        self.insert_key(entity, key);
+        Ok(previous)
+    }
+
+    /// Set the component for the given entity and index it by its key.
+    /// Returns the previous data stored in self for the given entity.
+    /// Does not check if the entity exists and doesn't lock the entities; only use this if you know it exists.
+    pub fn set_unchecked(&mut self, entity: Entity, data: T) -> Option<T> {
+        let key = data.index_key();
+        let previous = self.storage.set_unchecked(entity, data);
+        self.unindex(entity.index);
+        self.insert_key(entity, key);
+        previous
+    }
+
+    /// Remove the component of the given entity after it was despawned.
+    /// Returns the data associated with the given entity in self, if any.
+    /// Components set for another entity at the same index are left untouched.
+    pub fn remove_for_despawn(&mut self, entity: Entity) -> Option<T> {
+        let removed = self.storage.remove_for_despawn(entity)?;
+        self.unindex(entity.index);
+        Some(removed)
+    }
+
+    /// Remove the component for the given entity.
+    /// Returns the previous data associated with the given entity in self.
+    pub fn remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        let removed = self.storage.remove(entity)?;
+        if removed.is_some() {
+            self.unindex(entity.index);
+        }
+        Ok(removed)
+    }
+
+    /// Only allow mutating the components of self from the given thread, or from any thread for None;
+    /// see `VecStorage::bind_to_thread()`.
+    pub fn bind_to_thread(&mut self, thread: Option<ThreadId>) {
+        self.storage.bind_to_thread(thread);
+    }
+
+    /// The memory used by self, including the estimated size of the index; see `MemoryUsage`.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = self.storage.memory_usage();
+        usage.bytes += self.keys.capacity() * std::mem::size_of::<(u32, T::Key)>()
+            + self.index.capacity() * std::mem::size_of::<(T::Key, Vec<Entity>)>()
+            + self.index.values().map(Vec::capacity).sum::<usize>() * std::mem::size_of::<Entity>()
+            + self.stale.capacity() * std::mem::size_of::<Entity>();
+        usage
+    }
+
+    /// Remove the data stored in self for all entities.
+    pub fn clear(&mut self) {
+        self.storage.clear();
+        self.index.clear();
+        self.keys.clear();
+        self.stale.clear();
+    }
+
+    /// Remove the components of all alive entities from self, leaving the entities themselves untouched.
+    /// Returns the number of removed components.
+    pub fn clear_checked(&mut self) -> usize {
+        self.index.clear();
+        self.keys.clear();
+        self.stale.clear();
+        self.storage.clear_checked()
+    }
+
+    /// Grow the storage to hold components for all entity indices below `index_end`;
+    /// see `VecStorage::grow_to()`.
+    pub fn grow_to(&mut self, index_end: u32) {
+        self.storage.grow_to(index_end);
+    }
+
+    /// Set the current tick; see `VecStorage::set_tick()`.
+    pub fn set_tick(&mut self, tick: u32) {
+        self.storage.set_tick(tick);
+    }
+
+    /// The tick the component of the given entity was last modified at; see `VecStorage::last_modified()`.
+    pub fn last_modified(&self, entity: Entity) -> Option<u32> {
+        self.storage.last_modified(entity)
+    }
+
+    /// List the alive entities whose component was modified after the given tick; see `VecStorage::changes_since()`.
+    pub fn changes_since(&self, since: u32) -> Vec<(Entity, Option<&T>)> {
+        self.storage.changes_since(since)
+    }
+
+    /// Rewrite the entities stored in all components of self; see `MapEntities`.
+    /// All components are re-indexed the next time the index is used.
+    pub fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity)
+    where
+        T: MapEntities,
+    {
+        self.storage.map_entities(mapper);
+        self.stale.extend(self.index.values().flatten().copied());
+    }
+
+    /// Check that self uses the given entities and doesn't hold components for unallocated indices.
+    /// `storage` is the name of this storage used for error reporting.
+    pub fn check_integrity(
+        &self,
+        entities: &Arc<RwLock<Entities>>,
+        storage: &'static str,
+    ) -> Result<(), IntegrityError> {
+        self.storage.check_integrity(entities, storage)
+    }
+
+    /// Clone the data and index stored in self into a new IndexedStorage<T> that uses the given entities.
+    pub fn clone_with_entities(&self, entities: Arc<RwLock<Entities>>) -> Self
+    where
+        T: Clone,
+    {
+        Self {
+            storage: self.storage.clone_with_entities(entities),
+            index: self.index.clone(),
+            keys: self.keys.clone(),
+            stale: self.stale.clone(),
+        }
+    }
+}
+
+/// Storages are equal if the same alive entities have equal components in them.
+impl<T: IndexKey + PartialEq> PartialEq for IndexedStorage<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.storage == other.storage
+    }
+}
+
+impl<T: IndexKey + Eq> Eq for IndexedStorage<T> {}
+
+/// Shows the components of all alive entities by entity.
+impl<T: IndexKey + Debug> Debug for IndexedStorage<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.storage.fmt(f)
+    }
+}
+
+impl<T: IndexKey> Storage<T> for IndexedStorage<T> {
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.get(entity)
+    }
+
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_mut(entity)
+    }
+
+    fn get_unchecked(&self, entity: Entity) -> Option<&T> {
+        self.get_unchecked(entity)
+    }
+
+    fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_mut_unchecked(entity)
+    }
+}
+
+impl<T: IndexKey> TrackChanges<T> for IndexedStorage<T> {
+    fn tick(&self) -> u32 {
+        self.storage.tick()
+    }
+
+    fn changes_in(&self, ticks: std::ops::Range<u32>) -> Vec<(Entity, Option<&T>)> {
+        self.storage.changes_in(ticks)
+    }
+}
+
+impl<T: IndexKey> ModelStorage<T> for IndexedStorage<T> {
+    fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        self.set(entity, data)
+    }
+
+    fn remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        self.remove(entity)
+    }
+
+    fn remove_for_despawn(&mut self, entity: Entity) -> Option<T> {
+        self.remove_for_despawn(entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Eq, PartialEq)]
+    struct Cell(i32, i32);
+
+    impl IndexKey for Cell {
+        type Key = (i32, i32);
+
+        fn index_key(&self) -> Self::Key {
+            (self.0, self.1)
+        }
+    }
+
+    fn sorted(entities: impl Iterator<Item = Entity>) -> Vec<Entity> {
+        let mut entities = entities.collect::<Vec<_>>();
+        entities.sort_by_key(|entity| entity.index);
+        entities
+    }
+
+    #[test]
+    fn index_follows_mutations() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(3)));
+        let mut cells = IndexedStorage::<Cell>::new(Arc::clone(&entities), 3);
+        let (first, second) = {
+            let mut lock = entities.write().unwrap();
+            (lock.spawn(), lock.spawn())
+        };
+        cells.set(first, Cell(0, 0))?;
+        cells.set(second, Cell(0, 0))?;
+        assert_eq!(sorted(cells.find(&(0, 0))), vec![first, second]);
+
+        cells.set(first, Cell(1, 0))?;
+        assert_eq!(sorted(cells.find(&(0, 0))), vec![second]);
+        assert_eq!(sorted(cells.find(&(1, 0))), vec![first]);
+
+        cells.get_mut(second).unwrap().1 = 2;
+        assert_eq!(cells.find(&(0, 0)).count(), 0);
+        assert_eq!(sorted(cells.find(&(0, 2))), vec![second]);
+
+        cells.remove(first)?;
+        assert_eq!(cells.find(&(1, 0)).count(), 0);
+        entities.write().unwrap().despawn(second)?;
+        cells.remove_for_despawn(second);
+        assert!(cells.index.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use std::fmt::Debug;
+
+/// Type whose fields can be inspected generically, e.g. to show components in an editor.
+/// Can be derived via `#[derive(Inspect)]` for structs whose fields implement `Debug`.
+pub trait Inspect {
+    /// The names of the fields of this type, in declaration order.
+    fn field_names() -> &'static [&'static str]
+    where
+        Self: Sized;
+
+    /// Call `visitor` with the name and value of every field of self, in declaration order.
+    fn inspect_fields(&self, visitor: &mut dyn FnMut(&'static str, &dyn Debug));
+}
+
+/// Metadata about a component type of a World.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ComponentInfo {
+    /// The name of the component type.
+    pub name: &'static str,
+    /// The name of the storage field of the component in the World.
+    pub storage_name: &'static str,
+    /// The names of the fields of the component type; see `Inspect::field_names()`.
+    pub field_names: &'static [&'static str],
+}
+
+/// Visitor for the components of an entity; see the generated `inspect()` method of Worlds
+/// generated with the `inspect` option.
+pub trait ComponentVisitor {
+    /// Visit a single component.
+    fn visit(&mut self, info: &ComponentInfo, component: &dyn Inspect);
+}
+
+impl<F: FnMut(&ComponentInfo, &dyn Inspect)> ComponentVisitor for F {
+    fn visit(&mut self, info: &ComponentInfo, component: &dyn Inspect) {
+        self(info, component)
+    }
+}
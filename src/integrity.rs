@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+/// Error indicating that the storages of a World are inconsistent with its entities.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum IntegrityError {
+    /// The storage doesn't use the same `Entities` as the World it belongs to.
+    #[error("storage `{storage}` does not share the entities of its world")]
+    ForeignEntities { storage: &'static str },
+    /// The storage holds a component for an index that was never allocated by the entities.
+    #[error("storage `{storage}` holds a component for unallocated index {index}")]
+    UnallocatedIndex { storage: &'static str, index: u32 },
+}
+
+/// Integrity check for a World; implemented for all Worlds generated by `genesis`.
+pub trait WorldIntegrity {
+    /// Verify that all storages share the entities of the World and that they don't hold
+    /// components for indices that were never allocated.
+    fn check(&self) -> Result<(), IntegrityError>;
+}
@@ -0,0 +1,15 @@
+//! Building blocks of the JSON patch API generated for Worlds passed the `json` flag in `#[world]`.
+
+use crate::register::RegisterError;
+use thiserror::Error;
+
+/// Error returned by the generated `apply_json_patch()` and `entity_to_json()` of a World.
+#[derive(Error, Debug)]
+pub enum JsonError {
+    /// The JSON value isn't a valid template, or a component can't be serialized.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    /// Registering the patched components failed, e.g. because the entity doesn't exist.
+    #[error(transparent)]
+    Register(#[from] RegisterError),
+}
@@ -0,0 +1,337 @@
+use crate::StorageKind;
+use std::fmt::Write;
+
+/// Memory layout information about a component type of a World.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ComponentLayout {
+    /// The name of the component type.
+    pub name: &'static str,
+    /// The storage kind used for the component.
+    pub storage: StorageKind,
+    /// The size of the component type in bytes.
+    pub size: usize,
+    /// The alignment of the component type in bytes.
+    pub align: usize,
+}
+
+impl ComponentLayout {
+    /// Format the given layouts as a table, one component per line.
+    pub fn report(layouts: &[ComponentLayout]) -> String {
+        let name_width = layouts
+            .iter()
+            .map(|layout| layout.name.len())
+            .chain(std::iter::once("component".len()))
+            .max()
+            .unwrap_or_default();
+
+        let mut report = format!(
+            "{:<name_width$}  {:<7}  {:>8}  {:>5}\n",
+            "component",
+            "storage",
+            "size",
+            "align",
+            name_width = name_width
+        );
+        for layout in layouts {
+            writeln!(
+                report,
+                "{:<name_width$}  {:<7}  {:>8}  {:>5}",
+                layout.name,
+                layout.storage.name(),
+                layout.size,
+                layout.align,
+                name_width = name_width
+            )
+            .unwrap();
+        }
+        report
+    }
+}
+
+/// Selects the warning emitted by Worlds passed `warn_component_size` for components larger than the
+/// limit: only `check()` of `SizeCheck<true>` is deprecated, so the warning appears at compile time
+/// without failing the build.
+#[doc(hidden)]
+pub struct SizeCheck<const EXCEEDED: bool>;
+
+impl SizeCheck<false> {
+    pub const fn check() {}
+}
+
+impl SizeCheck<true> {
+    #[deprecated(
+        note = "component is larger than `warn_component_size`; store it in a MapStorage or a Box, or pass `#[component(allow_large)]`"
+    )]
+    pub const fn check() {}
+}
+
+/// How densely the slots of a `VecStorage<T>` of a World are occupied; see `VecStorage::occupancy()`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct StorageOccupancy {
+    /// The name of the storage field.
+    pub storage: &'static str,
+    /// The name of the component type.
+    pub name: &'static str,
+    /// The number of slots holding a component.
+    pub occupied: usize,
+    /// The number of allocated slots.
+    pub slots: usize,
+}
+
+impl StorageOccupancy {
+    /// The fraction of allocated slots holding a component; 1.0 for storages without slots.
+    pub fn occupancy(&self) -> f64 {
+        if self.slots == 0 {
+            1.0
+        } else {
+            self.occupied as f64 / self.slots as f64
+        }
+    }
+}
+
+/// The occupancy of all `VecStorage`s of a World, returned by the generated `fragmentation_report()`.
+/// Storages whose slots are mostly empty, e.g. because only few entities have their component,
+/// waste memory and iteration time and are better stored in a `MapStorage`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct FragmentationReport {
+    /// The occupancy of every `VecStorage` of the World, in declaration order.
+    pub storages: Vec<StorageOccupancy>,
+}
+
+impl FragmentationReport {
+    /// The storages occupied less than the given fraction, e.g. 0.25.
+    pub fn poorly_occupied(&self, threshold: f64) -> impl Iterator<Item = &StorageOccupancy> {
+        self.storages
+            .iter()
+            .filter(move |storage| storage.occupancy() < threshold)
+    }
+
+    /// List the storages occupied less than the given fraction, one per line, with a suggestion.
+    pub fn report(&self, threshold: f64) -> String {
+        let mut report = String::new();
+        for storage in self.poorly_occupied(threshold) {
+            writeln!(
+                report,
+                "{} ({}): {} of {} slots occupied ({:.0}%); consider MapStorage<{}>",
+                storage.storage,
+                storage.name,
+                storage.occupied,
+                storage.slots,
+                storage.occupancy() * 100.0,
+                storage.name
+            )
+            .unwrap();
+        }
+        report
+    }
+}
+
+/// The memory used by a storage; see `memory_usage()` of the storage types.
+/// Bytes are estimated from the capacities of the buffers of the storage and the sizes of their elements,
+/// not counting memory owned by the components themselves, e.g. the contents of a `String`.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct MemoryUsage {
+    /// The number of components in the storage.
+    pub components: usize,
+    /// The number of allocated slots; the capacity for storages based on hash maps.
+    pub slots: usize,
+    /// The number of allocated bytes.
+    pub bytes: usize,
+}
+
+/// The memory used by one storage of a World; see `MemorySnapshot`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct StorageMemory {
+    /// The name of the storage field.
+    pub storage: &'static str,
+    /// The name of the component type.
+    pub name: &'static str,
+    pub usage: MemoryUsage,
+}
+
+/// The memory used by every storage of a World at one point in time, taken by the generated
+/// `memory_snapshot()`. Compare two snapshots of the same World with `diff()`, e.g. taken an hour apart,
+/// to find the storage whose component count creeps up.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MemorySnapshot {
+    /// The memory used by every storage of the World, in declaration order.
+    pub storages: Vec<StorageMemory>,
+}
+
+impl MemorySnapshot {
+    /// The bytes allocated by all storages.
+    pub fn bytes(&self) -> usize {
+        self.storages
+            .iter()
+            .map(|storage| storage.usage.bytes)
+            .sum()
+    }
+
+    /// The growth of every storage from self to the given later snapshot, matched by storage name;
+    /// storages missing from self count as empty.
+    pub fn diff(&self, later: &MemorySnapshot) -> MemoryDiff {
+        let storages = later
+            .storages
+            .iter()
+            .map(|storage| {
+                let earlier = self
+                    .storages
+                    .iter()
+                    .find(|earlier| earlier.storage == storage.storage)
+                    .map(|earlier| earlier.usage)
+                    .unwrap_or_default();
+                let growth = |later: usize, earlier: usize| later as i64 - earlier as i64;
+                StorageGrowth {
+                    storage: storage.storage,
+                    name: storage.name,
+                    components: growth(storage.usage.components, earlier.components),
+                    slots: growth(storage.usage.slots, earlier.slots),
+                    bytes: growth(storage.usage.bytes, earlier.bytes),
+                }
+            })
+            .collect();
+        MemoryDiff { storages }
+    }
+}
+
+/// The change in memory use of one storage between two snapshots; negative if it shrank.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct StorageGrowth {
+    /// The name of the storage field.
+    pub storage: &'static str,
+    /// The name of the component type.
+    pub name: &'static str,
+    pub components: i64,
+    pub slots: i64,
+    pub bytes: i64,
+}
+
+/// The growth of the storages of a World between two snapshots; see `MemorySnapshot::diff()`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MemoryDiff {
+    /// The growth of every storage of the later snapshot, in declaration order.
+    pub storages: Vec<StorageGrowth>,
+}
+
+impl MemoryDiff {
+    /// The storages that gained components, slots or bytes, by growth in bytes and then in components,
+    /// largest first.
+    pub fn grown(&self) -> Vec<&StorageGrowth> {
+        let mut grown = self
+            .storages
+            .iter()
+            .filter(|storage| storage.components > 0 || storage.slots > 0 || storage.bytes > 0)
+            .collect::<Vec<_>>();
+        grown.sort_by_key(|storage| std::cmp::Reverse((storage.bytes, storage.components)));
+        grown
+    }
+
+    /// List the grown storages, one per line, largest first; empty if no storage grew.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        for storage in self.grown() {
+            writeln!(
+                report,
+                "{} ({}): {:+} components, {:+} slots, {:+} bytes",
+                storage.storage, storage.name, storage.components, storage.slots, storage.bytes
+            )
+            .unwrap();
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_diff() {
+        let snapshot = |positions: MemoryUsage, effects: MemoryUsage| MemorySnapshot {
+            storages: vec![
+                StorageMemory {
+                    storage: "positions",
+                    name: "Position",
+                    usage: positions,
+                },
+                StorageMemory {
+                    storage: "effects",
+                    name: "Effect",
+                    usage: effects,
+                },
+            ],
+        };
+        let usage = |components, slots, bytes| MemoryUsage {
+            components,
+            slots,
+            bytes,
+        };
+        let earlier = snapshot(usage(10, 16, 256), usage(2, 3, 96));
+        let later = snapshot(usage(8, 16, 256), usage(40, 56, 1792));
+        assert_eq!(later.bytes(), 2048);
+
+        let diff = earlier.diff(&later);
+        assert_eq!(diff.storages[0].components, -2);
+        assert_eq!(diff.grown().len(), 1);
+        assert_eq!(
+            diff.report(),
+            "effects (Effect): +38 components, +53 slots, +1696 bytes\n"
+        );
+        assert_eq!(MemorySnapshot::default().diff(&earlier).grown().len(), 2);
+    }
+
+    #[test]
+    fn fragmentation_report() {
+        let report = FragmentationReport {
+            storages: vec![
+                StorageOccupancy {
+                    storage: "positions",
+                    name: "Position",
+                    occupied: 60,
+                    slots: 64,
+                },
+                StorageOccupancy {
+                    storage: "bosses",
+                    name: "Boss",
+                    occupied: 1,
+                    slots: 64,
+                },
+                StorageOccupancy {
+                    storage: "unused",
+                    name: "Unused",
+                    occupied: 0,
+                    slots: 0,
+                },
+            ],
+        };
+        assert_eq!(report.poorly_occupied(0.25).count(), 1);
+        assert_eq!(
+            report.report(0.25),
+            "bosses (Boss): 1 of 64 slots occupied (2%); consider MapStorage<Boss>\n"
+        );
+    }
+
+    #[test]
+    fn report_table() {
+        let layouts = [
+            ComponentLayout {
+                name: "Position",
+                storage: StorageKind::Vec,
+                size: 8,
+                align: 4,
+            },
+            ComponentLayout {
+                name: "Huge",
+                storage: StorageKind::Map,
+                size: 2048,
+                align: 8,
+            },
+        ];
+        assert_eq!(
+            ComponentLayout::report(&layouts),
+            "component  storage      size  align\n\
+             Position   vec             8      4\n\
+             Huge       map          2048      8\n"
+        );
+    }
+}
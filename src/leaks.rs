@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::panic::Location;
+
+/// Where an entity was spawned, as recorded by the leak tracking of `Entities`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum SpawnOrigin {
+    /// The call site of the spawn, recorded via `#[track_caller]`.
+    Caller(&'static Location<'static>),
+    /// A label passed to `Entities::spawn_labeled()`.
+    Label(&'static str),
+}
+
+impl Display for SpawnOrigin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpawnOrigin::Caller(location) => write!(f, "{}", location),
+            SpawnOrigin::Label(label) => write!(f, "{}", label),
+        }
+    }
+}
+
+/// Alive entities spawned at the same origin, as reported by `Entities::report_leaks()`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct LeakReport {
+    /// Where the entities were spawned.
+    pub origin: SpawnOrigin,
+    /// The number of alive entities spawned at this origin.
+    pub count: usize,
+    /// The frame the oldest of these entities was spawned in; see `Entities::next_frame()`.
+    pub oldest_frame: u32,
+}
+
+/// Records the origin and frame of every spawned entity by index.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LeakTracker {
+    spawns: HashMap<u32, (SpawnOrigin, u32)>,
+}
+
+impl LeakTracker {
+    pub(crate) fn record(&mut self, index: u32, origin: SpawnOrigin, frame: u32) {
+        self.spawns.insert(index, (origin, frame));
+    }
+
+    pub(crate) fn forget(&mut self, index: u32) {
+        self.spawns.remove(&index);
+    }
+
+    /// Group the entities spawned at least `min_age` frames before `frame` by origin,
+    /// most frequent origins first.
+    pub(crate) fn report(&self, frame: u32, min_age: u32) -> Vec<LeakReport> {
+        let mut reports: Vec<LeakReport> = vec![];
+        for (origin, spawned_in) in self.spawns.values() {
+            if frame.wrapping_sub(*spawned_in) < min_age {
+                continue;
+            }
+            match reports.iter_mut().find(|report| report.origin == *origin) {
+                Some(report) => {
+                    report.count += 1;
+                    report.oldest_frame = report.oldest_frame.min(*spawned_in);
+                }
+                None => reports.push(LeakReport {
+                    origin: *origin,
+                    count: 1,
+                    oldest_frame: *spawned_in,
+                }),
+            }
+        }
+        reports.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then(a.oldest_frame.cmp(&b.oldest_frame))
+        });
+        reports
+    }
+}
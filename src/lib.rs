@@ -1,20 +1,214 @@
 #![deny(rust_2018_idioms)]
 #![deny(clippy::all)]
 
+mod access;
+mod allocations;
+mod bitset;
+mod change_cursor;
+mod changes;
+mod clear_component;
+mod command_buffer;
+mod component_ref;
+mod despawn;
+mod determinism;
+mod double_buffered;
+mod dynamic_storage;
 mod entity;
+mod entity_group;
+mod entity_iter;
+mod from_query;
+mod has_component;
+mod history;
+mod indexed_storage;
+mod inspect;
+mod integrity;
+mod layout;
+mod leaks;
+mod lifecycle;
+mod limit;
+mod lock;
+mod map_entities;
 mod mapstorage;
+#[cfg(feature = "mmap")]
+mod mmap_storage;
+mod model;
 mod no_such_entity;
+mod profile;
+mod query_stats;
 mod register;
+mod registry;
+mod scene;
+mod shards;
+mod shared;
+mod stable_storage;
+mod storage;
+mod storage_error;
+mod template_library;
+mod thread_check;
+mod trace;
+mod unknown_data;
+mod uuids;
 mod vecstorage;
+mod world_eq;
 
 pub use genesis_impl::*;
 
+#[cfg(feature = "rkyv")]
+pub mod archive;
+pub mod console;
+pub mod surrogate;
+pub mod testing;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "json")]
+pub mod json;
+
+#[cfg(feature = "ron")]
+pub mod ron_format;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "rkyv")]
+#[doc(hidden)]
+pub use rkyv;
+#[cfg(feature = "schemars")]
+#[doc(hidden)]
+pub use schemars;
+
+#[doc(hidden)]
+pub use serde;
+
+#[cfg(feature = "json")]
+#[doc(hidden)]
+pub use serde_json;
+
+#[cfg(feature = "ron")]
+#[doc(hidden)]
+pub use ron;
+
+#[cfg(feature = "wasm")]
+#[doc(hidden)]
+pub use serde_wasm_bindgen;
+
+#[cfg(feature = "wasm")]
+#[doc(hidden)]
+pub use wasm_bindgen;
+
+pub use access::AccessConflict;
+pub use access::AccessGrants;
+pub use access::AccessSet;
+pub use access::AccessToken;
+pub use access::Read;
+pub use access::Write;
+pub use allocations::AllocationCount;
+pub use allocations::AllocationKind;
+pub use allocations::AllocationReport;
+pub use bitset::BitSet;
+pub use bitset::BitSetView;
+pub use change_cursor::ChangeCursor;
+pub use change_cursor::TrackChanges;
+pub use changes::ChangeSet;
+pub use clear_component::ClearComponent;
+pub use command_buffer::CommandBuffer;
+pub use component_ref::ComponentRef;
+pub use despawn::Despawn;
+pub use despawn::Relation;
+pub use determinism::check_determinism;
+pub use determinism::Divergence;
+pub use double_buffered::DoubleBuffered;
+pub use dynamic_storage::DynamicStorage;
+pub use dynamic_storage::UnsupportedMigration;
+pub use entity::ClearProgress;
 pub use entity::Entities;
 pub use entity::Entity;
+pub use entity::ParseEntityError;
+pub use entity::ReusePolicy;
+pub use entity_group::EntityGroup;
+pub use entity_iter::EntityIterator;
+pub use from_query::FromQuery;
+pub use has_component::HasComponent;
+pub use has_component::HasEntities;
+pub use history::Fork;
+pub use history::History;
+pub use indexed_storage::IndexKey;
+pub use indexed_storage::IndexedStorage;
+pub use inspect::ComponentInfo;
+pub use inspect::ComponentVisitor;
+pub use inspect::Inspect;
+pub use integrity::IntegrityError;
+pub use integrity::WorldIntegrity;
+pub use layout::ComponentLayout;
+pub use layout::FragmentationReport;
+pub use layout::MemoryDiff;
+pub use layout::MemorySnapshot;
+pub use layout::MemoryUsage;
+#[doc(hidden)]
+pub use layout::SizeCheck;
+pub use layout::StorageGrowth;
+pub use layout::StorageMemory;
+pub use layout::StorageOccupancy;
+pub use leaks::LeakReport;
+pub use leaks::SpawnOrigin;
+pub use lifecycle::Lifecycle;
+pub use lifecycle::LifecycleError;
+pub use limit::EntityLimit;
+pub use limit::EntityLimitReached;
+pub use limit::Watermark;
+pub use lock::ReadFor;
+pub use lock::TryLockFor;
+pub use lock::WouldBlock;
+pub use lock::WriteFor;
+pub use map_entities::MapEntities;
 pub use mapstorage::MapStorage;
+#[cfg(feature = "mmap")]
+pub use mmap_storage::MmapComponent;
+#[cfg(feature = "mmap")]
+pub use mmap_storage::MmapStorage;
+pub use model::ModelStorage;
+pub use model::ModelViolation;
+pub use model::StorageModel;
+pub use model::StorageOp;
 pub use no_such_entity::NoSuchEntity;
+pub use profile::Access;
+pub use profile::ProfileScope;
+pub use profile::StorageProfile;
+pub use query_stats::QueryStats;
+pub use register::MoveError;
 pub use register::Register;
+pub use register::RegisterError;
+pub use register::RegisterWithReport;
+pub use register::Registered;
+pub use registry::registry;
+pub use registry::ComponentNames;
+pub use registry::DebugWorld;
+pub use registry::RegisteredWorld;
+pub use registry::WorldRegistry;
+pub use scene::SceneTemplate;
+pub use shards::Shard;
+pub use shards::ShardError;
+pub use shards::Shards;
+pub use shared::Shared;
+pub use stable_storage::StableStorage;
+pub use storage::Storage;
+pub use storage::StorageKind;
+pub use storage::TakeStorage;
+pub use storage_error::StorageError;
+pub use surrogate::Surrogate;
+pub use template_library::Overlay;
+pub use template_library::TemplateError;
+pub use template_library::TemplateLibrary;
+#[doc(hidden)]
+pub use trace::query as trace_query;
+pub use unknown_data::UnknownData;
+pub use unknown_data::UnknownValue;
+pub use uuids::UuidError;
+pub use uuids::Uuids;
 pub use vecstorage::VecStorage;
+pub use world_eq::WorldEq;
+pub use world_eq::WorldMismatch;
 
 #[cfg(test)]
 mod tests {
@@ -50,8 +244,8 @@ mod tests {
             let mut write = self.entities.write().unwrap();
 
             write.despawn(id)?;
-            self.vec.remove_unchecked(id);
-            self.map.remove_unchecked(id);
+            self.vec.remove_for_despawn(id);
+            self.map.remove_for_despawn(id);
 
             Ok(())
         }
@@ -0,0 +1,37 @@
+use crate::entity::Entity;
+use crate::no_such_entity::NoSuchEntity;
+use thiserror::Error;
+
+/// The lifecycle state of an alive entity, for frames processed in phases like
+/// spawn initialization, update and cleanup; see `Entities::spawn_staged()`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
+pub enum Lifecycle {
+    /// Spawned via `Entities::spawn_staged()` and not yet activated, e.g. because its components are
+    /// still being initialized.
+    Spawning,
+    /// Fully initialized. Entities spawned via `spawn()` start in this state.
+    #[default]
+    Alive,
+    /// Marked for despawning at the end of the frame via `Entities::mark_pending_despawn()`.
+    PendingDespawn,
+}
+
+/// Error returned when changing the lifecycle state of an entity fails.
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LifecycleError {
+    /// The entity doesn't exist.
+    #[error(transparent)]
+    NoSuchEntity(#[from] NoSuchEntity),
+    /// The entity is not in a state it can change to the requested state from.
+    #[error("entity {entity} can't change from {from:?} to {to:?}")]
+    InvalidTransition {
+        entity: Entity,
+        from: Lifecycle,
+        to: Lifecycle,
+    },
+}
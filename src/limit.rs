@@ -0,0 +1,35 @@
+use thiserror::Error;
+
+/// A cap on the number of alive entities, with a lower threshold to warn at; see `Entities::with_limit()`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
+pub struct EntityLimit {
+    /// The number of alive entities at which the watermark reaches `Watermark::Warning`.
+    pub warn_at: u32,
+    /// The maximum number of alive entities; `Entities::try_spawn()` fails once it is reached.
+    pub max: u32,
+}
+
+/// How close the number of alive entities is to the configured `EntityLimit`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Watermark {
+    /// Below the warning threshold, or no limit is configured.
+    Normal,
+    /// At or above the warning threshold; callers should apply backpressure, e.g. reject new connections.
+    Warning,
+    /// The limit is reached; `Entities::try_spawn()` fails until entities are despawned.
+    Full,
+}
+
+/// Error returned by `try_spawn()` when the configured `EntityLimit` is reached, or when all indices up to
+/// the configured max index are in use; see `Entities::with_max_index()`.
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq)]
+#[error("Entity limit of {limit} alive entities reached")]
+pub struct EntityLimitReached {
+    /// The maximum number of alive entities, or the number of usable indices.
+    pub limit: u32,
+}
@@ -0,0 +1,246 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// The delay before the first retry of a contested lock; it doubles with every retry up to `MAX_BACKOFF`.
+const MIN_BACKOFF: Duration = Duration::from_micros(50);
+const MAX_BACKOFF: Duration = Duration::from_millis(5);
+
+/// Error returned when a lock couldn't be acquired without blocking, or within the given timeout.
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq)]
+#[error("lock is held by another thread")]
+pub struct WouldBlock;
+
+/// Timed variants of `RwLock::try_read()` and `RwLock::try_write()` for async code, e.g. for the `Entities` of a
+/// World shared with an async server, which should skip a frame of work instead of stalling its runtime
+/// when the lock is contested.
+///
+/// The returned futures try to acquire the lock whenever they are polled. While it is held by another
+/// thread, they yield to the executor instead of blocking the thread, and retry after a backoff that starts
+/// at 50µs and doubles up to 5ms, until the lock is acquired or the timeout has passed; with a zero timeout,
+/// they resolve on the first poll. `RwLock` can't notify waiters when it is released, so a background
+/// thread shared by all these futures wakes them for their retries; the runtime's threads stay free
+/// in the meantime. Like the storages, they panic if the lock is poisoned.
+pub trait TryLockFor<T: ?Sized> {
+    /// Acquire a read lock, waiting at most the given timeout for writers to release the lock.
+    fn try_read_for(&self, timeout: Duration) -> ReadFor<'_, T>;
+
+    /// Acquire the write lock, waiting at most the given timeout for others to release the lock.
+    fn try_write_for(&self, timeout: Duration) -> WriteFor<'_, T>;
+}
+
+impl<T: ?Sized> TryLockFor<T> for RwLock<T> {
+    fn try_read_for(&self, timeout: Duration) -> ReadFor<'_, T> {
+        ReadFor {
+            lock: self,
+            deadline: Instant::now().checked_add(timeout),
+            backoff: MIN_BACKOFF,
+        }
+    }
+
+    fn try_write_for(&self, timeout: Duration) -> WriteFor<'_, T> {
+        WriteFor {
+            lock: self,
+            deadline: Instant::now().checked_add(timeout),
+            backoff: MIN_BACKOFF,
+        }
+    }
+}
+
+/// Future returned by `TryLockFor::try_read_for()`.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct ReadFor<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    /// None if the timeout doesn't fit into an `Instant`, i.e. never expires.
+    deadline: Option<Instant>,
+    /// The delay before the next retry.
+    backoff: Duration,
+}
+
+impl<'a, T: ?Sized> Future for ReadFor<'a, T> {
+    type Output = Result<RwLockReadGuard<'a, T>, WouldBlock>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        poll_lock(this.deadline, &mut this.backoff, cx, this.lock.try_read())
+    }
+}
+
+/// Future returned by `TryLockFor::try_write_for()`.
+#[must_use = "futures do nothing unless polled"]
+#[derive(Debug)]
+pub struct WriteFor<'a, T: ?Sized> {
+    lock: &'a RwLock<T>,
+    /// None if the timeout doesn't fit into an `Instant`, i.e. never expires.
+    deadline: Option<Instant>,
+    /// The delay before the next retry.
+    backoff: Duration,
+}
+
+impl<'a, T: ?Sized> Future for WriteFor<'a, T> {
+    type Output = Result<RwLockWriteGuard<'a, T>, WouldBlock>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        poll_lock(this.deadline, &mut this.backoff, cx, this.lock.try_write())
+    }
+}
+
+/// Resolve with the acquired guard, or with WouldBlock once the deadline has passed; otherwise, schedule
+/// another poll after the given backoff, but no later than the deadline, double the backoff and yield.
+fn poll_lock<G>(
+    deadline: Option<Instant>,
+    backoff: &mut Duration,
+    cx: &mut Context<'_>,
+    attempt: Result<G, TryLockError<G>>,
+) -> Poll<Result<G, WouldBlock>> {
+    match now_or_never(attempt) {
+        Ok(guard) => Poll::Ready(Ok(guard)),
+        Err(WouldBlock) => {
+            let now = Instant::now();
+            if deadline.is_some_and(|deadline| now >= deadline) {
+                return Poll::Ready(Err(WouldBlock));
+            }
+            let retry = now.checked_add(*backoff).unwrap_or(now);
+            let retry = deadline.map_or(retry, |deadline| retry.min(deadline));
+            wake_at(retry, cx.waker().clone());
+            *backoff = (*backoff * 2).min(MAX_BACKOFF);
+            Poll::Pending
+        }
+    }
+}
+
+/// Wake the given waker at the given instant from the background thread of the lock futures.
+fn wake_at(instant: Instant, waker: Waker) {
+    static TIMER: OnceLock<Sender<(Instant, Waker)>> = OnceLock::new();
+    let timer = TIMER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::Builder::new()
+            .name(String::from("genesis-lock-timer"))
+            .spawn(move || run_timer(receiver))
+            .expect("failed to spawn the timer thread of the lock futures");
+        sender
+    });
+    // The receiver lives as long as the process, since the sender is never dropped.
+    timer.send((instant, waker)).ok();
+}
+
+/// Wake the received wakers at their instants. There are only ever a few contested lock futures at once,
+/// so they are kept in an unordered list.
+fn run_timer(receiver: Receiver<(Instant, Waker)>) {
+    let mut pending: Vec<(Instant, Waker)> = Vec::new();
+    loop {
+        let next = pending.iter().map(|(instant, _)| *instant).min();
+        let received = match next {
+            Some(next) => receiver.recv_timeout(next.saturating_duration_since(Instant::now())),
+            None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(entry) => pending.push(entry),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        let now = Instant::now();
+        pending.retain(|(instant, waker)| {
+            if *instant <= now {
+                waker.wake_by_ref();
+            }
+            *instant > now
+        });
+    }
+}
+
+/// Acquire a read lock only if no other thread holds the write lock right now, e.g. for the `try_` methods
+/// of the storages.
+pub(crate) fn try_read_now<T: ?Sized>(
+    lock: &RwLock<T>,
+) -> Result<RwLockReadGuard<'_, T>, WouldBlock> {
+    now_or_never(lock.try_read())
+}
+
+fn now_or_never<G>(attempt: Result<G, TryLockError<G>>) -> Result<G, WouldBlock> {
+    match attempt {
+        Ok(guard) => Ok(guard),
+        Err(TryLockError::Poisoned(_)) => panic!("lock poisoned"),
+        Err(TryLockError::WouldBlock) => Err(WouldBlock),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Wake, Waker};
+
+    /// Counts how often the futures were woken for another poll.
+    #[derive(Default)]
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn poll_once<F: Future + Unpin>(future: &mut F, waker: &Waker) -> Poll<F::Output> {
+        Pin::new(future).poll(&mut Context::from_waker(waker))
+    }
+
+    #[test]
+    fn backs_off_while_write_locked() {
+        let counter = Arc::new(CountingWaker::default());
+        let waker = Waker::from(Arc::clone(&counter));
+        let lock = RwLock::new(1);
+        {
+            let _write = lock.write().unwrap();
+            let mut read = lock.try_read_for(Duration::ZERO);
+            assert!(matches!(
+                poll_once(&mut read, &waker),
+                Poll::Ready(Err(WouldBlock))
+            ));
+
+            let start = Instant::now();
+            let mut write = lock.try_write_for(Duration::from_millis(20));
+            let mut polls = 0;
+            let result = loop {
+                polls += 1;
+                if let Poll::Ready(result) = poll_once(&mut write, &waker) {
+                    break result;
+                }
+                // Only poll again once woken, like an executor would.
+                while counter.0.load(Ordering::SeqCst) < polls {
+                    thread::yield_now();
+                }
+            };
+            assert!(result.is_err());
+            assert!(start.elapsed() >= Duration::from_millis(20));
+            // Backing off from 50µs up to 5ms takes 7 retries to reach 5ms and at most 3 more until 20ms,
+            // rather than polling continuously.
+            assert!(polls <= 12, "polled {} times", polls);
+        }
+
+        let mut read = lock.try_read_for(Duration::ZERO);
+        match poll_once(&mut read, &waker) {
+            Poll::Ready(Ok(guard)) => assert_eq!(*guard, 1),
+            _ => panic!("read lock not acquired"),
+        }
+        let mut write = lock.try_write_for(Duration::from_secs(1));
+        match poll_once(&mut write, &waker) {
+            Poll::Ready(Ok(mut guard)) => *guard = 2,
+            _ => panic!("write lock not acquired"),
+        }
+        assert_eq!(*lock.read().unwrap(), 2);
+        assert_eq!(try_read_now(&lock).map(|guard| *guard), Ok(2));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::Entity;
+
+/// Type containing entities that need to be rewritten when entities are remapped, e.g. when
+/// importing data from another World.
+/// Components implementing this trait can be marked with `#[component(maps_entities)]` in a World
+/// definition so that the generated `map_entities()` of the World rewrites them.
+pub trait MapEntities {
+    /// Replace every entity stored in self with the result of applying `mapper` to it.
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity);
+}
+
+impl MapEntities for Entity {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity) {
+        *self = mapper(*self);
+    }
+}
+
+impl<T: MapEntities> MapEntities for Option<T> {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity) {
+        if let Some(inner) = self {
+            inner.map_entities(mapper);
+        }
+    }
+}
+
+impl<T: MapEntities> MapEntities for Vec<T> {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity) {
+        for inner in self {
+            inner.map_entities(mapper);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_nested_entities() {
+        let entity = |index| Entity {
+            index,
+            generation: 0,
+        };
+        let mut entities = vec![Some(entity(1)), None, Some(entity(2))];
+        entities.map_entities(&mut |e| Entity {
+            index: e.index + 10,
+            generation: e.generation + 1,
+        });
+        assert_eq!(
+            entities,
+            vec![
+                Some(Entity {
+                    index: 11,
+                    generation: 1
+                }),
+                None,
+                Some(Entity {
+                    index: 12,
+                    generation: 1
+                })
+            ]
+        );
+    }
+}
@@ -1,31 +1,93 @@
+use crate::allocations::{self, AllocationKind};
+use crate::bitset::BitSet;
+use crate::integrity::IntegrityError;
+use crate::layout::MemoryUsage;
+use crate::lock::try_read_now;
+use crate::map_entities::MapEntities;
 use crate::no_such_entity::NoSuchEntity;
+use crate::profile::{self, Access};
+use crate::storage_error::StorageError;
+use crate::thread_check::OwnerThread;
+use crate::trace;
 use crate::Entities;
 use crate::Entity;
-use std::collections::HashMap;
-use std::fmt::Debug;
+use crate::ModelStorage;
+use crate::Storage;
+use crate::VecStorage;
+use allocator_api2::alloc::{Allocator, Global};
+use hashbrown::DefaultHashBuilder;
+use hashbrown::HashMap;
+use std::fmt::{Debug, Formatter};
 use std::sync::{Arc, RwLock};
+use std::thread::ThreadId;
 
 /// A storage type based on a HashMap, intended for sparsely used components.
-#[derive(Debug)]
-pub struct MapStorage<T> {
-    map: HashMap<u32, T>,
+/// Cloning a MapStorage<T> clones its data; the clone shares the same `Entities` as the original.
+/// The memory of the storage comes from the allocator `A`, e.g. an arena that is freed at once between levels.
+/// Storages of Worlds generated by `#[world]` use the global allocator.
+/// With the `rkyv` feature enabled, storages can be archived without their entities; see `attach_entities()`.
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
+pub struct MapStorage<T, A: Allocator + Clone = Global> {
+    /// The components by entity index, together with the generation of the entity they were set for.
+    #[cfg_attr(feature = "rkyv", with(crate::archive::AsHashMap))]
+    map: HashMap<u32, (u32, T), DefaultHashBuilder, A>,
+    #[cfg_attr(feature = "rkyv", with(crate::archive::Detached))]
     entities: Arc<RwLock<Entities>>,
+    /// The thread allowed to mutate the components; see `bind_to_thread()`.
+    #[cfg_attr(feature = "rkyv", with(rkyv::with::Skip))]
+    owner: OwnerThread,
 }
 
 impl<T> MapStorage<T> {
     /// Create a new MapStorage<T>.
     pub fn new(entity_allocator: Arc<RwLock<Entities>>) -> Self {
+        Self::new_in(entity_allocator, Global)
+    }
+}
+
+impl<T, A: Allocator + Clone> MapStorage<T, A> {
+    /// Create a new MapStorage<T> that allocates from the given allocator.
+    pub fn new_in(entity_allocator: Arc<RwLock<Entities>>, allocator: A) -> Self {
         Self {
-            map: HashMap::new(),
+            map: HashMap::new_in(allocator),
             entities: entity_allocator,
+            owner: OwnerThread::current(),
         }
     }
 
+    /// The alive entity at the given index, if the component stored for the index with the given generation
+    /// was set for it rather than for an earlier entity at the same index.
+    fn stored_entity(entities: &Entities, index: u32, generation: u32) -> Option<Entity> {
+        entities
+            .current_entity_at(index)
+            .filter(|entity| entity.generation == generation)
+    }
+
+    /// Iterate over the alive entities of the given entities that have a component in self, by index.
+    fn alive_components<'a>(
+        &'a self,
+        entities: &'a Entities,
+    ) -> impl Iterator<Item = (Entity, &'a T)> + 'a {
+        entities
+            .iter()
+            .filter_map(move |entity| match self.map.get(&entity.index) {
+                Some((generation, component)) if *generation == entity.generation => {
+                    Some((entity, component))
+                }
+                _ => None,
+            })
+    }
+
     /// Get a reference to the associated component for the given entity, if any.
     pub fn get(&self, entity: Entity) -> Option<&T> {
         let lock = self.entities.read().unwrap();
         if lock.exists(entity) {
-            self.map.get(&entity.index)
+            self.get_unchecked(entity)
         } else {
             None
         }
@@ -33,50 +95,451 @@ impl<T> MapStorage<T> {
 
     /// Get a mutable reference to the associated component for the given entity, if any.
     pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
-        let lock = self.entities.read().unwrap();
-        if lock.exists(entity) {
-            self.map.get_mut(&entity.index)
+        let exists = self.entities.read().unwrap().exists(entity);
+        if exists {
+            self.get_mut_unchecked(entity)
         } else {
             None
         }
     }
 
+    /// Get a reference to the associated component for the given entity, if any.
+    /// Does not check if the entity exists; only use this if you know it exists, e.g.
+    /// because you retrieved this in a loop iterating over all alive entities.
+    pub fn get_unchecked(&self, entity: Entity) -> Option<&T> {
+        let _span = profile::record::<T>(Access::Get);
+        match self.map.get(&entity.index) {
+            Some((generation, component)) if *generation == entity.generation => Some(component),
+            _ => None,
+        }
+    }
+
+    /// Get a mutable reference to the associated component for the given entity, if any.
+    /// Does not check if the entity exists; only use this if you know it exists, e.g.
+    /// because you retrieved this in a loop iterating over all alive entities.
+    pub fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T> {
+        self.owner.check::<T>("get_mut");
+        let _span = profile::record::<T>(Access::GetMut);
+        match self.map.get_mut(&entity.index) {
+            Some((generation, component)) if *generation == entity.generation => Some(component),
+            _ => None,
+        }
+    }
+
     /// Set the component for the given entity.
     /// Returns Err(NoSuchEnitty) if the given entity doesn't exist.
     /// Otherwise, returns the previous data stored in self for the given entity.
-    pub fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, NoSuchEntity> {
-        let lock = self.entities.read().unwrap();
-        if lock.exists(entity) {
-            Ok(self.map.insert(entity.index, data))
+    pub fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        let exists = self.entities.read().unwrap().exists(entity);
+        if exists {
+            Ok(self.set_unchecked(entity, data))
         } else {
-            Err(NoSuchEntity {})
+            Err(NoSuchEntity::new(entity).with_operation("set").into())
+        }
+    }
+
+    /// Set the component for the given entity.
+    /// Returns the previous data stored in self for the given entity.
+    /// Does not check if the entity exists and doesn't lock the entities; only use this if you know
+    /// it exists, e.g. because you just spawned it while holding the write lock of the entities.
+    pub fn set_unchecked(&mut self, entity: Entity, data: T) -> Option<T> {
+        self.owner.check::<T>("set");
+        let _span = profile::record::<T>(Access::Set);
+        trace::component::<T>("set", entity);
+        let capacity = self.map.capacity();
+        // A component of an earlier entity at the same index is dropped rather than returned.
+        let previous = self
+            .map
+            .insert(entity.index, (entity.generation, data))
+            .filter(|(generation, _component)| *generation == entity.generation)
+            .map(|(_generation, component)| component);
+        self.record_rehash(capacity);
+        previous
+    }
+
+    /// Record a rehash of the map from the given capacity with the `track_allocations` feature.
+    fn record_rehash(&self, capacity: usize) {
+        allocations::record_growth::<T, (u32, (u32, T))>(
+            AllocationKind::Rehash,
+            capacity,
+            self.map.capacity(),
+        );
+    }
+
+    /// Remove the component of the given entity after it was despawned.
+    /// Returns the data associated with the given entity in self, if any.
+    /// Doesn't lock the entities; a component set for another entity at the same index,
+    /// e.g. because the given entity is stale, is left untouched.
+    pub fn remove_for_despawn(&mut self, entity: Entity) -> Option<T> {
+        self.owner.check::<T>("remove_for_despawn");
+        let _span = profile::record::<T>(Access::Remove);
+        match self.map.get(&entity.index) {
+            Some((generation, _component)) if *generation == entity.generation => {
+                self.remove_existing(entity)
+            }
+            _ => None,
         }
     }
 
     /// Remove the component for the given entity.
     /// Returns the previous data associated with the given entity in self.
-    /// Does not check if the entity exists; only use this if you know it exists, e.g.
-    /// through invariants in your code or because you retrieved this in a loop iterating
-    /// over all alive entities.
+    #[deprecated(
+        note = "use `remove_for_despawn`, which leaves components of newer entities untouched"
+    )]
     pub fn remove_unchecked(&mut self, entity: Entity) -> Option<T> {
-        self.map.remove(&entity.index)
+        self.remove_for_despawn(entity)
     }
 
     /// Remove the component for the given entity.
     /// Returns the previous data associated with the given entity in self.
-    pub fn remove(&mut self, entity: Entity) -> Result<Option<T>, NoSuchEntity> {
-        let lock = self.entities.read().unwrap();
+    pub fn remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        let _span = profile::record::<T>(Access::Remove);
+        let exists = self.entities.read().unwrap().exists(entity);
+        if exists {
+            Ok(self.remove_existing(entity))
+        } else {
+            Err(NoSuchEntity::new(entity).with_operation("remove").into())
+        }
+    }
+
+    /// Like `get()`, but returns Err(StorageError::WouldBlock) instead of blocking if the entities are locked
+    /// by another thread, and Err(StorageError::NoSuchEntity) if the given entity doesn't exist.
+    pub fn try_get(&self, entity: Entity) -> Result<Option<&T>, StorageError> {
+        self.try_check_exists(entity, "try_get")?;
+        Ok(self.get_unchecked(entity))
+    }
+
+    /// Like `get_mut()`, but returns Err(StorageError::WouldBlock) instead of blocking if the entities are
+    /// locked by another thread, and Err(StorageError::NoSuchEntity) if the given entity doesn't exist.
+    pub fn try_get_mut(&mut self, entity: Entity) -> Result<Option<&mut T>, StorageError> {
+        self.try_check_exists(entity, "try_get_mut")?;
+        Ok(self.get_mut_unchecked(entity))
+    }
+
+    /// Like `set()`, but returns Err(StorageError::WouldBlock) instead of blocking if the entities are
+    /// locked by another thread.
+    pub fn try_set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        self.try_check_exists(entity, "try_set")?;
+        Ok(self.set_unchecked(entity, data))
+    }
+
+    /// Like `remove()`, but returns Err(StorageError::WouldBlock) instead of blocking if the entities are
+    /// locked by another thread.
+    pub fn try_remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        let _span = profile::record::<T>(Access::Remove);
+        self.try_check_exists(entity, "try_remove")?;
+        Ok(self.remove_existing(entity))
+    }
+
+    /// Remove the component stored at the index of the given entity, which is known to exist.
+    /// A component of an earlier entity at the same index counts as absent and is dropped.
+    fn remove_existing(&mut self, entity: Entity) -> Option<T> {
+        self.owner.check::<T>("remove");
+        let removed = self
+            .map
+            .remove(&entity.index)
+            .filter(|(generation, _component)| *generation == entity.generation)
+            .map(|(_generation, component)| component);
+        if removed.is_some() {
+            trace::component::<T>("remove", entity);
+        }
+        removed
+    }
+
+    /// Check that the given entity exists without blocking on the lock of the entities.
+    fn try_check_exists(
+        &self,
+        entity: Entity,
+        operation: &'static str,
+    ) -> Result<(), StorageError> {
+        let lock = try_read_now(&self.entities)?;
         if lock.exists(entity) {
-            Ok(self.map.remove(&entity.index))
+            Ok(())
         } else {
-            Err(NoSuchEntity)
+            Err(NoSuchEntity::new(entity).with_operation(operation).into())
+        }
+    }
+
+    /// Replace the contents of the given bit set with the indices of the alive entities that have a
+    /// component in self, e.g. to intersect it with other storages; see `BitSet`.
+    /// Only the stored components are visited, so this stays cheap for rare components.
+    pub fn presence_into(&self, bits: &mut BitSet) {
+        let entities = self.entities.read().unwrap();
+        bits.reset(entities.index_base());
+        for (index, (generation, _)) in self.map.iter() {
+            if Self::stored_entity(&entities, *index, *generation).is_some() {
+                bits.insert(*index);
+            }
+        }
+    }
+
+    /// Use the given entities, e.g. those of the World after deserializing self from an rkyv archive,
+    /// which doesn't include them. They must have the same index base as the entities self was archived with.
+    #[cfg(feature = "rkyv")]
+    pub fn attach_entities(&mut self, entities: Arc<RwLock<Entities>>) {
+        self.entities = entities;
+    }
+
+    /// Only allow mutating the components of self from the given thread, or from any thread for None, with the
+    /// `thread_checks` feature; storages are bound to the thread that created them. Does nothing without it.
+    pub fn bind_to_thread(&mut self, thread: Option<ThreadId>) {
+        self.owner = OwnerThread::new(thread);
+    }
+
+    /// The memory used by self; see `MemoryUsage`.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            components: self.map.len(),
+            slots: self.map.capacity(),
+            bytes: self.map.allocation_size(),
         }
     }
 
     /// Remove the data stored in self for all entities.
     pub fn clear(&mut self) {
+        self.owner.check::<T>("clear");
         self.map.clear();
     }
+
+    /// Remove the components of all alive entities from self, leaving the entities themselves untouched.
+    /// Returns the number of removed components.
+    pub fn clear_checked(&mut self) -> usize {
+        self.owner.check::<T>("clear_checked");
+        let lock = self.entities.read().unwrap();
+        let len = self.map.len();
+        self.map.retain(|index, (generation, _component)| {
+            Self::stored_entity(&lock, *index, *generation).is_none()
+        });
+        len - self.map.len()
+    }
+
+    /// Iterate over all alive entities that have a component in self, in no particular order;
+    /// see `ordered()` for a stable order. The entities are read-locked until the returned iterator is dropped.
+    pub fn keys(&self) -> impl Iterator<Item = Entity> + '_ {
+        let lock = self.entities.read().unwrap();
+        self.map
+            .iter()
+            .filter_map(move |(index, (generation, _component))| {
+                Self::stored_entity(&lock, *index, *generation)
+            })
+    }
+
+    /// Iterate over the components of all alive entities in self, in no particular order.
+    /// The entities are read-locked until the returned iterator is dropped.
+    pub fn values(&self) -> impl Iterator<Item = &T> + '_ {
+        let lock = self.entities.read().unwrap();
+        self.map
+            .iter()
+            .filter(move |(index, (generation, _component))| {
+                Self::stored_entity(&lock, **index, *generation).is_some()
+            })
+            .map(|(_index, (_generation, component))| component)
+    }
+
+    /// Iterate mutably over the components of all alive entities in self, in no particular order.
+    /// The entities are read-locked until the returned iterator is dropped.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> + '_ {
+        self.owner.check::<T>("values_mut");
+        let lock = self.entities.read().unwrap();
+        self.map
+            .iter_mut()
+            .filter(move |(index, (generation, _component))| {
+                Self::stored_entity(&lock, **index, *generation).is_some()
+            })
+            .map(|(_index, (_generation, component))| component)
+    }
+
+    /// Iterate over all alive entities that have a component in self with their components, in ascending
+    /// index order, e.g. for rendering or replays that must not depend on the hash order of `keys()`.
+    /// Collects and sorts the components first, so it takes O(n log n) for n components.
+    pub fn ordered(&self) -> impl Iterator<Item = (Entity, &T)> + '_ {
+        let lock = self.entities.read().unwrap();
+        let mut components = self
+            .map
+            .iter()
+            .filter_map(|(index, (generation, component))| {
+                Some((Self::stored_entity(&lock, *index, *generation)?, component))
+            })
+            .collect::<std::vec::Vec<_>>();
+        components.sort_unstable_by_key(|(entity, _component)| entity.index);
+        components.into_iter()
+    }
+
+    /// Like `ordered()`, but iterates mutably over the components.
+    pub fn ordered_mut(&mut self) -> impl Iterator<Item = (Entity, &mut T)> + '_ {
+        self.owner.check::<T>("ordered_mut");
+        let lock = self.entities.read().unwrap();
+        let mut components = self
+            .map
+            .iter_mut()
+            .filter_map(|(index, (generation, component))| {
+                Some((Self::stored_entity(&lock, *index, *generation)?, component))
+            })
+            .collect::<std::vec::Vec<_>>();
+        components.sort_unstable_by_key(|(entity, _component)| entity.index);
+        components.into_iter()
+    }
+
+    /// Set the components for all given entities, locking the entities only once.
+    /// Returns Err(StorageError::NoSuchEntity) for the first entity that doesn't exist; components before it
+    /// have already been set, components after it are dropped.
+    pub fn extend<I>(&mut self, components: I) -> Result<(), StorageError>
+    where
+        I: IntoIterator<Item = (Entity, T)>,
+    {
+        self.owner.check::<T>("extend");
+        let lock = self.entities.read().unwrap();
+        for (entity, component) in components {
+            if !lock.exists(entity) {
+                return Err(NoSuchEntity::new(entity).with_operation("extend").into());
+            }
+            let capacity = self.map.capacity();
+            self.map
+                .insert(entity.index, (entity.generation, component));
+            self.record_rehash(capacity);
+        }
+        Ok(())
+    }
+
+    /// Take the entities and the owner thread of self and iterate over all stored components with the entities
+    /// they were set for.
+    pub(crate) fn into_components(
+        self,
+    ) -> (
+        Arc<RwLock<Entities>>,
+        OwnerThread,
+        impl Iterator<Item = (Entity, T)>,
+    ) {
+        let components = self
+            .map
+            .into_iter()
+            .map(|(index, (generation, component))| (Entity { index, generation }, component));
+        (self.entities, self.owner, components)
+    }
+
+    /// Rewrite the entities stored in all components of self; see `MapEntities`.
+    pub fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity)
+    where
+        T: MapEntities,
+    {
+        self.owner.check::<T>("map_entities");
+        for (_generation, component) in self.map.values_mut() {
+            component.map_entities(mapper);
+        }
+    }
+
+    /// Check that self uses the given entities and doesn't hold components for unallocated indices.
+    /// `storage` is the name of this storage used for error reporting.
+    pub fn check_integrity(
+        &self,
+        entities: &Arc<RwLock<Entities>>,
+        storage: &'static str,
+    ) -> Result<(), IntegrityError> {
+        if !Arc::ptr_eq(&self.entities, entities) {
+            return Err(IntegrityError::ForeignEntities { storage });
+        }
+        let highest_index = self.map.keys().max().copied();
+        match highest_index {
+            Some(index) if index >= entities.read().unwrap().index_end() => {
+                Err(IntegrityError::UnallocatedIndex { storage, index })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Clone the data stored in self into a new MapStorage<T> that uses the given entities.
+    /// This is used to fork a World, where the forked storages must not share the original `Entities`.
+    pub fn clone_with_entities(&self, entities: Arc<RwLock<Entities>>) -> Self
+    where
+        T: Clone,
+    {
+        Self {
+            map: self.map.clone(),
+            entities,
+            owner: self.owner,
+        }
+    }
+}
+
+/// Moves the components of a VecStorage<T> into a MapStorage<T> that uses the same entities, e.g. when a
+/// component turns out to be rarely used; see `DynamicStorage::migrate()`.
+impl<T> From<VecStorage<T>> for MapStorage<T> {
+    fn from(storage: VecStorage<T>) -> Self {
+        let (entities, owner, components) = storage.into_components();
+        let mut map = MapStorage::new(entities);
+        map.owner = owner;
+        for (entity, component) in components {
+            map.set_unchecked(entity, component);
+        }
+        map
+    }
+}
+
+/// Storages are equal if the same alive entities have equal components in them.
+impl<T: PartialEq, A: Allocator + Clone> PartialEq for MapStorage<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        let left = self.entities.read().unwrap();
+        if Arc::ptr_eq(&self.entities, &other.entities) {
+            return self
+                .alive_components(&left)
+                .eq(other.alive_components(&left));
+        }
+        let right = other.entities.read().unwrap();
+        self.alive_components(&left)
+            .eq(other.alive_components(&right))
+    }
+}
+
+impl<T: Eq, A: Allocator + Clone> Eq for MapStorage<T, A> {}
+
+/// Shows the components of all alive entities by entity.
+impl<T: Debug, A: Allocator + Clone> Debug for MapStorage<T, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.entities.try_read() {
+            Ok(entities) => f
+                .debug_map()
+                .entries(self.alive_components(&entities))
+                .finish(),
+            Err(_) => f.write_str("MapStorage { <entities locked> }"),
+        }
+    }
+}
+
+impl<T, A: Allocator + Clone> Storage<T> for MapStorage<T, A> {
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.get(entity)
+    }
+
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_mut(entity)
+    }
+
+    fn get_unchecked(&self, entity: Entity) -> Option<&T> {
+        self.get_unchecked(entity)
+    }
+
+    fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_mut_unchecked(entity)
+    }
+
+    fn sparse_presence_into(&self, bits: &mut BitSet) -> bool {
+        self.presence_into(bits);
+        true
+    }
+}
+
+impl<T, A: Allocator + Clone> ModelStorage<T> for MapStorage<T, A> {
+    fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        self.set(entity, data)
+    }
+
+    fn remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        self.remove(entity)
+    }
+
+    fn remove_for_despawn(&mut self, entity: Entity) -> Option<T> {
+        self.remove_for_despawn(entity)
+    }
 }
 
 #[cfg(test)]
@@ -99,7 +562,7 @@ mod tests {
     }
 
     #[test]
-    fn map_get() -> Result<(), NoSuchEntity> {
+    fn map_get() -> Result<(), StorageError> {
         let entities = Arc::new(RwLock::new(Entities::new(3)));
         let mut map = MapStorage::<MapTestData>::new(Arc::clone(&entities));
         let entity = {
@@ -115,7 +578,7 @@ mod tests {
     }
 
     #[test]
-    fn map_set_exists() -> Result<(), NoSuchEntity> {
+    fn map_set_exists() -> Result<(), StorageError> {
         let entities = Arc::new(RwLock::new(Entities::new(3)));
         let mut map = MapStorage::<MapTestData>::new(Arc::clone(&entities));
         let entity = {
@@ -135,7 +598,7 @@ mod tests {
     }
 
     #[test]
-    fn remove_missing_is_ok() -> Result<(), NoSuchEntity> {
+    fn remove_missing_is_ok() -> Result<(), StorageError> {
         let entities = Arc::new(RwLock::new(Entities::new(3)));
         let mut map = MapStorage::<MapTestData>::new(Arc::clone(&entities));
         let entity = {
@@ -148,7 +611,7 @@ mod tests {
     }
 
     #[test]
-    fn can_insert_after_remove() -> Result<(), NoSuchEntity> {
+    fn can_insert_after_remove() -> Result<(), StorageError> {
         let entities = Arc::new(RwLock::new(Entities::new(3)));
         let mut map = MapStorage::<MapTestData>::new(Arc::clone(&entities));
         let entity = {
@@ -180,7 +643,23 @@ mod tests {
     }
 
     #[test]
-    fn map_iter() -> Result<(), NoSuchEntity> {
+    fn remove_for_despawn_ignores_stale_entities() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(3)));
+        let mut map = MapStorage::<MapTestData>::new(Arc::clone(&entities));
+        let stale = entities.write().unwrap().spawn();
+        let entity = {
+            let mut lock = entities.write().unwrap();
+            lock.despawn(stale)?;
+            lock.spawn()
+        };
+        map.set(entity, MapTestData(2))?;
+        assert_eq!(map.remove_for_despawn(stale), None);
+        assert_eq!(map.remove_for_despawn(entity), Some(MapTestData(2)));
+        Ok(())
+    }
+
+    #[test]
+    fn map_iter() -> Result<(), StorageError> {
         let entities = Arc::new(RwLock::new(Entities::new(3)));
         let mut map = MapStorage::<MapTestData>::new(Arc::clone(&entities));
         let entity_a = {
@@ -212,7 +691,7 @@ mod tests {
             .map(|entity| (entity, map.get(entity)))
             .filter(|(_entity, data)| data.is_some())
             .collect::<Vec<_>>();
-        v.sort_by(|(entity_a, _a), (entity_b, _b)| entity_a.index.cmp(&entity_b.index));
+        v.sort_by_key(|(entity, _data)| entity.index);
         assert_eq!(
             v,
             vec![
@@ -222,4 +701,222 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn integrity_detects_unallocated_index() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(3)));
+        let mut map = MapStorage::<MapTestData>::new(Arc::clone(&entities));
+        let entity = {
+            let mut lock = entities.write().unwrap();
+            lock.spawn();
+            lock.spawn()
+        };
+        map.set(entity, MapTestData(1))?;
+        assert_eq!(map.check_integrity(&entities, "map"), Ok(()));
+
+        let other_entities = Arc::new(RwLock::new(Entities::new(0)));
+        assert_eq!(
+            map.check_integrity(&other_entities, "map"),
+            Err(IntegrityError::ForeignEntities { storage: "map" })
+        );
+
+        let forked = map.clone_with_entities(Arc::clone(&other_entities));
+        assert_eq!(
+            forked.check_integrity(&other_entities, "map"),
+            Err(IntegrityError::UnallocatedIndex {
+                storage: "map",
+                index: 1
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn map_compares_alive_components() -> Result<(), StorageError> {
+        let left_entities = Arc::new(RwLock::new(Entities::new(3)));
+        let right_entities = Arc::new(RwLock::new(Entities::new(3)));
+        let mut left = MapStorage::<MapTestData>::new(Arc::clone(&left_entities));
+        let mut right = MapStorage::<MapTestData>::new(Arc::clone(&right_entities));
+        let entity = left_entities.write().unwrap().spawn();
+        assert_eq!(right_entities.write().unwrap().spawn(), entity);
+        left.set(entity, MapTestData(1))?;
+        assert_ne!(left, right);
+        right.set(entity, MapTestData(1))?;
+        assert_eq!(left, right);
+        assert_eq!(
+            format!("{:?}", left),
+            format!("{{{:?}: MapTestData(1)}}", entity)
+        );
+
+        let _lock = left_entities.write().unwrap();
+        assert_eq!(format!("{:?}", left), "MapStorage { <entities locked> }");
+        Ok(())
+    }
+
+    #[test]
+    fn map_bulk_operations() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(3)));
+        let mut map = MapStorage::<MapTestData>::new(Arc::clone(&entities));
+        let (entity_a, entity_b, entity_c) = {
+            let mut lock = entities.write().unwrap();
+            (lock.spawn(), lock.spawn(), lock.spawn())
+        };
+        map.extend(vec![
+            (entity_a, MapTestData(1)),
+            (entity_b, MapTestData(2)),
+            (entity_c, MapTestData(3)),
+        ])?;
+        // despawned directly, so the component is still stored but must be skipped.
+        entities.write().unwrap().despawn(entity_c)?;
+
+        let mut keys = map.keys().collect::<Vec<_>>();
+        keys.sort_by_key(|entity| entity.index);
+        assert_eq!(keys, vec![entity_a, entity_b]);
+
+        for value in map.values_mut() {
+            value.0 *= 10;
+        }
+        let mut values = map.values().copied().collect::<Vec<_>>();
+        values.sort_by_key(|value| value.0);
+        assert_eq!(values, vec![MapTestData(10), MapTestData(20)]);
+
+        assert!(map.extend(vec![(entity_c, MapTestData(4))]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn iterates_in_index_order() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(64)));
+        let mut map = MapStorage::<MapTestData>::new(Arc::clone(&entities));
+        let spawned = {
+            let mut lock = entities.write().unwrap();
+            (0..64).map(|_| lock.spawn()).collect::<Vec<_>>()
+        };
+        for entity in spawned.iter().rev() {
+            map.set(*entity, MapTestData(entity.index as i32))?;
+        }
+        entities.write().unwrap().despawn(spawned[5])?;
+
+        for (entity, component) in map.ordered_mut() {
+            component.0 += entity.index as i32;
+        }
+        let ordered = map.ordered().collect::<Vec<_>>();
+        assert_eq!(ordered.len(), 63);
+        assert!(ordered
+            .windows(2)
+            .all(|pair| pair[0].0.index < pair[1].0.index));
+        assert!(ordered
+            .iter()
+            .all(|(entity, component)| component.0 == 2 * entity.index as i32));
+        Ok(())
+    }
+
+    #[test]
+    fn ordered_skips_components_of_earlier_generations() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(2)));
+        let mut map = MapStorage::new(Arc::clone(&entities));
+        let (kept, despawned) = {
+            let mut entities = entities.write().unwrap();
+            (entities.spawn(), entities.spawn())
+        };
+        map.set(kept, MapTestData(1))?;
+        map.set(despawned, MapTestData(2))?;
+        entities.write().unwrap().despawn(despawned)?;
+        let respawned = entities.write().unwrap().spawn();
+        assert_eq!(respawned.index, despawned.index);
+
+        assert_eq!(
+            map.ordered().collect::<Vec<_>>(),
+            vec![(kept, &MapTestData(1))]
+        );
+        assert_eq!(
+            map.ordered_mut().collect::<Vec<_>>(),
+            vec![(kept, &mut MapTestData(1))]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn map_ignores_components_of_earlier_generations() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(2)));
+        let mut map = MapStorage::new(Arc::clone(&entities));
+        let (kept, despawned) = {
+            let mut entities = entities.write().unwrap();
+            (entities.spawn(), entities.spawn())
+        };
+        map.set(kept, MapTestData(1))?;
+        map.set(despawned, MapTestData(2))?;
+        // Despawn without removing the component, e.g. from another storage's point of view.
+        entities.write().unwrap().despawn(despawned)?;
+        let respawned = entities.write().unwrap().spawn();
+        assert_eq!(respawned.index, despawned.index);
+
+        assert_eq!(map.get(respawned), None);
+        assert_eq!(map.get_unchecked(respawned), None);
+        assert_eq!(map.get_mut_unchecked(respawned), None);
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec![kept]);
+        assert_eq!(map.values().collect::<Vec<_>>(), vec![&MapTestData(1)]);
+        assert_eq!(
+            map.values_mut().collect::<Vec<_>>(),
+            vec![&mut MapTestData(1)]
+        );
+        // Only the component of the alive entity is removed; the stale one stays until it is overwritten.
+        assert_eq!(map.clear_checked(), 1);
+        map.set(respawned, MapTestData(3))?;
+        assert_eq!(map.get(respawned), Some(&MapTestData(3)));
+        Ok(())
+    }
+
+    #[test]
+    fn map_set_and_remove_ignore_components_of_earlier_generations() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(1)));
+        let mut map = MapStorage::new(Arc::clone(&entities));
+        let despawned = entities.write().unwrap().spawn();
+        map.set(despawned, MapTestData(1))?;
+        entities.write().unwrap().despawn(despawned)?;
+        let respawned = entities.write().unwrap().spawn();
+        assert_eq!(respawned.index, despawned.index);
+
+        assert_eq!(map.remove(respawned)?, None);
+        assert_eq!(map.set(respawned, MapTestData(2))?, None);
+        assert_eq!(map.remove(respawned)?, Some(MapTestData(2)));
+        Ok(())
+    }
+
+    #[cfg(feature = "rkyv")]
+    #[derive(Debug, Eq, PartialEq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+    #[archive(check_bytes)]
+    struct MapArchiveData(i32);
+
+    #[cfg(feature = "rkyv")]
+    #[test]
+    fn archive_map_storage() -> Result<(), StorageError> {
+        use rkyv::Deserialize;
+
+        let entities = Arc::new(RwLock::new(Entities::new(2)));
+        let mut storage = MapStorage::new(Arc::clone(&entities));
+        let (kept, stale) = {
+            let mut entities = entities.write().unwrap();
+            (entities.spawn(), entities.spawn())
+        };
+        storage.set(kept, MapArchiveData(1))?;
+        storage.set(stale, MapArchiveData(2))?;
+        // Despawn without removing the component, so only its stored generation tells it apart.
+        entities.write().unwrap().despawn(stale)?;
+        let respawned = entities.write().unwrap().spawn();
+        assert_eq!(respawned.index, stale.index);
+
+        let bytes = rkyv::to_bytes::<_, 256>(&storage).unwrap();
+        let archived = rkyv::check_archived_root::<MapStorage<MapArchiveData>>(&bytes).unwrap();
+        let mut restored: MapStorage<MapArchiveData> =
+            archived.deserialize(&mut rkyv::Infallible).unwrap();
+        restored.attach_entities(Arc::clone(&entities));
+
+        assert_eq!(restored.get(kept), Some(&MapArchiveData(1)));
+        let mut present = BitSet::new(0);
+        restored.presence_into(&mut present);
+        assert!(present.contains(kept.index));
+        assert!(!present.contains(respawned.index));
+        Ok(())
+    }
 }
@@ -0,0 +1,499 @@
+use crate::allocations::{self, AllocationKind};
+use crate::entity::Entity;
+use crate::integrity::IntegrityError;
+use crate::layout::MemoryUsage;
+use crate::map_entities::MapEntities;
+use crate::no_such_entity::NoSuchEntity;
+use crate::profile::{self, Access};
+use crate::storage_error::StorageError;
+use crate::thread_check::OwnerThread;
+use crate::trace;
+use crate::Entities;
+use crate::ModelStorage;
+use crate::Storage;
+use memmap2::{MmapMut, MmapOptions};
+use std::fmt::{Debug, Formatter};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use std::marker::PhantomData;
+use std::mem::size_of;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::thread::ThreadId;
+
+/// A component type that can be stored in a `MmapStorage<T>`, which reinterprets the bytes of a file
+/// as components.
+///
+/// # Safety
+/// Every bit pattern, including all zeroes, must be a valid value of the type, and the type must not
+/// contain pointers or references, e.g. a `#[repr(C)]` struct of numbers.
+pub unsafe trait MmapComponent: Copy + 'static {}
+
+macro_rules! impl_mmap_component {
+    ($($ty:ty),*) => {
+        $(unsafe impl MmapComponent for $ty {})*
+    };
+}
+
+impl_mmap_component!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: MmapComponent, const N: usize> MmapComponent for [T; N] {}
+
+/// The layout of every entity index in the mapped memory.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Slot<T> {
+    /// The generation of the entity the component was set for.
+    generation: u32,
+    /// Non-zero if the slot holds a component.
+    occupied: u32,
+    component: T,
+}
+
+/// A storage type that keeps its components in memory-mapped pages, either anonymous or backed by a
+/// file; see `open()`. The operating system loads the pages of a file lazily when they are first
+/// accessed and can evict them again, so this suits huge, mostly read datasets like static world
+/// geometry, which may exceed the available memory.
+///
+/// Components of a file are matched to entities by index and generation, so the entities of the World
+/// have to be spawned in the same order as when the file was written.
+/// Growing the storage or clearing it writes to the file.
+///
+/// A file must not be modified by anything but its storage while it is open, e.g. by another process or a
+/// second mapping of it; references to components would change or be torn behind the borrow checker's back,
+/// which is undefined behaviour even though every bit pattern is a valid component. `open()` locks the file
+/// against other storages, but can't stop programs ignoring advisory locks.
+pub struct MmapStorage<T: MmapComponent> {
+    map: MmapMut,
+    /// The backing file, or None for anonymous memory.
+    file: Option<File>,
+    /// The index base of the entities; the first slot holds the component of the entity at this index.
+    base: u32,
+    entities: Arc<RwLock<Entities>>,
+    /// The thread allowed to mutate the components; see `bind_to_thread()`.
+    owner: OwnerThread,
+    marker: PhantomData<T>,
+}
+
+impl<T: MmapComponent> MmapStorage<T> {
+    /// Create a new MmapStorage<T> in anonymous memory with the specified initial capacity.
+    pub fn new(entities: Arc<RwLock<Entities>>, capacity: u32) -> Self {
+        let map = MmapMut::map_anon(Self::byte_len(capacity as usize))
+            .expect("failed to map anonymous memory");
+        Self::with_map(entities, map, None)
+    }
+
+    /// Create a new MmapStorage<T> backed by the file at the given path, which is created if it doesn't
+    /// exist yet. The components already stored in the file are kept; see the type-level documentation.
+    ///
+    /// The storage needs exclusive access to the file while it is open: it takes an advisory exclusive lock
+    /// on the file, and fails with `io::ErrorKind::WouldBlock` if another storage, in this or another process,
+    /// holds it. Advisory locks don't stop other programs from writing to the file, which must not happen
+    /// while it is mapped; see the type-level documentation.
+    pub fn open(entities: Arc<RwLock<Entities>>, path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        file.try_lock().map_err(|error| match error {
+            TryLockError::WouldBlock => io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "the file is locked by another memory-mapped storage",
+            ),
+            TryLockError::Error(error) => error,
+        })?;
+        let len = file.metadata()?.len() as usize;
+        let slots = len / size_of::<Slot<T>>();
+        file.set_len(Self::byte_len(slots) as u64)?;
+        // Safety: the mapping is only sound while nothing but this storage accesses the file, since the
+        // components are handed out as references that must not change or be torn behind them. The lock
+        // taken above excludes other storages for as long as the file is kept open by self; other writers,
+        // e.g. programs ignoring the lock or truncating the file, are excluded by the contract of `open()`.
+        let map = unsafe { MmapOptions::new().map_mut(&file)? };
+        Ok(Self::with_map(entities, map, Some(file)))
+    }
+
+    fn with_map(entities: Arc<RwLock<Entities>>, map: MmapMut, file: Option<File>) -> Self {
+        assert!(
+            std::mem::align_of::<Slot<T>>() <= 4096,
+            "memory-mapped components must be aligned to at most 4096 bytes"
+        );
+        let base = entities.read().unwrap().index_base();
+        Self {
+            map,
+            file,
+            base,
+            entities,
+            owner: OwnerThread::current(),
+            marker: PhantomData,
+        }
+    }
+
+    /// The number of bytes mapped for the given number of slots; mappings can't be empty.
+    fn byte_len(slots: usize) -> usize {
+        usize::max(slots, 1) * size_of::<Slot<T>>()
+    }
+
+    fn slots(&self) -> &[Slot<T>] {
+        let len = self.map.len() / size_of::<Slot<T>>();
+        // The mapping is page-aligned and every bit pattern is a valid slot; see `MmapComponent`.
+        unsafe { std::slice::from_raw_parts(self.map.as_ptr() as *const Slot<T>, len) }
+    }
+
+    fn slots_mut(&mut self) -> &mut [Slot<T>] {
+        let len = self.map.len() / size_of::<Slot<T>>();
+        unsafe { std::slice::from_raw_parts_mut(self.map.as_mut_ptr() as *mut Slot<T>, len) }
+    }
+
+    /// The position of the given entity in the slots, or None if its index is below the index base.
+    fn slot(&self, entity: Entity) -> Option<usize> {
+        entity
+            .index
+            .checked_sub(self.base)
+            .map(|slot| slot as usize)
+    }
+
+    /// Grow the mapping to hold at least the given number of slots, at least doubling its size.
+    fn grow(&mut self, slots: usize) {
+        let len = Self::byte_len(usize::max(self.slots().len() * 2, slots));
+        allocations::record::<T>(AllocationKind::Growth, len);
+        let map = match &self.file {
+            Some(file) => {
+                self.map
+                    .flush()
+                    .expect("failed to flush memory-mapped storage");
+                file.set_len(len as u64)
+                    .expect("failed to grow the file of a memory-mapped storage");
+                // Safety: self still holds the file and its lock; see `open()`.
+                unsafe { MmapOptions::new().map_mut(file) }
+            }
+            None => MmapMut::map_anon(len).map(|mut map| {
+                map[..self.map.len()].copy_from_slice(&self.map);
+                map
+            }),
+        };
+        self.map = map.expect("failed to grow memory-mapped storage");
+    }
+
+    /// Iterate over the alive entities of the given entities that have a component in self, by index.
+    fn alive_components<'a>(
+        &'a self,
+        entities: &'a Entities,
+    ) -> impl Iterator<Item = (Entity, &'a T)> + 'a {
+        entities
+            .iter()
+            .filter_map(move |entity| Some((entity, self.get_unchecked(entity)?)))
+    }
+
+    /// Get a reference to the component associated with the given entity in self, if any.
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        let lock = self.entities.read().unwrap();
+        if lock.exists(entity) {
+            self.get_unchecked(entity)
+        } else {
+            None
+        }
+    }
+
+    /// Get a mutable reference to the component associated with the given entity in self, if any.
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        let exists = self.entities.read().unwrap().exists(entity);
+        if exists {
+            self.get_mut_unchecked(entity)
+        } else {
+            None
+        }
+    }
+
+    /// Get a reference to the component associated with the given entity in self, if any.
+    /// Does not check if the entity exists; only use this if you know it exists.
+    pub fn get_unchecked(&self, entity: Entity) -> Option<&T> {
+        let _span = profile::record::<T>(Access::Get);
+        let slot = self.slots().get(self.slot(entity)?)?;
+        if slot.occupied != 0 && slot.generation == entity.generation {
+            Some(&slot.component)
+        } else {
+            None
+        }
+    }
+
+    /// Get a mutable reference to the component associated with the given entity in self, if any.
+    /// Does not check if the entity exists; only use this if you know it exists.
+    pub fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T> {
+        self.owner.check::<T>("get_mut");
+        let _span = profile::record::<T>(Access::GetMut);
+        let index = self.slot(entity)?;
+        let slot = self.slots_mut().get_mut(index)?;
+        if slot.occupied != 0 && slot.generation == entity.generation {
+            Some(&mut slot.component)
+        } else {
+            None
+        }
+    }
+
+    /// Set the component for the given entity.
+    /// Returns Err(StorageError::NoSuchEntity) if the given entity doesn't exist.
+    /// Otherwise, returns Ok(data), where data is previous data evicted by this operation (if any).
+    pub fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        let exists = self.entities.read().unwrap().exists(entity);
+        if exists {
+            Ok(self.set_unchecked(entity, data))
+        } else {
+            Err(NoSuchEntity::new(entity).with_operation("set").into())
+        }
+    }
+
+    /// Set the component for the given entity.
+    /// Returns the previous data stored in self for the given entity.
+    /// Does not check if the entity exists and doesn't lock the entities; only use this if you know it exists.
+    ///
+    /// # Panics
+    /// Panics if the storage has to grow and its file can't be resized.
+    pub fn set_unchecked(&mut self, entity: Entity, data: T) -> Option<T> {
+        self.owner.check::<T>("set");
+        let _span = profile::record::<T>(Access::Set);
+        let index = self
+            .slot(entity)
+            .expect("entity index below the index base of the storage's entities");
+        if index >= self.slots().len() {
+            self.grow(index + 1);
+        }
+        trace::component::<T>("set", entity);
+        let slot = &mut self.slots_mut()[index];
+        let previous = if slot.occupied != 0 && slot.generation == entity.generation {
+            Some(slot.component)
+        } else {
+            None
+        };
+        *slot = Slot {
+            generation: entity.generation,
+            occupied: 1,
+            component: data,
+        };
+        previous
+    }
+
+    /// Remove the component of the given entity after it was despawned.
+    /// Returns the data associated with the given entity in self, if any.
+    /// Components set for another entity at the same index are left untouched.
+    pub fn remove_for_despawn(&mut self, entity: Entity) -> Option<T> {
+        self.owner.check::<T>("remove_for_despawn");
+        let _span = profile::record::<T>(Access::Remove);
+        let index = self.slot(entity)?;
+        let slot = self.slots_mut().get_mut(index)?;
+        if slot.occupied != 0 && slot.generation == entity.generation {
+            slot.occupied = 0;
+            trace::component::<T>("remove", entity);
+            Some(slot.component)
+        } else {
+            None
+        }
+    }
+
+    /// Remove the component for the given entity.
+    /// Returns the previous data associated with the given entity in self.
+    pub fn remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        let exists = self.entities.read().unwrap().exists(entity);
+        if exists {
+            Ok(self.remove_for_despawn(entity))
+        } else {
+            Err(NoSuchEntity::new(entity).with_operation("remove").into())
+        }
+    }
+
+    /// Only allow mutating the components of self from the given thread, or from any thread for None, with
+    /// the `thread_checks` feature; storages are bound to the thread that created them.
+    pub fn bind_to_thread(&mut self, thread: Option<ThreadId>) {
+        self.owner = OwnerThread::new(thread);
+    }
+
+    /// The memory used by self, counting the whole mapping as bytes; see `MemoryUsage`.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            components: self
+                .slots()
+                .iter()
+                .filter(|slot| slot.occupied != 0)
+                .count(),
+            slots: self.slots().len(),
+            bytes: self.map.len(),
+        }
+    }
+
+    /// Remove the data stored in self for all entities.
+    pub fn clear(&mut self) {
+        self.owner.check::<T>("clear");
+        for slot in self.slots_mut() {
+            slot.occupied = 0;
+        }
+    }
+
+    /// Remove the components of all alive entities from self, leaving the entities themselves untouched.
+    /// Returns the number of removed components.
+    pub fn clear_checked(&mut self) -> usize {
+        let alive = self.entities.read().unwrap().iter_snapshot();
+        alive
+            .filter(|entity| self.remove_for_despawn(*entity).is_some())
+            .count()
+    }
+
+    /// Write changes of a file-backed storage to the file; they are written eventually in any case.
+    pub fn flush(&self) -> io::Result<()> {
+        self.map.flush()
+    }
+
+    /// Rewrite the entities stored in all components of self; see `MapEntities`.
+    pub fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity)
+    where
+        T: MapEntities,
+    {
+        self.owner.check::<T>("map_entities");
+        for slot in self
+            .slots_mut()
+            .iter_mut()
+            .filter(|slot| slot.occupied != 0)
+        {
+            slot.component.map_entities(mapper);
+        }
+    }
+
+    /// Check that self uses the given entities and doesn't hold components for unallocated indices.
+    /// `storage` is the name of this storage used for error reporting.
+    pub fn check_integrity(
+        &self,
+        entities: &Arc<RwLock<Entities>>,
+        storage: &'static str,
+    ) -> Result<(), IntegrityError> {
+        if !Arc::ptr_eq(&self.entities, entities) {
+            return Err(IntegrityError::ForeignEntities { storage });
+        }
+        let highest_index = self
+            .slots()
+            .iter()
+            .rposition(|slot| slot.occupied != 0)
+            .map(|index| self.base + index as u32);
+        match highest_index {
+            Some(index) if index >= entities.read().unwrap().index_end() => {
+                Err(IntegrityError::UnallocatedIndex { storage, index })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Copy the data stored in self into a new MmapStorage<T> in anonymous memory that uses the given entities.
+    pub fn clone_with_entities(&self, entities: Arc<RwLock<Entities>>) -> Self {
+        let mut map = MmapMut::map_anon(self.map.len()).expect("failed to map anonymous memory");
+        map.copy_from_slice(&self.map);
+        let mut storage = Self::with_map(entities, map, None);
+        storage.owner = self.owner;
+        storage
+    }
+}
+
+/// Clones copy the data into anonymous memory and share the same `Entities` as the original.
+impl<T: MmapComponent> Clone for MmapStorage<T> {
+    fn clone(&self) -> Self {
+        self.clone_with_entities(Arc::clone(&self.entities))
+    }
+}
+
+/// Storages are equal if the same alive entities have equal components in them.
+impl<T: MmapComponent + PartialEq> PartialEq for MmapStorage<T> {
+    fn eq(&self, other: &Self) -> bool {
+        let left = self.entities.read().unwrap();
+        if Arc::ptr_eq(&self.entities, &other.entities) {
+            return self
+                .alive_components(&left)
+                .eq(other.alive_components(&left));
+        }
+        let right = other.entities.read().unwrap();
+        self.alive_components(&left)
+            .eq(other.alive_components(&right))
+    }
+}
+
+impl<T: MmapComponent + Eq> Eq for MmapStorage<T> {}
+
+/// Shows the components of all alive entities by entity.
+impl<T: MmapComponent + Debug> Debug for MmapStorage<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.entities.try_read() {
+            Ok(entities) => f
+                .debug_map()
+                .entries(self.alive_components(&entities))
+                .finish(),
+            Err(_) => write!(f, "MmapStorage {{ <entities locked> }}"),
+        }
+    }
+}
+
+impl<T: MmapComponent> Storage<T> for MmapStorage<T> {
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.get(entity)
+    }
+
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_mut(entity)
+    }
+
+    fn get_unchecked(&self, entity: Entity) -> Option<&T> {
+        self.get_unchecked(entity)
+    }
+
+    fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_mut_unchecked(entity)
+    }
+}
+
+impl<T: MmapComponent> ModelStorage<T> for MmapStorage<T> {
+    fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        self.set(entity, data)
+    }
+
+    fn remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        self.remove(entity)
+    }
+
+    fn remove_for_despawn(&mut self, entity: Entity) -> Option<T> {
+        self.remove_for_despawn(entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persists_components_in_file() -> Result<(), StorageError> {
+        let path = std::env::temp_dir().join(format!("genesis-mmap-{}.bin", std::process::id()));
+        let entities = Arc::new(RwLock::new(Entities::new(2)));
+        let (first, second, third) = {
+            let mut lock = entities.write().unwrap();
+            (lock.spawn(), lock.spawn(), lock.spawn())
+        };
+        {
+            let mut storage = MmapStorage::<[f32; 3]>::open(Arc::clone(&entities), &path).unwrap();
+            storage.set(first, [1.0, 2.0, 3.0])?;
+            storage.set(third, [4.0, 5.0, 6.0])?;
+            assert_eq!(storage.remove(first)?, Some([1.0, 2.0, 3.0]));
+            storage.set(first, [7.0, 8.0, 9.0])?;
+            storage.flush().unwrap();
+        }
+
+        let storage = MmapStorage::<[f32; 3]>::open(Arc::clone(&entities), &path).unwrap();
+        assert_eq!(storage.get(first), Some(&[7.0, 8.0, 9.0]));
+        assert_eq!(storage.get(second), None);
+        assert_eq!(storage.get(third), Some(&[4.0, 5.0, 6.0]));
+        assert_eq!(storage.clone(), storage);
+
+        entities.write().unwrap().despawn(third)?;
+        let reused = entities.write().unwrap().spawn();
+        assert_eq!(storage.get(reused), None);
+        assert_eq!(storage.check_integrity(&entities, "points"), Ok(()));
+        drop(storage);
+        std::fs::remove_file(&path).unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,340 @@
+use crate::entity::Entity;
+use crate::storage_error::StorageError;
+use crate::Entities;
+use crate::Storage;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+
+/// The mutating interface of the storage types, used by `StorageModel` to apply operations.
+/// Implemented for `VecStorage<T>`, `MapStorage<T>`, `IndexedStorage<T>`, `MmapStorage<T>` and `StableStorage<T>`;
+/// `DoubleBuffered<T>` only makes set components visible after `flip()`, so it doesn't follow the model.
+pub trait ModelStorage<T>: Storage<T> {
+    /// Associate the given component with the given entity, returning the previous component, if any.
+    fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError>;
+
+    /// Remove the component associated with the given entity, if any.
+    fn remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError>;
+
+    /// Remove the component of an entity that has just been despawned.
+    fn remove_for_despawn(&mut self, entity: Entity) -> Option<T>;
+}
+
+/// An abstract operation on a storage and its entities, applied by `StorageModel::apply()`.
+/// Entities are given by their position in the list of all entities ever spawned by the model, taken
+/// modulo its length, so every generated index is valid and despawned entities are exercised as well.
+///
+/// Sequences of operations are meant to be generated by property-testing crates like `proptest`
+/// or `quickcheck`, e.g. with `prop_oneof!` over the variants.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StorageOp<T> {
+    /// Spawn a new entity.
+    Spawn,
+    /// Despawn an entity and remove its component like a World does.
+    Despawn(usize),
+    /// Set the component of an entity.
+    Set(usize, T),
+    /// Remove the component of an entity.
+    Remove(usize),
+    /// Get the component of an entity.
+    Get(usize),
+    /// Despawn all entities and remove their components like `clear()` on a World does.
+    Clear,
+}
+
+/// A storage returned something else than the reference model of `StorageModel` for an operation.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub struct ModelViolation {
+    /// The position of the operation in the applied sequence.
+    pub step: usize,
+    /// The operation, formatted with `Debug`.
+    pub operation: String,
+    /// The result expected by the model, formatted with `Debug`.
+    pub expected: String,
+    /// The result returned by the storage, formatted with `Debug`.
+    pub actual: String,
+}
+
+impl Display for ModelViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "step {} ({}): expected {}, but the storage returned {}",
+            self.step, self.operation, self.expected, self.actual
+        )
+    }
+}
+
+/// A reference model of a storage: a map from alive entities to their components.
+/// Applying `StorageOp`s to the model and a storage at the same time checks that the storage behaves
+/// like the map, e.g. that components of despawned entities are removed and can't be accessed through
+/// stale entities whose index was reused.
+#[derive(Debug)]
+pub struct StorageModel<T> {
+    entities: Arc<RwLock<Entities>>,
+    spawned: Vec<Entity>,
+    components: HashMap<Entity, T>,
+    steps: usize,
+}
+
+impl<T: Clone + Debug + PartialEq> StorageModel<T> {
+    /// Create a new model for a storage of the given entities, which must not have any alive entities yet.
+    pub fn new(entities: Arc<RwLock<Entities>>) -> Self {
+        Self {
+            entities,
+            spawned: vec![],
+            components: HashMap::new(),
+            steps: 0,
+        }
+    }
+
+    /// All entities spawned so far, including despawned ones, in the order `StorageOp`s refer to them.
+    pub fn spawned(&self) -> &[Entity] {
+        &self.spawned
+    }
+
+    /// Apply the given operation to the storage and the model, and check that the storage returned
+    /// the same as the model and that every spawned entity still has the component the model expects.
+    /// Operations other than `Spawn` do nothing until an entity has been spawned.
+    pub fn apply(
+        &mut self,
+        storage: &mut impl ModelStorage<T>,
+        operation: StorageOp<T>,
+    ) -> Result<(), ModelViolation> {
+        let step = self.steps;
+        self.steps += 1;
+        let violation = |expected: &dyn Debug, actual: &dyn Debug| ModelViolation {
+            step,
+            operation: format!("{:?}", operation),
+            expected: format!("{:?}", expected),
+            actual: format!("{:?}", actual),
+        };
+
+        let entity = match &operation {
+            StorageOp::Spawn => {
+                let entity = self.entities.write().unwrap().spawn();
+                self.spawned.push(entity);
+                None
+            }
+            StorageOp::Clear => None,
+            StorageOp::Despawn(entity)
+            | StorageOp::Set(entity, _)
+            | StorageOp::Remove(entity)
+            | StorageOp::Get(entity) => match self.spawned.len() {
+                0 => return Ok(()),
+                len => Some(self.spawned[entity % len]),
+            },
+        };
+
+        match (&operation, entity) {
+            (StorageOp::Despawn(_), Some(entity)) => {
+                let despawned = self.entities.write().unwrap().despawn(entity).is_ok();
+                if despawned {
+                    let expected = self.components.remove(&entity);
+                    let actual = storage.remove_for_despawn(entity);
+                    if actual != expected {
+                        return Err(violation(&expected, &actual));
+                    }
+                }
+            }
+            (StorageOp::Clear, None) => {
+                let mut despawned = vec![];
+                {
+                    let mut entities = self.entities.write().unwrap();
+                    entities.begin_clear();
+                    entities.clear_step(u32::MAX, |entity| despawned.push(entity));
+                }
+                for entity in despawned {
+                    let expected = self.components.remove(&entity);
+                    let actual = storage.remove_for_despawn(entity);
+                    if actual != expected {
+                        return Err(violation(&expected, &actual));
+                    }
+                }
+            }
+            (StorageOp::Set(_, data), Some(entity)) => {
+                let alive = self.entities.read().unwrap().exists(entity);
+                let actual = storage.set(entity, data.clone());
+                let matches = match &actual {
+                    Ok(previous) => alive && *previous == self.components.get(&entity).cloned(),
+                    Err(StorageError::NoSuchEntity(_)) => !alive,
+                    Err(_) => false,
+                };
+                if !matches {
+                    let expected = self.expected_result(entity, alive);
+                    return Err(violation(&expected, &actual));
+                }
+                if alive {
+                    self.components.insert(entity, data.clone());
+                }
+            }
+            (StorageOp::Remove(_), Some(entity)) => {
+                let alive = self.entities.read().unwrap().exists(entity);
+                let actual = storage.remove(entity);
+                let matches = match &actual {
+                    Ok(removed) => alive && *removed == self.components.get(&entity).cloned(),
+                    Err(StorageError::NoSuchEntity(_)) => !alive,
+                    Err(_) => false,
+                };
+                if !matches {
+                    let expected = self.expected_result(entity, alive);
+                    return Err(violation(&expected, &actual));
+                }
+                self.components.remove(&entity);
+            }
+            _ => {}
+        }
+
+        for &entity in &self.spawned {
+            let expected = self.components.get(&entity);
+            let actual = storage.get(entity);
+            if actual != expected {
+                return Err(ModelViolation {
+                    expected: format!("{:?} for {}", expected, entity),
+                    actual: format!("{:?} for {}", actual, entity),
+                    ..violation(&(), &())
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply the given operations in order; see `apply()`.
+    pub fn apply_all(
+        &mut self,
+        storage: &mut impl ModelStorage<T>,
+        operations: impl IntoIterator<Item = StorageOp<T>>,
+    ) -> Result<(), ModelViolation> {
+        for operation in operations {
+            self.apply(storage, operation)?;
+        }
+        Ok(())
+    }
+
+    /// The result the model expects from setting or removing the component of the given entity.
+    fn expected_result(&self, entity: Entity, alive: bool) -> Result<Option<T>, &'static str> {
+        if alive {
+            Ok(self.components.get(&entity).cloned())
+        } else {
+            Err("NoSuchEntity")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MapStorage, ReusePolicy, VecStorage};
+
+    /// A deterministic sequence of operations mixing all variants.
+    fn operations(count: u32) -> impl Iterator<Item = StorageOp<u32>> {
+        let mut state = 0x9e37_79b9_u32;
+        (0..count).map(move |_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            let entity = (state >> 8) as usize;
+            match state % 64 {
+                0..=15 => StorageOp::Spawn,
+                16..=23 => StorageOp::Despawn(entity),
+                24..=39 => StorageOp::Set(entity, state >> 16),
+                40..=47 => StorageOp::Remove(entity),
+                63 => StorageOp::Clear,
+                _ => StorageOp::Get(entity),
+            }
+        })
+    }
+
+    /// A storage that forgets to remove components on despawn.
+    struct LeakyStorage(VecStorage<u32>);
+
+    impl Storage<u32> for LeakyStorage {
+        fn get(&self, entity: Entity) -> Option<&u32> {
+            self.0.get(entity)
+        }
+
+        fn get_mut(&mut self, entity: Entity) -> Option<&mut u32> {
+            self.0.get_mut(entity)
+        }
+
+        fn get_unchecked(&self, entity: Entity) -> Option<&u32> {
+            self.0.get_unchecked(entity)
+        }
+
+        fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut u32> {
+            self.0.get_mut_unchecked(entity)
+        }
+    }
+
+    impl ModelStorage<u32> for LeakyStorage {
+        fn set(&mut self, entity: Entity, data: u32) -> Result<Option<u32>, StorageError> {
+            self.0.set(entity, data)
+        }
+
+        fn remove(&mut self, entity: Entity) -> Result<Option<u32>, StorageError> {
+            self.0.remove(entity)
+        }
+
+        fn remove_for_despawn(&mut self, _entity: Entity) -> Option<u32> {
+            None
+        }
+    }
+
+    #[test]
+    fn storages_follow_the_model() -> Result<(), ModelViolation> {
+        for policy in [ReusePolicy::LowestFree, ReusePolicy::Lifo] {
+            let entities = Arc::new(RwLock::new(Entities::new(2).with_policy(policy)));
+            let mut storage = VecStorage::new(Arc::clone(&entities), 2);
+            StorageModel::new(entities).apply_all(&mut storage, operations(2000))?;
+
+            let entities = Arc::new(RwLock::new(Entities::new(2).with_policy(policy)));
+            let mut storage = MapStorage::new(Arc::clone(&entities));
+            StorageModel::new(entities).apply_all(&mut storage, operations(2000))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn wrapping_generations_follow_the_model() -> Result<(), ModelViolation> {
+        let entities = Arc::new(RwLock::new(Entities::new(2)));
+        {
+            // Leave the first slots with generations that wrap around after a few reuses.
+            let mut entities = entities.write().unwrap();
+            let aged = (0..4)
+                .map(|index| Entity {
+                    index,
+                    generation: u32::MAX - index,
+                })
+                .collect::<Vec<_>>();
+            entities.mirror_alive(&aged);
+            entities.mirror_alive(&[]);
+        }
+        let mut storage = VecStorage::new(Arc::clone(&entities), 2);
+        let mut model = StorageModel::new(entities);
+        model.apply_all(&mut storage, operations(2000))?;
+        let wrapped = |entity: &Entity| entity.index < 4 && entity.generation < 4;
+        assert!(model.spawned().iter().any(wrapped));
+        Ok(())
+    }
+
+    #[test]
+    fn reports_leaked_components() {
+        let entities = Arc::new(RwLock::new(Entities::new(2)));
+        let mut storage = LeakyStorage(VecStorage::new(Arc::clone(&entities), 2));
+        let mut model = StorageModel::new(entities);
+        let violation = model
+            .apply_all(
+                &mut storage,
+                vec![
+                    StorageOp::Spawn,
+                    StorageOp::Set(0, 7),
+                    StorageOp::Despawn(0),
+                ],
+            )
+            .unwrap_err();
+        assert_eq!(violation.step, 2);
+        assert_eq!(violation.expected, "Some(7)");
+        assert_eq!(violation.actual, "None");
+    }
+}
@@ -1,13 +1,76 @@
+use crate::entity::Entity;
 use std::fmt::{Display, Formatter};
 use thiserror::Error;
 
 /// Error indicating that an entity passed to some operation doesn't exist.
 /// This usually indicates that the generational index of the entity was outdated.
-#[derive(Error, Debug)]
-pub struct NoSuchEntity;
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq)]
+pub struct NoSuchEntity {
+    /// The entity that doesn't exist.
+    pub entity: Entity,
+    /// The operation that failed, if known.
+    pub operation: Option<&'static str>,
+    /// The name of the storage involved, if known.
+    pub storage: Option<&'static str>,
+}
+
+impl NoSuchEntity {
+    /// Create a new NoSuchEntity error for the given entity.
+    pub fn new(entity: Entity) -> Self {
+        Self {
+            entity,
+            operation: None,
+            storage: None,
+        }
+    }
+
+    /// Record the operation that failed.
+    pub fn with_operation(self, operation: &'static str) -> Self {
+        Self {
+            operation: Some(operation),
+            ..self
+        }
+    }
+
+    /// Record the name of the storage involved.
+    pub fn in_storage(self, storage: &'static str) -> Self {
+        Self {
+            storage: Some(storage),
+            ..self
+        }
+    }
+}
 
 impl Display for NoSuchEntity {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "No such entity")
+        write!(f, "No such entity {}", self.entity)?;
+        if let Some(operation) = self.operation {
+            write!(f, " in `{}`", operation)?;
+        }
+        if let Some(storage) = self.storage {
+            write!(f, " on storage `{}`", storage)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_context() {
+        let entity = Entity {
+            index: 4,
+            generation: 1,
+        };
+        assert_eq!(NoSuchEntity::new(entity).to_string(), "No such entity 4:1");
+        assert_eq!(
+            NoSuchEntity::new(entity)
+                .with_operation("set")
+                .in_storage("names")
+                .to_string(),
+            "No such entity 4:1 in `set` on storage `names`"
+        );
     }
 }
@@ -0,0 +1,191 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::marker::PhantomData;
+
+/// The kinds of storage accesses counted by a `ProfileScope`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Access {
+    /// A component was read, e.g. via `get()` or a query.
+    Get,
+    /// A component was accessed mutably, e.g. via `get_mut()` or a query.
+    GetMut,
+    /// A component was set.
+    Set,
+    /// A component was removed, including removals of despawned entities.
+    Remove,
+}
+
+impl Access {
+    /// A short name for the access kind.
+    pub fn name(self) -> &'static str {
+        match self {
+            Access::Get => "get",
+            Access::GetMut => "get_mut",
+            Access::Set => "set",
+            Access::Remove => "remove",
+        }
+    }
+}
+
+/// The storage accesses counted by a `ProfileScope`, by component type and access kind.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct StorageProfile {
+    /// The name of the scope, e.g. the name of the World type.
+    pub scope: &'static str,
+    /// The number of accesses by component type name and access kind.
+    pub counts: BTreeMap<(&'static str, Access), u64>,
+}
+
+impl StorageProfile {
+    /// The number of accesses of all kinds to the storage of the given component type.
+    pub fn total(&self, component: &str) -> u64 {
+        self.counts
+            .iter()
+            .filter(|((name, _), _)| *name == component)
+            .map(|(_, count)| count)
+            .sum()
+    }
+
+    /// Format the counts as folded stacks, i.e. one `scope;component;access count` line per entry,
+    /// which tools like `inferno` or `flamegraph.pl` turn into a flamegraph of storage accesses.
+    pub fn to_folded(&self) -> String {
+        let mut folded = String::new();
+        for ((component, access), count) in &self.counts {
+            let _ = writeln!(
+                folded,
+                "{};{};{} {}",
+                self.scope,
+                component,
+                access.name(),
+                count
+            );
+        }
+        folded
+    }
+}
+
+thread_local! {
+    static ACTIVE_PROFILES: RefCell<Vec<StorageProfile>> = const { RefCell::new(vec![]) };
+}
+
+/// Counts the storage accesses of the current thread while it is alive, e.g. during one frame;
+/// create one via the generated `profile_scope()` of a World or `ProfileScope::new()`.
+/// Nested scopes count the accesses of their inner scopes as well.
+///
+/// Accesses are only counted with the `profile` feature, which also emits a `tracing` span for every
+/// storage access and a `tracing` event for every count when a scope is finished, e.g. for tracy.
+/// Without it, scopes are free and their profiles are empty.
+#[must_use = "accesses are only counted while the scope is alive"]
+pub struct ProfileScope {
+    depth: usize,
+    /// Scopes are bound to the thread whose accesses they count.
+    marker: PhantomData<*const ()>,
+}
+
+impl ProfileScope {
+    /// Start counting the storage accesses of the current thread under the given scope name.
+    pub fn new(scope: &'static str) -> Self {
+        let depth = ACTIVE_PROFILES.with(|profiles| {
+            let mut profiles = profiles.borrow_mut();
+            profiles.push(StorageProfile {
+                scope,
+                counts: BTreeMap::new(),
+            });
+            profiles.len() - 1
+        });
+        Self {
+            depth,
+            marker: PhantomData,
+        }
+    }
+
+    /// The accesses counted so far.
+    pub fn profile(&self) -> StorageProfile {
+        ACTIVE_PROFILES.with(|profiles| {
+            profiles
+                .borrow()
+                .get(self.depth)
+                .cloned()
+                .unwrap_or_default()
+        })
+    }
+
+    /// Stop counting and return the counted accesses.
+    pub fn finish(self) -> StorageProfile {
+        let profile = self.profile();
+        #[cfg(feature = "profile")]
+        for ((component, access), count) in &profile.counts {
+            tracing::debug!(
+                target: "genesis::profile",
+                scope = profile.scope,
+                component = *component,
+                access = access.name(),
+                count = *count,
+            );
+        }
+        profile
+    }
+}
+
+impl Drop for ProfileScope {
+    fn drop(&mut self) {
+        let _ = ACTIVE_PROFILES.try_with(|profiles| profiles.borrow_mut().truncate(self.depth));
+    }
+}
+
+/// Record an access to the storage of components of type `T` in all active scopes of this thread.
+#[cfg(feature = "profile")]
+pub(crate) fn record<T>(access: Access) -> tracing::span::EnteredSpan {
+    let component = std::any::type_name::<T>();
+    ACTIVE_PROFILES.with(|profiles| {
+        for profile in profiles.borrow_mut().iter_mut() {
+            *profile.counts.entry((component, access)).or_insert(0) += 1;
+        }
+    });
+    tracing::trace_span!("storage", component, access = access.name()).entered()
+}
+
+/// Stands in for the span entered by `record()` without the `profile` feature.
+#[cfg(not(feature = "profile"))]
+pub(crate) struct NoSpan;
+
+/// Record an access to the storage of components of type `T`; does nothing without the `profile` feature.
+#[cfg(not(feature = "profile"))]
+#[inline(always)]
+#[allow(clippy::extra_unused_type_parameters)]
+pub(crate) fn record<T>(_access: Access) -> NoSpan {
+    NoSpan
+}
+
+#[cfg(all(test, feature = "profile"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_accesses_in_nested_scopes() {
+        let outer = ProfileScope::new("frame");
+        let _ = record::<u32>(Access::Get);
+        {
+            let inner = ProfileScope::new("system");
+            let _ = record::<u32>(Access::Set);
+            let _ = record::<u64>(Access::Get);
+            assert_eq!(inner.finish().total("u64"), 1);
+        }
+        let _ = record::<u32>(Access::Get);
+
+        let profile = outer.finish();
+        assert_eq!(profile.total("u32"), 3);
+        assert_eq!(
+            profile.to_folded(),
+            "frame;u32;get 2\nframe;u32;set 1\nframe;u64;get 1\n"
+        );
+        assert_eq!(
+            ProfileScope::new("idle").finish(),
+            StorageProfile {
+                scope: "idle",
+                counts: BTreeMap::new(),
+            }
+        );
+    }
+}
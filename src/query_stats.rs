@@ -0,0 +1,55 @@
+use std::fmt::{Display, Formatter};
+
+/// Statistics about a single run of a query, returned by `query_explain!`.
+/// These help diagnosing slow queries and choosing better storage types for their components.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct QueryStats {
+    /// What drove the iteration of the query: `entities` when iterating over all alive entities, or the name of
+    /// the component type whose storage listed the candidates, e.g. a `MapStorage`; see
+    /// `Storage::sparse_presence_into()`.
+    pub driver: &'static str,
+    /// The number of entities visited during iteration.
+    pub visited: usize,
+    /// The number of visited entities that had all required components.
+    pub matched: usize,
+    /// The number of locks acquired by the query: one for the entities and one per storage asked for its
+    /// components.
+    pub lock_acquisitions: usize,
+}
+
+impl QueryStats {
+    /// Describe how the query was executed, e.g.
+    /// `driven by entities: matched 2 of 10 visited entities, 1 lock acquisition(s)`.
+    pub fn explain(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for QueryStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "driven by {}: matched {} of {} visited entities, {} lock acquisition(s)",
+            self.driver, self.matched, self.visited, self.lock_acquisitions
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explain() {
+        let stats = QueryStats {
+            driver: "entities",
+            visited: 10,
+            matched: 2,
+            lock_acquisitions: 1,
+        };
+        assert_eq!(
+            stats.explain(),
+            "driven by entities: matched 2 of 10 visited entities, 1 lock acquisition(s)"
+        );
+    }
+}
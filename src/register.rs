@@ -1,12 +1,120 @@
 use crate::entity::Entity;
 use crate::NoSuchEntity;
+use crate::StorageError;
+use thiserror::Error;
 
 /// Type that can be registered. Can be used to set components in ECS Worlds generated by `genesis`.
 pub trait Register<T> {
+    /// The error returned if registering fails.
+    /// This is `StorageError` unless the registered components have requirements; see `RegisterError`.
+    type Error;
+
     /// Register the given item for the given entity.
-    /// Returns Err(NoSuchEntity) if the given entity doesn't exist.
+    /// Returns an error if the given entity doesn't exist.
     /// Otherwise, returns the previously associated item.
     /// For normal components used to generate a World, this is equivalent to calling `.set()`
     /// on the corresponding storage field.
-    fn register(&mut self, entity: Entity, item: T) -> Result<Option<T>, NoSuchEntity>;
+    fn register(&mut self, entity: Entity, item: T) -> Result<Option<T>, Self::Error>;
+}
+
+/// Type that templates can be registered for while reporting what happened to each component, e.g. to
+/// trigger gameplay rules only when a buff is applied for the first time.
+/// Implemented for all Worlds generated by `genesis` for their templates, with a generated
+/// `<Template>Report` holding a `Registered<T>` per component.
+pub trait RegisterWithReport<T> {
+    /// The report of what happened to each component of the template.
+    type Report;
+    /// The error returned if registering fails; see `Register::Error`.
+    type Error;
+
+    /// Register the given template for the given entity like `Register::register()` does.
+    fn register_with_report(
+        &mut self,
+        entity: Entity,
+        template: T,
+    ) -> Result<Self::Report, Self::Error>;
+}
+
+/// What registering a template did with one of its components; see `RegisterWithReport`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Registered<T> {
+    /// The template didn't contain the component, so the entity's component was left untouched.
+    Skipped,
+    /// The entity didn't have the component before.
+    Added,
+    /// The entity had the component before; holds the replaced component.
+    Replaced(T),
+}
+
+impl<T> Registered<T> {
+    /// Check if the component was newly added to the entity.
+    pub fn is_added(&self) -> bool {
+        matches!(self, Registered::Added)
+    }
+
+    /// Check if the component replaced a previous component of the entity.
+    pub fn is_replaced(&self) -> bool {
+        matches!(self, Registered::Replaced(_))
+    }
+
+    /// The replaced component, if any.
+    pub fn replaced(self) -> Option<T> {
+        match self {
+            Registered::Replaced(previous) => Some(previous),
+            _ => None,
+        }
+    }
+}
+
+/// Error returned when registering components declared with `#[component(requires(...))]`.
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RegisterError {
+    /// The entity doesn't exist.
+    #[error(transparent)]
+    NoSuchEntity(#[from] NoSuchEntity),
+    /// The entity doesn't have a component required by the registered component.
+    #[error("component `{component}` of entity {entity} requires component `{required}`")]
+    MissingRequirement {
+        entity: Entity,
+        component: &'static str,
+        required: &'static str,
+    },
+    /// The storage of the registered component failed for another reason; see `StorageError`.
+    #[error(transparent)]
+    Storage(StorageError),
+}
+
+impl From<StorageError> for RegisterError {
+    fn from(error: StorageError) -> Self {
+        match error {
+            StorageError::NoSuchEntity(error) => RegisterError::NoSuchEntity(error),
+            other => RegisterError::Storage(other),
+        }
+    }
+}
+
+/// Error returned by the generated `move_between()` of Worlds storing a component type in several fields.
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum MoveError {
+    /// The entity doesn't exist.
+    #[error(transparent)]
+    NoSuchEntity(#[from] NoSuchEntity),
+    /// The storages don't hold the same component type, or are the same storage.
+    #[error("cannot move components from `{from}` to `{to}`, which must be different storages of one component type")]
+    IncompatibleStorages {
+        from: &'static str,
+        to: &'static str,
+    },
+    /// The entity has no component in the storage to move it from.
+    #[error("entity {entity} has no component in `{storage}` to move")]
+    MissingComponent {
+        entity: Entity,
+        storage: &'static str,
+    },
+    /// The entity already has a component in the storage to move it to.
+    #[error("entity {entity} already has a component in `{storage}`")]
+    Occupied {
+        entity: Entity,
+        storage: &'static str,
+    },
 }
@@ -0,0 +1,193 @@
+use crate::entity::Entity;
+use crate::no_such_entity::NoSuchEntity;
+use std::sync::{Arc, Mutex, OnceLock, RwLock, Weak};
+
+/// The names a component of a World can be referred to by in debug tooling.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ComponentNames {
+    /// The name of the storage field of the component.
+    pub storage: &'static str,
+    /// The template name of the component.
+    pub template: &'static str,
+    /// The name of the component type.
+    pub type_name: &'static str,
+}
+
+impl ComponentNames {
+    /// Check if the given name is one of the names of the component.
+    pub fn matches(&self, name: &str) -> bool {
+        name == self.storage || name == self.template || name == self.type_name
+    }
+}
+
+/// A World whose entities and components can be listed by debug tooling without knowing its type.
+/// Implemented by the `#[world]` macro for Worlds deriving `Debug`.
+pub trait DebugWorld {
+    /// The name of the World type.
+    fn type_name(&self) -> &'static str;
+
+    /// The names of all components of the World, in declaration order.
+    fn component_names(&self) -> &'static [ComponentNames];
+
+    /// All alive entities, by index.
+    fn alive_entities(&self) -> Vec<Entity>;
+
+    /// The storage field name and `Debug` representation of every component of the given entity,
+    /// in declaration order; empty if the entity doesn't exist.
+    fn debug_components(&self, entity: Entity) -> Vec<(&'static str, String)>;
+
+    /// Despawn the given entity and remove all its components.
+    fn despawn_entity(&mut self, entity: Entity) -> Result<(), NoSuchEntity>;
+}
+
+type SharedWorld = Arc<RwLock<dyn DebugWorld + Send + Sync>>;
+type WeakWorld = Weak<RwLock<dyn DebugWorld + Send + Sync>>;
+
+/// A World registered under a name in the global `WorldRegistry`.
+#[derive(Clone)]
+pub struct RegisteredWorld {
+    /// The name the World was registered under.
+    pub name: String,
+    /// The World.
+    pub world: SharedWorld,
+}
+
+/// A process-wide registry of named Worlds, e.g. `"client"` and `"server"` in one process, that lets
+/// debug tooling like consoles or inspector windows enumerate and inspect all live Worlds.
+/// Worlds are held weakly and drop out of the registry once they are dropped; see `registry()`.
+#[derive(Default)]
+pub struct WorldRegistry {
+    worlds: Mutex<Vec<(String, WeakWorld)>>,
+}
+
+impl WorldRegistry {
+    /// Register the given World under the given name, replacing any World registered under it before.
+    pub fn register<W: DebugWorld + Send + Sync + 'static>(
+        &self,
+        name: impl Into<String>,
+        world: &Arc<RwLock<W>>,
+    ) {
+        let name = name.into();
+        let world: SharedWorld = world.clone();
+        let mut worlds = self.worlds.lock().unwrap();
+        worlds.retain(|(registered, world)| *registered != name && world.strong_count() > 0);
+        worlds.push((name, Arc::downgrade(&world)));
+    }
+
+    /// Remove the World registered under the given name.
+    /// Returns true if a live World was registered under it.
+    pub fn unregister(&self, name: &str) -> bool {
+        let mut worlds = self.worlds.lock().unwrap();
+        let live = worlds
+            .iter()
+            .any(|(registered, world)| registered == name && world.strong_count() > 0);
+        worlds.retain(|(registered, _)| registered != name);
+        live
+    }
+
+    /// Get the live World registered under the given name, if any.
+    pub fn get(&self, name: &str) -> Option<SharedWorld> {
+        let worlds = self.worlds.lock().unwrap();
+        worlds
+            .iter()
+            .find(|(registered, _)| registered == name)
+            .and_then(|(_, world)| world.upgrade())
+    }
+
+    /// All live registered Worlds, in registration order.
+    /// Forgets Worlds that have been dropped.
+    pub fn worlds(&self) -> Vec<RegisteredWorld> {
+        let mut worlds = self.worlds.lock().unwrap();
+        worlds.retain(|(_, world)| world.strong_count() > 0);
+        worlds
+            .iter()
+            .filter_map(|(name, world)| {
+                Some(RegisteredWorld {
+                    name: name.clone(),
+                    world: world.upgrade()?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The global `WorldRegistry`. Worlds are only listed in it after being registered explicitly.
+pub fn registry() -> &'static WorldRegistry {
+    static REGISTRY: OnceLock<WorldRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(WorldRegistry::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Counter(u32);
+
+    impl DebugWorld for Counter {
+        fn type_name(&self) -> &'static str {
+            "Counter"
+        }
+
+        fn component_names(&self) -> &'static [ComponentNames] {
+            &[]
+        }
+
+        fn alive_entities(&self) -> Vec<Entity> {
+            (0..self.0)
+                .map(|index| Entity {
+                    index,
+                    generation: 0,
+                })
+                .collect()
+        }
+
+        fn debug_components(&self, _entity: Entity) -> Vec<(&'static str, String)> {
+            vec![]
+        }
+
+        fn despawn_entity(&mut self, entity: Entity) -> Result<(), NoSuchEntity> {
+            Err(NoSuchEntity::new(entity))
+        }
+    }
+
+    #[test]
+    fn forgets_dropped_worlds() {
+        let registry = WorldRegistry::default();
+        let client = Arc::new(RwLock::new(Counter(1)));
+        let server = Arc::new(RwLock::new(Counter(2)));
+        registry.register("client", &client);
+        registry.register("server", &server);
+
+        let names = |registry: &WorldRegistry| {
+            registry
+                .worlds()
+                .into_iter()
+                .map(|registered| registered.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&registry), vec!["client", "server"]);
+        let server_world = registry.get("server").unwrap();
+        assert_eq!(server_world.read().unwrap().alive_entities().len(), 2);
+        drop(server_world);
+
+        let replacement = Arc::new(RwLock::new(Counter(3)));
+        registry.register("client", &replacement);
+        drop(client);
+        assert_eq!(
+            registry
+                .get("client")
+                .unwrap()
+                .read()
+                .unwrap()
+                .alive_entities()
+                .len(),
+            3
+        );
+
+        drop(server);
+        assert!(registry.get("server").is_none());
+        assert_eq!(names(&registry), vec!["client"]);
+        assert!(registry.unregister("client"));
+        assert!(registry.worlds().is_empty());
+    }
+}
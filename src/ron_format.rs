@@ -0,0 +1,66 @@
+//! Building blocks of the RON helpers generated for Worlds passed the `ron` flag in `#[world]`.
+
+use crate::register::RegisterError;
+use crate::storage_error::StorageError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use thiserror::Error;
+
+/// Error returned by the generated RON helpers of a World, like `from_ron_str()` and `apply_ron_patch()`.
+#[derive(Error, Debug)]
+pub enum RonError {
+    /// The RON source isn't a valid template or World, with the position of the error.
+    #[error(transparent)]
+    Parse(#[from] ron::error::SpannedError),
+    /// A component can't be serialized.
+    #[error(transparent)]
+    Serialize(#[from] ron::Error),
+    /// Registering the deserialized components failed, e.g. because the entity doesn't exist.
+    #[error(transparent)]
+    Register(#[from] RegisterError),
+    /// Setting the components of a deserialized World failed.
+    #[error(transparent)]
+    Storage(#[from] StorageError),
+}
+
+/// Serialize the given value as pretty-printed RON, the format of the generated helpers.
+pub fn to_string<T: Serialize>(value: &T) -> Result<String, RonError> {
+    Ok(ron::ser::to_string_pretty(
+        value,
+        ron::ser::PrettyConfig::default(),
+    )?)
+}
+
+/// Deserialize a value from the given RON source.
+pub fn from_str<T: DeserializeOwned>(source: &str) -> Result<T, RonError> {
+    Ok(ron::from_str(source)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Eq, PartialEq, Serialize, Deserialize)]
+    enum Component {
+        Position { x: i32, y: i32 },
+        Name(String),
+    }
+
+    #[test]
+    fn round_trips_enum_variants() {
+        let components = vec![
+            Component::Position { x: 1, y: -2 },
+            Component::Name("a".into()),
+        ];
+        let source = to_string(&components).unwrap();
+        assert!(source.contains("Position(") && source.contains("Name(\"a\")"));
+        assert_eq!(from_str::<Vec<Component>>(&source).unwrap(), components);
+
+        let error = from_str::<Vec<Component>>("[Velocity(1)]").unwrap_err();
+        assert!(matches!(error, RonError::Parse(_)));
+        assert!(error
+            .to_string()
+            .starts_with("1:10: Unexpected variant named `Velocity`"));
+    }
+}
@@ -0,0 +1,153 @@
+use crate::entity::Entity;
+use crate::map_entities::MapEntities;
+use serde::{Deserialize, Serialize};
+use std::iter::FromIterator;
+
+/// The generation of local references; see `SceneTemplate::local()`.
+const LOCAL_GENERATION: u32 = u32::MAX;
+
+/// A group of entity templates spawned as one unit, e.g. a house prefab together with its furniture.
+/// Components of the templates refer to other entities of the same scene via local references, which
+/// are resolved to the spawned entities when the scene is spawned, so no manual id fix-up is needed.
+///
+/// Worlds generated by `genesis` get `spawn_scene(scene)`, which spawns one entity per template and
+/// resolves the local references of all components marked with `#[component(maps_entities)]`.
+/// Scenes can be serialized, e.g. to load prefabs from data files, if their templates can.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
+pub struct SceneTemplate<T> {
+    templates: Vec<T>,
+}
+
+impl<T> Default for SceneTemplate<T> {
+    fn default() -> Self {
+        Self { templates: vec![] }
+    }
+}
+
+// Functions on local references don't depend on the templates, so they are implemented for a single
+// type, which lets `SceneTemplate::local(0)` be called without naming a template type.
+impl SceneTemplate<()> {
+    /// The local reference to the entity of the template at the given position in the scene.
+    /// Local references are entities with the position as index and a generation of `u32::MAX`, so they
+    /// can be stored in components and data files like any other entity. As a consequence, entities of
+    /// the World with that generation can't be referenced by scenes.
+    pub fn local(position: usize) -> Entity {
+        Entity {
+            index: position as u32,
+            generation: LOCAL_GENERATION,
+        }
+    }
+
+    /// Check if the given entity is a local reference rather than an entity of a World.
+    pub fn is_local(entity: Entity) -> bool {
+        entity.generation == LOCAL_GENERATION
+    }
+
+    /// Resolve the given entity against the entities spawned for a scene, one per template in order:
+    /// local references become the spawned entity at their position, other entities are returned as is.
+    /// Local references beyond the spawned entities are returned as is as well, so they don't refer to
+    /// any alive entity.
+    pub fn resolve(entity: Entity, spawned: &[Entity]) -> Entity {
+        if Self::is_local(entity) {
+            if let Some(resolved) = spawned.get(entity.index as usize) {
+                return *resolved;
+            }
+        }
+        entity
+    }
+}
+
+impl<T> SceneTemplate<T> {
+    /// Create an empty scene.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a template to the scene and return the local reference to its entity, to be stored in
+    /// components of other templates of the scene.
+    pub fn add(&mut self, template: T) -> Entity {
+        self.templates.push(template);
+        SceneTemplate::local(self.templates.len() - 1)
+    }
+
+    /// The number of templates, i.e. of entities spawned for the scene.
+    pub fn len(&self) -> usize {
+        self.templates.len()
+    }
+
+    /// Check if the scene has no templates.
+    pub fn is_empty(&self) -> bool {
+        self.templates.is_empty()
+    }
+
+    /// The templates of the scene, in the order they were added.
+    pub fn templates(&self) -> &[T] {
+        &self.templates
+    }
+}
+
+impl<T: MapEntities> SceneTemplate<T> {
+    /// Resolve the local references in all templates to the given spawned entities, one per template
+    /// in order, and return the templates; see `resolve()`.
+    ///
+    /// # Panics
+    /// Panics if the number of spawned entities differs from the number of templates.
+    pub fn instantiate(self, spawned: &[Entity]) -> Vec<T> {
+        assert_eq!(
+            spawned.len(),
+            self.templates.len(),
+            "a scene needs one spawned entity per template"
+        );
+        let mut templates = self.templates;
+        for template in &mut templates {
+            template.map_entities(&mut |entity| SceneTemplate::resolve(entity, spawned));
+        }
+        templates
+    }
+}
+
+impl<T> FromIterator<T> for SceneTemplate<T> {
+    fn from_iter<I: IntoIterator<Item = T>>(templates: I) -> Self {
+        Self {
+            templates: templates.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_local_references() {
+        let mut scene = SceneTemplate::new();
+        let house = scene.add(None);
+        let outside = Entity {
+            index: 0,
+            generation: 3,
+        };
+        let chair = scene.add(Some(house));
+        scene.add(Some(chair));
+        scene.add(Some(outside));
+        scene.add(Some(SceneTemplate::local(9)));
+        assert!(SceneTemplate::is_local(house) && !SceneTemplate::is_local(outside));
+
+        let spawned = (10..15)
+            .map(|index| Entity {
+                index,
+                generation: 1,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            scene.instantiate(&spawned),
+            vec![
+                None,
+                Some(spawned[0]),
+                Some(spawned[1]),
+                Some(outside),
+                Some(SceneTemplate::local(9))
+            ]
+        );
+    }
+}
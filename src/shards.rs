@@ -0,0 +1,161 @@
+use crate::entity::Entity;
+use crate::limit::EntityLimitReached;
+use crate::no_such_entity::NoSuchEntity;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/// A World that can be used as one shard of `Shards`. Implemented by Worlds generated by `#[world]`.
+pub trait Shard: Sized {
+    /// Create an empty shard whose entities use the `size` indices starting at `base`; see
+    /// `Entities::with_max_index()`.
+    fn new_shard(initial_capacity: u32, base: u32, size: u32) -> Self;
+
+    /// Spawn a new entity unless all indices of the shard are in use.
+    fn try_spawn(&mut self) -> Result<Entity, EntityLimitReached>;
+
+    /// Move the given entity and all its components to `target`.
+    /// Returns the entity in `target`; the given entity no longer exists afterwards.
+    /// Components referring to the given entity are left unchanged; see `Shards::move_entity()`.
+    fn move_entity_to(&mut self, entity: Entity, target: &mut Self) -> Result<Entity, ShardError>;
+
+    /// Rewrite the entities stored in the components of the shard that implement `MapEntities`.
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity);
+}
+
+/// Error returned by operations on `Shards`.
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ShardError {
+    /// The shard with the given id isn't loaded.
+    #[error("shard {0} is not loaded")]
+    NotLoaded(u32),
+    /// The entity doesn't exist.
+    #[error(transparent)]
+    NoSuchEntity(#[from] NoSuchEntity),
+    /// All indices of the shard are in use.
+    #[error(transparent)]
+    Full(#[from] EntityLimitReached),
+}
+
+/// A World partitioned into shards, e.g. one per region of a large map, that can be loaded and
+/// unloaded independently.
+/// Every shard owns a disjoint range of `shard_size` entity indices, so the shard of an entity can be
+/// found from the entity alone. Spawn entities via `spawn()` or `Shard::try_spawn()`; a plain `spawn()`
+/// on a shard panics once all indices of its range are in use.
+#[derive(Debug, Clone)]
+pub struct Shards<W> {
+    shard_size: u32,
+    initial_capacity: u32,
+    shards: BTreeMap<u32, W>,
+}
+
+impl<W: Shard> Shards<W> {
+    /// Create a new Shards<W> without loaded shards.
+    /// Each shard holds up to `shard_size` entities and is created with the given initial capacity.
+    /// Panics if `shard_size` is 0.
+    pub fn new(shard_size: u32, initial_capacity: u32) -> Self {
+        assert!(shard_size > 0, "shard size must be greater than 0");
+        Self {
+            shard_size,
+            initial_capacity,
+            shards: BTreeMap::new(),
+        }
+    }
+
+    /// The maximum number of entities of each shard.
+    pub fn shard_size(&self) -> u32 {
+        self.shard_size
+    }
+
+    /// The id of the shard owning the given entity.
+    pub fn shard_of(&self, entity: Entity) -> u32 {
+        entity.index / self.shard_size
+    }
+
+    /// Load the shard with the given id, creating an empty shard if it isn't loaded yet.
+    /// Panics if the index range of the shard exceeds the range of entity indices.
+    pub fn load(&mut self, id: u32) -> &mut W {
+        let (initial_capacity, size) = (self.initial_capacity, self.shard_size);
+        self.shards.entry(id).or_insert_with(|| {
+            let base = id
+                .checked_mul(size)
+                .filter(|base| base.checked_add(size - 1).is_some())
+                .expect("shard index range exceeds the range of entity indices");
+            W::new_shard(initial_capacity, base, size)
+        })
+    }
+
+    /// Insert a previously unloaded shard with the given id.
+    /// `world` must have been created for this id, e.g. by `load()`.
+    /// Returns the shard previously loaded with this id, if any.
+    pub fn insert(&mut self, id: u32, world: W) -> Option<W> {
+        self.shards.insert(id, world)
+    }
+
+    /// Unload the shard with the given id, returning it if it was loaded.
+    pub fn unload(&mut self, id: u32) -> Option<W> {
+        self.shards.remove(&id)
+    }
+
+    /// Check if the shard with the given id is loaded.
+    pub fn is_loaded(&self, id: u32) -> bool {
+        self.shards.contains_key(&id)
+    }
+
+    /// Iterate over the ids of all loaded shards in ascending order.
+    pub fn loaded(&self) -> impl Iterator<Item = u32> + '_ {
+        self.shards.keys().copied()
+    }
+
+    /// Get a reference to the shard with the given id, if it is loaded.
+    pub fn get(&self, id: u32) -> Option<&W> {
+        self.shards.get(&id)
+    }
+
+    /// Get a mutable reference to the shard with the given id, if it is loaded.
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut W> {
+        self.shards.get_mut(&id)
+    }
+
+    /// Get a reference to the shard owning the given entity, if it is loaded.
+    pub fn world_of(&self, entity: Entity) -> Option<&W> {
+        self.get(self.shard_of(entity))
+    }
+
+    /// Get a mutable reference to the shard owning the given entity, if it is loaded.
+    pub fn world_of_mut(&mut self, entity: Entity) -> Option<&mut W> {
+        self.get_mut(self.shard_of(entity))
+    }
+
+    /// Spawn a new entity in the shard with the given id.
+    pub fn spawn(&mut self, id: u32) -> Result<Entity, ShardError> {
+        let shard = self.get_mut(id).ok_or(ShardError::NotLoaded(id))?;
+        Ok(shard.try_spawn()?)
+    }
+
+    /// Move the given entity and all its components to the shard with the id `to`.
+    /// Returns the entity in the target shard; the given entity no longer exists afterwards,
+    /// unless it already belongs to the target shard, in which case it is returned unchanged.
+    /// Components of loaded shards marked with `#[component(maps_entities)]` that refer to the given entity
+    /// are rewritten to refer to the returned one; those of unloaded shards are left unchanged.
+    pub fn move_entity(&mut self, entity: Entity, to: u32) -> Result<Entity, ShardError> {
+        let from = self.shard_of(entity);
+        if from == to {
+            return Ok(entity);
+        }
+        let mut source = self
+            .shards
+            .remove(&from)
+            .ok_or(ShardError::NotLoaded(from))?;
+        let result = match self.shards.get_mut(&to) {
+            Some(target) => source.move_entity_to(entity, target),
+            None => Err(ShardError::NotLoaded(to)),
+        };
+        self.shards.insert(from, source);
+        let moved = result?;
+        let mut remap = |e: Entity| if e == entity { moved } else { e };
+        for shard in self.shards.values_mut() {
+            shard.map_entities(&mut remap);
+        }
+        Ok(moved)
+    }
+}
@@ -0,0 +1,147 @@
+use crate::map_entities::MapEntities;
+use crate::Entity;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+/// A component value shared between many entities, e.g. a mesh or dialogue tree referenced by thousands
+/// of entities. Cloning only clones the `Arc`; mutable access through `DerefMut`, e.g. via `get_mut()` of
+/// a storage, clones the value first if it is shared with other entities, so changing the component of
+/// one entity never affects the others.
+///
+/// Component types are named by identifiers in World definitions, so store shared values under an alias:
+/// `type SharedMesh = Shared<Mesh>;` and `meshes: VecStorage<SharedMesh>`.
+pub struct Shared<T>(Arc<T>);
+
+impl<T> Shared<T> {
+    /// Wrap the given value.
+    pub fn new(value: T) -> Self {
+        Self(Arc::new(value))
+    }
+
+    /// Check if self and `other` share the same value.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// The number of components sharing the value, including self.
+    pub fn share_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+
+    /// The shared value as an `Arc`, e.g. to hand it to a render thread.
+    pub fn as_arc(&self) -> &Arc<T> {
+        &self.0
+    }
+}
+
+impl<T: Clone> Shared<T> {
+    /// Take the value, cloning it if it is shared with other components.
+    pub fn into_inner(self) -> T {
+        Arc::try_unwrap(self.0).unwrap_or_else(|shared| T::clone(&shared))
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T> Deref for Shared<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Clone> DerefMut for Shared<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        Arc::make_mut(&mut self.0)
+    }
+}
+
+impl<T> From<T> for Shared<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T> From<Arc<T>> for Shared<T> {
+    fn from(value: Arc<T>) -> Self {
+        Self(value)
+    }
+}
+
+impl<T: Default> Default for Shared<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Debug> Debug for Shared<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        T::fmt(self, f)
+    }
+}
+
+impl<T: PartialEq> PartialEq for Shared<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.ptr_eq(other) || **self == **other
+    }
+}
+
+impl<T: Eq> Eq for Shared<T> {}
+
+impl<T: Hash> Hash for Shared<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        T::hash(self, state)
+    }
+}
+
+impl<T: MapEntities + Clone> MapEntities for Shared<T> {
+    fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity) {
+        T::map_entities(self, mapper);
+    }
+}
+
+/// Serializes the value itself; deserialized values are not shared until cloned.
+impl<T: Serialize> Serialize for Shared<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        T::serialize(self, serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Shared<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self::new)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_on_write() {
+        let mesh = Shared::new(vec![1, 2, 3]);
+        let mut copy = mesh.clone();
+        assert!(copy.ptr_eq(&mesh));
+        assert_eq!(mesh.share_count(), 2);
+
+        copy.push(4);
+        assert!(!copy.ptr_eq(&mesh));
+        assert_eq!(*mesh, vec![1, 2, 3]);
+        assert_eq!(*copy, vec![1, 2, 3, 4]);
+        assert_eq!(mesh.share_count(), 1);
+
+        // Unshared values are changed in place.
+        let address = copy.as_ptr();
+        copy[0] = 0;
+        assert_eq!(copy.as_ptr(), address);
+        assert_eq!(copy.into_inner(), vec![0, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,459 @@
+use crate::allocations::{self, AllocationKind};
+use crate::entity::Entity;
+use crate::integrity::IntegrityError;
+use crate::layout::MemoryUsage;
+use crate::map_entities::MapEntities;
+use crate::no_such_entity::NoSuchEntity;
+use crate::profile::{self, Access};
+use crate::storage_error::StorageError;
+use crate::thread_check::OwnerThread;
+use crate::trace;
+use crate::Entities;
+use crate::ModelStorage;
+use crate::Storage;
+use std::fmt::{Debug, Formatter};
+use std::ptr::NonNull;
+use std::sync::{Arc, RwLock};
+use std::thread::ThreadId;
+
+/// The number of slots per page of a `StableStorage`.
+const PAGE_SLOTS: usize = 256;
+
+#[derive(Clone)]
+struct Slot<T> {
+    /// The generation of the entity the component was set for.
+    generation: u32,
+    component: Option<T>,
+}
+
+impl<T> Default for Slot<T> {
+    fn default() -> Self {
+        Self {
+            generation: 0,
+            component: None,
+        }
+    }
+}
+
+type Page<T> = Box<[Slot<T>]>;
+
+/// A storage type that never moves its components in memory while they are stored, e.g. for components
+/// whose raw pointers are held by a C physics library between frames.
+///
+/// Components are kept in separately allocated pages of slots, one slot per entity index, which are
+/// never reallocated or freed while the storage exists. A pointer to a component, e.g. from
+/// `get_ptr()`, stays valid until the component is removed, including when the entity is despawned
+/// or the storage is cleared; replacing the component via `set()` writes the new component to the same
+/// address. Clones store their components at new addresses.
+///
+/// Select it for a component with `#[component(stable)]` on a `VecStorage<T>` field of a World, or by
+/// declaring the field as `StableStorage<T>`.
+pub struct StableStorage<T> {
+    pages: Vec<Option<Page<T>>>,
+    /// The index base of the entities; the first slot holds the component of the entity at this index.
+    base: u32,
+    entities: Arc<RwLock<Entities>>,
+    /// The thread allowed to mutate the components; see `bind_to_thread()`.
+    owner: OwnerThread,
+}
+
+impl<T> StableStorage<T> {
+    /// Create a new StableStorage<T> with pages for the specified initial capacity.
+    pub fn new(entities: Arc<RwLock<Entities>>, capacity: u32) -> Self {
+        let base = entities.read().unwrap().index_base();
+        let mut storage = Self {
+            pages: vec![],
+            base,
+            entities,
+            owner: OwnerThread::current(),
+        };
+        storage.grow_to(base + capacity);
+        storage
+    }
+
+    /// The page and the position in the page of the given entity, or None if its index is below the
+    /// index base.
+    fn position(&self, entity: Entity) -> Option<(usize, usize)> {
+        let slot = entity.index.checked_sub(self.base)? as usize;
+        Some((slot / PAGE_SLOTS, slot % PAGE_SLOTS))
+    }
+
+    fn slot(&self, entity: Entity) -> Option<&Slot<T>> {
+        let (page, slot) = self.position(entity)?;
+        self.pages.get(page)?.as_ref().map(|page| &page[slot])
+    }
+
+    fn slot_mut(&mut self, entity: Entity) -> Option<&mut Slot<T>> {
+        let (page, slot) = self.position(entity)?;
+        self.pages
+            .get_mut(page)?
+            .as_mut()
+            .map(|page| &mut page[slot])
+    }
+
+    fn new_page() -> Page<T> {
+        allocations::record::<T>(
+            AllocationKind::Growth,
+            PAGE_SLOTS * std::mem::size_of::<Slot<T>>(),
+        );
+        (0..PAGE_SLOTS).map(|_| Slot::default()).collect()
+    }
+
+    /// Allocate the pages for all entity indices below `index_end` up front, e.g. before spawning a
+    /// known number of entities; see `Entities::reserve()`. Pages are otherwise allocated when the first
+    /// component in them is set.
+    pub fn grow_to(&mut self, index_end: u32) {
+        let slots = index_end.saturating_sub(self.base) as usize;
+        let pages = slots.div_ceil(PAGE_SLOTS);
+        if self.pages.len() < pages {
+            self.pages.resize_with(pages, || None);
+        }
+        for page in &mut self.pages[..pages] {
+            page.get_or_insert_with(Self::new_page);
+        }
+    }
+
+    /// The number of allocated slots; every allocated page holds 256 slots.
+    pub fn slots(&self) -> usize {
+        self.pages.iter().flatten().count() * PAGE_SLOTS
+    }
+
+    /// Iterate over the alive entities of the given entities that have a component in self, by index.
+    fn alive_components<'a>(
+        &'a self,
+        entities: &'a Entities,
+    ) -> impl Iterator<Item = (Entity, &'a T)> + 'a {
+        entities
+            .iter()
+            .filter_map(move |entity| Some((entity, self.get_unchecked(entity)?)))
+    }
+
+    /// Get a reference to the component associated with the given entity in self, if any.
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        let lock = self.entities.read().unwrap();
+        if lock.exists(entity) {
+            self.get_unchecked(entity)
+        } else {
+            None
+        }
+    }
+
+    /// Get a mutable reference to the component associated with the given entity in self, if any.
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        let exists = self.entities.read().unwrap().exists(entity);
+        if exists {
+            self.get_mut_unchecked(entity)
+        } else {
+            None
+        }
+    }
+
+    /// Get a reference to the component associated with the given entity in self, if any.
+    /// Does not check if the entity exists; only use this if you know it exists.
+    pub fn get_unchecked(&self, entity: Entity) -> Option<&T> {
+        let _span = profile::record::<T>(Access::Get);
+        match self.slot(entity)? {
+            Slot {
+                generation,
+                component: Some(component),
+            } if *generation == entity.generation => Some(component),
+            _ => None,
+        }
+    }
+
+    /// Get a mutable reference to the component associated with the given entity in self, if any.
+    /// Does not check if the entity exists; only use this if you know it exists.
+    pub fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T> {
+        self.owner.check::<T>("get_mut");
+        let _span = profile::record::<T>(Access::GetMut);
+        match self.slot_mut(entity)? {
+            Slot {
+                generation,
+                component: Some(component),
+            } if *generation == entity.generation => Some(component),
+            _ => None,
+        }
+    }
+
+    /// Get a pointer to the component associated with the given entity in self, if any, e.g. to hand it
+    /// to a C library. The pointer stays valid until the component is removed from self; it must only
+    /// be dereferenced while no reference to the component obtained from self is alive.
+    pub fn get_ptr(&mut self, entity: Entity) -> Option<NonNull<T>> {
+        self.get_mut(entity).map(NonNull::from)
+    }
+
+    /// Set the component for the given entity.
+    /// Returns Err(StorageError::NoSuchEntity) if the given entity doesn't exist.
+    /// Otherwise, returns Ok(data), where data is previous data evicted by this operation (if any).
+    pub fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        let exists = self.entities.read().unwrap().exists(entity);
+        if exists {
+            Ok(self.set_unchecked(entity, data))
+        } else {
+            Err(NoSuchEntity::new(entity).with_operation("set").into())
+        }
+    }
+
+    /// Set the component for the given entity, at the address of its previous component if it had one.
+    /// Returns the previous data stored in self for the given entity.
+    /// Does not check if the entity exists and doesn't lock the entities; only use this if you know it exists.
+    pub fn set_unchecked(&mut self, entity: Entity, data: T) -> Option<T> {
+        self.owner.check::<T>("set");
+        let _span = profile::record::<T>(Access::Set);
+        let index = entity
+            .index
+            .checked_sub(self.base)
+            .expect("entity index below the index base of the storage's entities");
+        self.grow_to(self.base + index + 1);
+        trace::component::<T>("set", entity);
+        let slot = self.slot_mut(entity).unwrap();
+        if slot.generation == entity.generation {
+            slot.component.replace(data)
+        } else {
+            slot.generation = entity.generation;
+            slot.component = Some(data);
+            None
+        }
+    }
+
+    /// Remove the component of the given entity after it was despawned.
+    /// Returns the data associated with the given entity in self, if any.
+    /// Components set for another entity at the same index are left untouched.
+    pub fn remove_for_despawn(&mut self, entity: Entity) -> Option<T> {
+        self.owner.check::<T>("remove_for_despawn");
+        let _span = profile::record::<T>(Access::Remove);
+        let slot = self.slot_mut(entity)?;
+        if slot.generation == entity.generation {
+            let removed = slot.component.take();
+            if removed.is_some() {
+                trace::component::<T>("remove", entity);
+            }
+            removed
+        } else {
+            None
+        }
+    }
+
+    /// Remove the component for the given entity.
+    /// Returns the previous data associated with the given entity in self.
+    pub fn remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        let exists = self.entities.read().unwrap().exists(entity);
+        if exists {
+            Ok(self.remove_for_despawn(entity))
+        } else {
+            Err(NoSuchEntity::new(entity).with_operation("remove").into())
+        }
+    }
+
+    /// Only allow mutating the components of self from the given thread, or from any thread for None, with the
+    /// `thread_checks` feature; storages are bound to the thread that created them. Does nothing without it.
+    pub fn bind_to_thread(&mut self, thread: Option<ThreadId>) {
+        self.owner = OwnerThread::new(thread);
+    }
+
+    /// The memory used by self; see `MemoryUsage`.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let slots = self.slots();
+        MemoryUsage {
+            components: self
+                .pages
+                .iter()
+                .flatten()
+                .flat_map(|page| page.iter())
+                .filter(|slot| slot.component.is_some())
+                .count(),
+            slots,
+            bytes: slots * std::mem::size_of::<Slot<T>>()
+                + self.pages.capacity() * std::mem::size_of::<Option<Page<T>>>(),
+        }
+    }
+
+    /// Remove the data stored in self for all entities, keeping the pages allocated.
+    pub fn clear(&mut self) {
+        self.owner.check::<T>("clear");
+        for slot in self
+            .pages
+            .iter_mut()
+            .flatten()
+            .flat_map(|page| page.iter_mut())
+        {
+            slot.component = None;
+        }
+    }
+
+    /// Remove the components of all alive entities from self, leaving the entities themselves untouched.
+    /// Returns the number of removed components.
+    pub fn clear_checked(&mut self) -> usize {
+        let alive = self.entities.read().unwrap().iter_snapshot();
+        alive
+            .filter(|entity| self.remove_for_despawn(*entity).is_some())
+            .count()
+    }
+
+    /// Rewrite the entities stored in all components of self; see `MapEntities`.
+    pub fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity)
+    where
+        T: MapEntities,
+    {
+        self.owner.check::<T>("map_entities");
+        for component in self
+            .pages
+            .iter_mut()
+            .flatten()
+            .flat_map(|page| page.iter_mut())
+            .filter_map(|slot| slot.component.as_mut())
+        {
+            component.map_entities(mapper);
+        }
+    }
+
+    /// Check that self uses the given entities and doesn't hold components for unallocated indices.
+    /// `storage` is the name of this storage used for error reporting.
+    pub fn check_integrity(
+        &self,
+        entities: &Arc<RwLock<Entities>>,
+        storage: &'static str,
+    ) -> Result<(), IntegrityError> {
+        if !Arc::ptr_eq(&self.entities, entities) {
+            return Err(IntegrityError::ForeignEntities { storage });
+        }
+        let highest_index = self
+            .pages
+            .iter()
+            .enumerate()
+            .filter_map(|(page, slots)| Some((page, slots.as_ref()?)))
+            .flat_map(|(page, slots)| {
+                slots
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, slot)| slot.component.is_some())
+                    .map(move |(slot, _)| page * PAGE_SLOTS + slot)
+            })
+            .last()
+            .map(|index| self.base + index as u32);
+        match highest_index {
+            Some(index) if index >= entities.read().unwrap().index_end() => {
+                Err(IntegrityError::UnallocatedIndex { storage, index })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Copy the data stored in self into a new StableStorage<T> that uses the given entities.
+    /// The components of the copy are stored at new addresses.
+    pub fn clone_with_entities(&self, entities: Arc<RwLock<Entities>>) -> Self
+    where
+        T: Clone,
+    {
+        Self {
+            pages: self.pages.clone(),
+            base: self.base,
+            entities,
+            owner: self.owner,
+        }
+    }
+}
+
+/// Clones share the same `Entities` as the original and store their components at new addresses.
+impl<T: Clone> Clone for StableStorage<T> {
+    fn clone(&self) -> Self {
+        self.clone_with_entities(Arc::clone(&self.entities))
+    }
+}
+
+/// Storages are equal if the same alive entities have equal components in them.
+impl<T: PartialEq> PartialEq for StableStorage<T> {
+    fn eq(&self, other: &Self) -> bool {
+        let left = self.entities.read().unwrap();
+        if Arc::ptr_eq(&self.entities, &other.entities) {
+            return self
+                .alive_components(&left)
+                .eq(other.alive_components(&left));
+        }
+        let right = other.entities.read().unwrap();
+        self.alive_components(&left)
+            .eq(other.alive_components(&right))
+    }
+}
+
+impl<T: Eq> Eq for StableStorage<T> {}
+
+/// Shows the components of all alive entities by entity.
+impl<T: Debug> Debug for StableStorage<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.entities.try_read() {
+            Ok(entities) => f
+                .debug_map()
+                .entries(self.alive_components(&entities))
+                .finish(),
+            Err(_) => write!(f, "StableStorage {{ <entities locked> }}"),
+        }
+    }
+}
+
+impl<T> Storage<T> for StableStorage<T> {
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.get(entity)
+    }
+
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_mut(entity)
+    }
+
+    fn get_unchecked(&self, entity: Entity) -> Option<&T> {
+        self.get_unchecked(entity)
+    }
+
+    fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_mut_unchecked(entity)
+    }
+}
+
+impl<T> ModelStorage<T> for StableStorage<T> {
+    fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        self.set(entity, data)
+    }
+
+    fn remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        self.remove(entity)
+    }
+
+    fn remove_for_despawn(&mut self, entity: Entity) -> Option<T> {
+        self.remove_for_despawn(entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn components_keep_their_address() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(2)));
+        let mut storage = StableStorage::<u64>::new(Arc::clone(&entities), 2);
+        let first = entities.write().unwrap().spawn();
+        storage.set(first, 1)?;
+        let pointer = storage.get_ptr(first).unwrap();
+
+        // Spawning far beyond the first page allocates new pages without moving the old ones.
+        let later = (0..1000)
+            .map(|_| entities.write().unwrap().spawn())
+            .collect::<Vec<_>>();
+        for (value, entity) in later.iter().enumerate() {
+            storage.set(*entity, value as u64)?;
+        }
+        assert_eq!(storage.set(first, 2)?, Some(1));
+        assert_eq!(storage.get_ptr(first), Some(pointer));
+        assert_eq!(unsafe { *pointer.as_ptr() }, 2);
+        assert_eq!(storage.slots(), 4 * PAGE_SLOTS);
+
+        entities.write().unwrap().despawn(first)?;
+        assert_eq!(storage.remove_for_despawn(first), Some(2));
+        let reused = entities.write().unwrap().spawn();
+        assert_eq!(storage.get(reused), None);
+        storage.set(reused, 3)?;
+        assert_eq!(storage.check_integrity(&entities, "bodies"), Ok(()));
+        assert_eq!(storage.clone(), storage);
+        Ok(())
+    }
+}
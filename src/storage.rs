@@ -0,0 +1,71 @@
+use crate::{BitSet, Entity};
+
+/// The kinds of storages that can be used for components of a World.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum StorageKind {
+    /// `VecStorage<T>`
+    Vec,
+    /// `MapStorage<T>`
+    Map,
+    /// `DoubleBuffered<T>`
+    DoubleBuffered,
+    /// `IndexedStorage<T>`
+    Indexed,
+    /// `MmapStorage<T>`
+    Mmap,
+    /// `StableStorage<T>`
+    Stable,
+    /// `DynamicStorage<T>`
+    Dynamic,
+}
+
+impl StorageKind {
+    /// A short name for the storage kind.
+    pub fn name(self) -> &'static str {
+        match self {
+            StorageKind::Vec => "vec",
+            StorageKind::Map => "map",
+            StorageKind::DoubleBuffered => "double",
+            StorageKind::Indexed => "indexed",
+            StorageKind::Mmap => "mmap",
+            StorageKind::Stable => "stable",
+            StorageKind::Dynamic => "dynamic",
+        }
+    }
+}
+
+/// Common interface of the storage types, used by generated code such as `query!`.
+pub trait Storage<T> {
+    /// Get a reference to the component associated with the given entity, if any.
+    fn get(&self, entity: Entity) -> Option<&T>;
+
+    /// Get a mutable reference to the component associated with the given entity, if any.
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T>;
+
+    /// Get a reference to the component associated with the given entity, if any.
+    /// Does not check if the entity exists; only use this if you know it exists.
+    fn get_unchecked(&self, entity: Entity) -> Option<&T>;
+
+    /// Get a mutable reference to the component associated with the given entity, if any.
+    /// Does not check if the entity exists; only use this if you know it exists.
+    fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T>;
+
+    /// Replace the contents of the given bit set with the indices of the alive entities that have a component
+    /// in self and return true, if self can list them without visiting every alive entity, e.g. a `MapStorage`.
+    /// Queries are then driven by the storage with the fewest components instead of all alive entities.
+    /// Returns false and leaves the bit set untouched by default.
+    fn sparse_presence_into(&self, _bits: &mut BitSet) -> bool {
+        false
+    }
+}
+
+/// A split borrow of the storages of a World that hands out every storage at most once.
+/// Implemented for the `<World>Storages` struct generated by the `#[world]` macro and used by `query!`.
+pub trait TakeStorage<'a, T> {
+    /// The type of the storage holding components of type `T`.
+    type Storage: Storage<T> + 'a;
+
+    /// Take the storage for components of type `T`.
+    /// Returns None if the storage has already been taken.
+    fn take_storage(&mut self) -> Option<&'a mut Self::Storage>;
+}
@@ -0,0 +1,71 @@
+use crate::entity::Entity;
+use crate::lock::WouldBlock;
+use crate::no_such_entity::NoSuchEntity;
+use thiserror::Error;
+
+/// Error returned by operations on storages like `VecStorage::set()`.
+/// Any `NoSuchEntity` converts into a `StorageError`, so `?` keeps working in functions returning it.
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StorageError {
+    /// The entity doesn't exist.
+    #[error(transparent)]
+    NoSuchEntity(#[from] NoSuchEntity),
+    /// The storage can't hold any more components.
+    #[error("storage capacity of {capacity} components exceeded")]
+    CapacityExceeded { capacity: usize },
+    /// The entity belongs to another World than the storage.
+    #[error("entity {entity} belongs to another world")]
+    WrongWorld { entity: Entity },
+    /// The storage is disabled and doesn't accept changes.
+    #[error("storage is disabled")]
+    Disabled,
+    /// The entities are locked by another thread; returned by non-blocking operations like `try_set()`.
+    #[error(transparent)]
+    WouldBlock(#[from] WouldBlock),
+}
+
+impl StorageError {
+    /// Record the name of the storage involved; only kept for `StorageError::NoSuchEntity`.
+    pub fn in_storage(self, storage: &'static str) -> Self {
+        match self {
+            StorageError::NoSuchEntity(error) => {
+                StorageError::NoSuchEntity(error.in_storage(storage))
+            }
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_from_no_such_entity() {
+        fn despawned(entity: Entity) -> Result<(), StorageError> {
+            Err(NoSuchEntity::new(entity).with_operation("set"))?
+        }
+
+        let entity = Entity {
+            index: 2,
+            generation: 0,
+        };
+        let error = despawned(entity).unwrap_err().in_storage("names");
+        assert_eq!(
+            error,
+            StorageError::NoSuchEntity(
+                NoSuchEntity::new(entity)
+                    .with_operation("set")
+                    .in_storage("names")
+            )
+        );
+        assert_eq!(
+            error.to_string(),
+            "No such entity 2:0 in `set` on storage `names`"
+        );
+        assert_eq!(
+            StorageError::Disabled.in_storage("names"),
+            StorageError::Disabled
+        );
+    }
+}
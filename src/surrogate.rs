@@ -0,0 +1,52 @@
+//! Serialization of components through surrogates, for components that can't implement `Serialize`
+//! and `Deserialize` themselves, e.g. because they hold GPU handles or other runtime resources.
+//!
+//! Mark the storage field of such a component with `#[component(serialize_with = MySurrogate)]`, where
+//! `MySurrogate` implements `Surrogate<T>`; the component enum and the templates of the World then
+//! serialize the component as its surrogate, so whole-World snapshots like `changes_since()` work with
+//! a mix of plain-data and runtime-resource components. The functions of this module are called by
+//! the generated serde attributes.
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A serializable stand-in for components of type `T`, e.g. the path of a texture for a texture handle.
+pub trait Surrogate<T>: Serialize + for<'de> Deserialize<'de> {
+    /// Create the surrogate of the given component.
+    fn from_component(component: &T) -> Self;
+
+    /// Turn the surrogate back into a component, e.g. by loading the resource it describes.
+    fn into_component(self) -> T;
+}
+
+/// Serialize the given component as its surrogate `S`.
+pub fn serialize<S: Surrogate<T>, T, Z: Serializer>(
+    component: &T,
+    serializer: Z,
+) -> Result<Z::Ok, Z::Error> {
+    S::from_component(component).serialize(serializer)
+}
+
+/// Deserialize a component from its surrogate `S`.
+pub fn deserialize<'de, S: Surrogate<T>, T, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    S::deserialize(deserializer).map(S::into_component)
+}
+
+/// Serialize the given optional component as its optional surrogate `S`, e.g. for template fields.
+pub fn serialize_option<S: Surrogate<T>, T, Z: Serializer>(
+    component: &Option<T>,
+    serializer: Z,
+) -> Result<Z::Ok, Z::Error> {
+    component
+        .as_ref()
+        .map(S::from_component)
+        .serialize(serializer)
+}
+
+/// Deserialize an optional component from its optional surrogate `S`, e.g. for template fields.
+pub fn deserialize_option<'de, S: Surrogate<T>, T, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<T>, D::Error> {
+    Option::<S>::deserialize(deserializer).map(|surrogate| surrogate.map(S::into_component))
+}
@@ -0,0 +1,215 @@
+use crate::register::RegisterError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Type whose values can be layered on top of a base value; implemented for all templates generated by
+/// `genesis`, where every component set in `self` overrides the one of `base`.
+pub trait Overlay {
+    /// Combine self with `base`, preferring the values of self.
+    fn overlay(self, base: Self) -> Self;
+}
+
+/// Error returned when resolving or spawning a template from a `TemplateLibrary` fails.
+#[derive(Error, Debug, Clone, Eq, PartialEq)]
+pub enum TemplateError {
+    /// The library has no template with the given name, e.g. because a base template is missing.
+    #[error("unknown template `{0}`")]
+    UnknownTemplate(String),
+    /// The given template is its own base, directly or through other templates.
+    #[error("template `{0}` inherits from itself")]
+    Cycle(String),
+    /// Registering the resolved template failed.
+    #[error(transparent)]
+    Register(#[from] RegisterError),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LibraryEntry<T> {
+    base: Option<String>,
+    template: T,
+}
+
+/// A collection of named templates that can inherit from each other, e.g. an `elite_goblin` template
+/// that only overrides the health and loot of a `goblin` base template. Templates are resolved by
+/// layering them over their bases via `Overlay`, so changes to a base apply to all derived templates.
+///
+/// Worlds generated by `genesis` whose template is `Clone` get `spawn_from_library(library, name)`,
+/// which resolves the named template and spawns an entity from it.
+/// Libraries can be serialized, e.g. to load them from data files, if their templates can.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
+pub struct TemplateLibrary<T> {
+    entries: HashMap<String, LibraryEntry<T>>,
+}
+
+impl<T> Default for TemplateLibrary<T> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Overlay + Clone> TemplateLibrary<T> {
+    /// Create an empty library.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a template without a base under the given name, replacing any template of the same name.
+    pub fn insert(&mut self, name: impl Into<String>, template: T) {
+        self.entries.insert(
+            name.into(),
+            LibraryEntry {
+                base: None,
+                template,
+            },
+        );
+    }
+
+    /// Add a template inheriting from the template named `base` under the given name, replacing any
+    /// template of the same name. `overrides` holds the components that differ from the base.
+    /// The base doesn't have to be added yet; it is looked up when resolving.
+    pub fn insert_derived(
+        &mut self,
+        name: impl Into<String>,
+        base: impl Into<String>,
+        overrides: T,
+    ) {
+        self.entries.insert(
+            name.into(),
+            LibraryEntry {
+                base: Some(base.into()),
+                template: overrides,
+            },
+        );
+    }
+
+    /// Remove the template with the given name, returning its own components without those of its bases.
+    /// Templates deriving from it fail to resolve until a template of that name is added again.
+    pub fn remove(&mut self, name: &str) -> Option<T> {
+        self.entries.remove(name).map(|entry| entry.template)
+    }
+
+    /// Check if the library has a template with the given name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// The name of the base of the given template, if the template exists and has one.
+    pub fn base(&self, name: &str) -> Option<&str> {
+        self.entries.get(name)?.base.as_deref()
+    }
+
+    /// The number of templates in the library.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if the library is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Resolve the template with the given name by layering it over its bases, the most derived template
+    /// taking precedence.
+    pub fn resolve(&self, name: &str) -> Result<T, TemplateError> {
+        let mut chain = vec![];
+        let mut current = name;
+        loop {
+            let entry = self
+                .entries
+                .get(current)
+                .ok_or_else(|| TemplateError::UnknownTemplate(current.to_owned()))?;
+            chain.push(&entry.template);
+            match &entry.base {
+                Some(base) if chain.len() > self.entries.len() => {
+                    return Err(TemplateError::Cycle(base.clone()))
+                }
+                Some(base) => current = base,
+                None => break,
+            }
+        }
+
+        let mut layers = chain.into_iter().rev().cloned();
+        let root = layers.next().unwrap();
+        Ok(layers.fold(root, |base, template| template.overlay(base)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Default, Eq, PartialEq)]
+    struct Stats {
+        health: Option<u32>,
+        speed: Option<u32>,
+        loot: Option<&'static str>,
+    }
+
+    impl Overlay for Stats {
+        fn overlay(self, base: Self) -> Self {
+            Self {
+                health: self.health.or(base.health),
+                speed: self.speed.or(base.speed),
+                loot: self.loot.or(base.loot),
+            }
+        }
+    }
+
+    #[test]
+    fn resolves_inherited_templates() {
+        let mut library = TemplateLibrary::new();
+        library.insert_derived(
+            "goblin_king",
+            "elite_goblin",
+            Stats {
+                loot: Some("crown"),
+                ..Stats::default()
+            },
+        );
+        library.insert(
+            "goblin",
+            Stats {
+                health: Some(10),
+                speed: Some(3),
+                loot: Some("coins"),
+            },
+        );
+        library.insert_derived(
+            "elite_goblin",
+            "goblin",
+            Stats {
+                health: Some(30),
+                ..Stats::default()
+            },
+        );
+
+        assert_eq!(
+            library.resolve("goblin_king"),
+            Ok(Stats {
+                health: Some(30),
+                speed: Some(3),
+                loot: Some("crown"),
+            })
+        );
+        assert_eq!(library.base("goblin_king"), Some("elite_goblin"));
+        assert_eq!(
+            library.resolve("troll"),
+            Err(TemplateError::UnknownTemplate(String::from("troll")))
+        );
+
+        library.insert_derived("goblin", "goblin_king", Stats::default());
+        assert!(matches!(
+            library.resolve("elite_goblin"),
+            Err(TemplateError::Cycle(_))
+        ));
+        library.remove("goblin");
+        assert_eq!(
+            library.resolve("elite_goblin"),
+            Err(TemplateError::UnknownTemplate(String::from("goblin")))
+        );
+    }
+}
@@ -0,0 +1,168 @@
+//! Helpers for unit tests of systems: `TestWorldBuilder` sets up a World with deterministic entities
+//! and components, and `assert_has()`, `assert_lacks()` and `assert_component()` check the components
+//! of an entity with readable panic messages.
+//!
+//! # Example
+//! ```ignore
+//! let (mut world, entities) = TestWorldBuilder::new(World::new(8))
+//!     .entities(3)
+//!     .with(|i, _| Some(Position { position: (i as u32, 0) }))
+//!     .build();
+//! move_system(&mut world);
+//! assert_component(&world, entities[1], &Position { position: (1, 1) });
+//! ```
+
+use crate::entity::Entity;
+use crate::has_component::{HasComponent, HasEntities};
+use crate::register::Register;
+use crate::storage::Storage;
+use std::any::type_name;
+use std::fmt::Debug;
+
+/// Builder setting up a World for tests with entities at fixed indices and generations, so that
+/// entities and their debug output are the same in every run regardless of how the World was created.
+///
+/// Entities are placed directly in the entities of the World, without the auto-default components
+/// that `spawn()` adds.
+pub struct TestWorldBuilder<W> {
+    world: W,
+    entities: Vec<Entity>,
+}
+
+impl<W: HasEntities> TestWorldBuilder<W> {
+    /// Start building on the given World, usually a freshly created one.
+    pub fn new(world: W) -> Self {
+        Self {
+            world,
+            entities: vec![],
+        }
+    }
+
+    /// Add `n` entities with generation 0 at the indices following the highest alive index, or starting
+    /// at the index base if no entities are alive.
+    pub fn entities(mut self, n: u32) -> Self {
+        let entities = self.world.entities().read().unwrap();
+        let start = entities
+            .iter()
+            .map(|entity| entity.index + 1)
+            .max()
+            .unwrap_or_else(|| entities.index_base());
+        drop(entities);
+        for index in start..start + n {
+            self = self.entity(index, 0);
+        }
+        self
+    }
+
+    /// Add an entity with the given index and generation.
+    ///
+    /// # Panics
+    /// Panics if an entity with the given index is already alive, or if the index is below the index base
+    /// of the entities.
+    #[track_caller]
+    pub fn entity(mut self, index: u32, generation: u32) -> Self {
+        let entity = Entity { index, generation };
+        let mut entities = self.world.entities().write().unwrap();
+        assert!(
+            index >= entities.index_base(),
+            "entity {} is below the index base {}",
+            entity,
+            entities.index_base()
+        );
+        assert!(
+            entities.current_entity_at(index).is_none(),
+            "an entity with index {} is already alive",
+            index
+        );
+        let mut alive = entities.iter().collect::<Vec<_>>();
+        alive.push(entity);
+        entities.mirror_alive(&alive);
+        drop(entities);
+        self.entities.push(entity);
+        self
+    }
+
+    /// Register the component returned by `component` for every entity added so far; `component` gets
+    /// the position of the entity in the order the entities were added and the entity itself.
+    ///
+    /// # Panics
+    /// Panics if registering a component fails, e.g. because a required component is missing.
+    #[track_caller]
+    pub fn with<T>(mut self, mut component: impl FnMut(usize, Entity) -> Option<T>) -> Self
+    where
+        W: Register<T>,
+        W::Error: Debug,
+    {
+        for (i, entity) in self.entities.iter().copied().enumerate() {
+            if let Some(component) = component(i, entity) {
+                if let Err(error) = self.world.register(entity, component) {
+                    panic!(
+                        "failed to register {} for entity {}: {:?}",
+                        type_name::<T>(),
+                        entity,
+                        error
+                    );
+                }
+            }
+        }
+        self
+    }
+
+    /// The entities added so far, in the order they were added.
+    pub fn added(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    /// Finish building, returning the World and the added entities in the order they were added.
+    pub fn build(self) -> (W, Vec<Entity>) {
+        (self.world, self.entities)
+    }
+}
+
+/// Assert that the given entity has a component of type T.
+#[track_caller]
+pub fn assert_has<T, W: HasComponent<T>>(world: &W, entity: Entity) {
+    assert!(
+        world.storage().get(entity).is_some(),
+        "expected entity {} to have a {}",
+        entity,
+        type_name::<T>()
+    );
+}
+
+/// Assert that the given entity has no component of type T.
+#[track_caller]
+pub fn assert_lacks<T: Debug, W: HasComponent<T>>(world: &W, entity: Entity) {
+    if let Some(component) = world.storage().get(entity) {
+        panic!(
+            "expected entity {} to have no {}, but it has {:?}",
+            entity,
+            type_name::<T>(),
+            component
+        );
+    }
+}
+
+/// Assert that the given entity has a component of type T equal to `expected`.
+#[track_caller]
+pub fn assert_component<T: Debug + PartialEq, W: HasComponent<T>>(
+    world: &W,
+    entity: Entity,
+    expected: &T,
+) {
+    match world.storage().get(entity) {
+        Some(component) => assert_eq!(
+            component,
+            expected,
+            "unexpected {} of entity {}",
+            type_name::<T>(),
+            entity
+        ),
+        None => panic!(
+            "expected entity {} to have {:?}, but it has no {}",
+            entity,
+            expected,
+            type_name::<T>()
+        ),
+    }
+}
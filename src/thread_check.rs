@@ -0,0 +1,96 @@
+use std::thread::ThreadId;
+
+/// The thread a storage or the entities may be mutated from with the `thread_checks` feature; see
+/// `bind_to_thread()` of the storages and entities. They are bound to the thread that created them, i.e. the
+/// one creating their World.
+/// Without the feature, this is empty and checks are free.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) struct OwnerThread {
+    #[cfg(feature = "thread_checks")]
+    thread: Option<ThreadId>,
+}
+
+/// The current thread, e.g. for storages deserialized from an archive.
+impl Default for OwnerThread {
+    fn default() -> Self {
+        Self::current()
+    }
+}
+
+impl OwnerThread {
+    /// The current thread.
+    pub(crate) fn current() -> Self {
+        Self::new(Some(std::thread::current().id()))
+    }
+
+    /// The given thread, or any thread for None.
+    #[cfg_attr(not(feature = "thread_checks"), allow(unused_variables))]
+    pub(crate) fn new(thread: Option<ThreadId>) -> Self {
+        Self {
+            #[cfg(feature = "thread_checks")]
+            thread,
+        }
+    }
+
+    /// Panic if the current thread isn't the owner thread, naming the mutating operation on the storage
+    /// of components of type `T`.
+    #[cfg(feature = "thread_checks")]
+    #[track_caller]
+    pub(crate) fn check<T>(self, operation: &'static str) {
+        self.check_on(
+            operation,
+            format_args!("the storage of `{}`", std::any::type_name::<T>()),
+        );
+    }
+
+    /// Panic if the current thread isn't the owner thread, naming the mutating operation on the entities.
+    #[cfg(feature = "thread_checks")]
+    #[track_caller]
+    pub(crate) fn check_entities(self, operation: &'static str) {
+        self.check_on(operation, format_args!("the entities"));
+    }
+
+    #[cfg(feature = "thread_checks")]
+    #[track_caller]
+    fn check_on(self, operation: &'static str, target: std::fmt::Arguments<'_>) {
+        let current = std::thread::current().id();
+        if let Some(owner) = self.thread.filter(|owner| *owner != current) {
+            panic!(
+                "`{}` on {} from thread {:?}, which is bound to thread {:?}; see `bind_to_thread()`",
+                operation,
+                target,
+                current,
+                owner
+            );
+        }
+    }
+
+    /// Check that the current thread may mutate a storage; does nothing without the `thread_checks` feature.
+    #[cfg(not(feature = "thread_checks"))]
+    #[inline(always)]
+    #[allow(clippy::extra_unused_type_parameters)]
+    pub(crate) fn check<T>(self, _operation: &'static str) {}
+
+    /// Check that the current thread may mutate the entities; does nothing without the `thread_checks` feature.
+    #[cfg(not(feature = "thread_checks"))]
+    #[inline(always)]
+    pub(crate) fn check_entities(self, _operation: &'static str) {}
+}
+
+#[cfg(all(test, feature = "thread_checks"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_allows_owner_thread() {
+        let owner = OwnerThread::current();
+        owner.check::<u32>("set");
+        let other =
+            std::thread::spawn(move || std::panic::catch_unwind(|| owner.check::<u32>("set")));
+        assert!(other.join().unwrap().is_err());
+        let unbound = OwnerThread::new(None);
+        std::thread::spawn(move || unbound.check::<u32>("set"))
+            .join()
+            .unwrap();
+    }
+}
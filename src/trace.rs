@@ -0,0 +1,55 @@
+use crate::entity::Entity;
+
+/// The target of structural change events, e.g. for filtering with `genesis::structure=debug`.
+#[cfg(feature = "tracing")]
+const TARGET: &str = "genesis::structure";
+
+/// Emit a debug event for an entity being spawned or despawned.
+#[cfg(feature = "tracing")]
+pub(crate) fn entity(operation: &'static str, entity: Entity) {
+    tracing::debug!(target: TARGET, entity = %entity, operation);
+}
+
+/// Emit a debug event for a component of type `T` being set or removed.
+#[cfg(feature = "tracing")]
+pub(crate) fn component<T>(operation: &'static str, entity: Entity) {
+    tracing::debug!(
+        target: TARGET,
+        entity = %entity,
+        component = std::any::type_name::<T>(),
+        operation
+    );
+}
+
+/// Enter a debug span for a query over the given component types, e.g. `Position, Velocity`, until the
+/// returned guard is dropped. Called by the code generated by `query!`.
+#[cfg(feature = "tracing")]
+pub fn query(components: &'static str) -> QuerySpan {
+    tracing::debug_span!(target: TARGET, "query", components).entered()
+}
+
+/// The guard of the span of a query; see `query()`.
+#[cfg(feature = "tracing")]
+pub type QuerySpan = tracing::span::EnteredSpan;
+
+/// Emit a debug event for an entity being spawned or despawned; does nothing without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+pub(crate) fn entity(_operation: &'static str, _entity: Entity) {}
+
+/// Emit a debug event for a component being set or removed; does nothing without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+#[allow(clippy::extra_unused_type_parameters)]
+pub(crate) fn component<T>(_operation: &'static str, _entity: Entity) {}
+
+/// Enter a span for a query; does nothing without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+#[inline(always)]
+pub fn query(_components: &'static str) -> QuerySpan {
+    QuerySpan
+}
+
+/// The guard of the span of a query, which is empty without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+pub struct QuerySpan;
@@ -0,0 +1,229 @@
+use serde::de::{Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{Formatter, Write};
+
+/// A value of any self-describing format, e.g. JSON or RON, kept without knowing its type.
+/// Structs and enums are kept in the form the format describes them in, e.g. as maps.
+#[derive(Debug, Clone)]
+pub enum UnknownValue {
+    /// A unit or missing value, e.g. JSON `null`.
+    Unit,
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    Seq(Vec<UnknownValue>),
+    /// The entries of a map, in the order they were deserialized.
+    Map(Vec<(UnknownValue, UnknownValue)>),
+}
+
+/// Floats are compared by their bits, so values compare equal exactly if they serialize the same.
+impl PartialEq for UnknownValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (UnknownValue::Unit, UnknownValue::Unit) => true,
+            (UnknownValue::Bool(a), UnknownValue::Bool(b)) => a == b,
+            (UnknownValue::I64(a), UnknownValue::I64(b)) => a == b,
+            (UnknownValue::U64(a), UnknownValue::U64(b)) => a == b,
+            (UnknownValue::F64(a), UnknownValue::F64(b)) => a.to_bits() == b.to_bits(),
+            (UnknownValue::String(a), UnknownValue::String(b)) => a == b,
+            (UnknownValue::Bytes(a), UnknownValue::Bytes(b)) => a == b,
+            (UnknownValue::Seq(a), UnknownValue::Seq(b)) => a == b,
+            (UnknownValue::Map(a), UnknownValue::Map(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for UnknownValue {}
+
+impl Serialize for UnknownValue {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            UnknownValue::Unit => serializer.serialize_unit(),
+            UnknownValue::Bool(value) => serializer.serialize_bool(*value),
+            UnknownValue::I64(value) => serializer.serialize_i64(*value),
+            UnknownValue::U64(value) => serializer.serialize_u64(*value),
+            UnknownValue::F64(value) => serializer.serialize_f64(*value),
+            UnknownValue::String(value) => serializer.serialize_str(value),
+            UnknownValue::Bytes(value) => serializer.serialize_bytes(value),
+            UnknownValue::Seq(values) => {
+                let mut seq = serializer.serialize_seq(Some(values.len()))?;
+                for value in values {
+                    seq.serialize_element(value)?;
+                }
+                seq.end()
+            }
+            UnknownValue::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+struct UnknownValueVisitor;
+
+impl<'de> Visitor<'de> for UnknownValueVisitor {
+    type Value = UnknownValue;
+
+    fn expecting(&self, formatter: &mut Formatter<'_>) -> std::fmt::Result {
+        formatter.write_str("any value")
+    }
+
+    fn visit_bool<E>(self, value: bool) -> Result<UnknownValue, E> {
+        Ok(UnknownValue::Bool(value))
+    }
+
+    fn visit_i64<E>(self, value: i64) -> Result<UnknownValue, E> {
+        Ok(UnknownValue::I64(value))
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<UnknownValue, E> {
+        Ok(UnknownValue::U64(value))
+    }
+
+    fn visit_f64<E>(self, value: f64) -> Result<UnknownValue, E> {
+        Ok(UnknownValue::F64(value))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<UnknownValue, E> {
+        Ok(UnknownValue::String(value.to_owned()))
+    }
+
+    fn visit_string<E>(self, value: String) -> Result<UnknownValue, E> {
+        Ok(UnknownValue::String(value))
+    }
+
+    fn visit_bytes<E>(self, value: &[u8]) -> Result<UnknownValue, E> {
+        Ok(UnknownValue::Bytes(value.to_owned()))
+    }
+
+    fn visit_byte_buf<E>(self, value: Vec<u8>) -> Result<UnknownValue, E> {
+        Ok(UnknownValue::Bytes(value))
+    }
+
+    fn visit_unit<E>(self) -> Result<UnknownValue, E> {
+        Ok(UnknownValue::Unit)
+    }
+
+    fn visit_none<E>(self) -> Result<UnknownValue, E> {
+        Ok(UnknownValue::Unit)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<UnknownValue, D::Error> {
+        UnknownValue::deserialize(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<UnknownValue, D::Error> {
+        UnknownValue::deserialize(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<UnknownValue, A::Error> {
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or_default());
+        while let Some(value) = seq.next_element()? {
+            values.push(value);
+        }
+        Ok(UnknownValue::Seq(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<UnknownValue, A::Error> {
+        let mut entries = Vec::with_capacity(map.size_hint().unwrap_or_default());
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(UnknownValue::Map(entries))
+    }
+}
+
+impl<'de> Deserialize<'de> for UnknownValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(UnknownValueVisitor)
+    }
+}
+
+/// The fields of a deserialized template that don't belong to any of its components, stored in the
+/// `unknown_data` field of templates of Worlds passed the `unknown_data` flag. Content made with a newer
+/// build that has additional components, or with an older one that had components removed since,
+/// then loads instead of failing. The skipped fields are listed by `report()` and serialized again
+/// with the template, so saving the content doesn't lose them.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct UnknownData {
+    fields: BTreeMap<String, UnknownValue>,
+}
+
+impl UnknownData {
+    /// Check if no fields were skipped.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// The number of skipped fields.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// The names of the skipped fields, in alphabetical order.
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.fields.keys().map(String::as_str)
+    }
+
+    /// The value of the skipped field with the given name.
+    pub fn get(&self, field: &str) -> Option<&UnknownValue> {
+        self.fields.get(field)
+    }
+
+    /// Remove the skipped field with the given name, e.g. after migrating it to a known component.
+    pub fn remove(&mut self, field: &str) -> Option<UnknownValue> {
+        self.fields.remove(field)
+    }
+
+    /// Add the fields of `base` that self doesn't have, like `Overlay` does for components.
+    pub fn overlay(mut self, base: Self) -> Self {
+        for (field, value) in base.fields {
+            self.fields.entry(field).or_insert(value);
+        }
+        self
+    }
+
+    /// List the skipped fields, one per line; empty if no fields were skipped.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        for field in self.fields() {
+            writeln!(report, "skipped unknown component `{}`", field).unwrap();
+        }
+        report
+    }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_unknown_values() {
+        let json = serde_json::json!({
+            "armor": {"value": 3, "tags": ["heavy", null], "weight": 1.5},
+            "mana": -2,
+        });
+        let data = serde_json::from_value::<UnknownData>(json.clone()).unwrap();
+        assert_eq!(data.fields().collect::<Vec<_>>(), vec!["armor", "mana"]);
+        assert_eq!(data.get("mana"), Some(&UnknownValue::I64(-2)));
+        assert_eq!(
+            data.report(),
+            "skipped unknown component `armor`\nskipped unknown component `mana`\n"
+        );
+        assert_eq!(serde_json::to_value(&data).unwrap(), json);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::entity::Entity;
+use crate::no_such_entity::NoSuchEntity;
+use crate::Entities;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use thiserror::Error;
+
+/// Error returned when associating a UUID with an entity fails.
+#[derive(Error, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum UuidError {
+    /// The entity doesn't exist.
+    #[error(transparent)]
+    NoSuchEntity(#[from] NoSuchEntity),
+    /// The UUID already belongs to another alive entity.
+    #[error("uuid {uuid:032x} already belongs to entity {entity}")]
+    Taken { uuid: u128, entity: Entity },
+}
+
+/// A bidirectional mapping between entities and externally supplied UUIDs, e.g. the ids of a save game
+/// or a server database, which stay stable across index reuse and world reloads.
+/// UUIDs are plain `u128`s, e.g. from `uuid::Uuid::as_u128()`.
+///
+/// Worlds declared with the `uuids` option hold one in their `uuids` field and remove the UUID of
+/// every despawned entity from it. Lookups only return alive entities either way.
+#[derive(Debug)]
+pub struct Uuids {
+    by_uuid: HashMap<u128, Entity>,
+    by_entity: HashMap<Entity, u128>,
+    entities: Arc<RwLock<Entities>>,
+}
+
+impl Uuids {
+    /// Create a new, empty mapping for the given entities.
+    pub fn new(entities: Arc<RwLock<Entities>>) -> Self {
+        Self {
+            by_uuid: HashMap::new(),
+            by_entity: HashMap::new(),
+            entities,
+        }
+    }
+
+    /// Associate the given UUID with the given entity, replacing the previous UUID of the entity.
+    /// Returns the previous UUID of the entity, if any.
+    /// Returns Err(UuidError::Taken) if the UUID already belongs to another alive entity.
+    pub fn insert(&mut self, entity: Entity, uuid: u128) -> Result<Option<u128>, UuidError> {
+        let entities = self.entities.read().unwrap();
+        if !entities.exists(entity) {
+            return Err(NoSuchEntity::new(entity)
+                .with_operation("insert uuid")
+                .into());
+        }
+        match self.by_uuid.get(&uuid) {
+            Some(&owner) if owner != entity && entities.exists(owner) => {
+                return Err(UuidError::Taken {
+                    uuid,
+                    entity: owner,
+                })
+            }
+            Some(&owner) => {
+                self.by_entity.remove(&owner);
+            }
+            None => {}
+        }
+        drop(entities);
+
+        let previous = self.by_entity.insert(entity, uuid);
+        if let Some(previous) = previous {
+            self.by_uuid.remove(&previous);
+        }
+        self.by_uuid.insert(uuid, entity);
+        Ok(previous)
+    }
+
+    /// The alive entity with the given UUID, if any.
+    pub fn entity(&self, uuid: u128) -> Option<Entity> {
+        let entity = *self.by_uuid.get(&uuid)?;
+        self.entities
+            .read()
+            .unwrap()
+            .exists(entity)
+            .then_some(entity)
+    }
+
+    /// The UUID of the given entity, if it is alive and has one.
+    pub fn uuid(&self, entity: Entity) -> Option<u128> {
+        let uuid = *self.by_entity.get(&entity)?;
+        self.entities.read().unwrap().exists(entity).then_some(uuid)
+    }
+
+    /// Remove the UUID of an entity that has just been despawned.
+    /// Doesn't lock the entities.
+    pub fn remove_for_despawn(&mut self, entity: Entity) -> Option<u128> {
+        let uuid = self.by_entity.remove(&entity)?;
+        self.by_uuid.remove(&uuid);
+        Some(uuid)
+    }
+
+    /// The number of UUIDs in self, including those of entities despawned without removing them.
+    pub fn len(&self) -> usize {
+        self.by_uuid.len()
+    }
+
+    /// Check if self holds no UUIDs.
+    pub fn is_empty(&self) -> bool {
+        self.by_uuid.is_empty()
+    }
+
+    /// Iterate over all alive entities with a UUID and their UUIDs, in arbitrary order.
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, u128)> + '_ {
+        let entities = self.entities.read().unwrap();
+        let alive = self
+            .by_entity
+            .iter()
+            .filter(|(entity, _)| entities.exists(**entity))
+            .map(|(&entity, &uuid)| (entity, uuid))
+            .collect::<Vec<_>>();
+        alive.into_iter()
+    }
+
+    /// Remove all UUIDs.
+    pub fn clear(&mut self) {
+        self.by_uuid.clear();
+        self.by_entity.clear();
+    }
+
+    /// Clone the mapping, associating the clone with the given entities, e.g. those of a forked World.
+    pub fn clone_with_entities(&self, entities: Arc<RwLock<Entities>>) -> Self {
+        Self {
+            by_uuid: self.by_uuid.clone(),
+            by_entity: self.by_entity.clone(),
+            entities,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_uuids_of_alive_entities() {
+        let entities = Arc::new(RwLock::new(Entities::new(2)));
+        let mut uuids = Uuids::new(Arc::clone(&entities));
+        let first = entities.write().unwrap().spawn();
+        let second = entities.write().unwrap().spawn();
+
+        assert_eq!(uuids.insert(first, 1), Ok(None));
+        assert_eq!(
+            uuids.insert(second, 1),
+            Err(UuidError::Taken {
+                uuid: 1,
+                entity: first
+            })
+        );
+        assert_eq!(uuids.insert(first, 2), Ok(Some(1)));
+        assert_eq!(uuids.entity(1), None);
+        assert_eq!(uuids.entity(2), Some(first));
+        assert_eq!(uuids.uuid(first), Some(2));
+
+        // The UUID of an entity despawned without removing it can be taken over.
+        entities.write().unwrap().despawn(first).unwrap();
+        assert_eq!(uuids.entity(2), None);
+        assert_eq!(uuids.insert(second, 2), Ok(None));
+        assert_eq!(uuids.uuid(first), None);
+        assert_eq!(uuids.iter().collect::<Vec<_>>(), vec![(second, 2)]);
+
+        entities.write().unwrap().despawn(second).unwrap();
+        assert_eq!(uuids.remove_for_despawn(second), Some(2));
+        assert!(uuids.is_empty());
+        assert!(matches!(
+            uuids.insert(second, 3),
+            Err(UuidError::NoSuchEntity(_))
+        ));
+    }
+}
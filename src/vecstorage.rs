@@ -1,114 +1,760 @@
 use super::entity::Entity;
+use crate::allocations::{self, AllocationKind};
+use crate::bitset::BitSet;
+use crate::change_cursor::TrackChanges;
+use crate::integrity::IntegrityError;
+use crate::layout::MemoryUsage;
+use crate::lock::try_read_now;
+use crate::map_entities::MapEntities;
 use crate::no_such_entity::NoSuchEntity;
+use crate::profile::{self, Access};
+use crate::storage_error::StorageError;
+use crate::thread_check::OwnerThread;
+use crate::trace;
 use crate::Entities;
-use std::fmt::Debug;
+use crate::MapStorage;
+use crate::ModelStorage;
+use crate::Storage;
+use allocator_api2::alloc::{Allocator, Global};
+use allocator_api2::vec::Vec;
+use std::fmt::{Debug, Formatter};
 use std::sync::{Arc, RwLock};
+use std::thread::ThreadId;
 
 /// A storage type that stores components in a contiguous Vec<T>.
-#[derive(Debug)]
-pub struct VecStorage<T> {
-    vec: Vec<Option<T>>,
+/// Cloning a VecStorage<T> clones its data; the clone shares the same `Entities` as the original.
+/// The memory of the storage comes from the allocator `A`, e.g. an arena that is freed at once between levels.
+/// Storages of Worlds generated by `#[world]` use the global allocator.
+/// With the `rkyv` feature enabled, storages can be archived without their entities; see `attach_entities()`.
+#[derive(Clone)]
+#[cfg_attr(
+    feature = "rkyv",
+    derive(rkyv::Archive, rkyv::Serialize, rkyv::Deserialize),
+    archive(check_bytes)
+)]
+pub struct VecStorage<T, A: Allocator + Clone = Global> {
+    #[cfg_attr(feature = "rkyv", with(crate::archive::AsVec))]
+    vec: Vec<Option<T>, A>,
+    /// The generation of the entity each component in `vec` was set for.
+    #[cfg_attr(feature = "rkyv", with(crate::archive::AsVec))]
+    generations: Vec<u32, A>,
+    /// The tick each component in `vec` was last modified at; see `set_tick()`.
+    #[cfg_attr(feature = "rkyv", with(crate::archive::AsVec))]
+    ticks: Vec<u32, A>,
+    /// The current tick, recorded for every modified component.
+    tick: u32,
+    /// The index base of the entities; `vec[0]` holds the component of the entity at this index.
+    base: u32,
+    #[cfg_attr(feature = "rkyv", with(crate::archive::Detached))]
     entities: Arc<RwLock<Entities>>,
+    /// The thread allowed to mutate the components; see `bind_to_thread()`.
+    #[cfg_attr(feature = "rkyv", with(rkyv::with::Skip))]
+    owner: OwnerThread,
 }
 
 impl<T> VecStorage<T> {
     /// Create a new VecStorage<T> with the specified initial capacity.
     pub fn new(entities: Arc<RwLock<Entities>>, capacity: u32) -> Self {
-        let mut vec = vec![];
+        Self::new_in(entities, capacity, Global)
+    }
+}
+
+impl<T, A: Allocator + Clone> VecStorage<T, A> {
+    /// Create a new VecStorage<T> with the specified initial capacity that allocates from the given allocator.
+    pub fn new_in(entities: Arc<RwLock<Entities>>, capacity: u32, allocator: A) -> Self {
+        let base = entities.read().unwrap().index_base();
+        Self::new_in_with_base(entities, base, capacity, allocator)
+    }
+
+    /// Like `new_in()`, for callers that already hold the lock of the given entities and pass their index
+    /// base, since locking them again could deadlock if a writer is waiting.
+    fn new_in_with_base(
+        entities: Arc<RwLock<Entities>>,
+        base: u32,
+        capacity: u32,
+        allocator: A,
+    ) -> Self {
+        let mut vec = Vec::new_in(allocator.clone());
         vec.resize_with(capacity as usize, Default::default);
-        Self { vec, entities }
+        let mut generations = Vec::new_in(allocator.clone());
+        generations.resize(capacity as usize, 0);
+        let mut ticks = Vec::new_in(allocator);
+        ticks.resize(capacity as usize, 0);
+        Self {
+            vec,
+            generations,
+            ticks,
+            tick: 0,
+            base,
+            entities,
+            owner: OwnerThread::current(),
+        }
+    }
+
+    /// The position in `vec` of the given entity, or None if its index is below the index base.
+    fn slot(&self, entity: Entity) -> Option<usize> {
+        entity
+            .index
+            .checked_sub(self.base)
+            .map(|slot| slot as usize)
+    }
+
+    /// The slot of the given entity if the component stored in it, if any, was set for it rather than
+    /// for an earlier entity at the same index.
+    fn current_slot(&self, entity: Entity) -> Option<usize> {
+        let slot = self.slot(entity)?;
+        (self.generations.get(slot) == Some(&entity.generation)).then_some(slot)
+    }
+
+    /// Iterate over the alive entities of the given entities that have a component in self, by index.
+    fn alive_components<'a>(
+        &'a self,
+        entities: &'a Entities,
+    ) -> impl Iterator<Item = (Entity, &'a T)> + 'a {
+        entities.iter().filter_map(move |entity| {
+            let slot = self.current_slot(entity)?;
+            Some((entity, self.vec.get(slot)?.as_ref()?))
+        })
     }
 
     /// Get a reference to the component associated with the given entity in self, if any.
     pub fn get(&self, entity: Entity) -> Option<&T> {
         let lock = self.entities.read().unwrap();
         if lock.exists(entity) {
-            self.vec
-                .get(entity.index as usize)
-                .unwrap_or(&None)
-                .as_ref()
+            self.get_unchecked(entity)
         } else {
             None
         }
     }
 
     /// Get a mutable reference to the component associated with the given entity in self, if any.
+    /// The component counts as modified at the current tick.
     pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
-        let lock = self.entities.read().unwrap();
-        if lock.exists(entity) {
-            if let Some(entry) = self.vec.get_mut(entity.index as usize) {
-                entry.as_mut()
-            } else {
-                None
-            }
+        let exists = self.entities.read().unwrap().exists(entity);
+        if exists {
+            self.get_mut_unchecked(entity)
         } else {
             None
         }
     }
 
+    /// Get a reference to the component associated with the given entity in self, if any.
+    /// Does not check if the entity exists; only use this if you know it exists, e.g.
+    /// because you retrieved this in a loop iterating over all alive entities.
+    pub fn get_unchecked(&self, entity: Entity) -> Option<&T> {
+        let _span = profile::record::<T>(Access::Get);
+        self.current_slot(entity)
+            .and_then(|slot| self.vec.get(slot))
+            .and_then(|entry| entry.as_ref())
+    }
+
+    /// Get a mutable reference to the component associated with the given entity in self, if any.
+    /// Does not check if the entity exists; only use this if you know it exists, e.g.
+    /// because you retrieved this in a loop iterating over all alive entities.
+    /// The component counts as modified at the current tick.
+    pub fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T> {
+        self.owner.check::<T>("get_mut");
+        let _span = profile::record::<T>(Access::GetMut);
+        let slot = self.current_slot(entity)?;
+        let component = self.vec.get_mut(slot)?.as_mut()?;
+        self.ticks[slot] = self.tick;
+        Some(component)
+    }
+
+    /// Call `f` for every component of an alive entity in index order, e.g. for dense per-frame updates.
+    /// Iterates the underlying Vec directly while locking the entities only once, instead of locking and
+    /// looking up every entity like `get_mut()`. All visited components count as modified at the current tick.
+    pub fn for_each_mut(&mut self, mut f: impl FnMut(Entity, &mut T)) {
+        self.owner.check::<T>("for_each_mut");
+        let _span = profile::record::<T>(Access::GetMut);
+        let entities = self.entities.read().unwrap();
+        let slots = self
+            .vec
+            .iter_mut()
+            .zip(&self.generations)
+            .zip(self.ticks.iter_mut());
+        for (slot, ((component, generation), tick)) in slots.enumerate() {
+            if let Some(component) = component {
+                let entity = Entity {
+                    index: self.base + slot as u32,
+                    generation: *generation,
+                };
+                if entities.exists(entity) {
+                    *tick = self.tick;
+                    f(entity, component);
+                }
+            }
+        }
+    }
+
+    /// Like `for_each_mut()`, but splits the components into `threads` contiguous chunks processed in
+    /// parallel on scoped threads, e.g. for expensive per-component updates of large storages.
+    /// Components within a chunk are visited in index order; `threads` is at least 1.
+    pub fn par_for_each_mut(&mut self, threads: usize, f: impl Fn(Entity, &mut T) + Sync)
+    where
+        T: Send,
+    {
+        self.owner.check::<T>("par_for_each_mut");
+        let _span = profile::record::<T>(Access::GetMut);
+        let entities = self.entities.read().unwrap();
+        let chunk_len = match self.vec.len().div_ceil(threads.max(1)) {
+            0 => return,
+            chunk_len => chunk_len,
+        };
+        let (entities, f, base, current) = (&*entities, &f, self.base, self.tick);
+        let chunks = self
+            .vec
+            .chunks_mut(chunk_len)
+            .zip(self.generations.chunks(chunk_len))
+            .zip(self.ticks.chunks_mut(chunk_len));
+        std::thread::scope(|scope| {
+            for (i, ((components, generations), ticks)) in chunks.enumerate() {
+                scope.spawn(move || {
+                    let slots = components.iter_mut().zip(generations).zip(ticks);
+                    for (slot, ((component, generation), tick)) in slots.enumerate() {
+                        if let Some(component) = component {
+                            let entity = Entity {
+                                index: base + (i * chunk_len + slot) as u32,
+                                generation: *generation,
+                            };
+                            if entities.exists(entity) {
+                                *tick = current;
+                                f(entity, component);
+                            }
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    /// Set the current tick, e.g. the simulation tick of the World.
+    /// Components set or mutably accessed afterwards record it as their last modification.
+    pub fn set_tick(&mut self, tick: u32) {
+        self.tick = tick;
+    }
+
+    /// The current tick; see `set_tick()`.
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    /// The tick the component of the given entity was last set or mutably accessed at,
+    /// or None if the entity doesn't exist or has no component in self.
+    pub fn last_modified(&self, entity: Entity) -> Option<u32> {
+        self.get(entity)?;
+        self.slot(entity).map(|slot| self.ticks[slot])
+    }
+
+    /// List the alive entities whose component was set, mutably accessed or removed after the given tick,
+    /// by index, with their current component or None if it was removed; see `set_tick()`.
+    pub fn changes_since(&self, since: u32) -> std::vec::Vec<(Entity, Option<&T>)> {
+        self.changes_where(|tick| tick > since)
+    }
+
+    /// List the alive entities whose component was set, mutably accessed or removed at one of the given
+    /// ticks, e.g. for a `ChangeCursor`; see `changes_since()`.
+    /// Removals at tick 0 can't be told apart from slots that never held a component and aren't listed.
+    pub fn changes_in(&self, ticks: std::ops::Range<u32>) -> std::vec::Vec<(Entity, Option<&T>)> {
+        let mut changes = self.changes_where(|tick| ticks.contains(&tick));
+        if ticks.contains(&0) {
+            changes.retain(|(entity, component)| {
+                component.is_some() || self.slot(*entity).is_some_and(|slot| self.ticks[slot] > 0)
+            });
+        }
+        changes
+    }
+
+    /// List the alive entities whose component was last modified at a tick matching the given predicate.
+    fn changes_where(&self, matches: impl Fn(u32) -> bool) -> std::vec::Vec<(Entity, Option<&T>)> {
+        let lock = self.entities.read().unwrap();
+        self.ticks
+            .iter()
+            .enumerate()
+            .filter(|(_, tick)| matches(**tick))
+            .filter_map(|(slot, _)| {
+                let entity = lock.current_entity_at(self.base + slot as u32)?;
+                if self.generations[slot] != entity.generation {
+                    return None;
+                }
+                Some((entity, self.vec[slot].as_ref()))
+            })
+            .collect()
+    }
+
+    /// Grow the storage once to hold components for all entity indices below `index_end`, so setting them
+    /// doesn't reallocate, e.g. to `Entities::index_end()` after `Entities::reserve()`.
+    pub fn grow_to(&mut self, index_end: u32) {
+        let len = index_end.saturating_sub(self.base) as usize;
+        if len > self.vec.len() {
+            let additional = len - self.vec.len();
+            let capacity = self.vec.capacity();
+            self.vec.reserve_exact(additional);
+            self.generations.reserve_exact(additional);
+            self.ticks.reserve_exact(additional);
+            self.vec.resize_with(len, || None);
+            self.generations.resize(len, 0);
+            self.ticks.resize(len, 0);
+            self.record_growth(capacity);
+        }
+    }
+
+    /// Record the growth of the slots from the given capacity with the `track_allocations` feature.
+    fn record_growth(&self, capacity: usize) {
+        allocations::record_growth::<T, (Option<T>, u32, u32)>(
+            AllocationKind::Growth,
+            capacity,
+            self.vec.capacity(),
+        );
+    }
+
     /// Set the component for the given entity.
-    /// Returns Err(NoSuchEntity) if the given entity doesn't exist.
+    /// Returns Err(StorageError::NoSuchEntity) if the given entity doesn't exist.
     /// Otherwise, returns Ok(data), where data is previous data evicted by this operation (if any).
-    pub fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, NoSuchEntity> {
-        let lock = self.entities.read().unwrap();
-        if lock.exists(entity) {
-            match self.vec.get_mut(entity.index as usize) {
-                None => {
-                    // Double capacity or grow enough to have room for the next index, if doubling is not enough
-                    let new_len = usize::max(self.vec.capacity() * 2, entity.index as usize + 1);
-                    self.vec.resize_with(new_len, || None);
-
-                    self.vec[entity.index as usize] = Some(data);
-                    Ok(None)
+    pub fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        let exists = self.entities.read().unwrap().exists(entity);
+        if exists {
+            Ok(self.set_unchecked(entity, data))
+        } else {
+            Err(NoSuchEntity::new(entity).with_operation("set").into())
+        }
+    }
+
+    /// Set the component for the given entity.
+    /// Returns the previous data stored in self for the given entity.
+    /// Does not check if the entity exists and doesn't lock the entities; only use this if you know
+    /// it exists, e.g. because you just spawned it while holding the write lock of the entities.
+    pub fn set_unchecked(&mut self, entity: Entity, data: T) -> Option<T> {
+        self.owner.check::<T>("set");
+        let _span = profile::record::<T>(Access::Set);
+        let index = self
+            .slot(entity)
+            .expect("entity index below the index base of the storage's entities");
+        if index >= self.vec.len() {
+            // Double capacity or grow enough to have room for the next index, if doubling is not enough
+            let capacity = self.vec.capacity();
+            let new_len = usize::max(capacity * 2, index + 1);
+            self.vec.resize_with(new_len, || None);
+            self.generations.resize(new_len, 0);
+            self.ticks.resize(new_len, 0);
+            self.record_growth(capacity);
+        }
+
+        trace::component::<T>("set", entity);
+        let stale = self.generations[index] != entity.generation;
+        self.generations[index] = entity.generation;
+        self.ticks[index] = self.tick;
+        // A component of an earlier entity at the same index is dropped rather than returned.
+        self.vec[index].replace(data).filter(|_| !stale)
+    }
+
+    /// Remove the component of the given entity after it was despawned.
+    /// Returns the data associated with the given entity in self, if any.
+    /// Doesn't lock the entities; a component set for another entity at the same index,
+    /// e.g. because the given entity is stale, is left untouched.
+    pub fn remove_for_despawn(&mut self, entity: Entity) -> Option<T> {
+        self.owner.check::<T>("remove_for_despawn");
+        let _span = profile::record::<T>(Access::Remove);
+        match self.slot(entity) {
+            Some(index) if self.generations.get(index) == Some(&entity.generation) => {
+                let removed = self.vec[index].take();
+                if removed.is_some() {
+                    trace::component::<T>("remove", entity);
                 }
-                Some(entry) => Ok(entry.replace(data)),
+                removed
             }
-        } else {
-            Err(NoSuchEntity {})
+            _ => None,
         }
     }
 
     /// Remove the component for the given entity.
     /// Returns the previous data associated with the given entity in self.
-    /// Does not check if the entity exists; only use this if you know it exists, e.g.
-    /// through invariants in your code or because you retrieved this in a loop iterating
-    /// over all alive entities.
+    #[deprecated(
+        note = "use `remove_for_despawn`, which leaves components of newer entities untouched"
+    )]
     pub fn remove_unchecked(&mut self, entity: Entity) -> Option<T> {
-        if let Some(entry) = self.vec.get_mut(entity.index as usize) {
-            entry.take()
-        } else {
-            None
-        }
+        self.remove_for_despawn(entity)
     }
 
     /// Remove the component for the given entity.
     /// Returns the previous data associated with the given entity in self.
-    pub fn remove(&mut self, entity: Entity) -> Result<Option<T>, NoSuchEntity> {
-        let lock = self.entities.read().unwrap();
+    /// The removal counts as a modification at the current tick; see `changes_since()`.
+    pub fn remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        let _span = profile::record::<T>(Access::Remove);
+        let exists = self.entities.read().unwrap().exists(entity);
+        if exists {
+            Ok(self.remove_existing(entity))
+        } else {
+            Err(NoSuchEntity::new(entity).with_operation("remove").into())
+        }
+    }
+
+    /// Remove the component of the given entity, which is known to exist.
+    /// A component of an earlier entity at the same index counts as absent and is left in place.
+    fn remove_existing(&mut self, entity: Entity) -> Option<T> {
+        self.owner.check::<T>("remove");
+        let slot = self.current_slot(entity)?;
+        let removed = self.vec.get_mut(slot)?.take();
+        if removed.is_some() {
+            trace::component::<T>("remove", entity);
+            self.ticks[slot] = self.tick;
+        }
+        removed
+    }
+
+    /// The number of allocated slots, i.e. one more than the highest entity index a component was set for.
+    pub fn slots(&self) -> usize {
+        self.vec.len()
+    }
+
+    /// The number of slots holding a component.
+    pub fn occupied(&self) -> usize {
+        self.vec.iter().filter(|entry| entry.is_some()).count()
+    }
+
+    /// The fraction of allocated slots holding a component; 1.0 if no slots are allocated.
+    /// A low occupancy means most slots are wasted, e.g. because only few entities have the component.
+    pub fn occupancy(&self) -> f64 {
+        match self.slots() {
+            0 => 1.0,
+            slots => self.occupied() as f64 / slots as f64,
+        }
+    }
+
+    /// Use the given entities, e.g. those of the World after deserializing self from an rkyv archive,
+    /// which doesn't include them. They must have the same index base as the entities self was archived with.
+    #[cfg(feature = "rkyv")]
+    pub fn attach_entities(&mut self, entities: Arc<RwLock<Entities>>) {
+        self.entities = entities;
+    }
+
+    /// Only allow mutating the components of self from the given thread, or from any thread for None, with the
+    /// `thread_checks` feature; storages are bound to the thread that created them. Does nothing without it.
+    pub fn bind_to_thread(&mut self, thread: Option<ThreadId>) {
+        self.owner = OwnerThread::new(thread);
+    }
+
+    /// The memory used by self; see `MemoryUsage`.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            components: self.occupied(),
+            slots: self.slots(),
+            bytes: self.vec.capacity() * std::mem::size_of::<Option<T>>()
+                + (self.generations.capacity() + self.ticks.capacity())
+                    * std::mem::size_of::<u32>(),
+        }
+    }
+
+    /// Take the entities and the owner thread of self and iterate over all stored components with the entities
+    /// they were set for.
+    pub(crate) fn into_components(
+        self,
+    ) -> (
+        Arc<RwLock<Entities>>,
+        OwnerThread,
+        impl Iterator<Item = (Entity, T)>,
+    ) {
+        let base = self.base;
+        let components = self
+            .vec
+            .into_iter()
+            .zip(self.generations)
+            .enumerate()
+            .filter_map(move |(slot, (component, generation))| {
+                let entity = Entity {
+                    index: base + slot as u32,
+                    generation,
+                };
+                Some((entity, component?))
+            });
+        (self.entities, self.owner, components)
+    }
+
+    /// Like `get()`, but returns Err(StorageError::WouldBlock) instead of blocking if the entities are locked
+    /// by another thread, and Err(StorageError::NoSuchEntity) if the given entity doesn't exist.
+    pub fn try_get(&self, entity: Entity) -> Result<Option<&T>, StorageError> {
+        self.try_check_exists(entity, "try_get")?;
+        Ok(self.get_unchecked(entity))
+    }
+
+    /// Like `get_mut()`, but returns Err(StorageError::WouldBlock) instead of blocking if the entities are
+    /// locked by another thread, and Err(StorageError::NoSuchEntity) if the given entity doesn't exist.
+    pub fn try_get_mut(&mut self, entity: Entity) -> Result<Option<&mut T>, StorageError> {
+        self.try_check_exists(entity, "try_get_mut")?;
+        Ok(self.get_mut_unchecked(entity))
+    }
+
+    /// Like `set()`, but returns Err(StorageError::WouldBlock) instead of blocking if the entities are
+    /// locked by another thread.
+    pub fn try_set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        self.try_check_exists(entity, "try_set")?;
+        Ok(self.set_unchecked(entity, data))
+    }
+
+    /// Like `remove()`, but returns Err(StorageError::WouldBlock) instead of blocking if the entities are
+    /// locked by another thread.
+    pub fn try_remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        let _span = profile::record::<T>(Access::Remove);
+        self.try_check_exists(entity, "try_remove")?;
+        Ok(self.remove_existing(entity))
+    }
+
+    /// Check that the given entity exists without blocking on the lock of the entities.
+    fn try_check_exists(
+        &self,
+        entity: Entity,
+        operation: &'static str,
+    ) -> Result<(), StorageError> {
+        let lock = try_read_now(&self.entities)?;
         if lock.exists(entity) {
-            if let Some(entry) = self.vec.get_mut(entity.index as usize) {
-                Ok(entry.take())
-            } else {
-                Ok(None)
-            }
+            Ok(())
         } else {
-            Err(NoSuchEntity)
+            Err(NoSuchEntity::new(entity).with_operation(operation).into())
+        }
+    }
+
+    /// Replace the contents of the given bit set with the indices of the alive entities that have a
+    /// component in self, e.g. to intersect it with other storages; see `BitSet`.
+    /// Reusing the same bit set avoids allocating its words every time.
+    pub fn presence_into(&self, bits: &mut BitSet) {
+        let entities = self.entities.read().unwrap();
+        bits.reset(entities.index_base());
+        for (entity, _) in self.alive_components(&entities) {
+            bits.insert(entity.index);
         }
     }
 
     /// Remove the data stored in self for all entities.
     pub fn clear(&mut self) {
+        self.owner.check::<T>("clear");
         self.vec.clear();
+        self.generations.clear();
+        self.ticks.clear();
+    }
+
+    /// Remove the components of all alive entities from self, leaving the entities themselves untouched.
+    /// Returns the number of removed components. The removals count as modifications at the current tick;
+    /// see `changes_since()`.
+    pub fn clear_checked(&mut self) -> usize {
+        self.owner.check::<T>("clear_checked");
+        let entities = Arc::clone(&self.entities);
+        let lock = entities.read().unwrap();
+        lock.iter()
+            .filter(|entity| self.remove_existing(*entity).is_some())
+            .count()
+    }
+
+    /// Rewrite the entities stored in all components of self; see `MapEntities`.
+    pub fn map_entities(&mut self, mapper: &mut dyn FnMut(Entity) -> Entity)
+    where
+        T: MapEntities,
+    {
+        self.owner.check::<T>("map_entities");
+        for component in self.vec.iter_mut().flatten() {
+            component.map_entities(mapper);
+        }
+    }
+
+    /// Check that self uses the given entities and doesn't hold components for unallocated indices.
+    /// `storage` is the name of this storage used for error reporting.
+    pub fn check_integrity(
+        &self,
+        entities: &Arc<RwLock<Entities>>,
+        storage: &'static str,
+    ) -> Result<(), IntegrityError> {
+        if !Arc::ptr_eq(&self.entities, entities) {
+            return Err(IntegrityError::ForeignEntities { storage });
+        }
+        let highest_index = self
+            .vec
+            .iter()
+            .rposition(|entry| entry.is_some())
+            .map(|index| self.base + index as u32);
+        match highest_index {
+            Some(index) if index >= entities.read().unwrap().index_end() => {
+                Err(IntegrityError::UnallocatedIndex { storage, index })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Clone the data stored in self into a new VecStorage<T> that uses the given entities.
+    /// This is used to fork a World, where the forked storages must not share the original `Entities`.
+    pub fn clone_with_entities(&self, entities: Arc<RwLock<Entities>>) -> Self
+    where
+        T: Clone,
+    {
+        Self {
+            vec: self.vec.clone(),
+            generations: self.generations.clone(),
+            ticks: self.ticks.clone(),
+            tick: self.tick,
+            base: self.base,
+            entities,
+            owner: self.owner,
+        }
+    }
+}
+
+/// Moves the components of a MapStorage<T> into a VecStorage<T> that uses the same entities, e.g. when a
+/// component turns out to be set for most entities; see `DynamicStorage::migrate()`.
+/// The moved components count as modified at tick 0. Components of despawned entities are dropped, as a
+/// VecStorage<T> would otherwise hand them to the next entity at the same index.
+impl<T> From<MapStorage<T>> for VecStorage<T> {
+    fn from(storage: MapStorage<T>) -> Self {
+        let (entities, owner, components) = storage.into_components();
+        let lock = Arc::clone(&entities);
+        let lock = lock.read().unwrap();
+        let mut vec = VecStorage::new_in_with_base(entities, lock.index_base(), 0, Global);
+        vec.owner = owner;
+        vec.grow_to(lock.index_end());
+        for (entity, component) in components.filter(|(entity, _)| lock.exists(*entity)) {
+            vec.set_unchecked(entity, component);
+        }
+        vec
+    }
+}
+
+/// Storages are equal if the same alive entities have equal components in them.
+impl<T: PartialEq, A: Allocator + Clone> PartialEq for VecStorage<T, A> {
+    fn eq(&self, other: &Self) -> bool {
+        let left = self.entities.read().unwrap();
+        if Arc::ptr_eq(&self.entities, &other.entities) {
+            return self
+                .alive_components(&left)
+                .eq(other.alive_components(&left));
+        }
+        let right = other.entities.read().unwrap();
+        self.alive_components(&left)
+            .eq(other.alive_components(&right))
+    }
+}
+
+impl<T: Eq, A: Allocator + Clone> Eq for VecStorage<T, A> {}
+
+/// Shows the components of all alive entities by entity.
+impl<T: Debug, A: Allocator + Clone> Debug for VecStorage<T, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.entities.try_read() {
+            Ok(entities) => f
+                .debug_map()
+                .entries(self.alive_components(&entities))
+                .finish(),
+            Err(_) => f.write_str("VecStorage { <entities locked> }"),
+        }
+    }
+}
+
+impl<T, A: Allocator + Clone> Storage<T> for VecStorage<T, A> {
+    fn get(&self, entity: Entity) -> Option<&T> {
+        self.get(entity)
+    }
+
+    fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_mut(entity)
+    }
+
+    fn get_unchecked(&self, entity: Entity) -> Option<&T> {
+        self.get_unchecked(entity)
+    }
+
+    fn get_mut_unchecked(&mut self, entity: Entity) -> Option<&mut T> {
+        self.get_mut_unchecked(entity)
+    }
+}
+
+impl<T, A: Allocator + Clone> TrackChanges<T> for VecStorage<T, A> {
+    fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    fn changes_in(&self, ticks: std::ops::Range<u32>) -> std::vec::Vec<(Entity, Option<&T>)> {
+        self.changes_in(ticks)
+    }
+}
+
+impl<T, A: Allocator + Clone> ModelStorage<T> for VecStorage<T, A> {
+    fn set(&mut self, entity: Entity, data: T) -> Result<Option<T>, StorageError> {
+        self.set(entity, data)
+    }
+
+    fn remove(&mut self, entity: Entity) -> Result<Option<T>, StorageError> {
+        self.remove(entity)
+    }
+
+    fn remove_for_despawn(&mut self, entity: Entity) -> Option<T> {
+        self.remove_for_despawn(entity)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lock::WouldBlock;
 
     #[derive(Debug, Clone, Copy, Eq, PartialEq)]
     struct VecTestData(i32);
 
+    #[test]
+    fn vec_try_operations_do_not_block() {
+        let entities = Arc::new(RwLock::new(Entities::new(3)));
+        let mut vec = VecStorage::new(Arc::clone(&entities), 3);
+        let entity = entities.write().unwrap().spawn();
+        assert_eq!(vec.try_set(entity, VecTestData(1)), Ok(None));
+
+        {
+            let _lock = entities.write().unwrap();
+            assert_eq!(
+                vec.try_set(entity, VecTestData(2)),
+                Err(StorageError::WouldBlock(WouldBlock))
+            );
+            assert!(vec.try_get(entity).is_err());
+            assert!(vec.try_remove(entity).is_err());
+        }
+
+        assert_eq!(vec.try_get(entity), Ok(Some(&VecTestData(1))));
+        assert_eq!(vec.try_remove(entity), Ok(Some(VecTestData(1))));
+        entities.write().unwrap().despawn(entity).unwrap();
+        assert!(matches!(
+            vec.try_get_mut(entity),
+            Err(StorageError::NoSuchEntity(_))
+        ));
+    }
+
+    #[test]
+    fn for_each_mut_visits_alive_components() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(3)));
+        let mut storage = VecStorage::<VecTestData>::new(Arc::clone(&entities), 3);
+        let spawned = (0..5)
+            .map(|_| entities.write().unwrap().spawn())
+            .collect::<std::vec::Vec<_>>();
+        for (i, entity) in spawned.iter().enumerate() {
+            storage.set(*entity, VecTestData(i as i32))?;
+        }
+        entities.write().unwrap().despawn(spawned[1])?;
+
+        let mut visited = vec![];
+        storage.set_tick(2);
+        storage.for_each_mut(|entity, data| {
+            data.0 *= 10;
+            visited.push(entity);
+        });
+        assert_eq!(
+            visited,
+            vec![spawned[0], spawned[2], spawned[3], spawned[4]]
+        );
+        assert_eq!(storage.get(spawned[3]), Some(&VecTestData(30)));
+        assert_eq!(storage.last_modified(spawned[4]), Some(2));
+
+        storage.par_for_each_mut(2, |_, data| data.0 += 1);
+        let values = spawned
+            .iter()
+            .map(|entity| storage.get(*entity).map(|data| data.0))
+            .collect::<std::vec::Vec<_>>();
+        assert_eq!(values, vec![Some(1), None, Some(21), Some(31), Some(41)]);
+        Ok(())
+    }
+
     #[test]
     fn vec_get_not_set() {
         let entities = Arc::new(RwLock::new(Entities::new(3)));
@@ -122,7 +768,7 @@ mod tests {
     }
 
     #[test]
-    fn vec_get() -> Result<(), NoSuchEntity> {
+    fn vec_get() -> Result<(), StorageError> {
         let entities = Arc::new(RwLock::new(Entities::new(3)));
         let mut vec = VecStorage::<VecTestData>::new(Arc::clone(&entities), 3);
 
@@ -139,7 +785,7 @@ mod tests {
     }
 
     #[test]
-    fn vec_set_exists() -> Result<(), NoSuchEntity> {
+    fn vec_set_exists() -> Result<(), StorageError> {
         let entities = Arc::new(RwLock::new(Entities::new(3)));
         let mut vec = VecStorage::<VecTestData>::new(Arc::clone(&entities), 3);
 
@@ -161,7 +807,7 @@ mod tests {
     }
 
     #[test]
-    fn can_insert_after_remove() -> Result<(), NoSuchEntity> {
+    fn can_insert_after_remove() -> Result<(), StorageError> {
         let entities = Arc::new(RwLock::new(Entities::new(3)));
         let mut vec = VecStorage::<VecTestData>::new(Arc::clone(&entities), 3);
 
@@ -200,7 +846,7 @@ mod tests {
     }
 
     #[test]
-    fn inserting_grows_vec_enough() -> Result<(), NoSuchEntity> {
+    fn inserting_grows_vec_enough() -> Result<(), StorageError> {
         let capacity = 1;
         let n = 3;
         let entities = Arc::new(RwLock::new(Entities::new(capacity)));
@@ -222,7 +868,7 @@ mod tests {
     }
 
     #[test]
-    fn remove_missing_is_ok() -> Result<(), NoSuchEntity> {
+    fn remove_missing_is_ok() -> Result<(), StorageError> {
         let entities = Arc::new(RwLock::new(Entities::new(3)));
         let mut vec = VecStorage::<VecTestData>::new(Arc::clone(&entities), 3);
         let entity = {
@@ -235,7 +881,194 @@ mod tests {
     }
 
     #[test]
-    fn test_iter_update() -> Result<(), NoSuchEntity> {
+    fn remove_for_despawn_ignores_stale_entities() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(3)));
+        let mut vec = VecStorage::<VecTestData>::new(Arc::clone(&entities), 3);
+        let stale = entities.write().unwrap().spawn();
+        vec.set(stale, VecTestData(1))?;
+        assert_eq!(vec.remove_for_despawn(stale), Some(VecTestData(1)));
+
+        let entity = {
+            let mut lock = entities.write().unwrap();
+            lock.despawn(stale)?;
+            lock.spawn()
+        };
+        assert_eq!(entity.index, stale.index);
+        vec.set(entity, VecTestData(2))?;
+        assert_eq!(vec.remove_for_despawn(stale), None);
+        assert_eq!(vec.get(entity), Some(&VecTestData(2)));
+        Ok(())
+    }
+
+    #[test]
+    fn records_last_modified_tick() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(1)));
+        let mut vec = VecStorage::<VecTestData>::new(Arc::clone(&entities), 1);
+        let (first, second) = {
+            let mut lock = entities.write().unwrap();
+            (lock.spawn(), lock.spawn())
+        };
+        vec.set_tick(3);
+        vec.set(first, VecTestData(1))?;
+        vec.set_tick(7);
+        vec.set(second, VecTestData(2))?;
+        assert_eq!(vec.last_modified(first), Some(3));
+        assert_eq!(vec.last_modified(second), Some(7));
+
+        vec.set_tick(9);
+        vec.get(first);
+        assert_eq!(vec.last_modified(first), Some(3));
+        vec.get_mut(first).unwrap().0 += 1;
+        assert_eq!(vec.last_modified(first), Some(9));
+
+        vec.remove(second)?;
+        assert_eq!(vec.last_modified(second), None);
+        assert_eq!(
+            vec.changes_since(7),
+            vec![(first, Some(&VecTestData(2))), (second, None)]
+        );
+        assert!(vec.changes_since(9).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn compares_alive_components() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(3)));
+        let mut vec = VecStorage::<VecTestData>::new(Arc::clone(&entities), 3);
+        let (first, second) = {
+            let mut lock = entities.write().unwrap();
+            (lock.spawn(), lock.spawn())
+        };
+        vec.set(first, VecTestData(1))?;
+        vec.set(second, VecTestData(2))?;
+        let mut other = vec.clone();
+        assert_eq!(vec, other);
+
+        other.set(second, VecTestData(3))?;
+        assert_ne!(vec, other);
+        entities.write().unwrap().despawn(second)?;
+        assert_eq!(vec, other);
+        assert_eq!(
+            format!("{:?}", vec),
+            format!("{{{:?}: VecTestData(1)}}", first)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn respects_index_base() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(2).with_index_base(1000)));
+        let mut vec = VecStorage::<VecTestData>::new(Arc::clone(&entities), 2);
+        let entity = entities.write().unwrap().spawn();
+        vec.set(entity, VecTestData(1))?;
+        assert_eq!(vec.vec.len(), 2);
+        assert_eq!(vec.get(entity), Some(&VecTestData(1)));
+        assert_eq!(vec.check_integrity(&entities, "vec"), Ok(()));
+
+        let below_base = Entity {
+            index: 0,
+            generation: 0,
+        };
+        assert_eq!(vec.get_unchecked(below_base), None);
+        assert_eq!(vec.remove_for_despawn(below_base), None);
+        assert_eq!(vec.clear_checked(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn allocates_from_given_allocator() -> Result<(), StorageError> {
+        use allocator_api2::alloc::AllocError;
+        use std::alloc::Layout;
+        use std::ptr::NonNull;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Clone, Default)]
+        struct CountingAllocator(Arc<AtomicUsize>);
+
+        unsafe impl Allocator for CountingAllocator {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Global.allocate(layout)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                Global.deallocate(ptr, layout)
+            }
+        }
+
+        let allocator = CountingAllocator::default();
+        let entities = Arc::new(RwLock::new(Entities::new(3)));
+        let mut vec =
+            VecStorage::<VecTestData, _>::new_in(Arc::clone(&entities), 0, allocator.clone());
+        let entity = entities.write().unwrap().spawn();
+        vec.set(entity, VecTestData(1))?;
+        assert_eq!(vec.get(entity), Some(&VecTestData(1)));
+        assert!(allocator.0.load(Ordering::SeqCst) > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn clear_checked_keeps_entities() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(3)));
+        let mut vec = VecStorage::<VecTestData>::new(Arc::clone(&entities), 3);
+        let (entity_a, entity_b) = {
+            let mut lock = entities.write().unwrap();
+            (lock.spawn(), lock.spawn())
+        };
+        vec.set(entity_a, VecTestData(1))?;
+        vec.set(entity_b, VecTestData(2))?;
+
+        assert_eq!(vec.clear_checked(), 2);
+        assert_eq!(vec.get(entity_a), None);
+        assert!(entities.read().unwrap().exists(entity_a));
+
+        vec.set(entity_b, VecTestData(3))?;
+        assert_eq!(vec.get(entity_b), Some(&VecTestData(3)));
+        Ok(())
+    }
+
+    #[test]
+    fn clear_checked_records_removals_of_current_components() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(2)));
+        let mut vec = VecStorage::<VecTestData>::new(Arc::clone(&entities), 2);
+        let (kept, despawned) = {
+            let mut lock = entities.write().unwrap();
+            (lock.spawn(), lock.spawn())
+        };
+        vec.set(kept, VecTestData(1))?;
+        vec.set(despawned, VecTestData(2))?;
+        entities.write().unwrap().despawn(despawned)?;
+        let respawned = entities.write().unwrap().spawn();
+        assert_eq!(respawned.index, despawned.index);
+
+        vec.set_tick(1);
+        assert_eq!(vec.clear_checked(), 1);
+        assert_eq!(vec.changes_since(0), vec![(kept, None)]);
+        Ok(())
+    }
+
+    #[test]
+    fn clone_with_entities_is_independent() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(3)));
+        let mut vec = VecStorage::<VecTestData>::new(Arc::clone(&entities), 3);
+        let entity = entities.write().unwrap().spawn();
+        vec.set(entity, VecTestData(1))?;
+
+        let forked_entities = Arc::new(RwLock::new(entities.read().unwrap().clone()));
+        let mut forked = vec.clone_with_entities(Arc::clone(&forked_entities));
+        assert_eq!(forked.get(entity), Some(&VecTestData(1)));
+
+        forked.set(entity, VecTestData(2))?;
+        assert_eq!(vec.get(entity), Some(&VecTestData(1)));
+
+        forked_entities.write().unwrap().despawn(entity)?;
+        assert_eq!(forked.get(entity), None);
+        assert_eq!(vec.get(entity), Some(&VecTestData(1)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_iter_update() -> Result<(), StorageError> {
         let entities = Arc::new(RwLock::new(Entities::new(3)));
         let mut vec = VecStorage::<VecTestData>::new(Arc::clone(&entities), 3);
 
@@ -272,4 +1105,78 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn vec_ignores_components_of_earlier_generations() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(1)));
+        let mut vec = VecStorage::new(Arc::clone(&entities), 1);
+        let despawned = entities.write().unwrap().spawn();
+        vec.set(despawned, VecTestData(1))?;
+        // Despawn without removing the component, e.g. from another storage's point of view.
+        entities.write().unwrap().despawn(despawned)?;
+        let respawned = entities.write().unwrap().spawn();
+        assert_eq!(respawned.index, despawned.index);
+
+        assert_eq!(vec.get(respawned), None);
+        assert_eq!(vec.get_unchecked(respawned), None);
+        assert_eq!(vec.get_mut(respawned), None);
+        assert_eq!(vec.get_mut_unchecked(respawned), None);
+        vec.set(respawned, VecTestData(2))?;
+        assert_eq!(vec.get_unchecked(respawned), Some(&VecTestData(2)));
+        assert_eq!(vec.get_unchecked(despawned), None);
+        Ok(())
+    }
+
+    #[test]
+    fn vec_set_and_remove_ignore_components_of_earlier_generations() -> Result<(), StorageError> {
+        let entities = Arc::new(RwLock::new(Entities::new(1)));
+        let mut vec = VecStorage::new(Arc::clone(&entities), 1);
+        let despawned = entities.write().unwrap().spawn();
+        vec.set(despawned, VecTestData(1))?;
+        entities.write().unwrap().despawn(despawned)?;
+        let respawned = entities.write().unwrap().spawn();
+        assert_eq!(respawned.index, despawned.index);
+
+        assert_eq!(vec.remove(respawned)?, None);
+        assert_eq!(vec.set(respawned, VecTestData(2))?, None);
+        assert_eq!(vec.remove(respawned)?, Some(VecTestData(2)));
+        Ok(())
+    }
+
+    #[cfg(feature = "rkyv")]
+    #[derive(Debug, Eq, PartialEq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+    #[archive(check_bytes)]
+    struct VecArchiveData(i32);
+
+    #[cfg(feature = "rkyv")]
+    #[test]
+    fn archive_vec_storage() -> Result<(), StorageError> {
+        use rkyv::Deserialize;
+
+        let entities = Arc::new(RwLock::new(Entities::new(2)));
+        let mut storage = VecStorage::new(Arc::clone(&entities), 2);
+        let (kept, stale) = {
+            let mut entities = entities.write().unwrap();
+            (entities.spawn(), entities.spawn())
+        };
+        storage.set(kept, VecArchiveData(1))?;
+        storage.set(stale, VecArchiveData(2))?;
+        // Despawn without removing the component, so only its stored generation tells it apart.
+        entities.write().unwrap().despawn(stale)?;
+        let respawned = entities.write().unwrap().spawn();
+        assert_eq!(respawned.index, stale.index);
+
+        let bytes = rkyv::to_bytes::<_, 256>(&storage).unwrap();
+        let archived = rkyv::check_archived_root::<VecStorage<VecArchiveData>>(&bytes).unwrap();
+        let mut restored: VecStorage<VecArchiveData> =
+            archived.deserialize(&mut rkyv::Infallible).unwrap();
+        restored.attach_entities(Arc::clone(&entities));
+
+        assert_eq!(restored.get(kept), Some(&VecArchiveData(1)));
+        let mut present = BitSet::new(0);
+        restored.presence_into(&mut present);
+        assert!(present.contains(kept.index));
+        assert!(!present.contains(respawned.index));
+        Ok(())
+    }
 }
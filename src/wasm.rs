@@ -0,0 +1,48 @@
+//! Building blocks of the JavaScript bindings generated for Worlds passed `wasm = <name>` in `#[world]`.
+
+use crate::entity::Entity;
+use thiserror::Error;
+
+/// The largest integer a JavaScript number represents exactly, `Number.MAX_SAFE_INTEGER`.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+/// Error returned when a JavaScript number doesn't represent an entity.
+#[derive(Error, Debug, Clone, Copy, PartialEq)]
+#[error("{0} is not an entity")]
+pub struct NotAnEntity(pub f64);
+
+/// Convert an entity into a JavaScript number holding `Entity::to_bits()`.
+/// The conversion is exact as long as the generation of the entity is below 2^21, as JavaScript
+/// numbers are doubles with a 53 bit mantissa; entities with a higher generation should be passed
+/// as strings using `Display` instead.
+pub fn entity_to_js(entity: Entity) -> f64 {
+    entity.to_bits() as f64
+}
+
+/// Convert a JavaScript number created by `entity_to_js()` back into an entity.
+/// Returns Err(NotAnEntity) if the number isn't a non-negative safe integer.
+pub fn entity_from_js(number: f64) -> Result<Entity, NotAnEntity> {
+    if number.fract() == 0.0 && (0.0..=MAX_SAFE_INTEGER).contains(&number) {
+        Ok(Entity::from_bits(number as u64))
+    } else {
+        Err(NotAnEntity(number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entities_roundtrip_through_numbers() {
+        let entity = Entity {
+            index: u32::MAX,
+            generation: (1 << 21) - 1,
+        };
+        assert_eq!(entity_from_js(entity_to_js(entity)), Ok(entity));
+        assert_eq!(entity_from_js(-1.0), Err(NotAnEntity(-1.0)));
+        assert_eq!(entity_from_js(0.5), Err(NotAnEntity(0.5)));
+        assert!(entity_from_js(f64::NAN).is_err());
+        assert!(entity_from_js(MAX_SAFE_INTEGER + 2.0).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use crate::entity::Entity;
+use crate::Entities;
+use std::fmt::{Debug, Display, Formatter};
+
+/// A difference between two Worlds found by `WorldEq::mismatches()`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum WorldMismatch {
+    /// The entity is only alive in the left World.
+    OnlyLeft(Entity),
+    /// The entity is only alive in the right World.
+    OnlyRight(Entity),
+    /// The component in the given storage of an entity alive in both Worlds differs.
+    /// The components are given by their `Debug` representation, or None if the entity doesn't have one.
+    Component {
+        entity: Entity,
+        storage: &'static str,
+        left: Option<String>,
+        right: Option<String>,
+    },
+}
+
+impl WorldMismatch {
+    /// Compare the alive entities of two Worlds, returning the entities alive in only one of them.
+    pub fn entities(left: &Entities, right: &Entities) -> Vec<WorldMismatch> {
+        let only_left = left
+            .iter()
+            .filter(|entity| !right.exists(*entity))
+            .map(WorldMismatch::OnlyLeft);
+        let only_right = right
+            .iter()
+            .filter(|entity| !left.exists(*entity))
+            .map(WorldMismatch::OnlyRight);
+        only_left.chain(only_right).collect()
+    }
+
+    /// Compare the components of the given entity in the storage with the given name of two Worlds.
+    pub fn component<T: PartialEq + Debug>(
+        entity: Entity,
+        storage: &'static str,
+        left: Option<&T>,
+        right: Option<&T>,
+    ) -> Option<WorldMismatch> {
+        if left == right {
+            None
+        } else {
+            Some(WorldMismatch::Component {
+                entity,
+                storage,
+                left: left.map(|component| format!("{:?}", component)),
+                right: right.map(|component| format!("{:?}", component)),
+            })
+        }
+    }
+}
+
+impl Display for WorldMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        fn component(component: &Option<String>) -> &str {
+            component.as_deref().unwrap_or("none")
+        }
+
+        match self {
+            WorldMismatch::OnlyLeft(entity) => {
+                write!(f, "entity {} is only alive in the left world", entity)
+            }
+            WorldMismatch::OnlyRight(entity) => {
+                write!(f, "entity {} is only alive in the right world", entity)
+            }
+            WorldMismatch::Component {
+                entity,
+                storage,
+                left,
+                right,
+            } => write!(
+                f,
+                "entity {} differs in `{}`: left {}, right {}",
+                entity,
+                storage,
+                component(left),
+                component(right)
+            ),
+        }
+    }
+}
+
+/// Entity-by-entity comparison of two Worlds; implemented for Worlds generated by `genesis` that
+/// derive `PartialEq` and `Debug`. See `assert_world_eq!`.
+pub trait WorldEq {
+    /// Compare the alive entities of self and `other` and their components, returning all differences.
+    fn mismatches(&self, other: &Self) -> Vec<WorldMismatch>;
+}
+
+/// Assert that two Worlds have the same alive entities with equal components, using `WorldEq`.
+/// On failure, the panic message lists every mismatching entity and component.
+///
+/// # Example
+/// ```ignore
+/// let mut expected = World::new(3);
+/// let entity = expected.spawn();
+/// expected.register(entity, Position { position: (1, 0) })?;
+/// move_right(&mut world);
+/// assert_world_eq!(world, expected);
+/// ```
+#[macro_export]
+macro_rules! assert_world_eq {
+    ($left:expr, $right:expr $(,)?) => {{
+        let mismatches = $crate::WorldEq::mismatches(&$left, &$right);
+        if !mismatches.is_empty() {
+            let report = mismatches
+                .iter()
+                .map(|mismatch| ::std::format!("\n  {}", mismatch))
+                .collect::<::std::string::String>();
+            ::std::panic!("worlds are not equal:{}", report);
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_mismatches() {
+        let mut left = Entities::new(2);
+        let mut right = Entities::new(2);
+        let shared = left.spawn();
+        right.spawn();
+        let only_left = left.spawn();
+        assert_eq!(
+            WorldMismatch::entities(&left, &right),
+            vec![WorldMismatch::OnlyLeft(only_left)]
+        );
+
+        assert_eq!(
+            WorldMismatch::component(shared, "names", Some(&1), Some(&1)),
+            None
+        );
+        let mismatch = WorldMismatch::component(shared, "names", Some(&1), None).unwrap();
+        assert_eq!(
+            mismatch.to_string(),
+            "entity 0:0 differs in `names`: left 1, right none"
+        );
+    }
+}